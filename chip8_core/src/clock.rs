@@ -0,0 +1,32 @@
+use core::future::Future;
+
+/// Waits for the frames of [`Chip8::run_async`](crate::Chip8::run_async)
+///
+/// Implement it for the timer of the executor, e.g. with Embassy
+///
+/// ```ignore
+/// struct Frames(embassy_time::Ticker);
+///
+/// impl FrameClock for Frames {
+///     fn next_frame(&mut self) -> impl Future<Output = ()> {
+///         self.0.next()
+///     }
+/// }
+///
+/// let exit = chip8.run_async(&mut Frames(Ticker::every(Duration::from_hz(60)))).await;
+/// ```
+///
+/// or with tokio, where `tokio::time::Interval::tick` returns the instant it completed at:
+///
+/// ```ignore
+/// impl FrameClock for Frames {
+///     async fn next_frame(&mut self) {
+///         self.0.tick().await;
+///     }
+/// }
+/// ```
+pub trait FrameClock {
+    /// Wait until the next frame of 1 / [`Chip8::TIMER_FREQ`](crate::Chip8::TIMER_FREQ)
+    /// seconds starts
+    fn next_frame(&mut self) -> impl Future<Output = ()>;
+}
@@ -1,7 +1,13 @@
 use crate::instructions::{Instruction, Register};
-use crate::peripherals::{FallingEdges, Graphics, Keys, Pos, Random, Sprite, Timer};
+use crate::peripherals::{
+    DisplayMode, FallingEdges, Framebuffer, Graphics, Keys, Pos, Random, RisingEdges, Sprite, Timer,
+};
+use crate::quirks::{KeyWait, QuirksConfig, Timing};
+#[cfg(feature = "alloc")]
+use crate::snapshot::CoreSnapshot;
 use crate::Error;
 use ::core::borrow::Borrow;
+use ::core::ops::Range;
 #[cfg(feature = "std")]
 use log::{debug, trace};
 
@@ -15,6 +21,80 @@ fn bcd(mut val: u8) -> (u8, u8, u8) {
     (hundreds, tens, val)
 }
 
+/// How a byte of memory was accessed, one entry of a coverage map
+///
+/// See [`Core::set_coverage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Coverage(pub u8);
+
+impl Coverage {
+    /// The byte was executed as part of an instruction
+    pub const EXECUTED: Self = Self(0x01);
+    /// The byte was read as sprite data by `DRW`
+    pub const SPRITE: Self = Self(0x02);
+    /// The byte was written by `LD B, Vx` or `LD [I], Vx`
+    pub const WRITTEN: Self = Self(0x04);
+    /// The byte was written after it had been executed, by self-modifying code
+    pub const MODIFIED: Self = Self(0x08);
+
+    /// Whether all accesses of `other` happened to the byte
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether the byte was accessed at all
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// What happens when the program writes to the interpreter area below the start address,
+/// which holds the font
+///
+/// See [`Core::set_write_protection`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WriteProtection {
+    /// The program may write anywhere, like most interpreters
+    #[default]
+    Off,
+    /// Writes fail with [`Error::WriteProtected`], to catch programs corrupting the font
+    Fail,
+    /// The protected bytes are left unchanged, the rest of the write happens
+    Ignore,
+}
+
+/// What a tick of a [`Core`] did, see [`Core::tick`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TickState {
+    /// An instruction was executed
+    Executed,
+    /// `LD Vx, K` holds the PC until a key is pressed or released, see
+    /// [`QuirksConfig::key_wait`]. Until then the machine is idle, hosts may throttle it or
+    /// prompt for a key.
+    ///
+    /// Only `Chip8::run_async` and the web front end make use of it, the other runners and
+    /// tools tick a waiting machine like any other.
+    WaitingForKey,
+    /// Nothing was executed, as the program exited or the [`Chip8`](crate::Chip8) is paused
+    Idle,
+}
+
+/// The registers of a [`Core`], see [`Core::state`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CoreState {
+    /// The program counter
+    pub pc: u16,
+    /// The index register
+    pub i: u16,
+    /// The number of return addresses on the stack
+    pub sp: u8,
+    /// V0 - VF
+    pub reg: [u8; 16],
+}
+
 /// The CHIP-8 core, not including any peripherals
 #[derive(Debug)]
 pub struct Core<'memory> {
@@ -24,6 +104,24 @@ pub struct Core<'memory> {
     i: u16,
     pc: u16,
     sp: u8,
+    framebuffer: Framebuffer,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    audio_changed: bool,
+    flags: [u8; 16],
+    flags_changed: bool,
+    /// The bytes written by the last instruction
+    written: Option<Range<usize>>,
+    /// The cycles taken by the last instruction, see [`QuirksConfig::timing`]
+    cycles: u32,
+    /// The PC of the `LD Vx, K` waiting for a key and its cycles
+    waiting: Option<(u16, u32)>,
+    exited: bool,
+    quirks: QuirksConfig,
+    write_protection: WriteProtection,
+    font: [u8; 80],
+    coverage: Option<&'memory mut [Coverage]>,
+    decode_cache: Option<&'memory mut [Option<Instruction>]>,
     #[cfg(feature = "std")]
     last_instruction: Option<Instruction>,
 }
@@ -50,75 +148,417 @@ impl std::fmt::Display for Core<'_> {
 impl<'memory> Core<'memory> {
     const VF: Register = Register(15);
     const FONT_LEN: usize = 5;
+    /// The most memory I can address, 64 KiB
+    pub const MAX_MEM_LEN: usize = 0x10000;
+    /// The most nested calls, the stack pointer is a byte
+    pub const MAX_STACK_DEPTH: usize = u8::MAX as usize;
 
     /// Create a new CHIP-8 core
     ///
+    /// Memory beyond 4 KiB, up to the 64 KiB I can address, is only reached by XO-CHIP's
+    /// `LD I, LONG nnnn` and by programs larger than 3.5 KiB.
+    ///
+    /// The length of `stack` is the number of nested calls, e.g. 12 like the COSMAC VIP or
+    /// 16 like most later interpreters. At most [`Core::MAX_STACK_DEPTH`] entries are used,
+    /// deeper calls fail with [`Error::StackOverflow`].
+    ///
     /// # Panic
     /// This function panics if the following conditions are not fulfilled:
-    /// * mem.len() >= 2048
+    /// * 2048 <= mem.len() <= 65536
     /// * reg.len() >= 16
-    /// * stack.len >= 16
     pub fn new(mem: &'memory mut [u8], reg: &'memory mut [u8], stack: &'memory mut [u16]) -> Self {
-        assert!(mem.len() >= 2048);
+        assert!((2048..=Self::MAX_MEM_LEN).contains(&mem.len()));
         assert!(reg.len() >= 16);
-        assert!(stack.len() >= 16);
 
-        Self::load_font(mem);
+        mem[..Self::FONT.len()].copy_from_slice(&Self::FONT);
 
         Self {
             mem,
             reg,
             stack,
             i: 0,
-            pc: 0x200,
+            pc: QuirksConfig::START,
             sp: 0,
+            framebuffer: Framebuffer::new(),
+            audio_pattern: [0; 16],
+            pitch: 64,
+            audio_changed: false,
+            flags: [0; 16],
+            flags_changed: false,
+            written: None,
+            cycles: 0,
+            waiting: None,
+            exited: false,
+            quirks: QuirksConfig::default(),
+            write_protection: WriteProtection::Off,
+            font: Self::FONT,
+            coverage: None,
+            decode_cache: None,
             #[cfg(feature = "std")]
             last_instruction: None,
         }
     }
 
-    /// Load the default font into the cores memory
-    fn load_font(loc: &mut [u8]) {
-        loc[0..(Self::FONT_LEN * 16)].copy_from_slice(&[
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ]);
+    /// The quirks the core is running with
+    pub fn quirks(&self) -> &QuirksConfig {
+        &self.quirks
+    }
+
+    /// Set the quirks the core is running with
+    ///
+    /// If the start address changes, the PC is set to the new one, so set the quirks before
+    /// running the program.
+    pub fn set_quirks(&mut self, quirks: QuirksConfig) {
+        if quirks.start != self.quirks.start {
+            self.pc = quirks.start;
+        }
+        self.quirks = quirks;
+    }
+
+    /// Load `rom` to the start address, see [`QuirksConfig::start`], clearing the rest of
+    /// the memory after it
+    ///
+    /// Fails with [`Error::RomTooLarge`] if `rom` doesn't fit into the memory.
+    pub fn load_program(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let start = self.quirks.start as usize;
+        if rom.len() > self.mem.len().saturating_sub(start) {
+            return Err(Error::RomTooLarge(rom.len()));
+        }
+
+        let program = &mut self.memory_mut()[start..];
+        program.fill(0);
+        program[..rom.len()].copy_from_slice(rom);
+        Ok(())
+    }
+
+    /// What happens when the program writes below the start address, see
+    /// [`QuirksConfig::start`]
+    pub fn write_protection(&self) -> WriteProtection {
+        self.write_protection
+    }
+
+    /// Protect the interpreter area below the start address, 0x000 - 0x1FF by default, from
+    /// writes of the program, e.g. `LD [I], Vx` with a small I overwriting the font
+    ///
+    /// Only `LD B, Vx` and `LD [I], Vx` are affected, [`Core::memory_mut`] and
+    /// [`Core::set_font`] still write there.
+    pub fn set_write_protection(&mut self, protection: WriteProtection) {
+        self.write_protection = protection;
+    }
+
+    /// Replace the font `LD F, Vx` points into, 5 bytes for each of the hex digits 0 - F
+    ///
+    /// The font is written to 0x000 and loaded again by every [`Core::reset`].
+    pub fn set_font(&mut self, font: &[u8; 80]) {
+        self.font = *font;
+        self.memory_range_mut(0..font.len())
+            .expect("the memory holds at least 2 KiB")
+            .copy_from_slice(font);
+    }
+
+    /// Record the accesses of every address of memory in `coverage`
+    ///
+    /// The map is indexed by address, addresses beyond its end aren't recorded. It is kept
+    /// across resets, so it accumulates the coverage of all runs. Writes to bytes executed
+    /// before are marked as [`Coverage::MODIFIED`] and logged, to find self-modifying code.
+    pub fn set_coverage(&mut self, coverage: &'memory mut [Coverage]) {
+        self.coverage = Some(coverage);
+    }
+
+    /// The coverage map, if one was set
+    pub fn coverage(&self) -> Option<&[Coverage]> {
+        self.coverage.as_deref()
+    }
+
+    /// Keep decoded instructions in `cache`, so loops don't decode them on every iteration
+    ///
+    /// The cache is indexed by address, instructions beyond its end are always decoded.
+    /// Entries are invalidated whenever the memory they were decoded from is written.
+    pub fn set_decode_cache(&mut self, cache: &'memory mut [Option<Instruction>]) {
+        cache.fill(None);
+        self.decode_cache = Some(cache);
+    }
+
+    /// The current value of the program counter
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// The current value of the index register
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// The current value of the stack pointer
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// The number of return addresses on the stack, see [`Core::stack`]
+    pub fn stack_depth(&self) -> usize {
+        self.sp as usize
+    }
+
+    /// The most return addresses the stack holds, the length of the stack passed to
+    /// [`Core::new`] up to [`Core::MAX_STACK_DEPTH`]
+    pub fn stack_capacity(&self) -> usize {
+        self.stack.len().min(Self::MAX_STACK_DEPTH)
+    }
+
+    /// The general purpose registers V0 - VF
+    pub fn registers(&self) -> &[u8] {
+        &self.reg[..16]
+    }
+
+    /// The addresses of the `CALL`s currently on the stack, the innermost call last
+    ///
+    /// `RET` continues after the `CALL`, at the address on the stack plus two.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    /// The memory of the core, including the font and the loaded program
+    pub fn memory(&self) -> &[u8] {
+        self.mem
+    }
+
+    /// The memory of the core, e.g. to load a program or to restore it after a reset
+    ///
+    /// Clears the decode cache, as any instruction may be overwritten.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.invalidate(0, self.mem.len());
+        self.mem
+    }
+
+    /// The bytes of memory in `range`, e.g. to patch instructions in a debugger, `None` if the
+    /// range reaches past the end of memory
+    ///
+    /// Unlike [`Core::memory_mut`] only the decode cache of these bytes is cleared.
+    pub fn memory_range_mut(&mut self, range: Range<usize>) -> Option<&mut [u8]> {
+        if range.start > range.end || range.end > self.mem.len() {
+            return None;
+        }
+
+        self.invalidate(range.start, range.len());
+        Some(&mut self.mem[range])
+    }
+
+    /// Set the program counter, e.g. to skip an instruction in a debugger
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Set the index register
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    /// The general purpose registers V0 - VF, to modify them
+    pub fn registers_mut(&mut self) -> &mut [u8] {
+        &mut self.reg[..16]
+    }
+
+    /// A copy of the registers, e.g. to log them
+    pub fn state(&self) -> CoreState {
+        let mut reg = [0; 16];
+        reg.copy_from_slice(&self.reg[..16]);
+
+        CoreState {
+            pc: self.pc,
+            i: self.i,
+            sp: self.sp,
+            reg,
+        }
+    }
+
+    /// The framebuffer the core draws into
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// The XO-CHIP audio pattern buffer
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// The XO-CHIP pitch register
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// The SCHIP RPL user flags, saved by `LD R, Vx` and loaded by `LD Vx, R`
+    pub fn flags(&self) -> &[u8; 16] {
+        &self.flags
+    }
+
+    /// Set the RPL user flags, e.g. to restore the flags saved by an earlier session
+    pub fn set_flags(&mut self, flags: &[u8; 16]) {
+        self.flags = *flags;
+    }
+
+    /// Whether `LD Vx, K` waits for a key, see [`TickState::WaitingForKey`]
+    pub fn waiting_for_key(&self) -> bool {
+        self.waiting.is_some_and(|(pc, _)| pc == self.pc)
+    }
+
+    /// Whether the program exited with the SCHIP `EXIT` instruction
+    ///
+    /// An exited core doesn't execute any further instructions.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Save the current state, to return to it with [`Core::restore`]
+    #[cfg(feature = "alloc")]
+    pub fn snapshot(&self) -> CoreSnapshot {
+        CoreSnapshot {
+            mem: self.mem.to_vec(),
+            reg: self.reg.to_vec(),
+            stack: self.stack.to_vec(),
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            framebuffer: self.framebuffer.clone(),
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            flags: self.flags,
+            exited: self.exited,
+        }
+    }
+
+    /// Return to the state saved by [`Core::snapshot`]
+    ///
+    /// The whole framebuffer is marked as changed, so displays redraw it. A snapshot of a
+    /// core with differently sized memory, registers or stack is rejected with
+    /// [`Error::InvalidSnapshot`].
+    #[cfg(feature = "alloc")]
+    pub fn restore(&mut self, snapshot: &CoreSnapshot) -> Result<(), Error> {
+        if snapshot.mem.len() != self.mem.len()
+            || snapshot.reg.len() != self.reg.len()
+            || snapshot.stack.len() != self.stack.len()
+            || snapshot.sp as usize > self.stack.len()
+        {
+            return Err(Error::InvalidSnapshot);
+        }
+
+        self.mem.copy_from_slice(&snapshot.mem);
+        self.reg.copy_from_slice(&snapshot.reg);
+        self.stack.copy_from_slice(&snapshot.stack);
+        self.i = snapshot.i;
+        self.pc = snapshot.pc;
+        self.sp = snapshot.sp;
+        self.framebuffer = snapshot.framebuffer.clone();
+        self.framebuffer.mark_all_dirty();
+        self.audio_changed |=
+            self.audio_pattern != snapshot.audio_pattern || self.pitch != snapshot.pitch;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.flags_changed |= self.flags != snapshot.flags;
+        self.flags = snapshot.flags;
+        self.exited = snapshot.exited;
+        #[cfg(feature = "std")]
+        {
+            self.last_instruction = None;
+        }
+
+        self.invalidate(0, self.mem.len());
+        Ok(())
+    }
+
+    /// Reset the core to its power-on state
+    ///
+    /// The registers, the stack, the framebuffer and the XO-CHIP audio state are cleared, the
+    /// font is reloaded and execution starts at the start address again, see
+    /// [`QuirksConfig::start`]. The rest of the memory is left as is, writes of the program
+    /// have to be undone by reloading it. The RPL user flags outlive resets, as they did on
+    /// the HP48.
+    pub fn reset(&mut self) {
+        self.reg.fill(0);
+        self.stack.fill(0);
+        self.i = 0;
+        self.pc = self.quirks.start;
+        self.sp = 0;
+        self.framebuffer = Framebuffer::new();
+        self.audio_pattern = [0; 16];
+        self.pitch = 64;
+        self.audio_changed = true;
+        self.exited = false;
+        #[cfg(feature = "std")]
+        {
+            self.last_instruction = None;
+        }
+
+        self.invalidate(0, self.mem.len());
+        self.mem[..self.font.len()].copy_from_slice(&self.font);
+    }
+
+    /// Whether the audio pattern or pitch changed since the last call
+    pub(crate) fn take_audio_changed(&mut self) -> bool {
+        ::core::mem::replace(&mut self.audio_changed, false)
+    }
+
+    /// Whether the RPL user flags changed since the last call
+    pub(crate) fn take_flags_changed(&mut self) -> bool {
+        ::core::mem::replace(&mut self.flags_changed, false)
+    }
+
+    /// The bytes written by the instructions since the last call, if any
+    pub(crate) fn take_written(&mut self) -> Option<Range<usize>> {
+        self.written.take()
+    }
+
+    /// The cycles taken by the last instruction, 0 if none was executed since the last call
+    pub(crate) fn take_cycles(&mut self) -> u32 {
+        ::core::mem::take(&mut self.cycles)
     }
 
+    /// The default font, the sprites of the hex digits loaded to 0x000
+    const FONT: [u8; 80] = [
+        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+        0x20, 0x60, 0x20, 0x20, 0x70, // 1
+        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+    ];
+
     /// Execute a single tick of the core with the given peripherals
+    ///
+    /// `released` and `pressed` are the keys released and pressed since the last tick, which
+    /// of them `LD Vx, K` waits for depends on [`QuirksConfig::key_wait`]. While it waits,
+    /// the instruction isn't decoded again until one of them arrives.
+    #[allow(clippy::too_many_arguments)]
     pub fn tick<G, R, TD, TS>(
         &mut self,
         keys: Keys,
-        mut edges: FallingEdges,
+        mut released: FallingEdges,
+        mut pressed: RisingEdges,
         graphics: &mut G,
         random: &mut R,
         timer_delay: &mut TD,
         timer_sound: &mut TS,
-    ) -> Result<(), Error>
+    ) -> Result<TickState, Error>
     where
-        G: Graphics,
-        TD: Timer,
-        TS: Timer,
-        R: Random,
+        G: Graphics + ?Sized,
+        TD: Timer + ?Sized,
+        TS: Timer + ?Sized,
+        R: Random + ?Sized,
     {
         enum ModPc {
             Hold,
+            Wait,
             Normal,
-            Skip(u16),
+            Skip,
             Jump(u16),
             Ret(u16),
         }
@@ -129,17 +569,45 @@ impl<'memory> Core<'memory> {
         let mut pc_after = Normal;
         let mut pc = |pc| pc_after = pc;
 
-        let instruction = Instruction::try_from(&self.mem[self.pc as usize..])?;
+        if self.exited {
+            return Ok(TickState::Idle);
+        }
+
+        // A waiting LD Vx, K only needs to be executed again once a key arrives
+        let no_key = match self.quirks.key_wait {
+            KeyWait::Release => released.is_empty(),
+            KeyWait::Press => pressed.is_empty(),
+        };
+        match self.waiting {
+            Some((pc, cycles)) if pc == self.pc && no_key => {
+                self.cycles = cycles;
+                return Ok(TickState::WaitingForKey);
+            }
+            _ => self.waiting = None,
+        }
+
+        self.check_alignment(self.pc)?;
+        let instruction = self.fetch()?;
+        self.cover(
+            self.pc as usize,
+            instruction.size() as usize,
+            Coverage::EXECUTED,
+        );
         match &instruction {
             // SYS addr
-            // Jump to a machine code routine at nnn
-            I0NNN(_nnn) => unimplemented!(),
+            // Jump to a machine code routine at nnn, which can't be executed
+            I0NNN(_nnn) => {
+                return Err(Error::InvalidInstruction {
+                    opcode: self.opcode(),
+                    pc: self.pc,
+                })
+            }
 
             // CLS
             // Clear the display
             I00E0 => {
-                graphics.clear();
-                graphics.refresh();
+                self.framebuffer.clear();
+                self.present(graphics);
             }
 
             // RET
@@ -161,7 +629,7 @@ impl<'memory> Core<'memory> {
             // Skip next instruction if Vx = kk
             I3XNN(x, vv) => {
                 if *self.r(x) == vv.0 {
-                    pc(Skip(1));
+                    pc(Skip);
                 }
             }
 
@@ -169,7 +637,7 @@ impl<'memory> Core<'memory> {
             // Skip next instruction if Vx != kk
             I4XNN(x, vv) => {
                 if *self.r(x) != vv.0 {
-                    pc(Skip(1));
+                    pc(Skip);
                 }
             }
 
@@ -177,7 +645,7 @@ impl<'memory> Core<'memory> {
             // Skip next instruction if Vx = Vy
             I5XY0(x, y) => {
                 if *self.r(x) == *self.r(y) {
-                    pc(Skip(1));
+                    pc(Skip);
                 }
             }
 
@@ -197,16 +665,25 @@ impl<'memory> Core<'memory> {
             I8XY0(x, y) => *self.r(x) = *self.r(y),
 
             // OR Vx, Vy
-            // Set Vx = Vx OR Vy
-            I8XY1(x, y) => *self.r(x) |= *self.r(y),
+            // Set Vx = Vx OR Vy, reset VF with the reset_vf quirk
+            I8XY1(x, y) => {
+                *self.r(x) |= *self.r(y);
+                self.reset_vf();
+            }
 
             // AND Vx, Vy
-            // Set Vx = Vx AND Vy
-            I8XY2(x, y) => *self.r(x) &= *self.r(y),
+            // Set Vx = Vx AND Vy, reset VF with the reset_vf quirk
+            I8XY2(x, y) => {
+                *self.r(x) &= *self.r(y);
+                self.reset_vf();
+            }
 
             // XOR Vx, Vy
-            // Set Vx = Vx XOR Vy
-            I8XY3(x, y) => *self.r(x) ^= *self.r(y),
+            // Set Vx = Vx XOR Vy, reset VF with the reset_vf quirk
+            I8XY3(x, y) => {
+                *self.r(x) ^= *self.r(y);
+                self.reset_vf();
+            }
 
             // ADD Vx, Vy
             // Set Vx = Vx + Vy, set VF = carry
@@ -225,10 +702,11 @@ impl<'memory> Core<'memory> {
             }
 
             // SHR Vx {, Vy}, set VF
-            // Set Vx = Vx SHR 1
-            I8XY6(x, _y) => {
-                *self.r(Self::VF) = *self.r(x) & 0x01;
-                *self.r(x) /= 2;
+            // Set Vx = Vx SHR 1, or Vy SHR 1 with the shift_vy quirk
+            I8XY6(x, y) => {
+                let val = *self.r(self.shift_source(x, y));
+                *self.r(Self::VF) = val & 0x01;
+                *self.r(x) = val / 2;
             }
 
             // SUBN Vy, Vx
@@ -240,9 +718,9 @@ impl<'memory> Core<'memory> {
             }
 
             // SHL Vx {, Vy}, set VF
-            // Set Vx SHL 1
-            I8XYE(x, _y) => {
-                let (val, carry) = self.r(x).overflowing_mul(2);
+            // Set Vx = Vx SHL 1, or Vy SHL 1 with the shift_vy quirk
+            I8XYE(x, y) => {
+                let (val, carry) = self.r(self.shift_source(x, y)).overflowing_mul(2);
                 *self.r(x) = val;
                 *self.r(Self::VF) = if carry { 1 } else { 0 };
             }
@@ -251,7 +729,7 @@ impl<'memory> Core<'memory> {
             // Skip next instruction if Vx != Vy
             I9XY0(x, y) => {
                 if *self.r(x) != *self.r(y) {
-                    pc(Skip(1));
+                    pc(Skip);
                 }
             }
 
@@ -260,8 +738,14 @@ impl<'memory> Core<'memory> {
             IANNN(nnn) => self.i = nnn.0,
 
             // JP V0, addr
-            // Jump to location nnn + V0
-            IBNNN(nnn) => pc(Jump(nnn.0 + *self.r(Register(0)) as u16)),
+            // Jump to location nnn + V0, or xnn + Vx with the jump_vx quirk
+            IBNNN(nnn) => {
+                let offset = match self.quirks.jump_vx {
+                    true => Register((nnn.0 >> 8) as u8),
+                    false => Register(0),
+                };
+                pc(Jump(nnn.0 + *self.r(offset) as u16));
+            }
 
             // RND Vx, byte
             // Set Vx = random byte AND kk
@@ -271,29 +755,34 @@ impl<'memory> Core<'memory> {
 
             // DRW Vx, Vy, nibble
             // Display sprite (length: val bytes) starting at memory location I at (reg0, reg1)
-            // Set VF to 1 if collistion is detected
+            // A length of 0 displays a 16x16 sprite of 32 bytes in the hires mode (SCHIP)
+            // Set VF to 1 if collistion is detected, clip the sprite with the clip_sprites quirk
             IDXYN(x, y, v) => {
-                let start_address = self.i as usize;
-                let length = v.0 as usize;
+                let large = v.0 == 0 && self.framebuffer.mode() == DisplayMode::HiRes;
+                let length = if large { 32 } else { v.0 as usize };
+                let start_address = self.check_memory(length)?;
                 let reg0_value = self.reg[x.0 as usize];
                 let reg1_value = self.reg[y.0 as usize];
 
                 let pos = Pos(reg0_value, reg1_value);
+                self.cover(start_address, length, Coverage::SPRITE);
                 let sprite = Sprite(&self.mem[start_address..(start_address + length)]);
 
-                *self.r(Self::VF) = if graphics.toggle_sprite(pos, sprite) {
-                    1
-                } else {
-                    0
+                let collision = match (large, self.quirks.clip_sprites) {
+                    (false, true) => self.framebuffer.toggle_sprite_clipped(pos, sprite),
+                    (false, false) => self.framebuffer.toggle_sprite(pos, sprite),
+                    (true, true) => self.framebuffer.toggle_sprite16_clipped(pos, sprite),
+                    (true, false) => self.framebuffer.toggle_sprite16(pos, sprite),
                 };
-                graphics.refresh();
+                *self.r(Self::VF) = if collision { 1 } else { 0 };
+                self.present(graphics);
             }
 
             // SKP Vx
             // Skip next instruction if key with the value of Vx is pressed
             IEX9E(x) => {
                 if keys.pressed(*self.r(x)) {
-                    pc(Skip(1));
+                    pc(Skip);
                 }
             }
 
@@ -301,7 +790,7 @@ impl<'memory> Core<'memory> {
             // Skip next instruction if key with the value of Vx is not pressed
             IEXA1(x) => {
                 if !keys.pressed(*self.r(x)) {
-                    pc(Skip(1));
+                    pc(Skip);
                 }
             }
 
@@ -314,13 +803,16 @@ impl<'memory> Core<'memory> {
             // LD Vx, K
             // Wait for a key press, store the value of the key in Vx
             IFX0A(x) => {
-                let old_edges = edges.clone();
-                if let Some(idx) = edges.pop_next_idx() {
+                let key = match self.quirks.key_wait {
+                    KeyWait::Release => released.pop_next_idx(),
+                    KeyWait::Press => pressed.pop_next_idx(),
+                };
+                if let Some(idx) = key {
                     #[cfg(feature = "std")]
-                    debug!("IFX0A {:?}", old_edges);
+                    debug!("IFX0A {:X}", idx);
                     *self.r(x) = idx;
                 } else {
-                    pc(Hold);
+                    pc(Wait);
                 }
             }
 
@@ -333,10 +825,13 @@ impl<'memory> Core<'memory> {
             IFX18(x) => timer_sound.set(*self.r(x)),
 
             // ADD I, Vx
-            // Set I = I + Vx
+            // Set I = I + Vx, set VF = I > 0xFFF with the add_i_overflow quirk
             IFX1E(x) => {
                 let (val, _) = self.i.overflowing_add(*self.r(x) as u16);
                 self.i = val;
+                if self.quirks.add_i_overflow {
+                    *self.r(Self::VF) = if val > 0xFFF { 1 } else { 0 };
+                }
             }
 
             // LD F, Vx
@@ -346,41 +841,146 @@ impl<'memory> Core<'memory> {
             // LD B, Vx
             // Store BCD representation of Vx in memory locations I, I+1 and I+2
             IFX33(x) => {
+                let range = self.writable(3)?;
                 let (hundreds, tens, ones) = bcd(*self.r(x));
-                self.mem[self.i as usize] = hundreds;
-                self.mem[self.i as usize + 1] = tens;
-                self.mem[self.i as usize + 2] = ones;
+                let digits = [hundreds, tens, ones];
+                self.write(range, &digits);
             }
 
             // LD [I], Vx
             // Store registers V0 through Vx in memory starting at location I
             IFX55(x) => {
-                for i in 0..=x.0 {
-                    self.mem[self.i as usize + i as usize] = *self.r(&i.into());
+                let range = self.writable(x.0 as usize + 1)?;
+                let mut reg = [0; 16];
+                reg.copy_from_slice(&self.reg[..16]);
+                self.write(range, &reg[..=x.0 as usize]);
+                if self.quirks.increment_i {
+                    self.i = self.i.wrapping_add(x.0 as u16 + 1);
                 }
             }
 
             // LD Vx, [I]
             // Read registers V0 through Vx from memory starting at location I
             IFX65(x) => {
+                self.check_memory(x.0 as usize + 1)?;
                 for i in 0..=x.0 {
-                    *self.r(&i.into()) = self.mem[self.i as usize + i as usize];
+                    *self.r(Register::from(i)) = self.mem[self.i as usize + i as usize];
+                }
+                if self.quirks.increment_i {
+                    self.i = self.i.wrapping_add(x.0 as u16 + 1);
                 }
             }
+
+            // SCD nibble (SCHIP)
+            // Scroll the display down by nibble rows
+            I00CN(n) => {
+                self.framebuffer.scroll_down(n.0 as usize);
+                self.present(graphics);
+            }
+
+            // SCR (SCHIP)
+            // Scroll the display right by 4 columns
+            I00FB => {
+                self.framebuffer.scroll_right();
+                self.present(graphics);
+            }
+
+            // SCL (SCHIP)
+            // Scroll the display left by 4 columns
+            I00FC => {
+                self.framebuffer.scroll_left();
+                self.present(graphics);
+            }
+
+            // EXIT (SCHIP)
+            // Stop executing the program
+            I00FD => {
+                self.exited = true;
+                pc(Hold);
+            }
+
+            // LOW (SCHIP)
+            // Switch to the 64x32 display mode
+            I00FE => {
+                self.framebuffer.set_mode(DisplayMode::LoRes);
+                graphics.set_mode(DisplayMode::LoRes);
+                self.present(graphics);
+            }
+
+            // HIGH (SCHIP)
+            // Switch to the 128x64 display mode
+            I00FF => {
+                self.framebuffer.set_mode(DisplayMode::HiRes);
+                graphics.set_mode(DisplayMode::HiRes);
+                self.present(graphics);
+            }
+
+            // LD R, Vx (SCHIP)
+            // Store registers V0 through Vx in the RPL user flags
+            IFX75(x) => {
+                let n = x.index() as usize + 1;
+                self.flags[..n].copy_from_slice(&self.reg[..n]);
+                self.flags_changed = true;
+            }
+
+            // LD Vx, R (SCHIP)
+            // Read registers V0 through Vx from the RPL user flags
+            IFX85(x) => {
+                let n = x.index() as usize + 1;
+                self.reg[..n].copy_from_slice(&self.flags[..n]);
+            }
+
+            // LD AUDIO, [I] (XO-CHIP)
+            // Load 16 bytes starting at I into the audio pattern buffer
+            IF002 => {
+                let start = self.check_memory(16)?;
+                self.audio_pattern
+                    .copy_from_slice(&self.mem[start..(start + 16)]);
+                self.audio_changed = true;
+            }
+
+            // LD PITCH, Vx (XO-CHIP)
+            // Set the pitch register = Vx
+            IFX3A(x) => {
+                self.pitch = *self.r(x);
+                self.audio_changed = true;
+            }
+
+            // LD I, LONG nnnn (XO-CHIP)
+            // Set I = nnnn
+            IF000(nnnn) => self.i = nnnn.value(),
         }
 
+        self.cycles = match self.quirks.timing {
+            Timing::Uniform => 1,
+            Timing::CosmacVip if matches!(pc_after, Skip) => instruction.vip_cycles() + 4,
+            Timing::CosmacVip => instruction.vip_cycles(),
+        };
+
         // Update the program counter
         match pc_after {
             // Stall the program counter
             ModPc::Hold => (),
-            // Jump to the next 16 bit instruction
-            ModPc::Normal => self.pc += 2,
-            // Skip the next n instructions (+ jump to the next 16 bit instruction)
-            ModPc::Skip(n) => self.pc += 2 * (n + 1),
+            // Stall the program counter until a key arrives
+            ModPc::Wait => self.waiting = Some((self.pc, self.cycles)),
+            // Continue at the next instruction
+            ModPc::Normal => self.pc = self.pc.wrapping_add(instruction.size()),
+            // Skip the next instruction, which is 4 bytes for LD I, LONG nnnn
+            ModPc::Skip => {
+                let next = self.pc.wrapping_add(instruction.size());
+                let skipped = match self.mem.get(next as usize..next as usize + 2) {
+                    Some([0xF0, 0x00]) => 4,
+                    _ => 2,
+                };
+                self.pc = next.wrapping_add(skipped);
+            }
             // Set the PC to a fixed value
-            ModPc::Jump(pc) => self.pc = pc,
+            ModPc::Jump(pc) => {
+                self.check_alignment(pc)?;
+                self.pc = pc;
+            }
             // Return from call
-            ModPc::Ret(pc) => self.pc = pc + 2,
+            ModPc::Ret(pc) => self.pc = pc.wrapping_add(2),
         }
 
         #[cfg(feature = "std")]
@@ -389,7 +989,171 @@ impl<'memory> Core<'memory> {
             trace!("{}", self);
         }
 
-        Ok(())
+        Ok(match pc_after {
+            ModPc::Wait => TickState::WaitingForKey,
+            _ => TickState::Executed,
+        })
+    }
+
+    /// Decode the instruction at the PC, taking it from the decode cache if possible
+    fn fetch(&mut self) -> Result<Instruction, Error> {
+        let pc = self.pc as usize;
+
+        let cached = self.decode_cache.as_deref().and_then(|cache| cache.get(pc));
+        if let Some(Some(instruction)) = cached {
+            return Ok(instruction.clone());
+        }
+
+        // Past the end of memory there is no instruction to decode, like at its last byte
+        let instruction = Instruction::decode(self.mem.get(pc..).unwrap_or_default(), self.pc)?;
+        if let Some(entry) = self
+            .decode_cache
+            .as_deref_mut()
+            .and_then(|cache| cache.get_mut(pc))
+        {
+            *entry = Some(instruction.clone());
+        }
+
+        Ok(instruction)
+    }
+
+    /// Drop the cached instructions overlapping the `len` bytes from `start`
+    ///
+    /// A waiting `LD Vx, K` is decoded again, as it may have been overwritten.
+    fn invalidate(&mut self, start: usize, len: usize) {
+        self.waiting = None;
+        if let Some(cache) = &mut self.decode_cache {
+            // An instruction starting up to 3 bytes before `start` may reach into the range
+            let start = start.saturating_sub(3);
+            for entry in cache.iter_mut().skip(start).take(len + 3) {
+                *entry = None;
+            }
+        }
+    }
+
+    /// Record an access of `len` bytes from `start` in the coverage map
+    fn cover(&mut self, start: usize, len: usize, access: Coverage) {
+        if let Some(coverage) = &mut self.coverage {
+            for entry in coverage.iter_mut().skip(start).take(len) {
+                entry.0 |= access.0;
+            }
+        }
+    }
+
+    fn present<G: Graphics + ?Sized>(&mut self, graphics: &mut G) {
+        if let Some(dirty) = self.framebuffer.take_dirty() {
+            graphics.present(&self.framebuffer, dirty);
+        }
+    }
+
+    fn check_alignment(&self, addr: u16) -> Result<(), Error> {
+        if self.quirks.strict_alignment && addr & 1 != 0 {
+            Err(Error::InvalidAlignment { pc: self.pc })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The register shifted by `SHR Vx, Vy` and `SHL Vx, Vy`
+    fn shift_source<'r>(&self, x: &'r Register, y: &'r Register) -> &'r Register {
+        if self.quirks.shift_vy {
+            y
+        } else {
+            x
+        }
+    }
+
+    /// Reset VF after a logical operation, if the quirks say so
+    fn reset_vf(&mut self) {
+        if self.quirks.reset_vf {
+            *self.r(Self::VF) = 0;
+        }
+    }
+
+    /// Check that `len` bytes starting at I are inside of memory, returning I
+    fn check_memory(&self, len: usize) -> Result<usize, Error> {
+        let start = self.i as usize;
+
+        if start + len <= self.mem.len() {
+            Ok(start)
+        } else {
+            Err(Error::MemoryOutOfBounds {
+                opcode: self.opcode(),
+                pc: self.pc,
+                i: self.i,
+            })
+        }
+    }
+
+    /// The range of the `len` bytes at I the program may write, see
+    /// [`Core::set_write_protection`]
+    fn writable(&self, len: usize) -> Result<Range<usize>, Error> {
+        let start = self.check_memory(len)?;
+        let protected = self.quirks.start as usize;
+
+        match self.write_protection {
+            WriteProtection::Fail if start < protected => Err(Error::WriteProtected {
+                opcode: self.opcode(),
+                pc: self.pc,
+                i: self.i,
+            }),
+            WriteProtection::Ignore => Ok(protected.clamp(start, start + len)..start + len),
+            _ => Ok(start..start + len),
+        }
+    }
+
+    /// Write the part of `bytes`, which belong to I onwards, within `range`
+    fn write(&mut self, range: Range<usize>, bytes: &[u8]) {
+        let offset = range.start - self.i as usize;
+        self.invalidate(range.start, range.len());
+        self.mem[range.clone()].copy_from_slice(&bytes[offset..]);
+        self.cover_write(range.clone());
+        self.written = Some(range).filter(|range| !range.is_empty());
+    }
+
+    /// Record a write to `range` in the coverage map, marking the bytes executed before as
+    /// modified
+    fn cover_write(&mut self, range: Range<usize>) {
+        let Some(coverage) = &mut self.coverage else {
+            return;
+        };
+
+        let mut modified = false;
+        for entry in coverage.iter_mut().skip(range.start).take(range.len()) {
+            if entry.contains(Coverage::EXECUTED) {
+                entry.0 |= Coverage::MODIFIED.0;
+                modified = true;
+            }
+            entry.0 |= Coverage::WRITTEN.0;
+        }
+
+        #[cfg(feature = "std")]
+        if modified {
+            debug!(
+                "Self-modifying write to 0x{:03X}..0x{:03X} at 0x{:03X}",
+                range.start, range.end, self.pc
+            );
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = modified;
+    }
+
+    /// The raw instruction at the PC, 0 if the PC points past the end of memory
+    pub fn opcode(&self) -> u16 {
+        let pc = self.pc as usize;
+        match self.mem.get(pc..pc + 2) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]),
+            _ => 0,
+        }
+    }
+
+    /// The stack would have `depth` entries, 0 for a return from an empty stack
+    fn stack_overflow(&self, depth: usize) -> Error {
+        Error::StackOverflow {
+            opcode: self.opcode(),
+            pc: self.pc,
+            depth,
+        }
     }
 
     fn r(&mut self, reg: impl Borrow<Register>) -> &mut u8 {
@@ -397,20 +1161,22 @@ impl<'memory> Core<'memory> {
     }
 
     fn pop(&mut self) -> Result<u16, Error> {
-        self.sp -= 1;
-        let val = self
-            .stack
-            .get(self.sp as usize)
-            .ok_or(Error::StackOverflow)?;
+        let sp = self
+            .sp
+            .checked_sub(1)
+            .ok_or_else(|| self.stack_overflow(0))?;
+        let val = self.stack[sp as usize];
+        self.sp = sp;
 
-        Ok(*val)
+        Ok(val)
     }
 
     fn push(&mut self, val: u16) -> Result<(), Error> {
-        *self
-            .stack
-            .get_mut(self.sp as usize)
-            .ok_or(Error::StackOverflow)? = val;
+        let depth = self.stack_depth() + 1;
+        if depth > self.stack_capacity() {
+            return Err(self.stack_overflow(depth));
+        }
+        self.stack[self.sp as usize] = val;
         self.sp += 1;
 
         Ok(())
@@ -419,10 +1185,771 @@ impl<'memory> Core<'memory> {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::peripherals::testing::{
+        GraphicsCall, ManualTimer, RecordingGraphics, SequenceRandom,
+    };
+    use crate::peripherals::Rect;
+
+    /// The peripherals of the tests, kept for inspection
+    #[derive(Debug)]
+    struct Peripherals {
+        graphics: RecordingGraphics,
+        random: SequenceRandom,
+        delay: ManualTimer,
+        sound: ManualTimer,
+    }
+
+    fn peripherals() -> Peripherals {
+        Peripherals {
+            graphics: RecordingGraphics::default(),
+            random: SequenceRandom::new([0xAB, 0x0F]),
+            delay: ManualTimer::default(),
+            sound: ManualTimer::default(),
+        }
+    }
+
+    /// Execute a single tick, with the held keys changing from `before` to `after`
+    fn try_tick(
+        core: &mut Core<'_>,
+        peripherals: &mut Peripherals,
+        before: u16,
+        after: u16,
+    ) -> Result<TickState, Error> {
+        core.tick(
+            Keys(after),
+            Keys(before).falling_edges(&Keys(after)),
+            Keys(before).rising_edges(&Keys(after)),
+            &mut peripherals.graphics,
+            &mut peripherals.random,
+            &mut peripherals.delay,
+            &mut peripherals.sound,
+        )
+    }
+
+    /// Execute a single tick without any keys pressed, which has to succeed
+    fn tick(core: &mut Core<'_>, peripherals: &mut Peripherals) -> TickState {
+        try_tick(core, peripherals, 0, 0).unwrap()
+    }
+
+    /// The state after [`execute`]
+    #[derive(Debug)]
+    struct Run {
+        /// The result of the last tick
+        result: Result<(), Error>,
+        reg: [u8; 16],
+        i: u16,
+        pc: u16,
+        peripherals: Peripherals,
+    }
+
+    /// Execute `ticks` ticks of a program loaded at 0x200, stopping at the first error
+    fn execute(
+        program: &[u8],
+        quirks: QuirksConfig,
+        ticks: usize,
+        mut peripherals: Peripherals,
+    ) -> Run {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        mem[0x200..0x200 + program.len()].copy_from_slice(program);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_quirks(quirks);
+
+        let mut result = Ok(());
+        for _ in 0..ticks {
+            result = try_tick(&mut core, &mut peripherals, 0, 0).map(|_| ());
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let (i, pc) = (core.i(), core.pc());
+        Run {
+            result,
+            reg,
+            i,
+            pc,
+            peripherals,
+        }
+    }
+
+    /// Execute `ticks` ticks with `quirks`, returning the last result and the PC
+    fn run(program: &[u8], quirks: QuirksConfig, ticks: usize) -> (Result<(), Error>, u16) {
+        let run = execute(program, quirks, ticks, peripherals());
+        (run.result, run.pc)
+    }
+
+    #[test]
+    fn odd_jump_lenient() {
+        // JP 0x203; (pad); LD V0, 1
+        let program = [0x12, 0x03, 0x00, 0x60, 0x01];
+        assert_eq!(run(&program, QuirksConfig::default(), 1), (Ok(()), 0x203));
+        assert_eq!(run(&program, QuirksConfig::default(), 2), (Ok(()), 0x205));
+    }
+
+    #[test]
+    fn odd_jump_strict() {
+        let quirks = QuirksConfig {
+            strict_alignment: true,
+            ..QuirksConfig::default()
+        };
+
+        // JP 0x203
+        assert_eq!(
+            run(&[0x12, 0x03], quirks, 1),
+            (Err(Error::InvalidAlignment { pc: 0x200 }), 0x200)
+        );
+        // CALL 0x205
+        assert_eq!(
+            run(&[0x22, 0x05], quirks, 1),
+            (Err(Error::InvalidAlignment { pc: 0x200 }), 0x200)
+        );
+        // JP 0x204
+        assert_eq!(run(&[0x12, 0x04], quirks, 1), (Ok(()), 0x204));
+    }
+
+    #[test]
+    fn error_context() {
+        // LD I, 0xFFF; LD B, V0
+        let program = [0xAF, 0xFF, 0xF0, 0x33];
+        let err = Error::MemoryOutOfBounds {
+            opcode: 0xF033,
+            pc: 0x202,
+            i: 0xFFF,
+        };
+        assert_eq!(run(&program, QuirksConfig::default(), 2), (Err(err), 0x202));
+
+        // CALL 0x200
+        let err = Error::StackOverflow {
+            opcode: 0x2200,
+            pc: 0x200,
+            depth: 17,
+        };
+        assert_eq!(
+            run(&[0x22, 0x00], QuirksConfig::default(), 17),
+            (Err(err), 0x200)
+        );
+    }
+
+    #[test]
+    fn adversarial() {
+        let quirks = QuirksConfig::default();
+
+        // RET
+        let err = Error::StackOverflow {
+            opcode: 0x00EE,
+            pc: 0x200,
+            depth: 0,
+        };
+        assert_eq!(run(&[0x00, 0xEE], quirks, 1), (Err(err), 0x200));
+
+        // SYS 0x300
+        let err = Error::InvalidInstruction {
+            opcode: 0x0300,
+            pc: 0x200,
+        };
+        assert_eq!(run(&[0x03, 0x00], quirks, 1), (Err(err), 0x200));
+
+        // LD V0, 0xFF; JP V0, 0xFFF
+        let err = Error::InvalidAlignment { pc: 0x10FE };
+        assert_eq!(
+            run(&[0x60, 0xFF, 0xBF, 0xFF], quirks, 3),
+            (Err(err), 0x10FE)
+        );
+
+        // LD V0, 0xFF; SKP V0; SKNP V0
+        let program = [0x60, 0xFF, 0xE0, 0x9E, 0xE0, 0xA1];
+        assert_eq!(run(&program, quirks, 3), (Ok(()), 0x208));
+    }
+
+    #[test]
+    fn exit() {
+        // LD V0, 1; EXIT; JP 0x200
+        let program = [0x60, 0x01, 0x00, 0xFD, 0x12, 0x00];
+        assert_eq!(run(&program, QuirksConfig::default(), 4), (Ok(()), 0x202));
+
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        mem[0x200..0x202].copy_from_slice(&[0x00, 0xFD]);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        assert!(!core.exited());
+        tick(&mut core, &mut peripherals());
+        assert!(core.exited());
+    }
+
+    #[test]
+    fn key_wait() {
+        let wait = |key_wait, before: u16, after: u16| {
+            let mut mem = [0; 4096];
+            let mut reg = [0; 16];
+            let mut stack = [0; 16];
+            // LD V0, K
+            mem[0x200..0x202].copy_from_slice(&[0xF0, 0x0A]);
+            reg[0] = 0xFF;
+
+            let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+            core.set_quirks(QuirksConfig {
+                key_wait,
+                ..QuirksConfig::default()
+            });
+            try_tick(&mut core, &mut peripherals(), before, after).unwrap();
+
+            (core.pc(), core.registers()[0])
+        };
+
+        // Key 5 pressed
+        assert_eq!(wait(KeyWait::Release, 0x00, 0x20), (0x200, 0xFF));
+        assert_eq!(wait(KeyWait::Press, 0x00, 0x20), (0x202, 5));
+        // Key 5 released
+        assert_eq!(wait(KeyWait::Release, 0x20, 0x00), (0x202, 5));
+        assert_eq!(wait(KeyWait::Press, 0x20, 0x00), (0x200, 0xFF));
+    }
+
+    #[test]
+    fn waiting_for_key() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut peripherals = peripherals();
+        let mut tick =
+            |core: &mut Core<'_>, before, after| try_tick(core, &mut peripherals, before, after);
+
+        // LD V0, K; LD V1, K
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.load_program(&[0xF0, 0x0A, 0xF1, 0x0A]).unwrap();
+        assert_eq!(tick(&mut core, 0, 0), Ok(TickState::WaitingForKey));
+        assert_eq!(tick(&mut core, 0, 0x20), Ok(TickState::WaitingForKey));
+        assert!(core.waiting_for_key());
+        assert_eq!(tick(&mut core, 0x20, 0), Ok(TickState::Executed));
+        assert!(!core.waiting_for_key());
+        assert_eq!((core.pc(), core.registers()[0]), (0x202, 5));
+
+        // Overwriting the waiting instruction decodes it again, LD V1, 0x42
+        assert_eq!(tick(&mut core, 0, 0), Ok(TickState::WaitingForKey));
+        core.memory_range_mut(0x202..0x204)
+            .unwrap()
+            .copy_from_slice(&[0x61, 0x42]);
+        assert!(!core.waiting_for_key());
+        assert_eq!(tick(&mut core, 0, 0), Ok(TickState::Executed));
+        assert_eq!(core.registers()[1], 0x42);
+    }
+
+    #[test]
+    fn shift_vy() {
+        let shift = |shift_vy, op: u8| {
+            // LD V0, 0x81; LD V1, 0x42; SHR/SHL V0, V1
+            let program = [0x60, 0x81, 0x61, 0x42, 0x80, op];
+            let quirks = QuirksConfig {
+                shift_vy,
+                ..QuirksConfig::default()
+            };
+            let run = execute(&program, quirks, 3, peripherals());
+            (run.reg[0], run.reg[0xF])
+        };
+
+        // SHR V0, V1
+        assert_eq!(shift(false, 0x16), (0x40, 1));
+        assert_eq!(shift(true, 0x16), (0x21, 0));
+        // SHL V0, V1
+        assert_eq!(shift(false, 0x1E), (0x02, 1));
+        assert_eq!(shift(true, 0x1E), (0x84, 0));
+    }
+
+    #[test]
+    fn reset_vf() {
+        let logic = |reset_vf, op: u8| {
+            // LD V0, 0x0C; LD V1, 0x0A; LD VF, 0xFF; OR/AND/XOR V0, V1
+            let program = [0x60, 0x0C, 0x61, 0x0A, 0x6F, 0xFF, 0x80, op];
+            let quirks = QuirksConfig {
+                reset_vf,
+                ..QuirksConfig::default()
+            };
+            let run = execute(&program, quirks, 4, peripherals());
+            (run.reg[0], run.reg[0xF])
+        };
+
+        // OR V0, V1; AND V0, V1; XOR V0, V1
+        assert_eq!(logic(false, 0x11), (0x0E, 0xFF));
+        assert_eq!(logic(true, 0x11), (0x0E, 0));
+        assert_eq!(logic(false, 0x12), (0x08, 0xFF));
+        assert_eq!(logic(true, 0x12), (0x08, 0));
+        assert_eq!(logic(false, 0x13), (0x06, 0xFF));
+        assert_eq!(logic(true, 0x13), (0x06, 0));
+    }
+
+    #[test]
+    fn jump_vx() {
+        let jump = |jump_vx| {
+            // LD V0, 0x02; LD V3, 0x04; JP V0, 0x310
+            let program = [0x60, 0x02, 0x63, 0x04, 0xB3, 0x10];
+            let quirks = QuirksConfig {
+                jump_vx,
+                ..QuirksConfig::default()
+            };
+            execute(&program, quirks, 3, peripherals()).pc
+        };
+
+        assert_eq!(jump(false), 0x312);
+        assert_eq!(jump(true), 0x314);
+    }
+
+    #[test]
+    fn add_i_overflow() {
+        let add = |add_i_overflow, i: u16| {
+            // LD V0, 0x10; LD VF, 0xFF; LD I, i; ADD I, V0
+            let [hi, lo] = (0xA000 | i).to_be_bytes();
+            let program = [0x60, 0x10, 0x6F, 0xFF, hi, lo, 0xF0, 0x1E];
+            let quirks = QuirksConfig {
+                add_i_overflow,
+                ..QuirksConfig::default()
+            };
+            let run = execute(&program, quirks, 4, peripherals());
+            (run.i, run.reg[0xF])
+        };
+
+        assert_eq!(add(false, 0xFF8), (0x1008, 0xFF));
+        assert_eq!(add(true, 0xFF8), (0x1008, 1));
+        assert_eq!(add(true, 0xFE8), (0xFF8, 0));
+    }
+
+    #[test]
+    fn increment_i() {
+        let quirks = |increment_i| QuirksConfig {
+            increment_i,
+            ..QuirksConfig::default()
+        };
+
+        // LD V0, 0x01; LD V1, 0x02; LD I, 0x300; LD [I], V1; LD V2, [I]
+        let program = [0x60, 0x01, 0x61, 0x02, 0xA3, 0x00, 0xF1, 0x55, 0xF2, 0x65];
+        let run = execute(&program, quirks(false), 5, peripherals());
+        assert_eq!((run.reg[..3].to_vec(), run.i), (vec![1, 2, 0], 0x300));
+        // The load reads the bytes after the stored ones
+        let run = execute(&program, quirks(true), 5, peripherals());
+        assert_eq!((run.reg[..3].to_vec(), run.i), (vec![0, 0, 0], 0x305));
+
+        // I wraps around at the end of the largest memory
+        let mut mem = [0; Core::MAX_MEM_LEN];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_quirks(quirks(true));
+        // LD V0, 0x42; LD I, long 0xFFFF; LD [I], V0; LD V1, [I]
+        core.load_program(&[0x60, 0x42, 0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x55, 0xF1, 0x65])
+            .unwrap();
+        let mut peripherals = peripherals();
+        for _ in 0..4 {
+            tick(&mut core, &mut peripherals);
+        }
+        assert_eq!(core.memory()[0xFFFF], 0x42);
+        assert_eq!(
+            (core.registers()[..2].to_vec(), core.i()),
+            (vec![0xF0, 0x90], 2)
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        // CALL 0x204; (pad); LD V3, 0x42; LD I, 0x123; CLS
+        mem[0x200..0x20A]
+            .copy_from_slice(&[0x22, 0x04, 0x00, 0x00, 0x63, 0x42, 0xA1, 0x23, 0x00, 0xE0]);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        let mut peripherals = peripherals();
+        for _ in 0..3 {
+            tick(&mut core, &mut peripherals);
+        }
+        core.memory_mut()[0] = 0xAA;
+
+        assert_eq!(core.pc(), 0x208);
+        assert_eq!(core.registers()[3], 0x42);
+        assert_eq!(core.stack(), &[0x200]);
+
+        core.reset();
+
+        assert_eq!(core.pc(), 0x200);
+        assert_eq!(core.i(), 0);
+        assert_eq!(core.sp(), 0);
+        assert_eq!(core.registers(), &[0; 16]);
+        assert_eq!(core.memory()[0], 0xF0);
+        assert_eq!(core.memory()[0x204], 0x63);
+    }
+
+    #[test]
+    fn start_address() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_quirks(QuirksConfig {
+            start: QuirksConfig::ETI_660_START,
+            ..QuirksConfig::default()
+        });
+        assert_eq!(core.pc(), 0x600);
+
+        assert_eq!(
+            core.load_program(&[0; 0xA01]),
+            Err(Error::RomTooLarge(0xA01))
+        );
+        // LD V0, 0x42
+        core.load_program(&[0x60, 0x42]).unwrap();
+        assert_eq!(core.memory()[0x200..0x202], [0, 0]);
+        tick(&mut core, &mut peripherals());
+        assert_eq!(core.pc(), 0x602);
+        assert_eq!(core.registers()[0], 0x42);
+
+        core.reset();
+        assert_eq!(core.pc(), 0x600);
+    }
+
+    #[test]
+    fn stack_depth() {
+        let mut peripherals = peripherals();
+        let mut call = |core: &mut Core<'_>| try_tick(core, &mut peripherals, 0, 0);
+
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 12];
+
+        // CALL 0x200
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.load_program(&[0x22, 0x00]).unwrap();
+        assert_eq!(core.stack_capacity(), 12);
+        for depth in 1..=12 {
+            call(&mut core).unwrap();
+            assert_eq!(core.stack_depth(), depth);
+        }
+        let err = Error::StackOverflow {
+            opcode: 0x2200,
+            pc: 0x200,
+            depth: 13,
+        };
+        assert_eq!(call(&mut core), Err(err));
+        assert_eq!(core.stack_depth(), 12);
+
+        // Without a stack any call overflows
+        let mut mem = [0; 4096];
+        let mut core = Core::new(&mut mem, &mut reg, &mut []);
+        core.load_program(&[0x22, 0x00]).unwrap();
+        assert_eq!(core.stack_capacity(), 0);
+        let err = Error::StackOverflow {
+            opcode: 0x2200,
+            pc: 0x200,
+            depth: 1,
+        };
+        assert_eq!(call(&mut core), Err(err));
+
+        // Only the first 255 entries of a larger stack are used
+        let mut mem = [0; 4096];
+        let mut stack = [0; 300];
+        let core = Core::new(&mut mem, &mut reg, &mut stack);
+        assert_eq!(core.stack_capacity(), Core::MAX_STACK_DEPTH);
+    }
+
+    #[test]
+    fn write_protection() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD I, 0x1FE; LD V0, 0xAB; LD V3, 0xCD; LD [I], V3
+        let program = [0xA1, 0xFE, 0x60, 0xAB, 0x63, 0xCD, 0xF3, 0x55];
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.load_program(&program).unwrap();
+        core.set_write_protection(WriteProtection::Fail);
+        let mut peripherals = peripherals();
+        for _ in 0..3 {
+            tick(&mut core, &mut peripherals);
+        }
+        let err = Error::WriteProtected {
+            opcode: 0xF355,
+            pc: 0x206,
+            i: 0x1FE,
+        };
+        assert_eq!(try_tick(&mut core, &mut peripherals, 0, 0), Err(err));
+        assert_eq!(core.memory()[0x1FE..0x202], [0, 0, 0xA1, 0xFE]);
+
+        // Only the bytes from 0x200 on are written
+        core.reset();
+        core.set_write_protection(WriteProtection::Ignore);
+        for _ in 0..4 {
+            tick(&mut core, &mut peripherals);
+        }
+        assert_eq!(core.memory()[0x1FE..0x202], [0, 0, 0, 0xCD]);
+        assert_eq!(core.take_written(), Some(0x200..0x202));
+
+        // A custom font survives resets
+        let font = [0xFF; 80];
+        core.set_font(&font);
+        core.reset();
+        assert_eq!(core.memory()[..80], font);
+    }
+
+    #[test]
+    fn long_addressing() {
+        let mut mem = [0; Core::MAX_MEM_LEN];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        assert_eq!(core.load_program(&[0; 0x3000]), Ok(()));
+        // SE V0, 0; LD I, LONG 0x1234; LD I, LONG 0x9000; LD V1, [I]
+        core.load_program(&[
+            0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0xF0, 0x00, 0x90, 0x00, 0xF1, 0x65,
+        ])
+        .unwrap();
+        core.memory_mut()[0x9000..0x9002].copy_from_slice(&[0xAB, 0xCD]);
+
+        let mut peripherals = peripherals();
+        tick(&mut core, &mut peripherals);
+        assert_eq!(core.pc(), 0x206);
+        tick(&mut core, &mut peripherals);
+        assert_eq!(core.i(), 0x9000);
+        tick(&mut core, &mut peripherals);
+        assert_eq!(core.pc(), 0x20C);
+        assert_eq!(core.registers()[..2], [0xAB, 0xCD]);
+    }
+
     #[test]
     fn bcd() {
         assert_eq!(super::bcd(123), (1, 2, 3));
-        assert_eq!(super::bcd(023), (0, 2, 3));
-        assert_eq!(super::bcd(003), (0, 0, 3));
+        assert_eq!(super::bcd(23), (0, 2, 3));
+        assert_eq!(super::bcd(3), (0, 0, 3));
+    }
+
+    #[test]
+    fn rnd() {
+        // RND V0, 0x0F; RND V1, 0xFF
+        let run = execute(
+            &[0xC0, 0x0F, 0xC1, 0xFF],
+            QuirksConfig::default(),
+            2,
+            peripherals(),
+        );
+        assert_eq!(run.reg[..2], [0x0B, 0x0F]);
+    }
+
+    #[test]
+    fn drw() {
+        // LD V0, 2; LD F, V0; DRW V0, V0, 5; DRW V0, V0, 5
+        let program = [0x60, 0x02, 0xF0, 0x29, 0xD0, 0x05];
+        let run = execute(&program, QuirksConfig::default(), 3, peripherals());
+        assert_eq!(run.reg[0xF], 0);
+
+        let glyph = Rect {
+            x: 2,
+            y: 2,
+            width: 4,
+            height: 5,
+        };
+        let graphics = &run.peripherals.graphics;
+        assert_eq!(graphics.calls(), [GraphicsCall::Present(glyph)]);
+        crate::assert_display_eq!(
+            graphics.framebuffer(),
+            glyph,
+            "
+            ####
+            ...#
+            ####
+            #...
+            ####
+            "
+        );
+
+        // Drawing it again erases it, which is a collision
+        let program = [0x60, 0x02, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05];
+        let run = execute(&program, QuirksConfig::default(), 4, peripherals());
+        assert_eq!(run.reg[0xF], 1);
+        assert!(!run.peripherals.graphics.framebuffer().pixel(2, 2));
+    }
+
+    #[test]
+    fn drw_hires() {
+        // HIGH; LD I, 0x20C; DRW V0, V0, 0; SCD 2; SCR; JP 0x20A
+        let mut program = vec![
+            0x00, 0xFF, 0xA2, 0x0C, 0xD0, 0x00, 0x00, 0xC2, 0x00, 0xFB, 0x12, 0x0A,
+        ];
+        let mut sprite = [0; 32];
+        sprite[..2].copy_from_slice(&[0xFF, 0xFF]);
+        sprite[30..].copy_from_slice(&[0x80, 0x01]);
+        program.extend_from_slice(&sprite);
+
+        let run = execute(&program, QuirksConfig::default(), 5, peripherals());
+        assert_eq!(run.reg[0xF], 0);
+
+        let framebuffer = run.peripherals.graphics.framebuffer();
+        assert_eq!(framebuffer.mode(), DisplayMode::HiRes);
+        // The 16x16 sprite, two rows down and four columns right
+        assert!((4..20).all(|x| framebuffer.pixel(x, 2)));
+        assert!(!framebuffer.pixel(3, 2) && !framebuffer.pixel(20, 2));
+        assert!(framebuffer.pixel(4, 17) && framebuffer.pixel(19, 17));
+        assert!((0..128).all(|x| !framebuffer.pixel(x, 0)));
+    }
+
+    #[test]
+    fn timers() {
+        let mut peripherals = peripherals();
+        peripherals.delay.set(7);
+
+        // LD V0, DT; LD V1, 3; LD ST, V1; LD DT, V1
+        let program = [0xF0, 0x07, 0x61, 0x03, 0xF1, 0x18, 0xF1, 0x15];
+        let run = execute(&program, QuirksConfig::default(), 4, peripherals);
+
+        assert_eq!(run.reg[0], 7);
+        assert_eq!(run.peripherals.sound.get(), 3);
+        assert_eq!(run.peripherals.delay.get(), 3);
+        // The core leaves ticking the timers to the Chip8
+        assert_eq!(run.peripherals.delay.ticks(), 0);
+    }
+
+    #[test]
+    fn coverage() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut coverage = [Coverage::default(); 4096];
+        let mut peripherals = peripherals();
+
+        // LD I, 0x208; DRW V0, V0, 2; JP 0x204; padding; sprite
+        let program = [0xA2, 0x08, 0xD0, 0x02, 0x12, 0x04, 0x00, 0x00, 0xFF, 0x81];
+        mem[0x200..0x200 + program.len()].copy_from_slice(&program);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_coverage(&mut coverage);
+        for _ in 0..4 {
+            tick(&mut core, &mut peripherals);
+        }
+
+        let coverage = core.coverage().unwrap();
+        assert!(coverage[0x200..0x206]
+            .iter()
+            .all(|access| *access == Coverage::EXECUTED));
+        assert!(coverage[0x206..0x208]
+            .iter()
+            .all(|access| access.is_empty()));
+        assert!(coverage[0x208..0x20A]
+            .iter()
+            .all(|access| *access == Coverage::SPRITE));
+        assert!(coverage[0x20A].is_empty());
+        assert!(coverage[..0x200].iter().all(|access| access.is_empty()));
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut coverage = [Coverage::default(); 4096];
+        let mut peripherals = peripherals();
+
+        // LD I, 0x200; LD V0, 0x12; LD V1, 0x08; LD [I], V1; JP 0x200
+        let program = [0xA2, 0x00, 0x60, 0x12, 0x61, 0x08, 0xF1, 0x55, 0x12, 0x00];
+        mem[0x200..0x200 + program.len()].copy_from_slice(&program);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_coverage(&mut coverage);
+        for _ in 0..4 {
+            tick(&mut core, &mut peripherals);
+        }
+        let modified = Coverage(Coverage::EXECUTED.0 | Coverage::WRITTEN.0 | Coverage::MODIFIED.0);
+        assert_eq!(core.coverage().unwrap()[0x200..0x202], [modified; 2]);
+        // JP 0x200 runs the rewritten JP 0x208
+        tick(&mut core, &mut peripherals);
+        tick(&mut core, &mut peripherals);
+        assert_eq!(core.pc(), 0x208);
+
+        // Writes to bytes never executed aren't self-modifying
+        // LD I, 0x300; LD [I], V1
+        core.memory_mut()[0x208..0x20C].copy_from_slice(&[0xA3, 0x00, 0xF1, 0x55]);
+        tick(&mut core, &mut peripherals);
+        tick(&mut core, &mut peripherals);
+        assert_eq!(
+            core.coverage().unwrap()[0x300..0x302],
+            [Coverage::WRITTEN; 2]
+        );
+    }
+
+    #[test]
+    fn decode_cache() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut cache = [const { None }; 4096];
+        let mut peripherals = peripherals();
+
+        // CALL 0x20C; LD I, 0x20C; LD V0, 0x72; LD V1, 0x05; LD [I], V1; CALL 0x20C;
+        // LD V2, 0x01; RET
+        let program = [
+            0x22, 0x0C, 0xA2, 0x0C, 0x60, 0x72, 0x61, 0x05, 0xF1, 0x55, 0x22, 0x0C, 0x62, 0x01,
+            0x00, 0xEE,
+        ];
+        mem[0x200..0x200 + program.len()].copy_from_slice(&program);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_decode_cache(&mut cache);
+
+        // The subroutine is cached by the first call, then overwritten with ADD V2, 0x05
+        for _ in 0..3 {
+            tick(&mut core, &mut peripherals);
+        }
+        assert_eq!(core.registers()[2], 0x01);
+        for _ in 0..7 {
+            tick(&mut core, &mut peripherals);
+        }
+        assert_eq!(core.registers()[2], 0x06);
+        assert_eq!(core.pc(), 0x20C);
+
+        // Writes through memory_mut clear the cache as well
+        core.memory_mut()[0x20C..0x20E].copy_from_slice(&[0x62, 0x09]);
+        tick(&mut core, &mut peripherals);
+        assert_eq!(core.registers()[2], 0x09);
+    }
+
+    #[test]
+    fn modify_state() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut cache = [const { None }; 4096];
+        let mut peripherals = peripherals();
+
+        // LD V0, 0x01; LD V0, 0x02
+        mem[0x200..0x204].copy_from_slice(&[0x60, 0x01, 0x60, 0x02]);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_decode_cache(&mut cache);
+        tick(&mut core, &mut peripherals);
+        tick(&mut core, &mut peripherals);
+
+        // Patch the cached instruction, skip back to it and set the registers it uses
+        core.memory_range_mut(0x200..0x202)
+            .unwrap()
+            .copy_from_slice(&[0x80, 0x14]);
+        core.set_pc(0x200);
+        core.set_i(0x300);
+        core.registers_mut()[0] = 0x10;
+        core.registers_mut()[1] = 0x20;
+        tick(&mut core, &mut peripherals);
+
+        let mut reg = [0; 16];
+        reg[..2].copy_from_slice(&[0x30, 0x20]);
+        assert_eq!(
+            core.state(),
+            CoreState {
+                pc: 0x202,
+                i: 0x300,
+                sp: 0,
+                reg
+            }
+        );
+        assert!(core.memory_range_mut(0xFFF..0x1001).is_none());
     }
 }
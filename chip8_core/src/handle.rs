@@ -0,0 +1,286 @@
+use crate::pacing::FramePacer;
+use crate::peripherals::{DefaultPeripherals, Peripherals};
+use crate::{Chip8, Command, Core, Error, QuirksConfig, Snapshot};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+/// The requests of a [`Chip8Handle`] to the thread running the Chip8
+#[derive(Debug)]
+enum Request {
+    Command(Command),
+    LoadRom(Vec<u8>),
+    SaveState(Sender<Snapshot>),
+    Stop,
+}
+
+/// A [`Chip8`] running in real time on its own thread, controlled through a channel
+///
+/// The requests are executed between frames. The thread owns the memory and keeps a copy of
+/// the loaded program, so resets undo the writes of the program. It stops once the program
+/// fails, or once the handle is stopped or dropped.
+#[derive(Debug)]
+pub struct Chip8Handle {
+    requests: Sender<Request>,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+    /// The bytes of memory after the start address
+    program_len: usize,
+}
+
+impl Chip8Handle {
+    /// Run the program loaded into `mem` at `core_freq` on a new thread
+    ///
+    /// Fails with [`Error::InvalidCoreFrequency`] like [`Chip8::new`].
+    ///
+    /// # Panic
+    /// This function panics if `mem` is shorter than 2048 bytes or longer than 64 KiB, see
+    /// [`Core::new`].
+    pub fn spawn<P>(
+        mem: Vec<u8>,
+        quirks: QuirksConfig,
+        core_freq: u32,
+        peripherals: P,
+    ) -> Result<Self, Error>
+    where
+        P: Peripherals + Send + 'static,
+    {
+        assert!((2048..=Core::MAX_MEM_LEN).contains(&mem.len()));
+
+        let program_len = mem.len().saturating_sub(quirks.start as usize);
+        let (requests, received) = mpsc::channel();
+        let (ready, started) = mpsc::channel();
+        let thread =
+            thread::spawn(move || run(mem, quirks, core_freq, peripherals, received, ready));
+
+        // The thread only hangs up without reporting if it panicked, which join reports
+        match started.recv() {
+            Ok(Err(e)) => Err(e),
+            _ => Ok(Self {
+                requests,
+                thread: Some(thread),
+                program_len,
+            }),
+        }
+    }
+
+    /// Stop executing instructions and decrementing timers, see [`Chip8::pause`]
+    pub fn pause(&self) {
+        self.send(Request::Command(Command::Pause));
+    }
+
+    /// Continue after [`Chip8Handle::pause`]
+    pub fn resume(&self) {
+        self.send(Request::Command(Command::Resume));
+    }
+
+    /// Reset the Chip8 and reload the program, see [`Chip8::reset`]
+    pub fn reset(&self) {
+        self.send(Request::Command(Command::Reset));
+    }
+
+    /// Replace the program by `rom`, loaded at the start address of the quirks, and reset
+    ///
+    /// Fails with [`Error::RomTooLarge`] if `rom` doesn't fit into the memory.
+    pub fn load_rom(&self, rom: &[u8]) -> Result<(), Error> {
+        if rom.len() > self.program_len {
+            return Err(Error::RomTooLarge(rom.len()));
+        }
+
+        self.send(Request::LoadRom(rom.to_vec()));
+        Ok(())
+    }
+
+    /// Change the core frequency, see [`Chip8::set_core_freq`]
+    pub fn set_speed(&self, core_freq: u32) -> Result<(), Error> {
+        if core_freq == 0 || core_freq > Chip8::<DefaultPeripherals>::MAX_CORE_FREQ {
+            return Err(Error::InvalidCoreFrequency(core_freq));
+        }
+
+        self.send(Request::Command(Command::SetCoreFreq(core_freq)));
+        Ok(())
+    }
+
+    /// The state of the Chip8 at the end of the current frame, see [`Chip8::snapshot`]
+    ///
+    /// Returns `None` if the thread already stopped.
+    pub fn save_state(&self) -> Option<Snapshot> {
+        let (reply, snapshot) = mpsc::channel();
+        self.send(Request::SaveState(reply));
+        snapshot.recv().ok()
+    }
+
+    /// Whether the thread stopped, as the program failed
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Stop the Chip8 after the current frame and wait for the thread
+    ///
+    /// Returns the error the program failed with, if it did.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.join()
+    }
+
+    fn send(&self, request: Request) {
+        // A stopped thread is reported by is_finished and stop
+        let _ = self.requests.send(request);
+    }
+
+    fn join(&mut self) -> Result<(), Error> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+
+        self.send(Request::Stop);
+        match thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for Chip8Handle {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            let _ = self.join();
+        }
+    }
+}
+
+/// The thread of a [`Chip8Handle`], reporting whether the Chip8 was created to `ready`
+fn run<P: Peripherals>(
+    mut mem: Vec<u8>,
+    quirks: QuirksConfig,
+    core_freq: u32,
+    peripherals: P,
+    requests: Receiver<Request>,
+    ready: Sender<Result<(), Error>>,
+) -> Result<(), Error> {
+    let mut reg = [0; 16];
+    let mut stack = [0; 16];
+
+    let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+    core.set_quirks(quirks);
+    // The memory right after loading, including the font, restored on resets
+    let mut initial = core.memory().to_vec();
+
+    let mut chip8 = match Chip8::new(core, core_freq, peripherals) {
+        Ok(chip8) => chip8,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    let mut pacer = FramePacer::new(Chip8::<P>::TIMER_FREQ);
+    loop {
+        loop {
+            let request = match requests.try_recv() {
+                Ok(request) => request,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+
+            match request {
+                Request::Command(command) => {
+                    chip8.handle(command)?;
+                    if command == Command::Reset {
+                        chip8.core_mut().memory_mut().copy_from_slice(&initial);
+                    }
+                }
+                Request::LoadRom(rom) => {
+                    chip8.reset();
+                    chip8.core_mut().memory_mut().copy_from_slice(&initial);
+                    chip8.core_mut().load_program(&rom)?;
+                    initial.copy_from_slice(chip8.core().memory());
+                }
+                Request::SaveState(reply) => {
+                    let _ = reply.send(chip8.snapshot());
+                }
+                Request::Stop => return Ok(()),
+            }
+        }
+
+        // Paused frames don't carry fractions of ticks over, so the state stays the same
+        if !chip8.is_paused() {
+            let cycles = chip8.frame_cycles();
+            chip8.run_cycles(cycles as u64).exit.into_result()?;
+        }
+        pacer.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control() {
+        let mut mem = vec![0; 4096];
+        // LD V0, 1; JP 0x202
+        mem[0x200..0x204].copy_from_slice(&[0x60, 0x01, 0x12, 0x02]);
+
+        let handle = Chip8Handle::spawn(
+            mem,
+            QuirksConfig::default(),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+        assert_eq!(handle.set_speed(0), Err(Error::InvalidCoreFrequency(0)));
+        assert_eq!(handle.load_rom(&[0; 0xE01]), Err(Error::RomTooLarge(0xE01)));
+
+        handle.pause();
+        let paused = handle.save_state().unwrap();
+        assert_eq!(handle.save_state().unwrap(), paused);
+
+        // LD V1, 2; JP 0x202
+        handle.load_rom(&[0x61, 0x02, 0x12, 0x02]).unwrap();
+        handle.resume();
+        let mut state = handle.save_state().unwrap();
+        while state.core.pc != 0x202 {
+            state = handle.save_state().unwrap();
+        }
+        assert_eq!(state.core.reg[..2], [0, 2]);
+        assert_eq!(state.core.mem[0x200..0x204], [0x61, 0x02, 0x12, 0x02]);
+
+        assert!(!handle.is_finished());
+        assert_eq!(handle.stop(), Ok(()));
+    }
+
+    #[test]
+    fn failure() {
+        // An invalid instruction at 0x200
+        let mut mem = vec![0; 4096];
+        mem[0x200..0x202].copy_from_slice(&[0xFF, 0xFF]);
+
+        assert!(matches!(
+            Chip8Handle::spawn(
+                mem.clone(),
+                QuirksConfig::default(),
+                0,
+                DefaultPeripherals::default()
+            ),
+            Err(Error::InvalidCoreFrequency(0))
+        ));
+
+        let handle = Chip8Handle::spawn(
+            mem,
+            QuirksConfig::default(),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+        while !handle.is_finished() {
+            thread::yield_now();
+        }
+        assert!(handle.save_state().is_none());
+        assert_eq!(
+            handle.stop(),
+            Err(Error::InvalidInstruction {
+                opcode: 0xFFFF,
+                pc: 0x200
+            })
+        );
+    }
+}
@@ -1,10 +1,13 @@
 use crate::Error;
+use core::ops::Deref;
+use core::str::FromStr;
 use Instruction::*;
 
 /// A Register index, 0 - 0x0F
 ///
 /// CHIP-8 has 15 registers
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Register(pub(crate) u8);
 
 impl From<u8> for Register {
@@ -13,15 +16,22 @@ impl From<u8> for Register {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for Register {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Register {
+    /// The index of the register
+    pub fn index(&self) -> u8 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for Register {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "V{:X}", self.0)
     }
 }
 
 /// An Address, a 12 bit value (0x000 - 0x0FFF)
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Address(pub(crate) u16);
 
 impl From<(u8, u8, u8)> for Address {
@@ -30,15 +40,52 @@ impl From<(u8, u8, u8)> for Address {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for Address {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl From<u16> for Address {
+    fn from(val: u16) -> Self {
+        Self(val & 0x0FFF)
+    }
+}
+
+impl Address {
+    /// The address as a number
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for Address {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:03X}", self.0)
     }
 }
 
+/// A 16 bit address of XO-CHIP's `LD I, LONG nnnn` (F000 nnnn), reaching all of 64 KiB
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LongAddress(pub(crate) u16);
+
+impl From<u16> for LongAddress {
+    fn from(val: u16) -> Self {
+        Self(val)
+    }
+}
+
+impl LongAddress {
+    /// The address as a number
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for LongAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:04X}", self.0)
+    }
+}
+
 /// A 8 bit intermediate value
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Value8(pub(crate) u8);
 
 impl From<(u8, u8)> for Value8 {
@@ -47,15 +94,28 @@ impl From<(u8, u8)> for Value8 {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for Value8 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl From<u8> for Value8 {
+    fn from(val: u8) -> Self {
+        Self(val)
+    }
+}
+
+impl Value8 {
+    /// The value as a number
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for Value8 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:02X}", self.0)
     }
 }
 
 /// A 4 bit intermediate value
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Value4(pub(crate) u8);
 
 impl From<u8> for Value4 {
@@ -64,9 +124,15 @@ impl From<u8> for Value4 {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for Value4 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Value4 {
+    /// The value as a number
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for Value4 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:X}", self.0)
     }
 }
@@ -87,6 +153,7 @@ pub fn nibbles(val: u16) -> (u8, u8, u8, u8) {
 #[allow(missing_docs)]
 /// All possible Instructions the CHIP-8 cpu supports
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Instruction {
     I0NNN(Address),
     I00E0,
@@ -123,11 +190,23 @@ pub enum Instruction {
     IFX33(Register),
     IFX55(Register),
     IFX65(Register),
+    // SCHIP
+    I00CN(Value4),
+    I00FB,
+    I00FC,
+    I00FD,
+    I00FE,
+    I00FF,
+    IFX75(Register),
+    IFX85(Register),
+    // XO-CHIP
+    IF002,
+    IFX3A(Register),
+    IF000(LongAddress),
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             I0NNN(nnn) => write!(f, "SYS {}", nnn),
             I00E0 => write!(f, "CLS"),
@@ -164,6 +243,17 @@ impl std::fmt::Display for Instruction {
             IFX33(x) => write!(f, "LD B, {}", x),
             IFX55(x) => write!(f, "LD [I], {}", x),
             IFX65(x) => write!(f, "LD {}, [I]", x),
+            I00CN(n) => write!(f, "SCD {}", n),
+            I00FB => write!(f, "SCR"),
+            I00FC => write!(f, "SCL"),
+            I00FD => write!(f, "EXIT"),
+            I00FE => write!(f, "LOW"),
+            I00FF => write!(f, "HIGH"),
+            IFX75(x) => write!(f, "LD R, {}", x),
+            IFX85(x) => write!(f, "LD {}, R", x),
+            IF002 => write!(f, "LD AUDIO, [I]"),
+            IFX3A(x) => write!(f, "LD PITCH, {}", x),
+            IF000(nnnn) => write!(f, "LD I, LONG {}", nnnn),
         }
     }
 }
@@ -174,6 +264,12 @@ impl Instruction {
         match nnn {
             Address(0x00E0) => Ok(I00E0),
             Address(0x00EE) => Ok(I00EE),
+            Address(nnn @ 0x00C0..=0x00CF) => Ok(I00CN(Value4::from(nnn as u8))),
+            Address(0x00FB) => Ok(I00FB),
+            Address(0x00FC) => Ok(I00FC),
+            Address(0x00FD) => Ok(I00FD),
+            Address(0x00FE) => Ok(I00FE),
+            Address(0x00FF) => Ok(I00FF),
             Address(0x0200..=0x0FFF) => Ok(I0NNN(nnn)),
             _ => Err(()),
         }
@@ -223,25 +319,370 @@ impl Instruction {
     /// Decode all fxvv instructions
     fn decode_f(x: Register, vv: Value8) -> Result<Self, ()> {
         match vv {
+            Value8(0x02) if x == Register(0) => Ok(IF002),
             Value8(0x07) => Ok(IFX07(x)),
             Value8(0x0A) => Ok(IFX0A(x)),
             Value8(0x15) => Ok(IFX15(x)),
             Value8(0x18) => Ok(IFX18(x)),
             Value8(0x1E) => Ok(IFX1E(x)),
             Value8(0x29) => Ok(IFX29(x)),
+            Value8(0x3A) => Ok(IFX3A(x)),
             Value8(0x33) => Ok(IFX33(x)),
             Value8(0x55) => Ok(IFX55(x)),
             Value8(0x65) => Ok(IFX65(x)),
+            Value8(0x75) => Ok(IFX75(x)),
+            Value8(0x85) => Ok(IFX85(x)),
             _ => Err(()),
         }
     }
 }
 
-impl TryFrom<&[u8]> for Instruction {
-    type Error = Error;
+/// An operand of an instruction, as written in its mnemonic form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// A register `Vx`
+    Register(Register),
+    /// An address `nnn`
+    Address(Address),
+    /// A 16 bit address `LONG nnnn`
+    LongAddress(LongAddress),
+    /// An 8 bit value `nn`
+    Byte(Value8),
+    /// A 4 bit value `n`
+    Nibble(Value4),
+    /// The index register `I`
+    I,
+    /// The memory at the index register `[I]`
+    IndirectI,
+    /// The delay timer `DT`
+    DelayTimer,
+    /// The sound timer `ST`
+    SoundTimer,
+    /// A key press `K`
+    Key,
+    /// The font sprite of a digit `F`
+    Font,
+    /// The BCD representation of a value `B`
+    Bcd,
+    /// The XO-CHIP audio pattern buffer `AUDIO`
+    Audio,
+    /// The XO-CHIP audio pitch `PITCH`
+    Pitch,
+    /// The SCHIP RPL user flags `R`
+    Flags,
+}
+
+impl core::fmt::Display for Operand {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Operand::Register(x) => write!(f, "{}", x),
+            Operand::Address(nnn) => write!(f, "{}", nnn),
+            Operand::LongAddress(nnnn) => write!(f, "LONG {}", nnnn),
+            Operand::Byte(nn) => write!(f, "{}", nn),
+            Operand::Nibble(n) => write!(f, "{}", n),
+            Operand::I => write!(f, "I"),
+            Operand::IndirectI => write!(f, "[I]"),
+            Operand::DelayTimer => write!(f, "DT"),
+            Operand::SoundTimer => write!(f, "ST"),
+            Operand::Key => write!(f, "K"),
+            Operand::Font => write!(f, "F"),
+            Operand::Bcd => write!(f, "B"),
+            Operand::Audio => write!(f, "AUDIO"),
+            Operand::Pitch => write!(f, "PITCH"),
+            Operand::Flags => write!(f, "R"),
+        }
+    }
+}
+
+impl Instruction {
+    /// The mnemonic of the instruction, e.g. `LD`
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            I0NNN(_) => "SYS",
+            I00E0 => "CLS",
+            I00EE => "RET",
+            I1NNN(_) | IBNNN(_) => "JP",
+            I2NNN(_) => "CALL",
+            I3XNN(..) | I5XY0(..) => "SE",
+            I4XNN(..) | I9XY0(..) => "SNE",
+            I6XNN(..) | I8XY0(..) | IANNN(_) | IFX07(_) | IFX0A(_) | IFX15(_) | IFX18(_)
+            | IFX29(_) | IFX33(_) | IFX55(_) | IFX65(_) | IFX75(_) | IFX85(_) | IF002
+            | IFX3A(_) | IF000(_) => "LD",
+            I7XNN(..) | I8XY4(..) | IFX1E(_) => "ADD",
+            I8XY1(..) => "OR",
+            I8XY2(..) => "AND",
+            I8XY3(..) => "XOR",
+            I8XY5(..) => "SUB",
+            I8XY6(..) => "SHR",
+            I8XY7(..) => "SUBN",
+            I8XYE(..) => "SHL",
+            ICXNN(..) => "RND",
+            IDXYN(..) => "DRW",
+            IEX9E(_) => "SKP",
+            IEXA1(_) => "SKNP",
+            I00CN(_) => "SCD",
+            I00FB => "SCR",
+            I00FC => "SCL",
+            I00FD => "EXIT",
+            I00FE => "LOW",
+            I00FF => "HIGH",
+        }
+    }
+
+    /// The operands of the instruction in the order of its mnemonic form
+    pub fn operands(&self) -> impl Iterator<Item = Operand> {
+        let reg = |x: &Register| Some(Operand::Register(x.clone()));
+        let operands = match self {
+            I00E0 | I00EE | I00FB | I00FC | I00FD | I00FE | I00FF => [None, None, None],
+            I00CN(n) => [Some(Operand::Nibble(n.clone())), None, None],
+            I0NNN(nnn) | I1NNN(nnn) | I2NNN(nnn) => {
+                [Some(Operand::Address(nnn.clone())), None, None]
+            }
+            I3XNN(x, nn) | I4XNN(x, nn) | I6XNN(x, nn) | I7XNN(x, nn) | ICXNN(x, nn) => {
+                [reg(x), Some(Operand::Byte(nn.clone())), None]
+            }
+            I5XY0(x, y)
+            | I8XY0(x, y)
+            | I8XY1(x, y)
+            | I8XY2(x, y)
+            | I8XY3(x, y)
+            | I8XY4(x, y)
+            | I8XY5(x, y)
+            | I8XY6(x, y)
+            | I8XY7(x, y)
+            | I8XYE(x, y)
+            | I9XY0(x, y) => [reg(x), reg(y), None],
+            IANNN(nnn) => [Some(Operand::I), Some(Operand::Address(nnn.clone())), None],
+            IF000(nnnn) => [
+                Some(Operand::I),
+                Some(Operand::LongAddress(nnnn.clone())),
+                None,
+            ],
+            IBNNN(nnn) => [reg(&Register(0)), Some(Operand::Address(nnn.clone())), None],
+            IDXYN(x, y, n) => [reg(x), reg(y), Some(Operand::Nibble(n.clone()))],
+            IEX9E(x) | IEXA1(x) => [reg(x), None, None],
+            IFX07(x) => [reg(x), Some(Operand::DelayTimer), None],
+            IFX0A(x) => [reg(x), Some(Operand::Key), None],
+            IFX15(x) => [Some(Operand::DelayTimer), reg(x), None],
+            IFX18(x) => [Some(Operand::SoundTimer), reg(x), None],
+            IFX1E(x) => [Some(Operand::I), reg(x), None],
+            IFX29(x) => [Some(Operand::Font), reg(x), None],
+            IFX33(x) => [Some(Operand::Bcd), reg(x), None],
+            IFX55(x) => [Some(Operand::IndirectI), reg(x), None],
+            IFX65(x) => [reg(x), Some(Operand::IndirectI), None],
+            IFX75(x) => [Some(Operand::Flags), reg(x), None],
+            IFX85(x) => [reg(x), Some(Operand::Flags), None],
+            IF002 => [Some(Operand::Audio), Some(Operand::IndirectI), None],
+            IFX3A(x) => [Some(Operand::Pitch), reg(x), None],
+        };
+
+        operands.into_iter().flatten()
+    }
+
+    /// Whether the instruction may continue anywhere else than at the next instruction
+    ///
+    /// These are jumps, calls, returns and skips.
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            I00EE
+                | I1NNN(_)
+                | I2NNN(_)
+                | IBNNN(_)
+                | I3XNN(..)
+                | I4XNN(..)
+                | I5XY0(..)
+                | I9XY0(..)
+                | IEX9E(_)
+                | IEXA1(_)
+        )
+    }
+
+    /// The target of a jump or call, if it is known without executing the instruction
+    ///
+    /// `JP V0, nnn` returns `nnn`, which is only the target if `V0` is zero.
+    pub fn branch_target(&self) -> Option<u16> {
+        match self {
+            I1NNN(nnn) | I2NNN(nnn) | IBNNN(nnn) => Some(nnn.value()),
+            _ => None,
+        }
+    }
+
+    /// A rough relative cost of executing the instruction
+    ///
+    /// Register operations cost 1. Instructions accessing memory cost one more for each byte
+    /// accessed, `DRW` one more for each sprite row and `CLS` and the scrolls one more for
+    /// each 8 rows changed. This is not a cycle accurate model of any real hardware.
+    pub fn cycle_cost(&self) -> u32 {
+        match self {
+            I00E0 | I00CN(_) | I00FB | I00FC => 1 + 4,
+            IDXYN(_, _, n) => 1 + n.value() as u32,
+            IFX33(_) => 1 + 3,
+            IFX55(x) | IFX65(x) | IFX75(x) | IFX85(x) => 1 + x.index() as u32 + 1,
+            IF002 => 1 + 16,
+            _ => 1,
+        }
+    }
+
+    /// The machine cycles the COSMAC VIP interpreter takes to execute the instruction
+    ///
+    /// Skips which are taken take 4 cycles more. Times which depend on the operands, like the
+    /// shifting of sprites to their position, are averaged, and `DRW` doesn't include waiting
+    /// for the display interrupt. The SCHIP and XO-CHIP instructions, which the VIP doesn't
+    /// have, take as long as the most similar VIP instruction.
+    pub fn vip_cycles(&self) -> u32 {
+        match self {
+            I0NNN(_) => 12,
+            I00E0 | I00CN(_) | I00FB | I00FC => 3078,
+            I00EE => 10,
+            I1NNN(_) => 12,
+            I2NNN(_) => 26,
+            I3XNN(..) | I4XNN(..) => 10,
+            I5XY0(..) | I9XY0(..) => 14,
+            I6XNN(..) => 6,
+            I7XNN(..) => 10,
+            I8XY0(..) | I8XY1(..) | I8XY2(..) | I8XY3(..) | I8XY4(..) | I8XY5(..) | I8XY6(..)
+            | I8XY7(..) | I8XYE(..) => 44,
+            IANNN(_) | IF000(_) => 12,
+            IBNNN(_) => 22,
+            ICXNN(..) => 36,
+            IDXYN(_, _, n) => 26 + 46 * n.value() as u32,
+            IEX9E(_) | IEXA1(_) => 14,
+            IFX07(_) | IFX0A(_) | IFX15(_) | IFX18(_) => 10,
+            IFX1E(_) | IFX29(_) => 16,
+            IFX33(_) => 108,
+            IFX55(x) | IFX65(x) | IFX75(x) | IFX85(x) => 14 + 14 * (x.index() as u32 + 1),
+            I00FD | I00FE | I00FF | IFX3A(_) => 10,
+            IF002 => 14 + 14 * 16,
+        }
+    }
+
+    /// The size of the instruction in bytes, 4 for `LD I, LONG nnnn` and 2 for all others
+    pub fn size(&self) -> u16 {
+        match self {
+            IF000(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Encode the instruction, the inverse of decoding it with `Instruction::try_from`
+    pub fn encode(&self) -> Encoded {
+        let nnn = |op: u16, nnn: &Address| op << 12 | nnn.0;
+        let xnn = |op: u16, x: &Register, vv: &Value8| op << 12 | (x.0 as u16) << 8 | vv.0 as u16;
+        let xyn = |op: u16, x: &Register, y: &Register, n: u8| {
+            op << 12 | (x.0 as u16) << 8 | (y.0 as u16) << 4 | n as u16
+        };
+        let op_x = |op: u16, x: &Register, vv: u8| xnn(op, x, &Value8(vv));
+
+        let ins = match self {
+            I0NNN(a) => nnn(0x0, a),
+            I00E0 => 0x00E0,
+            I00EE => 0x00EE,
+            I1NNN(a) => nnn(0x1, a),
+            I2NNN(a) => nnn(0x2, a),
+            I3XNN(x, vv) => xnn(0x3, x, vv),
+            I4XNN(x, vv) => xnn(0x4, x, vv),
+            I5XY0(x, y) => xyn(0x5, x, y, 0x0),
+            I6XNN(x, vv) => xnn(0x6, x, vv),
+            I7XNN(x, vv) => xnn(0x7, x, vv),
+            I8XY0(x, y) => xyn(0x8, x, y, 0x0),
+            I8XY1(x, y) => xyn(0x8, x, y, 0x1),
+            I8XY2(x, y) => xyn(0x8, x, y, 0x2),
+            I8XY3(x, y) => xyn(0x8, x, y, 0x3),
+            I8XY4(x, y) => xyn(0x8, x, y, 0x4),
+            I8XY5(x, y) => xyn(0x8, x, y, 0x5),
+            I8XY6(x, y) => xyn(0x8, x, y, 0x6),
+            I8XY7(x, y) => xyn(0x8, x, y, 0x7),
+            I8XYE(x, y) => xyn(0x8, x, y, 0xE),
+            I9XY0(x, y) => xyn(0x9, x, y, 0x0),
+            IANNN(a) => nnn(0xA, a),
+            IBNNN(a) => nnn(0xB, a),
+            ICXNN(x, vv) => xnn(0xC, x, vv),
+            IDXYN(x, y, n) => xyn(0xD, x, y, n.0),
+            IEX9E(x) => op_x(0xE, x, 0x9E),
+            IEXA1(x) => op_x(0xE, x, 0xA1),
+            IFX07(x) => op_x(0xF, x, 0x07),
+            IFX0A(x) => op_x(0xF, x, 0x0A),
+            IFX15(x) => op_x(0xF, x, 0x15),
+            IFX18(x) => op_x(0xF, x, 0x18),
+            IFX1E(x) => op_x(0xF, x, 0x1E),
+            IFX29(x) => op_x(0xF, x, 0x29),
+            IFX33(x) => op_x(0xF, x, 0x33),
+            IFX55(x) => op_x(0xF, x, 0x55),
+            IFX65(x) => op_x(0xF, x, 0x65),
+            IFX75(x) => op_x(0xF, x, 0x75),
+            IFX85(x) => op_x(0xF, x, 0x85),
+            I00CN(n) => 0x00C0 | n.0 as u16,
+            I00FB => 0x00FB,
+            I00FC => 0x00FC,
+            I00FD => 0x00FD,
+            I00FE => 0x00FE,
+            I00FF => 0x00FF,
+            IF002 => 0xF002,
+            IFX3A(x) => op_x(0xF, x, 0x3A),
+            IF000(nnnn) => {
+                let [high, low] = nnnn.0.to_be_bytes();
+                return Encoded {
+                    bytes: [0xF0, 0x00, high, low],
+                    len: 4,
+                };
+            }
+        };
+
+        let [high, low] = ins.to_be_bytes();
+        Encoded {
+            bytes: [high, low, 0, 0],
+            len: 2,
+        }
+    }
+}
+
+/// The bytes of an encoded instruction, see [`Instruction::encode`]
+///
+/// Dereferences to the 2 bytes of the opcode, or 4 for `LD I, LONG nnnn`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Encoded {
+    bytes: [u8; 4],
+    len: usize,
+}
+
+impl Deref for Encoded {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for Encoded {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for Encoded {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        **self == other[..]
+    }
+}
+
+impl Instruction {
+    /// Decode the instruction at the start of `bytes`, which is located at `pc`
+    ///
+    /// `pc` is only used to give errors their location. `LD I, LONG nnnn` fails to decode
+    /// unless `bytes` holds its address as well.
+    pub fn decode(bytes: &[u8], pc: u16) -> Result<Self, Error> {
+        let ins = match bytes {
+            [high, low, ..] => u16::from_be_bytes([*high, *low]),
+            _ => return Err(Error::InvalidAlignment { pc }),
+        };
+        if ins == 0xF000 {
+            return match bytes {
+                [_, _, high, low, ..] => Ok(IF000(LongAddress(u16::from_be_bytes([*high, *low])))),
+                _ => Err(Error::InvalidInstruction { opcode: ins, pc }),
+            };
+        }
 
-    fn try_from(instruction: &[u8]) -> Result<Self, Error> {
-        let ins = u16::from_be_bytes(instruction[0..2].try_into()?);
         let decoded = match nibbles(ins) {
             (0x0, a, b, c) => Self::decode_0((a, b, c).into()),
             (0x1, a, b, c) => Ok(I1NNN((a, b, c).into())),
@@ -262,7 +703,238 @@ impl TryFrom<&[u8]> for Instruction {
             _ => Err(()),
         };
 
-        decoded.map_err(|_| Error::InvalidInstruction(ins))
+        decoded.map_err(|_| Error::InvalidInstruction { opcode: ins, pc })
+    }
+
+    /// Whether `opcode` decodes, like [`Instruction::decode`] but usable in constants
+    ///
+    /// `LD I, LONG nnnn` (F000) is valid, although it only decodes with the address after it.
+    pub const fn is_valid(opcode: u16) -> bool {
+        let (x, nn) = ((opcode >> 8) & 0xF, opcode & 0xFF);
+
+        match opcode >> 12 {
+            0x0 => matches!(
+                opcode,
+                0x00C0..=0x00CF | 0x00E0 | 0x00EE | 0x00FB..=0x00FF | 0x0200..=0x0FFF
+            ),
+            0x5 | 0x9 => opcode & 0xF == 0,
+            0x8 => matches!(opcode & 0xF, 0x0..=0x7 | 0xE),
+            0xE => matches!(nn, 0x9E | 0xA1),
+            0xF => match nn {
+                0x00 | 0x02 => x == 0,
+                0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x3A | 0x33 | 0x55 | 0x65 | 0x75
+                | 0x85 => true,
+                _ => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Instruction {
+    type Error = Error;
+
+    /// Decode the instruction at the start of `instruction`
+    ///
+    /// Errors are located at address 0, use [`Instruction::decode`] if the address is known.
+    fn try_from(instruction: &[u8]) -> Result<Self, Error> {
+        Self::decode(instruction, 0)
+    }
+}
+
+/// Decode `bytes` as consecutive instructions, the first one located at `base_addr`
+///
+/// Yields the address of each instruction along with the decoded instruction. Instructions
+/// which don't decode are skipped as 2 bytes. A trailing odd byte yields
+/// [`Error::InvalidAlignment`].
+pub fn decode_iter(bytes: &[u8], base_addr: u16) -> DecodeIter<'_> {
+    DecodeIter {
+        bytes,
+        addr: base_addr,
+    }
+}
+
+/// An iterator decoding instructions, see [`decode_iter`]
+#[derive(Clone, Debug)]
+pub struct DecodeIter<'a> {
+    bytes: &'a [u8],
+    addr: u16,
+}
+
+impl Iterator for DecodeIter<'_> {
+    type Item = (u16, Result<Instruction, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let addr = self.addr;
+        let decoded = Instruction::decode(self.bytes, addr);
+        let size = decoded.as_ref().map_or(2, Instruction::size);
+        self.bytes = self.bytes.get(size as usize..).unwrap_or_default();
+        self.addr = self.addr.wrapping_add(size);
+
+        Some((addr, decoded))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (
+            self.bytes.len().div_ceil(4),
+            Some(self.bytes.len().div_ceil(2)),
+        )
+    }
+}
+
+/// Opcodes which don't decode are rejected as [`arbitrary::Error::IncorrectFormat`]
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Instruction {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let opcode = u16::arbitrary(u)?;
+        if opcode == 0xF000 {
+            return Ok(IF000(LongAddress(u16::arbitrary(u)?)));
+        }
+        Self::decode(&opcode.to_be_bytes(), 0).map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (2, Some(4))
+    }
+}
+
+/// Parse a register `V0` - `VF`
+fn parse_register(s: &str) -> Option<Register> {
+    match s.strip_prefix(['V', 'v']) {
+        Some(index) if index.len() == 1 => u8::from_str_radix(index, 16).ok().map(Register),
+        _ => None,
+    }
+}
+
+/// Parse a hexadecimal number of at most `max`, with or without `0x` prefix
+fn parse_number(s: &str, max: u16) -> Option<u16> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|&value| value <= max)
+}
+
+fn parse_address(s: &str) -> Option<Address> {
+    parse_number(s, 0xFFF).map(Address)
+}
+
+fn parse_long_address(s: &str) -> Option<LongAddress> {
+    parse_number(s, 0xFFFF).map(LongAddress)
+}
+
+fn parse_value8(s: &str) -> Option<Value8> {
+    parse_number(s, 0xFF).map(|value| Value8(value as u8))
+}
+
+fn parse_value4(s: &str) -> Option<Value4> {
+    parse_number(s, 0xF).map(|value| Value4(value as u8))
+}
+
+/// Parse an instruction, see [`Instruction::from_str`]
+fn parse(s: &str) -> Option<Instruction> {
+    let s = s.trim();
+    let (mnemonic, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+
+    // Braces mark optional operands, as in `SHR V1 {,V2}`
+    let mut operands = [""; 3];
+    let mut count = 0;
+    if !rest.trim().is_empty() {
+        for operand in rest.split(',') {
+            let operand = operand.trim_matches(|c: char| c.is_whitespace() || c == '{' || c == '}');
+            if operand.is_empty() {
+                return None;
+            }
+            *operands.get_mut(count)? = operand;
+            count += 1;
+        }
+    }
+
+    let is = |operand: &str, keyword: &str| operand.eq_ignore_ascii_case(keyword);
+    let reg = parse_register;
+    let m = |name: &str| mnemonic.eq_ignore_ascii_case(name);
+
+    let instruction = match operands[..count] {
+        [] if m("CLS") => I00E0,
+        [] if m("RET") => I00EE,
+        [] if m("SCR") => I00FB,
+        [] if m("SCL") => I00FC,
+        [] if m("EXIT") => I00FD,
+        [] if m("LOW") => I00FE,
+        [] if m("HIGH") => I00FF,
+        [n] if m("SCD") => I00CN(parse_value4(n)?),
+        [a] if m("SYS") => I0NNN(parse_address(a)?),
+        [a] if m("JP") => I1NNN(parse_address(a)?),
+        [v0, a] if m("JP") && reg(v0)? == Register(0) => IBNNN(parse_address(a)?),
+        [a] if m("CALL") => I2NNN(parse_address(a)?),
+        [x, y] if m("SE") => match reg(y) {
+            Some(y) => I5XY0(reg(x)?, y),
+            None => I3XNN(reg(x)?, parse_value8(y)?),
+        },
+        [x, y] if m("SNE") => match reg(y) {
+            Some(y) => I9XY0(reg(x)?, y),
+            None => I4XNN(reg(x)?, parse_value8(y)?),
+        },
+        [i, a] if m("LD") && is(i, "I") => match a.split_once(char::is_whitespace) {
+            Some((long, nnnn)) if is(long, "LONG") => IF000(parse_long_address(nnnn.trim())?),
+            _ => IANNN(parse_address(a)?),
+        },
+        [dt, x] if m("LD") && is(dt, "DT") => IFX15(reg(x)?),
+        [st, x] if m("LD") && is(st, "ST") => IFX18(reg(x)?),
+        [f, x] if m("LD") && is(f, "F") => IFX29(reg(x)?),
+        [b, x] if m("LD") && is(b, "B") => IFX33(reg(x)?),
+        [i, x] if m("LD") && is(i, "[I]") => IFX55(reg(x)?),
+        [audio, i] if m("LD") && is(audio, "AUDIO") && is(i, "[I]") => IF002,
+        [pitch, x] if m("LD") && is(pitch, "PITCH") => IFX3A(reg(x)?),
+        [r, x] if m("LD") && is(r, "R") => IFX75(reg(x)?),
+        [x, dt] if m("LD") && is(dt, "DT") => IFX07(reg(x)?),
+        [x, k] if m("LD") && is(k, "K") => IFX0A(reg(x)?),
+        [x, i] if m("LD") && is(i, "[I]") => IFX65(reg(x)?),
+        [x, r] if m("LD") && is(r, "R") => IFX85(reg(x)?),
+        [x, y] if m("LD") => match reg(y) {
+            Some(y) => I8XY0(reg(x)?, y),
+            None => I6XNN(reg(x)?, parse_value8(y)?),
+        },
+        [i, x] if m("ADD") && is(i, "I") => IFX1E(reg(x)?),
+        [x, y] if m("ADD") => match reg(y) {
+            Some(y) => I8XY4(reg(x)?, y),
+            None => I7XNN(reg(x)?, parse_value8(y)?),
+        },
+        [x, y] if m("OR") => I8XY1(reg(x)?, reg(y)?),
+        [x, y] if m("AND") => I8XY2(reg(x)?, reg(y)?),
+        [x, y] if m("XOR") => I8XY3(reg(x)?, reg(y)?),
+        [x, y] if m("SUB") => I8XY5(reg(x)?, reg(y)?),
+        [x, y] if m("SUBN") => I8XY7(reg(x)?, reg(y)?),
+        [x] if m("SHR") => I8XY6(reg(x)?, reg(x)?),
+        [x, y] if m("SHR") => I8XY6(reg(x)?, reg(y)?),
+        [x] if m("SHL") => I8XYE(reg(x)?, reg(x)?),
+        [x, y] if m("SHL") => I8XYE(reg(x)?, reg(y)?),
+        [x, nn] if m("RND") => ICXNN(reg(x)?, parse_value8(nn)?),
+        [x, y, n] if m("DRW") => IDXYN(reg(x)?, reg(y)?, parse_value4(n)?),
+        [x] if m("SKP") => IEX9E(reg(x)?),
+        [x] if m("SKNP") => IEXA1(reg(x)?),
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+impl FromStr for Instruction {
+    type Err = Error;
+
+    /// Parse an instruction in the syntax of its `Display` implementation, e.g. `LD V3, 0x1F`
+    ///
+    /// Mnemonics and operands are case insensitive. Numbers are always hexadecimal, the `0x`
+    /// prefix is optional. The second operand of `SHR` and `SHL` defaults to the first one.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        parse(s).ok_or(Error::InvalidSyntax)
     }
 }
 
@@ -271,6 +943,7 @@ mod tests {
     use super::*;
     use crate::Error::*;
     use core::convert::TryFrom;
+    use proptest::prelude::*;
 
     macro_rules! itf_ok {
         ( $upper:expr, $lower:expr, $rhs:expr ) => {
@@ -288,6 +961,7 @@ mod tests {
     fn address() {
         assert_eq!(Address::from((0x0A, 0x0B, 0x0C)), Address(0xABC));
         assert_eq!(Address::from((0x1A, 0x2B, 0x4C)), Address(0xABC));
+        assert_eq!(Address::from(0xFABC), Address(0xABC));
     }
 
     #[test]
@@ -312,14 +986,265 @@ mod tests {
     fn decode_0_ok() {
         itf_ok!(0x00, 0xE0, I00E0);
         itf_ok!(0x00, 0xEE, I00EE);
+        itf_ok!(0x00, 0xC0, I00CN(Value4(0)));
+        itf_ok!(0x00, 0xC5, I00CN(Value4(5)));
+        itf_ok!(0x00, 0xFB, I00FB);
+        itf_ok!(0x00, 0xFC, I00FC);
+        itf_ok!(0x00, 0xFD, I00FD);
+        itf_ok!(0x00, 0xFE, I00FE);
+        itf_ok!(0x00, 0xFF, I00FF);
         itf_ok!(0x02, 0x00, I0NNN(Address(0x200)));
         itf_ok!(0x0F, 0xFF, I0NNN(Address(0xFFF)));
     }
 
     #[test]
     fn decode_0_err() {
-        itf_err!(0x00, 0x00, InvalidInstruction(0x0000));
-        itf_err!(0x01, 0xFF, InvalidInstruction(0x01FF));
+        itf_err!(
+            0x00,
+            0x00,
+            InvalidInstruction {
+                opcode: 0x0000,
+                pc: 0
+            }
+        );
+        itf_err!(
+            0x01,
+            0xFF,
+            InvalidInstruction {
+                opcode: 0x01FF,
+                pc: 0
+            }
+        );
+    }
+
+    #[test]
+    fn decode_f_xo_chip() {
+        itf_ok!(0xF0, 0x02, IF002);
+        itf_ok!(0xF5, 0x3A, IFX3A(Register(5)));
+        itf_err!(
+            0xF1,
+            0x02,
+            InvalidInstruction {
+                opcode: 0xF102,
+                pc: 0
+            }
+        );
+
+        assert_eq!(
+            Instruction::try_from([0xF0, 0x00, 0x12, 0x34].as_ref()),
+            Ok(IF000(LongAddress(0x1234)))
+        );
+        // The address is missing
+        itf_err!(
+            0xF0,
+            0x00,
+            InvalidInstruction {
+                opcode: 0xF000,
+                pc: 0
+            }
+        );
+    }
+
+    #[test]
+    fn encode_ok() {
+        assert_eq!(I00E0.encode(), [0x00, 0xE0]);
+        assert_eq!(I1NNN(Address(0xABC)).encode(), [0x1A, 0xBC]);
+        assert_eq!(I7XNN(Register(3), Value8(0x42)).encode(), [0x73, 0x42]);
+        assert_eq!(I8XYE(Register(1), Register(2)).encode(), [0x81, 0x2E]);
+        assert_eq!(
+            IDXYN(Register(1), Register(2), Value4(5)).encode(),
+            [0xD1, 0x25]
+        );
+        assert_eq!(IFX65(Register(0xF)).encode(), [0xFF, 0x65]);
+        assert_eq!(IF002.encode(), [0xF0, 0x02]);
+        assert_eq!(
+            IF000(LongAddress(0x1234)).encode(),
+            [0xF0, 0x00, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        // The instruction space is small enough to check every single word
+        for ins in 0..=u16::MAX {
+            let [high, low] = ins.to_be_bytes();
+            let bytes = [high, low, 0x12, 0x34];
+
+            if let Ok(decoded) = Instruction::try_from(bytes.as_ref()) {
+                let len = decoded.size() as usize;
+                assert_eq!(
+                    *decoded.encode(),
+                    bytes[..len],
+                    "{:04X} ({:?})",
+                    ins,
+                    decoded
+                );
+                assert_eq!(
+                    Instruction::try_from(decoded.encode().as_ref()),
+                    Ok(decoded)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn is_valid() {
+        for ins in 0..=u16::MAX {
+            let [high, low] = ins.to_be_bytes();
+            assert_eq!(
+                Instruction::is_valid(ins),
+                Instruction::try_from([high, low, 0, 0].as_ref()).is_ok(),
+                "{:04X}",
+                ins
+            );
+        }
+    }
+
+    #[test]
+    fn from_str_ok() {
+        assert_eq!("LD V3, 0x1F".parse(), Ok(I6XNN(Register(3), Value8(0x1F))));
+        assert_eq!("ld v3, 1f".parse(), Ok(I6XNN(Register(3), Value8(0x1F))));
+        assert_eq!("  CLS ".parse(), Ok(I00E0));
+        assert_eq!("JP V0, 0x300".parse(), Ok(IBNNN(Address(0x300))));
+        assert_eq!("LD F, VA".parse(), Ok(IFX29(Register(0xA))));
+        assert_eq!("LD [I], V5".parse(), Ok(IFX55(Register(5))));
+        assert_eq!("LD V5, [I]".parse(), Ok(IFX65(Register(5))));
+        assert_eq!("LD R, V7".parse(), Ok(IFX75(Register(7))));
+        assert_eq!("LD V7, R".parse(), Ok(IFX85(Register(7))));
+        assert_eq!("SHR V1".parse(), Ok(I8XY6(Register(1), Register(1))));
+        assert_eq!("SHR V1 {,V2}".parse(), Ok(I8XY6(Register(1), Register(2))));
+        assert_eq!(
+            "DRW V1, V2, F".parse(),
+            Ok(IDXYN(Register(1), Register(2), Value4(0xF)))
+        );
+        assert_eq!("SCD 4".parse(), Ok(I00CN(Value4(4))));
+        assert_eq!("LD I, 0x123".parse(), Ok(IANNN(Address(0x123))));
+        assert_eq!("LD I, long 0x1234".parse(), Ok(IF000(LongAddress(0x1234))));
+    }
+
+    #[test]
+    fn from_str_err() {
+        assert_eq!("".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("NOP".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("CLS V1".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("LD V3, 0x100".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("LD VG, 1".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("JP V1, 200".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("LD I, 0x1234".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!("ADD V1,, V2".parse::<Instruction>(), Err(InvalidSyntax));
+        assert_eq!(
+            "DRW V1, V2, 3, 4".parse::<Instruction>(),
+            Err(InvalidSyntax)
+        );
+    }
+
+    #[test]
+    fn display_from_str_round_trip() {
+        for ins in 0..=u16::MAX {
+            let [high, low] = ins.to_be_bytes();
+            if let Ok(decoded) = Instruction::try_from([high, low, 0x12, 0x34].as_ref()) {
+                assert_eq!(decoded.to_string().parse(), Ok(decoded));
+            }
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let ins = IDXYN(Register(1), Register(2), Value4(5));
+        assert_eq!(ins.mnemonic(), "DRW");
+        assert!(ins.operands().eq([
+            Operand::Register(Register(1)),
+            Operand::Register(Register(2)),
+            Operand::Nibble(Value4(5))
+        ]));
+        assert!(!ins.is_branch());
+        assert_eq!(ins.branch_target(), None);
+        assert_eq!(ins.cycle_cost(), 6);
+        assert_eq!(ins.vip_cycles(), 26 + 46 * 5);
+
+        let ins = I2NNN(Address(0x300));
+        assert_eq!(ins.mnemonic(), "CALL");
+        assert!(ins.operands().eq([Operand::Address(Address(0x300))]));
+        assert!(ins.is_branch());
+        assert_eq!(ins.branch_target(), Some(0x300));
+        assert_eq!(ins.cycle_cost(), 1);
+        assert_eq!(ins.vip_cycles(), 26);
+        assert_eq!(ins.size(), 2);
+
+        let ins = IF000(LongAddress(0x1234));
+        assert_eq!(ins.mnemonic(), "LD");
+        assert!(ins
+            .operands()
+            .eq([Operand::I, Operand::LongAddress(LongAddress(0x1234))]));
+        assert_eq!(ins.to_string(), "LD I, LONG 1234");
+        assert_eq!(ins.size(), 4);
+
+        assert!(I00E0.operands().next().is_none());
+        assert!(IFX65(Register(3))
+            .operands()
+            .eq([Operand::Register(Register(3)), Operand::IndirectI]));
+        assert!(I3XNN(Register(0), Value8(0)).is_branch());
+    }
+
+    #[test]
+    fn metadata_matches_display() {
+        for ins in 0..=u16::MAX {
+            let [high, low] = ins.to_be_bytes();
+            if let Ok(decoded) = Instruction::try_from([high, low, 0x12, 0x34].as_ref()) {
+                let operands: Vec<_> = decoded.operands().map(|op| op.to_string()).collect();
+                let text = match operands.is_empty() {
+                    true => decoded.mnemonic().to_string(),
+                    false => format!("{} {}", decoded.mnemonic(), operands.join(", ")),
+                };
+                assert!(decoded.to_string().starts_with(decoded.mnemonic()));
+                if let Some(target) = decoded.branch_target() {
+                    assert!(decoded.is_branch());
+                    assert_eq!(target, ins & 0xFFF);
+                }
+                assert_eq!(text.parse(), Ok(decoded));
+            }
+        }
+    }
+
+    #[test]
+    fn decode_iter_ok() {
+        let bytes = [
+            0x00, 0xE0, 0x01, 0xFF, 0xF0, 0x00, 0x12, 0x34, 0x12, 0x00, 0xEE,
+        ];
+        let mut iter = decode_iter(&bytes, 0x200);
+
+        assert_eq!(iter.size_hint(), (3, Some(6)));
+        assert_eq!(iter.next(), Some((0x200, Ok(I00E0))));
+        let err = InvalidInstruction {
+            opcode: 0x01FF,
+            pc: 0x202,
+        };
+        assert_eq!(iter.next(), Some((0x202, Err(err))));
+        assert_eq!(iter.next(), Some((0x204, Ok(IF000(LongAddress(0x1234))))));
+        assert_eq!(iter.next(), Some((0x208, Ok(I1NNN(Address(0x200))))));
+        assert_eq!(
+            iter.next(),
+            Some((0x20A, Err(InvalidAlignment { pc: 0x20A })))
+        );
+        assert_eq!(iter.next(), None);
+    }
+
+    proptest! {
+        #[test]
+        fn decoded_programs_encode_to_their_bytes(
+            bytes in proptest::collection::vec(any::<u8>(), 0..64)
+        ) {
+            let mut offset = 0;
+            for (addr, decoded) in decode_iter(&bytes, 0x200) {
+                prop_assert_eq!(addr as usize, 0x200 + offset);
+                let size = decoded.as_ref().map_or(2, Instruction::size) as usize;
+                if let Ok(ins) = decoded {
+                    prop_assert_eq!(&*ins.encode(), &bytes[offset..offset + size]);
+                }
+                offset += size;
+            }
+            prop_assert!(offset >= bytes.len());
+        }
     }
 
     #[test]
@@ -1,6 +1,6 @@
 #![forbid(unsafe_code)]
 #![warn(missing_docs, missing_debug_implementations, rust_2018_idioms)]
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 //! A CHIP-8 emulator written in rust
 //!
@@ -8,150 +8,1643 @@
 //! There is no `default` feature in this crate, stdlib support must be enabled manually.
 //!
 //! `std` : Enables stdlib support, by default the crate is compiled with `no_std`
+//!
+//! `alloc` : Enables [`DynChip8`], whose peripherals are boxed, without stdlib support.
+//! Implied by `std`.
+//!
+//! `arbitrary` : Implements [`arbitrary::Arbitrary`](https://docs.rs/arbitrary) for
+//! [`Instruction`](instructions::Instruction), [`Rom`](rom::Rom) and [`QuirksConfig`], for
+//! the fuzz targets in `fuzz/`. Implies `std`.
+//!
+//! `async` : Adds [`Chip8::run_async`], which awaits the frames of a
+//! [`FrameClock`](clock::FrameClock) instead of sleeping, for async executors like Embassy or
+//! tokio. Works without stdlib support.
+//!
+//! `defmt` : Implements [`defmt::Format`](https://docs.rs/defmt) for [`Error`],
+//! [`Instruction`](instructions::Instruction), [`Keys`](peripherals::Keys) and
+//! [`CoreState`](core::CoreState), to log them efficiently on embedded targets.
+//!
+//! `embedded-hal` : Adds peripherals driven by [`embedded-hal`](https://docs.rs/embedded-hal/0.2)
+//! pins and timers, [`CountDownTimer`](peripherals::hal::CountDownTimer) and
+//! [`MatrixKeypad`](peripherals::hal::MatrixKeypad).
+//!
+//! `tracing` : Enters a [`tracing`](https://docs.rs/tracing) span for every tick, with the
+//! `pc` and `opcode` of the executed instruction as fields, and for every frame of the
+//! timers. Works without stdlib support.
 
+#[cfg(any(feature = "alloc", test))]
+extern crate alloc;
+
+/// Pacing of the emulation by an async executor, requires the `async` feature
+#[cfg(feature = "async")]
+pub mod clock;
 /// The core CHIP-8 architecture
 pub mod core;
+/// Running a Chip8 on its own thread, requires the `std` feature
+#[cfg(feature = "std")]
+pub mod handle;
 /// The CHIP-8 instruction set
 pub mod instructions;
+/// Real time pacing of the emulation, requires the `std` feature
+#[cfg(feature = "std")]
+pub mod pacing;
 /// The CHIP-8 peripherals. This consists of traits and default implementations.
 pub mod peripherals;
+/// The commonly used types and traits of the crate, meant to be glob imported
+/// (`use chip8_core::prelude::*;`)
+pub mod prelude;
+/// Configurable behaviours of different CHIP-8 interpreters
+pub mod quirks;
+/// Checks of ROMs which work in constants, e.g. of the ROMs embedded with [`include_rom!`]
+pub mod rom;
+/// Saving and restoring the state of the machine, requires the `alloc` feature
+#[cfg(feature = "alloc")]
+pub mod snapshot;
+
+pub use crate::core::{Core, TickState};
+#[cfg(feature = "std")]
+pub use crate::handle::Chip8Handle;
+pub use crate::quirks::QuirksConfig;
+#[cfg(feature = "alloc")]
+pub use crate::snapshot::Snapshot;
+
+use crate::peripherals::{
+    FallingEdges, KeyEvent, Keypad, Keys, Peripherals, Persistence, RisingEdges, Speaker, Timer,
+};
+use ::core::ops::Range;
+use ::core::time::Duration;
 
-pub use crate::core::Core;
+/// Assert that a framebuffer, or a region of it, matches an [`AsciiDump`](peripherals::AsciiDump)
+///
+/// Leading and trailing whitespace of every line of the expected dump, as well as empty
+/// lines, are ignored, so the dump can be indented along with the test code.
+/// Requires `std` (or `alloc`) in the crate using the macro.
+///
+/// ```
+/// # use chip8_core::prelude::*;
+/// # use chip8_core::assert_display_eq;
+/// let mut fb = Framebuffer::new();
+/// fb.toggle_sprite(Pos(0, 0), Sprite(&[0b1010_0000]));
+///
+/// let region = Rect { x: 0, y: 0, width: 4, height: 2 };
+/// assert_display_eq!(fb, region, "
+///     #.#.
+///     ....
+/// ");
+/// ```
+#[macro_export]
+macro_rules! assert_display_eq {
+    ($framebuffer:expr, $expected:expr $(,)?) => {{
+        let framebuffer = &$framebuffer;
+        $crate::assert_display_eq!(framebuffer, framebuffer.bounds(), $expected)
+    }};
+    ($framebuffer:expr, $region:expr, $expected:expr $(,)?) => {{
+        let actual = format!("{}", $framebuffer.ascii_region($region));
+        let expected: Vec<&str> = $expected
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
 
-use crate::peripherals::{Graphics, Keypad, Random, Timer};
+        if actual.lines().ne(expected.iter().copied()) {
+            panic!(
+                "framebuffer differs\nexpected:\n{}\nactual:\n{}",
+                expected.join("\n"),
+                actual
+            );
+        }
+    }};
+}
+
+/// Embed a ROM like [`include_bytes!`], checking at compile time that it fits into memory
+///
+/// The ROM is loaded to `start` of `memory` bytes, by default to 0x200 of 4 KiB. With
+/// `decode`, every instruction reachable from the start has to decode as well, see
+/// [`rom::check_reachable`]. A ROM failing the checks doesn't compile:
+///
+/// ```text
+/// error[E0080]: evaluation panicked: the ROM reaches the invalid instruction 0x5121 at 0x204
+/// ```
+///
+/// The path is relative to the file using the macro, like the one of [`include_bytes!`].
+///
+/// ```ignore
+/// static PONG: &[u8] = chip8_core::include_rom!("../roms/pong.ch8", decode);
+/// static ETI: &[u8] = chip8_core::include_rom!("../roms/eti.ch8", start = 0x600, memory = 4096);
+/// ```
+#[macro_export]
+macro_rules! include_rom {
+    ($path:expr $(,)?) => {
+        $crate::include_rom!($path, start = $crate::QuirksConfig::START, memory = 4096)
+    };
+    ($path:expr, decode $(,)?) => {
+        $crate::include_rom!(
+            $path,
+            start = $crate::QuirksConfig::START,
+            memory = 4096,
+            decode
+        )
+    };
+    ($path:expr, start = $start:expr, memory = $memory:expr $(,)?) => {{
+        const ROM: &[u8] = include_bytes!($path);
+        const _: () = $crate::rom::assert_ok($crate::rom::check_size(ROM, $start, $memory));
+        ROM
+    }};
+    ($path:expr, start = $start:expr, memory = $memory:expr, decode $(,)?) => {{
+        const ROM: &[u8] = include_bytes!($path);
+        const _: () = $crate::rom::assert_ok($crate::rom::check_size(ROM, $start, $memory));
+        const _: () =
+            $crate::rom::assert_ok($crate::rom::check_reachable::<{ $memory }>(ROM, $start));
+        ROM
+    }};
+}
 
 /// Crate Error structure
+///
+/// Errors raised while executing a program carry the PC of the failing instruction.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// An invalid instruction was encountered
-    InvalidInstruction(u16),
-    /// The decoded instruction has invalid alignemnt
-    InvalidAlignment,
-    /// A stack overflow occured during execution
-    StackOverflow,
+    InvalidInstruction {
+        /// The raw instruction
+        opcode: u16,
+        /// The address of the instruction
+        pc: u16,
+    },
+    /// The decoded instruction has invalid alignemnt, or the PC is misaligned in strict mode
+    InvalidAlignment {
+        /// The address of the instruction, or of the jump to the misaligned address
+        pc: u16,
+    },
+    /// A stack overflow, or underflow by a return without a call, occured during execution
+    StackOverflow {
+        /// The raw instruction
+        opcode: u16,
+        /// The address of the instruction
+        pc: u16,
+        /// The depth the call tried to grow the stack to, 0 for a return without a call
+        depth: usize,
+    },
+    /// An instruction accessed memory beyond its end through the I register
+    MemoryOutOfBounds {
+        /// The raw instruction
+        opcode: u16,
+        /// The address of the instruction
+        pc: u16,
+        /// The value of the I register
+        i: u16,
+    },
+    /// An instruction wrote to the interpreter area below the start address, see
+    /// [`Core::set_write_protection`]
+    WriteProtected {
+        /// The raw instruction
+        opcode: u16,
+        /// The address of the instruction
+        pc: u16,
+        /// The value of the I register
+        i: u16,
+    },
+    /// The core frequency is outside of the supported range
+    InvalidCoreFrequency(u32),
+    /// An instruction could not be parsed from its textual form
+    InvalidSyntax,
+    /// A snapshot is malformed or doesn't fit the machine it is restored to
+    InvalidSnapshot,
+    /// A ROM of the given length doesn't fit into the memory after the start address
+    RomTooLarge(usize),
 }
 
-impl From<::core::array::TryFromSliceError> for Error {
-    fn from(_: ::core::array::TryFromSliceError) -> Self {
-        Self::InvalidAlignment
+impl ::core::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        match self {
+            Self::InvalidInstruction { opcode, pc } => {
+                write!(f, "Invalid instruction 0x{:04X} at 0x{:03X}", opcode, pc)
+            }
+            Self::InvalidAlignment { pc } => write!(f, "Invalid alignment at 0x{:03X}", pc),
+            Self::StackOverflow { opcode, pc, depth } => write!(
+                f,
+                "Stack overflow at 0x{:03X} (0x{:04X}, depth {})",
+                pc, opcode, depth
+            ),
+            Self::MemoryOutOfBounds { opcode, pc, i } => write!(
+                f,
+                "Memory access out of bounds at 0x{:03X} (0x{:04X}, I = 0x{:04X})",
+                pc, opcode, i
+            ),
+            Self::WriteProtected { opcode, pc, i } => write!(
+                f,
+                "Write to protected memory at 0x{:03X} (0x{:04X}, I = 0x{:04X})",
+                pc, opcode, i
+            ),
+            Self::InvalidCoreFrequency(freq) => write!(f, "Invalid core frequency: {} Hz", freq),
+            Self::InvalidSyntax => write!(f, "Invalid instruction syntax"),
+            Self::InvalidSnapshot => write!(f, "Invalid snapshot"),
+            Self::RomTooLarge(len) => write!(f, "ROM too large: {} bytes", len),
+        }
     }
 }
 
 #[cfg(feature = "std")]
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl std::error::Error for Error {}
+
+/// A request to change how a [`Chip8`] runs, e.g. sent by a frontend to the thread running it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Stop executing instructions and decrementing timers, see [`Chip8::pause`]
+    Pause,
+    /// Continue after [`Command::Pause`]
+    Resume,
+    /// Pause if running, resume if paused
+    TogglePause,
+    /// Reset the core and the timers, see [`Chip8::reset`]
+    Reset,
+    /// Change the core frequency, see [`Chip8::set_core_freq`]
+    SetCoreFreq(u32),
+}
+
+/// Counters of a [`Chip8`], see [`Chip8::stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of instructions executed, counting each tick waiting for a key with
+    /// `LD Vx, K` as one, but not the ticks after the program exited
+    pub instructions: u64,
+    /// The number of frames of 1 / [`Chip8::TIMER_FREQ`] seconds, i.e. of timer ticks
+    pub frames: u64,
+    /// The time the executed ticks take on the emulated machine
+    pub emulated_time: Duration,
+}
+
+impl Stats {
+    /// The counters accumulated since `earlier`
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            instructions: self.instructions.saturating_sub(earlier.instructions),
+            frames: self.frames.saturating_sub(earlier.frames),
+            emulated_time: self.emulated_time.saturating_sub(earlier.emulated_time),
+        }
+    }
+}
+
+/// The outcome of [`Chip8::run_until`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct RunSummary {
+    /// The counters accumulated during the run
+    pub stats: Stats,
+    /// Why the run ended
+    pub exit: RunExit,
+}
+
+/// Why [`Chip8::run`] or [`Chip8::run_until`] returned
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RunExit {
+    /// The program exited with the SCHIP `EXIT` instruction
+    Exited,
+    /// The program can't make progress anymore, see [`Chip8::set_stop_on_halt`]
+    Halted(HaltReason),
+    /// The PC reached the breakpoint at this address, see [`Chip8::set_breakpoints`]
+    Breakpoint(u16),
+    /// The program failed
+    Error(Error),
+    /// The caller stopped the run, e.g. once the cycles of [`Chip8::run_cycles`] were
+    /// executed
+    Stopped,
+}
+
+impl RunExit {
+    /// The error the program failed with as `Err`, any other exit as `Ok`, e.g. to
+    /// propagate errors with `?`
+    pub fn into_result(self) -> Result<Self, Error> {
         match self {
-            Self::InvalidInstruction(ins) => write!(f, "Invalid instruction: 0x{:02X}", ins),
-            Self::InvalidAlignment => write!(f, "Invalid alignment"),
-            Self::StackOverflow => write!(f, "Stack overflow"),
+            Self::Error(e) => Err(e),
+            exit => Ok(exit),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for Error {}
+/// Why a program can't make progress anymore, see [`RunExit::Halted`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HaltReason {
+    /// The instruction at the PC is a `JP` to itself, which many programs end with
+    InfiniteLoop,
+}
+
+/// Called by a [`Chip8`] whenever the program wrote to memory, see [`Chip8::set_write_hook`]
+///
+/// E.g. cheats keeping a value from changing, or tools watching the memory.
+pub trait WriteHook: ::core::fmt::Debug {
+    /// The instruction just executed wrote the bytes in `range`, which the hook may change
+    /// through `core`
+    fn written(&mut self, core: &mut Core<'_>, range: Range<usize>);
+}
+
+/// A [`Chip8`] with peripherals chosen at runtime, see [`DynPeripherals`](peripherals::DynPeripherals)
+#[cfg(feature = "alloc")]
+pub type DynChip8<'memory, 'p> = Chip8<'memory, peripherals::DynPeripherals<'p>>;
 
 /// A runnable CHIP-8 implementation. This includes a core + all necessary peripherals.
 #[derive(Debug)]
-pub struct Chip8<'memory, K, G, R, TD, TS> {
+pub struct Chip8<'memory, P> {
     core: Core<'memory>,
     core_freq: u32,
-    keypad: K,
-    graphics: G,
-    random: R,
-    timer_delay: TD,
-    timer_sound: TS,
-    timer_freq_div: u32,
-    timer_freq_count: u32,
+    peripherals: P,
+    speaker_active: bool,
+    timer_acc: u32,
+    frames: u64,
+    /// The fraction of a tick carried over to the next frame, in 1 / TIMER_FREQ ticks
+    frame_acc: u32,
+    /// The fraction of a tick carried over to the next [`Chip8::run_for`], in 1e-9 ticks
+    run_acc: u32,
+    paused: bool,
+    ticks: u64,
+    /// The ticks which executed an instruction, unlike the ones of an exited core
+    instructions: u64,
+    /// The cycles taken by the last tick
+    last_cycles: u32,
+    /// The key state built from the events of a queueing keypad
+    keys: Keys,
+    /// An event left for the next tick, as its key already changed during this one
+    deferred_event: Option<KeyEvent>,
+    write_hook: Option<&'memory mut dyn WriteHook>,
+    breakpoints: &'memory [u16],
+    /// The breakpoint the last run ended at, which the next run executes instead
+    resume_from: Option<u16>,
+    stop_on_halt: bool,
 }
 
 #[cfg(feature = "std")]
-impl<K, G, TD, TS, R> std::fmt::Display for Chip8<'_, K, G, TD, TS, R> {
+impl<P> std::fmt::Display for Chip8<'_, P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.core)
     }
 }
 
-impl<'memory, K, G, R, TD, TS> Chip8<'memory, K, G, R, TD, TS>
-where
-    K: Keypad,
-    G: Graphics,
-    TD: Timer,
-    TS: Timer,
-    R: Random,
-{
+impl<'memory, P: Peripherals> Chip8<'memory, P> {
+    /// The frequency at which the delay and sound timers are decremented
+    pub const TIMER_FREQ: u32 = 60;
+    /// The highest supported core frequency
+    pub const MAX_CORE_FREQ: u32 = 1_000_000;
+
     /// Generate a new Chip8
-    pub fn new(
-        core: Core<'memory>,
-        core_freq: u32,
-        keypad: K,
-        graphics: G,
-        random: R,
-        timer_delay: TD,
-        timer_sound: TS,
-    ) -> Self {
-        Self {
+    ///
+    /// The core frequency must be between 1 Hz and [`Self::MAX_CORE_FREQ`], otherwise
+    /// [`Error::InvalidCoreFrequency`] is returned. Frequencies below [`Self::TIMER_FREQ`]
+    /// are supported, the timers are then decremented multiple times per tick.
+    ///
+    /// The core starts with the RPL user flags stored by the persistence peripheral.
+    pub fn new(mut core: Core<'memory>, core_freq: u32, mut peripherals: P) -> Result<Self, Error> {
+        if core_freq == 0 || core_freq > Self::MAX_CORE_FREQ {
+            return Err(Error::InvalidCoreFrequency(core_freq));
+        }
+
+        core.set_flags(&peripherals.split().persistence.load());
+
+        Ok(Self {
             core,
             core_freq,
-            keypad,
-            graphics,
-            random,
-            timer_delay,
-            timer_sound,
-            timer_freq_div: core_freq / 60,
-            timer_freq_count: 0,
+            peripherals,
+            speaker_active: false,
+            timer_acc: 0,
+            frames: 0,
+            frame_acc: 0,
+            run_acc: 0,
+            paused: false,
+            ticks: 0,
+            instructions: 0,
+            last_cycles: 0,
+            keys: Keys(0),
+            deferred_event: None,
+            write_hook: None,
+            breakpoints: &[],
+            resume_from: None,
+            stop_on_halt: false,
+        })
+    }
+
+    /// The core of the Chip8
+    pub fn core(&self) -> &Core<'memory> {
+        &self.core
+    }
+
+    /// The core of the Chip8, e.g. to load a program
+    pub fn core_mut(&mut self) -> &mut Core<'memory> {
+        &mut self.core
+    }
+
+    /// The peripherals of the Chip8
+    pub fn peripherals(&self) -> &P {
+        &self.peripherals
+    }
+
+    /// The peripherals of the Chip8, e.g. to feed input to a keypad
+    pub fn peripherals_mut(&mut self) -> &mut P {
+        &mut self.peripherals
+    }
+
+    /// Call `hook` after every instruction writing to memory, replacing the previous hook
+    pub fn set_write_hook(&mut self, hook: &'memory mut dyn WriteHook) {
+        self.write_hook = Some(hook);
+    }
+
+    /// End runs with [`RunExit::Breakpoint`] before executing the instruction at any of
+    /// `breakpoints`, replacing the previous ones
+    ///
+    /// Running again after [`RunExit::Breakpoint`] executes the instruction at the
+    /// breakpoint, so the program continues past it.
+    pub fn set_breakpoints(&mut self, breakpoints: &'memory [u16]) {
+        self.breakpoints = breakpoints;
+    }
+
+    /// End runs with [`RunExit::Halted`] once the program can't make progress anymore, e.g.
+    /// as it jumps to itself
+    ///
+    /// Off by default, as frontends keep running halted programs, whose timers still count
+    /// down.
+    pub fn set_stop_on_halt(&mut self, stop: bool) {
+        self.stop_on_halt = stop;
+    }
+
+    /// Why the program can't make progress anymore, if it can't
+    fn halt_reason(&self) -> Option<HaltReason> {
+        let (pc, opcode) = (self.core.pc(), self.core.opcode());
+        let in_memory = (pc as usize) < self.core.memory().len();
+        let jumps_to_itself = opcode >> 12 == 0x1 && opcode & 0x0FFF == pc;
+        (in_memory && jumps_to_itself).then_some(HaltReason::InfiniteLoop)
+    }
+
+    /// The frequency instructions are executed at, in Hz
+    pub fn core_freq(&self) -> u32 {
+        self.core_freq
+    }
+
+    /// Change the frequency instructions are executed at, e.g. while a game is running
+    ///
+    /// The same limits as in [`Chip8::new`] apply. The timers keep their phase, the time
+    /// since their last tick carries over to the new frequency.
+    pub fn set_core_freq(&mut self, core_freq: u32) -> Result<(), Error> {
+        if core_freq == 0 || core_freq > Self::MAX_CORE_FREQ {
+            return Err(Error::InvalidCoreFrequency(core_freq));
+        }
+
+        // timer_acc counts in units of 1 / (core_freq * TIMER_FREQ) seconds
+        self.timer_acc = (self.timer_acc as u64 * core_freq as u64 / self.core_freq as u64) as u32;
+        self.core_freq = core_freq;
+
+        Ok(())
+    }
+
+    /// Whether the Chip8 is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop executing instructions and decrementing timers
+    ///
+    /// [`Chip8::tick`] does nothing while paused and the speaker is silenced.
+    pub fn pause(&mut self) {
+        if !self.paused && self.speaker_active {
+            self.peripherals.split().speaker.stop();
+        }
+        self.paused = true;
+    }
+
+    /// Continue after [`Chip8::pause`]
+    pub fn resume(&mut self) {
+        if self.paused && self.speaker_active {
+            self.peripherals.split().speaker.start();
+        }
+        self.paused = false;
+    }
+
+    /// Reset the core and clear the timers, see [`Core::reset`]
+    ///
+    /// Whether the Chip8 is paused doesn't change.
+    pub fn reset(&mut self) {
+        self.core.reset();
+        let peripherals = self.peripherals.split();
+        peripherals.delay_timer.set(0);
+        peripherals.sound_timer.set(0);
+        self.timer_acc = 0;
+
+        if self.speaker_active && !self.paused {
+            peripherals.speaker.stop();
+        }
+        self.speaker_active = false;
+    }
+
+    /// Execute a [`Command`]
+    pub fn handle(&mut self, command: Command) -> Result<(), Error> {
+        match command {
+            Command::Pause => self.pause(),
+            Command::Resume => self.resume(),
+            Command::TogglePause if self.paused => self.resume(),
+            Command::TogglePause => self.pause(),
+            Command::Reset => self.reset(),
+            Command::SetCoreFreq(core_freq) => self.set_core_freq(core_freq)?,
+        }
+
+        Ok(())
+    }
+
+    /// The number of ticks executed so far, not counting ticks while paused
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// The cycles the last tick took, 0 if the Chip8 was paused
+    ///
+    /// Every instruction takes one cycle, unless [`QuirksConfig::timing`] says otherwise.
+    pub fn last_cycles(&self) -> u32 {
+        self.last_cycles
+    }
+
+    /// The instructions, frames and emulated time executed so far, not counting pauses
+    ///
+    /// The counters keep running across resets.
+    pub fn stats(&self) -> Stats {
+        // timer_acc counts in units of 1 / (core_freq * TIMER_FREQ) seconds
+        let timer_freq = Self::TIMER_FREQ as u64;
+        let nanos = self.frames * 1_000_000_000 / timer_freq
+            + self.timer_acc as u64 * 1_000_000_000 / (self.core_freq as u64 * timer_freq);
+
+        Stats {
+            instructions: self.instructions,
+            frames: self.frames,
+            emulated_time: Duration::from_nanos(nanos),
         }
     }
 
-    /// Run the Chip8
+    /// The current value of the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.peripherals.delay_timer().get()
+    }
+
+    /// The current value of the sound timer
+    pub fn sound_timer(&self) -> u8 {
+        self.peripherals.sound_timer().get()
+    }
+
+    /// The time a tick takes at the current core frequency
+    #[cfg(feature = "std")]
+    pub fn cycle_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(1_000_000_000 / self.core_freq as u64)
+    }
+
+    /// The number of cycles to execute in the next frame of 1 / [`Self::TIMER_FREQ`] seconds,
+    /// e.g. with [`Chip8::run_cycles`]
+    ///
+    /// The core frequency rarely is a multiple of the frame rate, the fraction of a cycle
+    /// left over is carried over to the next frames. This way exactly `core_freq` cycles are
+    /// executed per second, e.g. 700 Hz alternates between frames of 11 and 12 cycles. With
+    /// the default [`QuirksConfig::timing`] a cycle is a tick.
+    pub fn frame_cycles(&mut self) -> u32 {
+        self.frame_acc += self.core_freq;
+        let cycles = self.frame_acc / Self::TIMER_FREQ;
+        self.frame_acc %= Self::TIMER_FREQ;
+
+        cycles
+    }
+
+    /// Run the Chip8 until the program exits, halts, reaches a breakpoint or fails
+    ///
+    /// A frame's worth of ticks is executed at once, followed by a sleep until the end of
+    /// the frame, see [`pacing::FramePacer`]. Never returns [`RunExit::Stopped`].
     ///
     /// Only available with the "std" feature, as [`std::thread::sleep`] is required.
     #[cfg(feature = "std")]
-    pub fn run(&mut self) -> Result<(), Error> {
-        use std::thread::sleep;
-        use std::time::{Duration, Instant};
+    pub fn run(&mut self) -> RunExit {
+        let mut pacer = pacing::FramePacer::new(Self::TIMER_FREQ);
 
-        let cycle_duration = Duration::from_micros(1_000_000 / self.core_freq as u64);
+        loop {
+            let cycles = self.frame_cycles();
+            match self.run_cycles(cycles as u64).exit {
+                RunExit::Stopped => pacer.wait(),
+                exit => return exit,
+            }
+        }
+    }
 
+    /// Run the Chip8 on an async executor
+    ///
+    /// Like [`Chip8::run`] a frame's worth of ticks is executed at once, followed by waiting
+    /// for the next frame of `clock`. While the program waits for a key with `LD Vx, K`, the
+    /// remaining ticks of the frame only advance the timers, so waiting for input takes no
+    /// time. Returns once the program exits, fails or, with [`Chip8::set_stop_on_halt`],
+    /// halts. Breakpoints are ignored, so it never returns [`RunExit::Breakpoint`] or
+    /// [`RunExit::Stopped`].
+    #[cfg(feature = "async")]
+    pub async fn run_async<C: clock::FrameClock>(&mut self, clock: &mut C) -> RunExit {
         loop {
-            let before_tick = Instant::now();
-            self.tick()?;
+            let mut cycles = self.frame_cycles();
+            while cycles > 0 {
+                if self.core.exited() {
+                    return RunExit::Exited;
+                }
+                if let Some(reason) = self.halt_reason().filter(|_| self.stop_on_halt) {
+                    return RunExit::Halted(reason);
+                }
 
-            if let Some(remaining) = cycle_duration.checked_sub(before_tick.elapsed()) {
-                sleep(remaining);
+                let state = match self.tick() {
+                    Ok(state) => state,
+                    Err(e) => return RunExit::Error(e),
+                };
+                let spent = self.last_cycles.max(1);
+                cycles = cycles.saturating_sub(spent);
+
+                if state == TickState::WaitingForKey {
+                    // The ticks left repeat the wait, which takes as long as this one
+                    let repeated = (cycles / spent) as u64;
+                    self.ticks += repeated;
+                    self.instructions += repeated;
+                    self.advance_timers(cycles);
+                    break;
+                }
             }
+            clock.next_frame().await;
         }
     }
 
-    /// Execute a single tick of the Chip8
-    pub fn tick(&mut self) -> Result<(), Error> {
-        self.tick_core()?;
+    /// Run as fast as possible until `stop` returns true, the program exits, halts, reaches
+    /// a breakpoint or fails
+    ///
+    /// `stop` is called before every tick, e.g. to limit the number of executed
+    /// instructions with [`Chip8::stats`]. A paused Chip8 only stops once `stop` says so.
+    pub fn run_until<F: FnMut(&Self) -> bool>(&mut self, mut stop: F) -> RunSummary {
+        let start = self.stats();
 
-        self.timer_freq_count += 1;
-        if self.timer_freq_count >= self.timer_freq_div {
-            self.timer_freq_count = 0;
-            self.tick_timers();
+        let exit = loop {
+            let pc = self.core.pc();
+            if self.core.exited() {
+                break RunExit::Exited;
+            }
+            // Checked before `stop`, so a breakpoint reached by the last tick of a run isn't
+            // stepped over by the next one
+            if self.resume_from != Some(pc) && self.breakpoints.contains(&pc) {
+                self.resume_from = Some(pc);
+                break RunExit::Breakpoint(pc);
+            }
+            if stop(self) {
+                break RunExit::Stopped;
+            }
+            if let Some(reason) = self.halt_reason().filter(|_| self.stop_on_halt) {
+                break RunExit::Halted(reason);
+            }
+            if let Err(e) = self.tick() {
+                break RunExit::Error(e);
+            }
+        };
+
+        RunSummary {
+            stats: self.stats().since(&start),
+            exit,
         }
+    }
 
-        Ok(())
+    /// Execute up to `cycles` cycles and return, unless the program exits or fails first
+    ///
+    /// Meant for hosts with their own event loop, which e.g. call it once per frame with
+    /// [`Chip8::frame_cycles`]. Ticks of a paused Chip8 take a cycle, but don't do anything.
+    /// An instruction taking more cycles than are left is still executed.
+    pub fn run_cycles(&mut self, cycles: u64) -> RunSummary {
+        let mut remaining = cycles;
+        let mut started = false;
+        self.run_until(|chip8| {
+            if started {
+                remaining = remaining.saturating_sub(chip8.last_cycles.max(1) as u64);
+            }
+            started = true;
+            remaining == 0
+        })
     }
 
-    fn tick_core(&mut self) -> Result<(), Error> {
-        let keys = self.keypad.pressed_keys();
-        let edges = self.keypad.last_released_key();
+    /// Execute the cycles which fit into `duration` at the core frequency and return, see
+    /// [`Chip8::run_cycles`]
+    ///
+    /// The fraction of a cycle which doesn't fit is carried over to the next call, so
+    /// calling it with the time elapsed since the last call keeps the core frequency, e.g.
+    /// with the frame time of a GUI or the timestamp of `requestAnimationFrame`.
+    pub fn run_for(&mut self, duration: Duration) -> RunSummary {
+        let nanos = duration.as_nanos() * self.core_freq as u128 + self.run_acc as u128;
+        self.run_acc = (nanos % 1_000_000_000) as u32;
 
+        self.run_cycles((nanos / 1_000_000_000) as u64)
+    }
+
+    /// Execute a single tick of the Chip8, unless it is paused
+    ///
+    /// Returns [`TickState::WaitingForKey`] while the program waits for a key, so hosts can
+    /// throttle the idle machine, and [`TickState::Idle`] if the Chip8 is paused.
+    pub fn tick(&mut self) -> Result<TickState, Error> {
+        if self.paused {
+            self.last_cycles = 0;
+            return Ok(TickState::Idle);
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "tick",
+            tick = self.ticks,
+            pc = self.core.pc(),
+            opcode = self.core.opcode(),
+        )
+        .entered();
+
+        let state = self.tick_core()?;
+        if state == TickState::Executed {
+            self.resume_from = None;
+        }
+        if state != TickState::Idle {
+            self.instructions += 1;
+        }
+        self.ticks += 1;
+        // An exited core idles for a cycle
+        self.last_cycles = self.core.take_cycles().max(1);
+
+        if let (Some(range), Some(hook)) = (self.core.take_written(), &mut self.write_hook) {
+            hook.written(&mut self.core, range);
+        }
+
+        let peripherals = self.peripherals.split();
+        if self.core.take_audio_changed() {
+            peripherals
+                .speaker
+                .set_pattern(self.core.audio_pattern(), self.core.pitch());
+        }
+        if self.core.take_flags_changed() {
+            peripherals.persistence.store(self.core.flags());
+        }
+        self.advance_timers(self.last_cycles);
+
+        Ok(state)
+    }
+
+    /// Advance the timers by the time of `cycles` cycles
+    fn advance_timers(&mut self, cycles: u32) {
+        // Accumulate the elapsed time in units of 1 / (core_freq * TIMER_FREQ) seconds,
+        // so that timers stay accurate even if core_freq isn't a multiple of TIMER_FREQ
+        self.timer_acc += Self::TIMER_FREQ * cycles;
+        while self.timer_acc >= self.core_freq {
+            self.timer_acc -= self.core_freq;
+            self.tick_timers();
+        }
+    }
+
+    fn tick_core(&mut self) -> Result<TickState, Error> {
+        let (keys, released, pressed) = if self.peripherals.split().keypad.queues_events() {
+            self.apply_key_events()
+        } else {
+            let keypad = self.peripherals.split().keypad;
+            (
+                keypad.pressed_keys(),
+                keypad.last_released_key(),
+                keypad.last_pressed_key(),
+            )
+        };
+
+        let peripherals = self.peripherals.split();
         self.core.tick(
             keys,
-            edges,
-            &mut self.graphics,
-            &mut self.random,
-            &mut self.timer_delay,
-            &mut self.timer_sound,
+            released,
+            pressed,
+            peripherals.graphics,
+            peripherals.random,
+            peripherals.delay_timer,
+            peripherals.sound_timer,
+        )
+    }
+
+    /// Apply the queued key events to the key state
+    ///
+    /// A key changes at most once per tick, later events of the key are deferred to the next
+    /// tick. This way a key pressed and released between two ticks is still held for a tick.
+    fn apply_key_events(&mut self) -> (Keys, FallingEdges, RisingEdges) {
+        let before = self.keys.clone();
+        let mut changed = 0;
+
+        while let Some(event) = self
+            .deferred_event
+            .take()
+            .or_else(|| self.peripherals.split().keypad.poll_event(self.ticks))
+        {
+            let bit = 1 << (event.key() & 0xF);
+            if changed & bit != 0 {
+                self.deferred_event = Some(event);
+                break;
+            }
+            changed |= bit;
+
+            match event {
+                KeyEvent::Down(_) => self.keys.0 |= bit,
+                KeyEvent::Up(_) => self.keys.0 &= !bit,
+            }
+        }
+
+        (
+            self.keys.clone(),
+            before.falling_edges(&self.keys),
+            before.rising_edges(&self.keys),
         )
     }
 
+    /// Save the current state, e.g. to step back in a debugger with [`Chip8::restore`]
+    #[cfg(feature = "alloc")]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            core: self.core.snapshot(),
+            delay_timer: self.delay_timer(),
+            sound_timer: self.sound_timer(),
+            timer_acc: self.timer_acc,
+            frame_acc: self.frame_acc,
+            ticks: self.ticks,
+            instructions: self.instructions,
+            frames: self.frames,
+        }
+    }
+
+    /// Return to the state saved by [`Chip8::snapshot`], see [`Core::restore`]
+    ///
+    /// The speaker is started or stopped to match the restored sound timer. The timers keep
+    /// their phase only if the snapshot was taken at the same core frequency.
+    #[cfg(feature = "alloc")]
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.core.restore(&snapshot.core)?;
+        self.timer_acc = snapshot.timer_acc;
+        self.frame_acc = snapshot.frame_acc;
+        self.ticks = snapshot.ticks;
+        self.instructions = snapshot.instructions;
+        self.frames = snapshot.frames;
+
+        let peripherals = self.peripherals.split();
+        peripherals.delay_timer.set(snapshot.delay_timer);
+        peripherals.sound_timer.set(snapshot.sound_timer);
+
+        // A paused Chip8 starts the speaker once it is resumed
+        let sound = snapshot.sound_timer != 0;
+        if sound != self.speaker_active {
+            self.speaker_active = sound;
+            match (sound, self.paused) {
+                (_, true) => (),
+                (true, false) => peripherals.speaker.start(),
+                (false, false) => peripherals.speaker.stop(),
+            }
+        }
+
+        Ok(())
+    }
+
     fn tick_timers(&mut self) {
-        self.timer_delay.tick();
-        self.timer_sound.tick();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("frame", tick = self.ticks).entered();
+
+        self.frames += 1;
+        let peripherals = self.peripherals.split();
+        peripherals.delay_timer.tick();
+        peripherals.sound_timer.tick();
+
+        let sound = peripherals.sound_timer.get() != 0;
+        if sound != self.speaker_active {
+            self.speaker_active = sound;
+
+            if sound {
+                peripherals.speaker.start();
+            } else {
+                peripherals.speaker.stop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peripherals::testing::{ScriptedKeypad, SequenceRandom};
+    use crate::peripherals::{
+        DefaultPeripherals, DownTimer, NullGraphics, PeripheralSet, RamPersistence,
+    };
+    use crate::quirks::Timing;
+
+    /// A speaker counting its starts and stops
+    #[derive(Debug, Default)]
+    struct CountingSpeaker {
+        starts: u32,
+        stops: u32,
+        pattern: Option<([u8; 16], u8)>,
+    }
+
+    impl Speaker for CountingSpeaker {
+        fn start(&mut self) {
+            self.starts += 1;
+        }
+
+        fn stop(&mut self) {
+            self.stops += 1;
+        }
+
+        fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+            self.pattern = Some((*pattern, pitch));
+        }
+    }
+
+    /// A down-counting timer counting its ticks
+    #[derive(Debug)]
+    struct CountingTimer {
+        timer: DownTimer<'static>,
+        ticks: u32,
+    }
+
+    impl Default for CountingTimer {
+        fn default() -> Self {
+            Self {
+                timer: DownTimer::new("counting"),
+                ticks: 0,
+            }
+        }
+    }
+
+    impl Timer for CountingTimer {
+        fn tick(&mut self) -> bool {
+            self.ticks += 1;
+            self.timer.tick()
+        }
+
+        fn get(&self) -> u8 {
+            self.timer.get()
+        }
+
+        fn set(&mut self, val: u8) {
+            self.timer.set(val);
+        }
+    }
+
+    /// The peripherals of the tests, inspected through [`Chip8::peripherals`]
+    type Peripherals = PeripheralSet<
+        ScriptedKeypad,
+        NullGraphics,
+        SequenceRandom,
+        CountingTimer,
+        CountingTimer,
+        CountingSpeaker,
+        RamPersistence,
+    >;
+
+    fn peripherals() -> Peripherals {
+        PeripheralSet {
+            keypad: ScriptedKeypad::new(),
+            graphics: NullGraphics,
+            random: SequenceRandom::new([0]),
+            delay_timer: CountingTimer::default(),
+            sound_timer: CountingTimer::default(),
+            speaker: CountingSpeaker::default(),
+            persistence: RamPersistence::default(),
+        }
+    }
+
+    #[test]
+    fn speaker_follows_sound_timer() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 2; LD ST, V0; JP 0x204
+        mem[0x200..0x206].copy_from_slice(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]);
+
+        let mut chip8 =
+            Chip8::new(Core::new(&mut mem, &mut reg, &mut stack), 60, peripherals()).unwrap();
+
+        for _ in 0..5 {
+            chip8.tick().unwrap();
+        }
+
+        let speaker = &chip8.peripherals().speaker;
+        assert_eq!(speaker.starts, 1);
+        assert_eq!(speaker.stops, 1);
+    }
+
+    #[test]
+    fn speaker_audio_pattern() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD I, 0x300; LD AUDIO, [I]; LD V1, 0x70; LD PITCH, V1
+        mem[0x200..0x208].copy_from_slice(&[0xA3, 0x00, 0xF0, 0x02, 0x61, 0x70, 0xF1, 0x3A]);
+        for (i, byte) in mem[0x300..0x310].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let mut chip8 =
+            Chip8::new(Core::new(&mut mem, &mut reg, &mut stack), 60, peripherals()).unwrap();
+
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+
+        let pattern = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        assert_eq!(chip8.peripherals().speaker.pattern, Some((pattern, 0x70)));
+    }
+
+    #[test]
+    fn commands() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 10; LD ST, V0; JP 0x204
+        mem[0x200..0x206].copy_from_slice(&[0x60, 0x0A, 0xF0, 0x18, 0x12, 0x04]);
+
+        let mut chip8 =
+            Chip8::new(Core::new(&mut mem, &mut reg, &mut stack), 60, peripherals()).unwrap();
+
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.sound_timer(), 9);
+
+        chip8.handle(Command::TogglePause).unwrap();
+        assert!(chip8.is_paused());
+        chip8.tick().unwrap();
+        assert_eq!(chip8.sound_timer(), 9);
+        assert_eq!(chip8.core().pc(), 0x204);
+
+        chip8.handle(Command::Resume).unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.sound_timer(), 8);
+
+        chip8.handle(Command::Reset).unwrap();
+        assert_eq!(chip8.sound_timer(), 0);
+        assert_eq!(chip8.core().pc(), 0x200);
+
+        assert_eq!(
+            chip8.handle(Command::SetCoreFreq(0)),
+            Err(Error::InvalidCoreFrequency(0))
+        );
+        chip8.handle(Command::SetCoreFreq(700)).unwrap();
+        assert_eq!(chip8.core_freq(), 700);
+
+        // Started by LD ST, stopped by the pause, restarted and stopped by the reset
+        let speaker = &chip8.peripherals().speaker;
+        assert_eq!(speaker.starts, 2);
+        assert_eq!(speaker.stops, 2);
+    }
+
+    #[test]
+    fn key_events() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 7; SKP V0; JP 0x204; LD V1, K; JP 0x208
+        mem[0x200..0x20A]
+            .copy_from_slice(&[0x60, 0x07, 0xE0, 0x9E, 0x12, 0x04, 0xF1, 0x0A, 0x12, 0x08]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            PeripheralSet {
+                keypad: ScriptedKeypad::new().tap(1, 7, 0),
+                ..peripherals()
+            },
+        )
+        .unwrap();
+
+        chip8.tick().unwrap();
+
+        // A tap between two ticks holds the key for one tick, so SKP sees it
+        chip8.tick().unwrap();
+        assert_eq!(chip8.core().pc(), 0x206);
+
+        // The release follows on the next tick, ending LD V1, K
+        chip8.tick().unwrap();
+        assert_eq!(chip8.core().pc(), 0x208);
+        assert_eq!(chip8.core().registers()[1], 7);
+        assert_eq!(chip8.ticks(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn run_async() {
+        use ::core::future::{ready, Future};
+        use ::core::task::{Context, Poll, Waker};
+
+        /// Counts the frames, which start right away
+        struct Frames(u32);
+
+        impl clock::FrameClock for Frames {
+            fn next_frame(&mut self) -> impl Future<Output = ()> {
+                self.0 += 1;
+                ready(())
+            }
+        }
+
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, K; EXIT
+        mem[0x200..0x204].copy_from_slice(&[0xF0, 0x0A, 0x00, 0xFD]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            PeripheralSet {
+                keypad: ScriptedKeypad::new().tap(30, 0x5, 3),
+                ..peripherals()
+            },
+        )
+        .unwrap();
+
+        let mut frames = Frames(0);
+        let exit = {
+            let mut run = ::core::pin::pin!(chip8.run_async(&mut frames));
+            let mut cx = Context::from_waker(Waker::noop());
+            loop {
+                if let Poll::Ready(exit) = run.as_mut().poll(&mut cx) {
+                    break exit;
+                }
+            }
+        };
+        assert_eq!(exit, RunExit::Exited);
+
+        // Waiting for the key skips the rest of each frame, so the press at tick 30 is seen
+        // at the start of the fourth frame and the release at the start of the fifth, which
+        // returns right after EXIT instead of waiting for the next frame
+        assert_eq!(frames.0, 4);
+        assert!(chip8.core().exited());
+        assert_eq!(chip8.core().registers()[0], 0x5);
+    }
+
+    #[test]
+    fn run_for() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // JP 0x200
+        mem[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+
+        assert_eq!(chip8.run_cycles(5).stats.instructions, 5);
+
+        // 700 Hz are 11.67 ticks per frame, the fractions add up over three frames
+        let frame = Duration::from_nanos(1_000_000_000 / 60);
+        let ticks: Vec<_> = (0..3)
+            .map(|_| chip8.run_for(frame).stats.instructions)
+            .collect();
+        assert_eq!(ticks, [11, 12, 11]);
+        assert_eq!(chip8.ticks(), 5 + 34);
+    }
+
+    #[test]
+    fn persistence() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD R, V1; LD V2, R; JP 0x204
+        mem[0x200..0x206].copy_from_slice(&[0xF1, 0x75, 0xF2, 0x85, 0x12, 0x04]);
+
+        reg[1] = 0x42;
+        let core = Core::new(&mut mem, &mut reg, &mut stack);
+
+        let flags = RamPersistence([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        let mut chip8 = Chip8::new(
+            core,
+            700,
+            PeripheralSet {
+                persistence: flags,
+                ..peripherals()
+            },
+        )
+        .unwrap();
+        assert_eq!(chip8.core().flags()[2], 3);
+
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.core().registers()[..3], [0, 0x42, 3]);
+
+        // Resets keep the flags
+        chip8.reset();
+        assert_eq!(chip8.core().flags()[1], 0x42);
+
+        assert_eq!(chip8.peripherals().persistence.0[..3], [0, 0x42, 3]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn dyn_peripherals() {
+        use crate::peripherals::DefaultPeripherals;
+
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 2; LD ST, V0; RND V1, 0xFF; JP 0x206
+        mem[0x200..0x208].copy_from_slice(&[0x60, 0x02, 0xF0, 0x18, 0xC1, 0xFF, 0x12, 0x06]);
+
+        let mut chip8: DynChip8<'_, '_> = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            120,
+            DefaultPeripherals::default().into(),
+        )
+        .unwrap();
+
+        // Peripherals can be swapped while running
+        chip8.peripherals_mut().random = alloc::boxed::Box::new(|| 0x42);
+
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.sound_timer(), 1);
+        assert_eq!(chip8.core().registers()[1], 0x42);
+    }
+
+    /// Count the timer ticks happening during `ticks` ticks at `core_freq`
+    fn timer_ticks(core_freq: u32, ticks: u32) -> Result<u32, Error> {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // JP 0x200
+        mem[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            core_freq,
+            peripherals(),
+        )?;
+
+        for _ in 0..ticks {
+            chip8.tick()?;
+        }
+
+        Ok(chip8.peripherals().delay_timer.ticks)
+    }
+
+    #[test]
+    fn set_core_freq() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // JP 0x200
+        mem[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            peripherals(),
+        )
+        .unwrap();
+
+        // Half a second at 700 Hz, then half a second at 1400 Hz
+        for _ in 0..350 {
+            chip8.tick().unwrap();
+        }
+        chip8.set_core_freq(1400).unwrap();
+        for _ in 0..700 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.peripherals().delay_timer.ticks, 60);
+
+        // Halfway to the next timer tick at 60 Hz, the rest takes 12 ticks at 1440 Hz
+        chip8.set_core_freq(120).unwrap();
+        chip8.tick().unwrap();
+        chip8.set_core_freq(1440).unwrap();
+        for _ in 0..11 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.peripherals().delay_timer.ticks, 60);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.peripherals().delay_timer.ticks, 61);
+
+        assert_eq!(chip8.set_core_freq(0), Err(Error::InvalidCoreFrequency(0)));
+        assert_eq!(chip8.core_freq(), 1440);
+    }
+
+    #[test]
+    fn invalid_core_freq() {
+        assert_eq!(timer_ticks(0, 1), Err(Error::InvalidCoreFrequency(0)));
+        assert_eq!(
+            timer_ticks(2_000_000, 1),
+            Err(Error::InvalidCoreFrequency(2_000_000))
+        );
+    }
+
+    #[test]
+    fn timer_freq() {
+        assert_eq!(timer_ticks(60, 10), Ok(10));
+        assert_eq!(timer_ticks(600, 600), Ok(60));
+        // 700 isn't a multiple of 60, timers must not drift
+        assert_eq!(timer_ticks(700, 700), Ok(60));
+        assert_eq!(timer_ticks(700, 12), Ok(1));
+        // Below 60 Hz, timers tick more than once per instruction
+        assert_eq!(timer_ticks(30, 5), Ok(10));
+        assert_eq!(timer_ticks(1, 1), Ok(60));
+    }
+
+    #[test]
+    fn frame_cycles() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+
+        // 700 Hz doesn't divide into 60 frames, the remainders must add up to a second
+        let frames: Vec<_> = (0..60).map(|_| chip8.frame_cycles()).collect();
+        assert_eq!(frames.iter().sum::<u32>(), 700);
+        assert!(frames.iter().all(|&cycles| cycles == 11 || cycles == 12));
+
+        // Below 60 Hz, most frames don't execute anything
+        chip8.set_core_freq(30).unwrap();
+        let frames: Vec<_> = (0..4).map(|_| chip8.frame_cycles()).collect();
+        assert_eq!(frames, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn run_until() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 0x05; JP 0x202
+        mem[0x200..0x204].copy_from_slice(&[0x60, 0x05, 0x12, 0x02]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+
+        let summary = chip8.run_until(|chip8| chip8.stats().instructions == 1050);
+        assert_eq!(summary.exit, RunExit::Stopped);
+        assert_eq!(summary.stats.instructions, 1050);
+        assert_eq!(summary.stats.frames, 90);
+        assert_eq!(summary.stats.emulated_time, Duration::from_millis(1500));
+
+        // The summary only counts the run, the stats everything so far
+        let summary = chip8.run_until(|chip8| chip8.stats().instructions == 1400);
+        assert_eq!(summary.stats.instructions, 350);
+        assert_eq!(summary.stats.emulated_time, Duration::from_millis(500));
+        assert_eq!(chip8.stats().frames, 120);
+
+        // EXIT ends the run, a jump to an odd address fails it
+        chip8.core_mut().memory_mut()[0x202..0x204].copy_from_slice(&[0x00, 0xFD]);
+        let summary = chip8.run_until(|_| false);
+        assert_eq!(summary.exit, RunExit::Exited);
+        assert_eq!(summary.stats.instructions, 1);
+
+        // Ticks of the exited core don't execute anything
+        chip8.tick().unwrap();
+        assert_eq!(chip8.stats().instructions, 1401);
+        assert_eq!(chip8.stats().since(&Stats::default()), chip8.stats());
+
+        chip8.reset();
+        chip8.core_mut().memory_mut()[0x202..0x204].copy_from_slice(&[0x12, 0x03]);
+        let mut quirks = *chip8.core().quirks();
+        quirks.strict_alignment = true;
+        chip8.core_mut().set_quirks(quirks);
+        let summary = chip8.run_until(|_| false);
+        assert_eq!(
+            summary.exit,
+            RunExit::Error(Error::InvalidAlignment { pc: 0x202 })
+        );
+    }
+
+    #[test]
+    fn run_exit() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 0x05; ADD V0, 1; JP 0x206
+        mem[0x200..0x206].copy_from_slice(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x06]);
+        mem[0x206..0x208].copy_from_slice(&[0x12, 0x06]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+        chip8.set_breakpoints(&[0x202]);
+
+        // The breakpoint ends the run before ADD, running again continues past it
+        let summary = chip8.run_until(|chip8| chip8.stats().instructions == 100);
+        assert_eq!(summary.exit, RunExit::Breakpoint(0x202));
+        assert_eq!(summary.stats.instructions, 1);
+        chip8.set_stop_on_halt(true);
+        let summary = chip8.run_until(|chip8| chip8.stats().instructions == 100);
+        assert_eq!(summary.exit, RunExit::Halted(HaltReason::InfiniteLoop));
+        assert_eq!(chip8.core().pc(), 0x206);
+        assert_eq!(chip8.core().registers()[0], 0x06);
+
+        // Without stopping on halts the loop runs until stopped
+        chip8.set_stop_on_halt(false);
+        let summary = chip8.run_until(|chip8| chip8.stats().instructions == 100);
+        assert_eq!(summary.exit, RunExit::Stopped);
+        assert_eq!(summary.stats.instructions, 97);
+    }
+
+    #[test]
+    fn halt_above_4k() {
+        let mut mem = [0; Core::MAX_MEM_LEN];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // JP 0x234 at 0x1234 and 0x234, only the latter jumps to itself
+        mem[0x1234..0x1236].copy_from_slice(&[0x12, 0x34]);
+        mem[0x234..0x236].copy_from_slice(&[0x12, 0x34]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+        chip8.set_stop_on_halt(true);
+        chip8.core_mut().set_pc(0x1234);
+
+        let summary = chip8.run_until(|chip8| chip8.stats().instructions == 100);
+        assert_eq!(summary.exit, RunExit::Halted(HaltReason::InfiniteLoop));
+        assert_eq!(summary.stats.instructions, 1);
+        assert_eq!(chip8.core().pc(), 0x234);
+    }
+
+    #[test]
+    fn breakpoint_at_run_cycles_boundary() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 0x05; ADD V0, 1; JP 0x204
+        mem[0x200..0x206].copy_from_slice(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x04]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+        chip8.set_breakpoints(&[0x202, 0x204]);
+
+        // The last cycle of the run reaches the breakpoint, which ends it instead of stopping
+        let summary = chip8.run_cycles(1);
+        assert_eq!(summary.exit, RunExit::Breakpoint(0x202));
+        assert_eq!(summary.stats.instructions, 1);
+
+        // The next run executes the instruction at the breakpoint
+        let summary = chip8.run_cycles(1);
+        assert_eq!(summary.exit, RunExit::Breakpoint(0x204));
+        assert_eq!(summary.stats.instructions, 1);
+        assert_eq!(chip8.core().registers()[0], 0x06);
+
+        // The jump to itself reaches its breakpoint again
+        let summary = chip8.run_cycles(10);
+        assert_eq!(summary.exit, RunExit::Breakpoint(0x204));
+        assert_eq!(summary.stats.instructions, 1);
+    }
+
+    #[test]
+    fn vip_timing() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 60; LD DT, V0; CLS; JP 0x204
+        mem[0x200..0x208].copy_from_slice(&[0x60, 0x3C, 0xF0, 0x15, 0x00, 0xE0, 0x12, 0x04]);
+
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        core.set_quirks(QuirksConfig {
+            timing: Timing::CosmacVip,
+            ..QuirksConfig::default()
+        });
+        let mut chip8 = Chip8::new(core, Timing::VIP_FREQ, DefaultPeripherals::default()).unwrap();
+
+        // 6 + 10 + 3078 + 12 cycles leave 562 of the frame, the next CLS still runs
+        let cycles = chip8.frame_cycles();
+        assert_eq!(cycles, 3668);
+        let summary = chip8.run_cycles(cycles as u64);
+        assert_eq!(summary.stats.instructions, 5);
+        assert_eq!(chip8.last_cycles(), 3078);
+        assert_eq!(chip8.stats().frames, 1);
+        assert_eq!(chip8.delay_timer(), 59);
+
+        // A taken skip takes 4 cycles more
+        let (mut skips, mut reg, mut stack) = ([0; 4096], [0; 16], [0; 16]);
+        // SE V0, 0; (skipped); SE V0, 1
+        skips[0x200..0x206].copy_from_slice(&[0x30, 0x00, 0x00, 0x00, 0x30, 0x01]);
+        let mut core = Core::new(&mut skips, &mut reg, &mut stack);
+        core.set_quirks(QuirksConfig {
+            timing: Timing::CosmacVip,
+            ..QuirksConfig::default()
+        });
+        let mut chip8 = Chip8::new(core, Timing::VIP_FREQ, DefaultPeripherals::default()).unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.last_cycles(), 14);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.last_cycles(), 10);
+
+        chip8.pause();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.last_cycles(), 0);
+    }
+
+    #[derive(Debug, Default)]
+    struct Freeze {
+        writes: Vec<Range<usize>>,
+    }
+
+    impl WriteHook for Freeze {
+        fn written(&mut self, core: &mut Core<'_>, range: Range<usize>) {
+            if range.contains(&0x301) {
+                core.memory_range_mut(0x301..0x302).unwrap()[0] = 0xAA;
+            }
+            self.writes.push(range);
+        }
+    }
+
+    #[test]
+    fn write_hook() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut freeze = Freeze::default();
+
+        // LD V0, 0x12; LD V1, 0x34; LD I, 0x300; LD [I], V1; LD I, 0x300; LD B, V0
+        mem[0x200..0x20C].copy_from_slice(&[
+            0x60, 0x12, 0x61, 0x34, 0xA3, 0x00, 0xF1, 0x55, 0xA3, 0x00, 0xF0, 0x33,
+        ]);
+
+        {
+            let mut chip8 = Chip8::new(
+                Core::new(&mut mem, &mut reg, &mut stack),
+                700,
+                DefaultPeripherals::default(),
+            )
+            .unwrap();
+            chip8.set_write_hook(&mut freeze);
+
+            chip8.run_cycles(4);
+            assert_eq!(chip8.core().memory()[0x300..0x302], [0x12, 0xAA]);
+            chip8.run_cycles(2);
+        }
+        assert_eq!(mem[0x300..0x303], [0, 0xAA, 8]);
+        assert_eq!(freeze.writes, [0x300..0x302, 0x300..0x303]);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn snapshot() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // LD V0, 0x3C; LD DT, V0; CALL 0x20A; (pad); (pad); ADD V0, 1; LD [I], V0; RET
+        mem[0x200..0x210].copy_from_slice(&[
+            0x60, 0x3C, 0xF0, 0x15, 0x22, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x70, 0x01, 0xF0, 0x55,
+            0x00, 0xEE,
+        ]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            60,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+
+        chip8.run_until(|chip8| chip8.stats().instructions == 3);
+        let snapshot = chip8.snapshot();
+        assert_eq!(chip8.delay_timer(), 0x3A);
+
+        chip8.run_until(|chip8| chip8.stats().instructions == 6);
+        assert_eq!(chip8.core().memory()[0], 0x3D);
+        assert_eq!(chip8.core().sp(), 0);
+        assert_eq!(chip8.delay_timer(), 0x37);
+
+        chip8.restore(&snapshot).unwrap();
+        assert_eq!(chip8.core().pc(), 0x20A);
+        assert_eq!(chip8.core().stack(), &[0x204]);
+        assert_eq!(chip8.core().registers()[0], 0x3C);
+        // The font is restored as well
+        assert_eq!(chip8.core().memory()[0], 0xF0);
+        assert_eq!(chip8.delay_timer(), 0x3A);
+        assert_eq!(chip8.stats().instructions, 3);
+        assert_eq!(chip8.snapshot(), snapshot);
     }
 }
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+/// Paces a loop to a fixed number of frames per second
+///
+/// Every frame ends at a deadline counted from the start of the first one, so rounding
+/// errors and the time spent executing don't accumulate. Run a frame's worth of ticks, e.g.
+/// [`Chip8::frame_cycles`](crate::Chip8::frame_cycles), then call [`FramePacer::wait`].
+#[derive(Debug)]
+pub struct FramePacer {
+    fps: u32,
+    start: Instant,
+    frames: u64,
+}
+
+impl FramePacer {
+    /// The number of frames a loop may fall behind before the pacer gives up catching up
+    ///
+    /// This happens e.g. after the process was suspended, running the missed frames as fast
+    /// as possible would then just fast-forward the program.
+    pub const MAX_LAG: u64 = 4;
+
+    /// Start pacing to `fps` frames per second
+    pub fn new(fps: u32) -> Self {
+        Self {
+            fps: fps.max(1),
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    /// Sleep until the end of the current frame
+    pub fn wait(&mut self) {
+        self.frames += 1;
+
+        let deadline = self.start + self.elapsed(self.frames);
+        let now = Instant::now();
+        match deadline.checked_duration_since(now) {
+            Some(remaining) => std::thread::sleep(remaining),
+            None if now - deadline > self.elapsed(Self::MAX_LAG) => {
+                self.start = now;
+                self.frames = 0;
+            }
+            None => (),
+        }
+    }
+
+    /// The time `frames` frames take
+    fn elapsed(&self, frames: u64) -> Duration {
+        Duration::from_nanos(frames * 1_000_000_000 / self.fps as u64)
+    }
+}
@@ -1,3 +1,13 @@
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+/// Peripherals for microcontrollers, requires the `embedded-hal` feature
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+/// Peripherals for deterministic tests, requires the `alloc` feature
+#[cfg(any(feature = "alloc", test))]
+pub mod testing;
+
 /// A struct describing a number of falling edges.
 /// This is important to detect button releases.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -23,20 +33,58 @@ impl FallingEdges {
         None
     }
 
+    /// Whether there are no edges left
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
     /// Add edges to self
     pub fn push_edges(&mut self, edges: &FallingEdges) {
         self.0 |= edges.0;
     }
 }
 
+/// A struct describing a number of rising edges.
+/// This is important to detect button presses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RisingEdges(u16);
+
+impl RisingEdges {
+    /// Pop the index of the next edge.
+    ///
+    /// Edges are always popped in ascending order (0 -> 16)
+    pub fn pop_next_idx(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let idx = self.0.trailing_zeros();
+        self.0 ^= 1 << idx;
+
+        Some(idx as u8)
+    }
+
+    /// Whether there are no edges left
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Add edges to self
+    pub fn push_edges(&mut self, edges: &RisingEdges) {
+        self.0 |= edges.0;
+    }
+}
+
 /// A struct describing the current state of the CHIP-8's keypad buttons
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Keys(pub u16);
 
 impl Keys {
     /// Whether the key with a given index is pressed
     pub fn pressed(&self, idx: u8) -> bool {
-        let bit = 1 << idx;
+        // Programs may ask for any value of a register, there are only keys 0 - F
+        let bit = 1u16.checked_shl(idx as u32).unwrap_or(0);
         self.0 & bit != 0
     }
 
@@ -45,6 +93,11 @@ impl Keys {
         FallingEdges(self.0 & !after.0)
     }
 
+    /// Calculates whether there are any rising edges between two distinct status of keys
+    pub fn rising_edges(&self, after: &Self) -> RisingEdges {
+        RisingEdges(after.0 & !self.0)
+    }
+
     /// Updates a state of keys with another state, returning any detected falling edges
     pub fn update(&mut self, after: &Self) -> Option<FallingEdges> {
         let edges = self.falling_edges(after);
@@ -58,12 +111,52 @@ impl Keys {
     }
 }
 
+/// A key of the keypad going down or up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// The key with the given index was pressed
+    Down(u8),
+    /// The key with the given index was released
+    Up(u8),
+}
+
+impl KeyEvent {
+    /// The index of the key
+    pub fn key(&self) -> u8 {
+        match self {
+            KeyEvent::Down(key) | KeyEvent::Up(key) => *key,
+        }
+    }
+}
+
 /// A trait describing a keypad
+///
+/// Keypads either report snapshots of their keys, which are polled every tick, or queue
+/// [`KeyEvent`]s. Snapshots miss keys which are pressed and released between two ticks,
+/// queued events don't.
 pub trait Keypad {
     /// The keys which are currently pressed
     fn pressed_keys(&self) -> Keys;
     /// The most recently released key
     fn last_released_key(&mut self) -> FallingEdges;
+    /// The most recently pressed key
+    fn last_pressed_key(&mut self) -> RisingEdges;
+
+    /// Whether the keypad queues [`KeyEvent`]s, which are then read with
+    /// [`Keypad::poll_event`] instead of polling the keys
+    fn queues_events(&self) -> bool {
+        false
+    }
+
+    /// Pop the next event which happened at or before `tick`, the number of ticks executed
+    /// so far
+    ///
+    /// Live keypads return their events as soon as possible, replays of recorded input use
+    /// `tick` to deliver events at the tick they were recorded at.
+    fn poll_event(&mut self, tick: u64) -> Option<KeyEvent> {
+        let _ = tick;
+        None
+    }
 }
 
 /// A dummy keypad.
@@ -79,6 +172,10 @@ impl Keypad for NullKeypad {
     fn last_released_key(&mut self) -> FallingEdges {
         FallingEdges(0)
     }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        RisingEdges(0)
+    }
 }
 
 /// X, Y coordinates on a grid
@@ -89,22 +186,372 @@ pub struct Pos(pub u8, pub u8);
 #[derive(Debug)]
 pub struct Sprite<'memory>(pub &'memory [u8]);
 
-/// A trait describing a display
-pub trait Graphics {
+/// A rectangular region of the display in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    /// The leftmost column
+    pub x: usize,
+    /// The topmost row
+    pub y: usize,
+    /// The number of columns
+    pub width: usize,
+    /// The number of rows
+    pub height: usize,
+}
+
+impl Rect {
+    /// The smallest rectangle containing both rectangles
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        Self {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+}
+
+/// The resolution of the display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// 64x32 pixels, the original CHIP-8 resolution
+    LoRes,
+    /// 128x64 pixels, the SCHIP high resolution mode
+    HiRes,
+}
+
+impl DisplayMode {
     /// The width of the display in pixels
-    const WIDTH: usize = 64;
+    pub const fn width(self) -> usize {
+        match self {
+            Self::LoRes => 64,
+            Self::HiRes => 128,
+        }
+    }
+
     /// The height of the display in pixels
-    const HEIGHT: usize = 32;
+    pub const fn height(self) -> usize {
+        match self {
+            Self::LoRes => 32,
+            Self::HiRes => 64,
+        }
+    }
+}
+
+/// A monochrome framebuffer of up to 128x64 pixels
+///
+/// This implements the drawing logic of the CHIP-8, so that [`Graphics`] implementations
+/// only have to present the finished framebuffer. The region changed since the last
+/// [`Framebuffer::take_dirty`] is tracked, so displays can skip redrawing unchanged pixels.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    mode: DisplayMode,
+    /// One row per u128, the MSB is the leftmost pixel
+    rows: [u128; Self::MAX_HEIGHT],
+    dirty: Option<Rect>,
+}
+
+impl PartialEq for Framebuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.mode == other.mode && self.rows == other.rows
+    }
+}
+
+impl Eq for Framebuffer {}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framebuffer {
+    /// The largest supported width in pixels
+    pub const MAX_WIDTH: usize = DisplayMode::HiRes.width();
+    /// The largest supported height in pixels
+    pub const MAX_HEIGHT: usize = DisplayMode::HiRes.height();
+
+    /// Create a new, cleared framebuffer in [`DisplayMode::LoRes`]
+    pub fn new() -> Self {
+        Self {
+            mode: DisplayMode::LoRes,
+            rows: [0; Self::MAX_HEIGHT],
+            dirty: None,
+        }
+    }
+
+    /// The current display mode
+    pub fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    /// Switch the display mode, clearing all pixels
+    pub fn set_mode(&mut self, mode: DisplayMode) {
+        self.mode = mode;
+        // Regions of the previous mode are meaningless now, the whole display changed
+        self.dirty = None;
+        self.clear();
+    }
+
+    /// The width of the framebuffer in pixels
+    pub fn width(&self) -> usize {
+        self.mode.width()
+    }
+
+    /// The height of the framebuffer in pixels
+    pub fn height(&self) -> usize {
+        self.mode.height()
+    }
+
+    /// Clear all pixels
+    pub fn clear(&mut self) {
+        self.rows = [0; Self::MAX_HEIGHT];
+        self.mark_dirty(self.bounds());
+    }
+
+    /// A rectangle covering the whole framebuffer
+    pub fn bounds(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.width(),
+            height: self.height(),
+        }
+    }
+
+    /// The region changed since the last call to [`Framebuffer::take_dirty`]
+    pub fn dirty(&self) -> Option<Rect> {
+        self.dirty
+    }
+
+    /// Return and reset the region changed since the last call
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// A framebuffer with the given pixels, one row per u128, all marked as changed
+    #[cfg(feature = "alloc")]
+    pub(crate) fn from_rows(mode: DisplayMode, rows: [u128; Self::MAX_HEIGHT]) -> Self {
+        let mut framebuffer = Self {
+            mode,
+            rows,
+            dirty: None,
+        };
+        framebuffer.mark_all_dirty();
+        framebuffer
+    }
+
+    /// The pixels, one row per u128 with the leftmost pixel in the MSB
+    #[cfg(feature = "alloc")]
+    pub(crate) fn rows(&self) -> &[u128; Self::MAX_HEIGHT] {
+        &self.rows
+    }
+
+    /// Mark the whole framebuffer as changed, e.g. after restoring it
+    #[cfg(feature = "alloc")]
+    pub(crate) fn mark_all_dirty(&mut self) {
+        self.dirty = Some(self.bounds());
+    }
+
+    fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(rect),
+            None => rect,
+        });
+    }
 
-    /// Clear the display
-    fn clear(&mut self);
-    /// Toggle a sprite at the given position
+    /// Whether the pixel at the given position is set
+    ///
+    /// # Panic
+    /// This function panics if the position is outside of the framebuffer.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        assert!(x < self.width() && y < self.height());
+        self.rows[y] >> (Self::MAX_WIDTH - 1 - x) & 0x01 == 1
+    }
+
+    /// Toggle a sprite at the given position, returning whether any pixel was cleared
     ///
     /// The pixels of the sprite are toggled individually by XORing the current pixel values
-    /// with the values of the sprite
-    fn toggle_sprite(&mut self, pos: Pos, sprite: Sprite<'_>) -> bool;
-    /// Refresh the display
-    fn refresh(&mut self);
+    /// with the values of the sprite. Sprites wrap around at the edges of the framebuffer.
+    pub fn toggle_sprite(&mut self, pos: Pos, sprite: Sprite<'_>) -> bool {
+        self.toggle(pos, sprite, 1, false)
+    }
+
+    /// Toggle a sprite at the given position like [`Framebuffer::toggle_sprite`], but clip it
+    /// at the edges of the framebuffer
+    ///
+    /// Only the position wraps around, the pixels of the sprite beyond the right and bottom
+    /// edges are left out.
+    pub fn toggle_sprite_clipped(&mut self, pos: Pos, sprite: Sprite<'_>) -> bool {
+        self.toggle(pos, sprite, 1, true)
+    }
+
+    /// Toggle a sprite which is 16 pixels wide, two bytes per row, like
+    /// [`Framebuffer::toggle_sprite`]
+    ///
+    /// These are the 16x16 sprites drawn by `DRW` with a length of 0 in [`DisplayMode::HiRes`].
+    pub fn toggle_sprite16(&mut self, pos: Pos, sprite: Sprite<'_>) -> bool {
+        self.toggle(pos, sprite, 2, false)
+    }
+
+    /// Toggle a sprite which is 16 pixels wide like [`Framebuffer::toggle_sprite16`], but clip
+    /// it at the edges of the framebuffer
+    pub fn toggle_sprite16_clipped(&mut self, pos: Pos, sprite: Sprite<'_>) -> bool {
+        self.toggle(pos, sprite, 2, true)
+    }
+
+    fn toggle(&mut self, pos: Pos, sprite: Sprite<'_>, row_bytes: usize, clip: bool) -> bool {
+        let mut collision = false;
+
+        let (width, height) = (self.width(), self.height());
+        let (x0, y0) = (pos.0 as usize % width, pos.1 as usize % height);
+
+        for (dy, row) in sprite.0.chunks(row_bytes).enumerate() {
+            if clip && y0 + dy >= height {
+                break;
+            }
+            let y = (y0 + dy) % height;
+
+            let row_width = 8 * row.len();
+            let pixels = row
+                .iter()
+                .fold(0u32, |pixels, &byte| pixels << 8 | byte as u32);
+
+            for dx in 0..row_width {
+                if pixels >> (row_width - 1 - dx) & 0x01 == 0 || clip && x0 + dx >= width {
+                    continue;
+                }
+
+                let x = (x0 + dx) % width;
+                let bit = 1 << (Self::MAX_WIDTH - 1 - x);
+
+                collision |= self.rows[y] & bit != 0;
+                self.rows[y] ^= bit;
+
+                self.mark_dirty(Rect {
+                    x,
+                    y,
+                    width: 1,
+                    height: 1,
+                });
+            }
+        }
+
+        collision
+    }
+
+    /// Scroll the pixels down by `n` rows, clearing the rows at the top
+    pub fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+
+        self.rows.copy_within(..height - n, n);
+        self.rows[..n].fill(0);
+        self.mark_dirty(self.bounds());
+    }
+
+    /// Scroll the pixels left by 4 columns, clearing the columns at the right edge
+    pub fn scroll_left(&mut self) {
+        let visible = self.visible_columns();
+        let height = self.height();
+
+        for row in &mut self.rows[..height] {
+            *row = *row << 4 & visible;
+        }
+        self.mark_dirty(self.bounds());
+    }
+
+    /// Scroll the pixels right by 4 columns, clearing the columns at the left edge
+    pub fn scroll_right(&mut self) {
+        let visible = self.visible_columns();
+        let height = self.height();
+
+        for row in &mut self.rows[..height] {
+            *row = *row >> 4 & visible;
+        }
+        self.mark_dirty(self.bounds());
+    }
+
+    /// The bits of a row which are pixels in the current mode
+    fn visible_columns(&self) -> u128 {
+        !0 << (Self::MAX_WIDTH - self.width())
+    }
+
+    /// A text rendering of the whole framebuffer, see [`AsciiDump`]
+    pub fn ascii(&self) -> AsciiDump<'_> {
+        self.ascii_region(self.bounds())
+    }
+
+    /// A text rendering of a region of the framebuffer, see [`AsciiDump`]
+    ///
+    /// # Panic
+    /// Formatting the dump panics if the region is outside of the framebuffer.
+    pub fn ascii_region(&self, region: Rect) -> AsciiDump<'_> {
+        AsciiDump {
+            framebuffer: self,
+            region,
+        }
+    }
+}
+
+/// A text rendering of a framebuffer, with one line per row of pixels
+///
+/// Set pixels are shown as `#`, cleared pixels as `.` and every row ends with a newline:
+///
+/// ```text
+/// #..#
+/// .##.
+/// ```
+///
+/// The format is meant for snapshot tests, see [`assert_display_eq`](crate::assert_display_eq).
+#[derive(Clone, Copy, Debug)]
+pub struct AsciiDump<'a> {
+    framebuffer: &'a Framebuffer,
+    region: Rect,
+}
+
+impl core::fmt::Display for AsciiDump<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use core::fmt::Write;
+
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.region;
+
+        for y in y..(y + height) {
+            for x in x..(x + width) {
+                f.write_char(if self.framebuffer.pixel(x, y) {
+                    '#'
+                } else {
+                    '.'
+                })?;
+            }
+            f.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A trait describing a display
+pub trait Graphics {
+    /// The display mode changed
+    ///
+    /// All following framebuffers have the dimensions of the new mode.
+    fn set_mode(&mut self, _mode: DisplayMode) {}
+    /// Present the framebuffer on the display
+    ///
+    /// This is called whenever the framebuffer changed, `dirty` is the region which changed
+    /// since the last call.
+    fn present(&mut self, framebuffer: &Framebuffer, dirty: Rect);
 }
 
 /// A dummy display.
@@ -113,11 +560,7 @@ pub trait Graphics {
 pub struct NullGraphics;
 
 impl Graphics for NullGraphics {
-    fn clear(&mut self) {}
-    fn toggle_sprite(&mut self, _pos: Pos, _sprite: Sprite<'_>) -> bool {
-        false
-    }
-    fn refresh(&mut self) {}
+    fn present(&mut self, _framebuffer: &Framebuffer, _dirty: Rect) {}
 }
 
 /// An implementation of a RNG
@@ -135,12 +578,82 @@ where
     }
 }
 
+/// A seedable xorshift pseudo random number generator
+///
+/// It is not suitable for anything but games, but it needs neither `std` nor any
+/// dependencies, and the same seed always produces the same sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorShiftRandom(u64);
+
+impl XorShiftRandom {
+    /// The seed used by [`XorShiftRandom::default`]
+    pub const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+    /// Create a new generator from a seed
+    ///
+    /// xorshift gets stuck at a state of zero, so a seed of zero is replaced by
+    /// [`Self::DEFAULT_SEED`].
+    pub fn new(seed: u64) -> Self {
+        if seed == 0 {
+            Self(Self::DEFAULT_SEED)
+        } else {
+            Self(seed)
+        }
+    }
+}
+
+impl Default for XorShiftRandom {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED)
+    }
+}
+
+impl Random for XorShiftRandom {
+    fn random(&mut self) -> u8 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+/// A random number generator seeded from the operating system's entropy source
+///
+/// Only available with the "std" feature.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct OsRandom(XorShiftRandom);
+
+#[cfg(feature = "std")]
+impl OsRandom {
+    /// Create a new generator, seeded by the operating system
+    pub fn new() -> std::io::Result<Self> {
+        let mut seed = [0; 8];
+        getrandom::getrandom(&mut seed)?;
+
+        Ok(Self(XorShiftRandom::new(u64::from_ne_bytes(seed))))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Random for OsRandom {
+    fn random(&mut self) -> u8 {
+        self.0.random()
+    }
+}
+
 /// A trait describing a timer
 ///
 /// A timer has a 8-bit value and must be down-counting
+///
+/// Like the delay and sound timers of the CHIP-8, a timer stops at zero instead of wrapping
+/// around to 255. The [`Chip8`](crate::Chip8) keeps ticking it at zero and sounds the
+/// speaker while the sound timer isn't zero, so a wrapping timer would never silence it.
 pub trait Timer {
-    /// Subtract one from the current value, wrapping around.
-    /// Returns true on wrap-around.
+    /// Subtract one from the current value, stopping at zero.
+    /// Returns true if the timer just reached zero.
     fn tick(&mut self) -> bool;
     /// Get the current timer value
     fn get(&self) -> u8;
@@ -164,15 +677,19 @@ impl<'name> DownTimer<'name> {
 
 impl Timer for DownTimer<'_> {
     fn tick(&mut self) -> bool {
-        let (new_val, overflow) = self.val.overflowing_sub(1);
-        self.val = new_val;
+        if self.val == 0 {
+            return false;
+        }
+
+        self.val -= 1;
+        let expired = self.val == 0;
 
         #[cfg(feature = "std")]
-        if log::log_enabled!(log::Level::Debug) && overflow {
+        if log::log_enabled!(log::Level::Debug) && expired {
             log::debug!("{} timer overflowed", self.name);
         }
 
-        overflow
+        expired
     }
 
     fn get(&self) -> u8 {
@@ -184,6 +701,395 @@ impl Timer for DownTimer<'_> {
     }
 }
 
+/// A trait describing a speaker (buzzer)
+///
+/// The speaker is started when the sound timer becomes non-zero and
+/// stopped when it reaches zero again.
+pub trait Speaker {
+    /// Start emitting a tone
+    fn start(&mut self);
+    /// Stop emitting a tone
+    fn stop(&mut self);
+    /// Set the XO-CHIP audio pattern and pitch
+    ///
+    /// The pattern is a 1-bit waveform of 128 samples (MSB first), which is played back
+    /// at `4000 * 2^((pitch - 64) / 48)` samples per second while the speaker is started.
+    /// Speakers which only support a fixed tone may ignore the pattern.
+    fn set_pattern(&mut self, _pattern: &[u8; 16], _pitch: u8) {}
+}
+
+/// The playback rate of an XO-CHIP audio pattern in samples per second
+#[cfg(feature = "std")]
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// A trait describing the storage of the SCHIP RPL user flags
+///
+/// The HP48 kept the flags across programs, SCHIP and XO-CHIP games use them to save high
+/// scores and progress.
+pub trait Persistence {
+    /// The stored flags, read once when the storage is attached
+    fn load(&mut self) -> [u8; 16];
+    /// Store the flags after the program changed them
+    fn store(&mut self, flags: &[u8; 16]);
+}
+
+/// RPL user flags kept in memory, they are lost once the program ends
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RamPersistence(pub [u8; 16]);
+
+impl Persistence for RamPersistence {
+    fn load(&mut self) -> [u8; 16] {
+        self.0
+    }
+
+    fn store(&mut self, flags: &[u8; 16]) {
+        self.0 = *flags;
+    }
+}
+
+/// An optional storage, `None` forgets the flags once the program ends
+impl<P: Persistence> Persistence for Option<P> {
+    fn load(&mut self) -> [u8; 16] {
+        self.as_mut().map_or([0; 16], P::load)
+    }
+
+    fn store(&mut self, flags: &[u8; 16]) {
+        if let Some(persistence) = self {
+            persistence.store(flags);
+        }
+    }
+}
+
+impl<P: Persistence + ?Sized> Persistence for &mut P {
+    fn load(&mut self) -> [u8; 16] {
+        (**self).load()
+    }
+
+    fn store(&mut self, flags: &[u8; 16]) {
+        (**self).store(flags)
+    }
+}
+
+/// A dummy speaker.
+/// It never makes a sound.
+#[derive(Debug)]
+pub struct NullSpeaker;
+
+impl Speaker for NullSpeaker {
+    fn start(&mut self) {}
+    fn stop(&mut self) {}
+}
+
+/// An optional speaker, `None` never makes a sound.
+impl<S: Speaker> Speaker for Option<S> {
+    fn start(&mut self) {
+        if let Some(speaker) = self {
+            speaker.start();
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(speaker) = self {
+            speaker.stop();
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        if let Some(speaker) = self {
+            speaker.set_pattern(pattern, pitch);
+        }
+    }
+}
+
+/// The peripherals of a [`Chip8`](crate::Chip8), bundled so that it only needs a single
+/// type parameter
+///
+/// [`PeripheralSet`] bundles any combination of peripherals, [`DefaultPeripherals`] is
+/// a set without any input or output. [`DynPeripherals`] are chosen at runtime.
+pub trait Peripherals {
+    /// The keypad
+    type Keypad: Keypad + ?Sized;
+    /// The display
+    type Graphics: Graphics + ?Sized;
+    /// The random number generator
+    type Random: Random + ?Sized;
+    /// The delay timer
+    type DelayTimer: Timer + ?Sized;
+    /// The sound timer
+    type SoundTimer: Timer + ?Sized;
+    /// The speaker
+    type Speaker: Speaker + ?Sized;
+    /// The storage of the RPL user flags
+    type Persistence: Persistence + ?Sized;
+
+    /// Borrow all peripherals at once
+    fn split(&mut self) -> PeripheralsMut<'_, Self>;
+    /// The delay timer
+    fn delay_timer(&self) -> &Self::DelayTimer;
+    /// The sound timer
+    fn sound_timer(&self) -> &Self::SoundTimer;
+}
+
+/// Mutable borrows of all [`Peripherals`] of a set
+#[allow(missing_debug_implementations)]
+pub struct PeripheralsMut<'a, P: Peripherals + ?Sized> {
+    /// The keypad
+    pub keypad: &'a mut P::Keypad,
+    /// The display
+    pub graphics: &'a mut P::Graphics,
+    /// The random number generator
+    pub random: &'a mut P::Random,
+    /// The delay timer
+    pub delay_timer: &'a mut P::DelayTimer,
+    /// The sound timer
+    pub sound_timer: &'a mut P::SoundTimer,
+    /// The speaker
+    pub speaker: &'a mut P::Speaker,
+    /// The storage of the RPL user flags
+    pub persistence: &'a mut P::Persistence,
+}
+
+/// A set of [`Peripherals`]
+#[derive(Debug)]
+pub struct PeripheralSet<K, G, R, TD, TS, S, P> {
+    /// The keypad
+    pub keypad: K,
+    /// The display
+    pub graphics: G,
+    /// The random number generator
+    pub random: R,
+    /// The delay timer
+    pub delay_timer: TD,
+    /// The sound timer
+    pub sound_timer: TS,
+    /// The speaker
+    pub speaker: S,
+    /// The storage of the RPL user flags
+    pub persistence: P,
+}
+
+impl<K, G, R, TD, TS, S, P> Peripherals for PeripheralSet<K, G, R, TD, TS, S, P>
+where
+    K: Keypad,
+    G: Graphics,
+    R: Random,
+    TD: Timer,
+    TS: Timer,
+    S: Speaker,
+    P: Persistence,
+{
+    type Keypad = K;
+    type Graphics = G;
+    type Random = R;
+    type DelayTimer = TD;
+    type SoundTimer = TS;
+    type Speaker = S;
+    type Persistence = P;
+
+    fn split(&mut self) -> PeripheralsMut<'_, Self> {
+        PeripheralsMut {
+            keypad: &mut self.keypad,
+            graphics: &mut self.graphics,
+            random: &mut self.random,
+            delay_timer: &mut self.delay_timer,
+            sound_timer: &mut self.sound_timer,
+            speaker: &mut self.speaker,
+            persistence: &mut self.persistence,
+        }
+    }
+
+    fn delay_timer(&self) -> &TD {
+        &self.delay_timer
+    }
+
+    fn sound_timer(&self) -> &TS {
+        &self.sound_timer
+    }
+}
+
+/// Peripherals without any input or output, e.g. to run a program headless
+pub type DefaultPeripherals = PeripheralSet<
+    NullKeypad,
+    NullGraphics,
+    XorShiftRandom,
+    DownTimer<'static>,
+    DownTimer<'static>,
+    NullSpeaker,
+    RamPersistence,
+>;
+
+impl Default for DefaultPeripherals {
+    fn default() -> Self {
+        Self {
+            keypad: NullKeypad,
+            graphics: NullGraphics,
+            random: XorShiftRandom::default(),
+            delay_timer: DownTimer::new("delay"),
+            sound_timer: DownTimer::new("sound"),
+            speaker: NullSpeaker,
+            persistence: RamPersistence::default(),
+        }
+    }
+}
+
+// Boxed peripherals forward to the boxed one. Random has none, as boxed closures already
+// are random number generators.
+
+#[cfg(feature = "alloc")]
+impl<K: Keypad + ?Sized> Keypad for Box<K> {
+    fn pressed_keys(&self) -> Keys {
+        (**self).pressed_keys()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        (**self).last_released_key()
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        (**self).last_pressed_key()
+    }
+
+    fn queues_events(&self) -> bool {
+        (**self).queues_events()
+    }
+
+    fn poll_event(&mut self, tick: u64) -> Option<KeyEvent> {
+        (**self).poll_event(tick)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<G: Graphics + ?Sized> Graphics for Box<G> {
+    fn set_mode(&mut self, mode: DisplayMode) {
+        (**self).set_mode(mode)
+    }
+
+    fn present(&mut self, framebuffer: &Framebuffer, dirty: Rect) {
+        (**self).present(framebuffer, dirty)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Timer + ?Sized> Timer for Box<T> {
+    fn tick(&mut self) -> bool {
+        (**self).tick()
+    }
+
+    fn get(&self) -> u8 {
+        (**self).get()
+    }
+
+    fn set(&mut self, val: u8) {
+        (**self).set(val)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Speaker + ?Sized> Speaker for Box<S> {
+    fn start(&mut self) {
+        (**self).start()
+    }
+
+    fn stop(&mut self) {
+        (**self).stop()
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        (**self).set_pattern(pattern, pitch)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<P: Persistence + ?Sized> Persistence for Box<P> {
+    fn load(&mut self) -> [u8; 16] {
+        (**self).load()
+    }
+
+    fn store(&mut self, flags: &[u8; 16]) {
+        (**self).store(flags)
+    }
+}
+
+/// Boxed peripherals, for frontends choosing them at runtime
+///
+/// The peripherals are `Send`, so that the [`DynChip8`](crate::DynChip8) can run on a
+/// thread of its own. Requires the `alloc` feature.
+#[cfg(feature = "alloc")]
+#[allow(missing_debug_implementations)]
+pub struct DynPeripherals<'p> {
+    /// The keypad
+    pub keypad: Box<dyn Keypad + Send + 'p>,
+    /// The display
+    pub graphics: Box<dyn Graphics + Send + 'p>,
+    /// The random number generator
+    pub random: Box<dyn Random + Send + 'p>,
+    /// The delay timer
+    pub delay_timer: Box<dyn Timer + Send + 'p>,
+    /// The sound timer
+    pub sound_timer: Box<dyn Timer + Send + 'p>,
+    /// The speaker
+    pub speaker: Box<dyn Speaker + Send + 'p>,
+    /// The storage of the RPL user flags
+    pub persistence: Box<dyn Persistence + Send + 'p>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'p> Peripherals for DynPeripherals<'p> {
+    type Keypad = dyn Keypad + Send + 'p;
+    type Graphics = dyn Graphics + Send + 'p;
+    type Random = dyn Random + Send + 'p;
+    type DelayTimer = dyn Timer + Send + 'p;
+    type SoundTimer = dyn Timer + Send + 'p;
+    type Speaker = dyn Speaker + Send + 'p;
+    type Persistence = dyn Persistence + Send + 'p;
+
+    fn split(&mut self) -> PeripheralsMut<'_, Self> {
+        PeripheralsMut {
+            keypad: &mut *self.keypad,
+            graphics: &mut *self.graphics,
+            random: &mut *self.random,
+            delay_timer: &mut *self.delay_timer,
+            sound_timer: &mut *self.sound_timer,
+            speaker: &mut *self.speaker,
+            persistence: &mut *self.persistence,
+        }
+    }
+
+    fn delay_timer(&self) -> &Self::DelayTimer {
+        &*self.delay_timer
+    }
+
+    fn sound_timer(&self) -> &Self::SoundTimer {
+        &*self.sound_timer
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'p, K, G, R, TD, TS, S, P> From<PeripheralSet<K, G, R, TD, TS, S, P>> for DynPeripherals<'p>
+where
+    K: Keypad + Send + 'p,
+    G: Graphics + Send + 'p,
+    R: Random + Send + 'p,
+    TD: Timer + Send + 'p,
+    TS: Timer + Send + 'p,
+    S: Speaker + Send + 'p,
+    P: Persistence + Send + 'p,
+{
+    fn from(set: PeripheralSet<K, G, R, TD, TS, S, P>) -> Self {
+        Self {
+            keypad: Box::new(set.keypad),
+            graphics: Box::new(set.graphics),
+            random: Box::new(set.random),
+            delay_timer: Box::new(set.delay_timer),
+            sound_timer: Box::new(set.sound_timer),
+            speaker: Box::new(set.speaker),
+            persistence: Box::new(set.persistence),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,6 +1104,22 @@ mod tests {
         assert_eq!(Keys(0x11).falling_edges(&Keys(0x11)), FallingEdges(0x00));
     }
 
+    #[test]
+    fn rising_edges() {
+        assert_eq!(Keys(0x00).rising_edges(&Keys(0x01)), RisingEdges(0x01));
+        assert_eq!(Keys(0x00).rising_edges(&Keys(0x11)), RisingEdges(0x11));
+        assert_eq!(Keys(0x01).rising_edges(&Keys(0x11)), RisingEdges(0x10));
+        assert_eq!(Keys(0x11).rising_edges(&Keys(0x01)), RisingEdges(0x00));
+        assert_eq!(Keys(0x11).rising_edges(&Keys(0x11)), RisingEdges(0x00));
+
+        let mut edges = RisingEdges(0x8001);
+        edges.push_edges(&RisingEdges(0x10));
+        assert_eq!(edges.pop_next_idx(), Some(0));
+        assert_eq!(edges.pop_next_idx(), Some(4));
+        assert_eq!(edges.pop_next_idx(), Some(15));
+        assert_eq!(edges.pop_next_idx(), None);
+    }
+
     #[test]
     fn keys_update() {
         let mut keys = Keys(0x00);
@@ -240,4 +1162,217 @@ mod tests {
         assert_eq!(edges.pop_next_idx(), Some(4));
         assert_eq!(edges.pop_next_idx(), None);
     }
+
+    #[test]
+    fn down_timer() {
+        let mut timer = DownTimer::new("test");
+        assert!(!timer.tick());
+        assert_eq!(timer.get(), 0);
+
+        timer.set(2);
+        assert!(!timer.tick());
+        assert_eq!(timer.get(), 1);
+        assert!(timer.tick());
+        assert_eq!(timer.get(), 0);
+        assert!(!timer.tick());
+        assert_eq!(timer.get(), 0);
+    }
+
+    #[test]
+    fn xorshift_random() {
+        let mut a = XorShiftRandom::new(42);
+        let mut b = XorShiftRandom::new(42);
+        let mut c = XorShiftRandom::new(43);
+
+        let seq_a: Vec<u8> = (0..32).map(|_| a.random()).collect();
+        let seq_b: Vec<u8> = (0..32).map(|_| b.random()).collect();
+        let seq_c: Vec<u8> = (0..32).map(|_| c.random()).collect();
+
+        assert_eq!(seq_a, seq_b);
+        assert_ne!(seq_a, seq_c);
+
+        let mut zero = XorShiftRandom::new(0);
+        assert!((0..32).any(|_| zero.random() != 0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn os_random() {
+        let mut a = OsRandom::new().unwrap();
+        let mut b = OsRandom::new().unwrap();
+
+        let seq_a: Vec<u8> = (0..32).map(|_| a.random()).collect();
+        let seq_b: Vec<u8> = (0..32).map(|_| b.random()).collect();
+
+        assert_ne!(seq_a, seq_b);
+    }
+
+    #[test]
+    fn framebuffer_toggle_sprite() {
+        let mut fb = Framebuffer::new();
+
+        assert!(!fb.toggle_sprite(Pos(0, 0), Sprite(&[0b1010_0000, 0b0101_0000])));
+        assert!(fb.pixel(0, 0));
+        assert!(!fb.pixel(1, 0));
+        assert!(fb.pixel(2, 0));
+        assert!(fb.pixel(1, 1));
+        assert!(fb.pixel(3, 1));
+
+        // Overlapping at (2, 0) clears it and collides
+        assert!(fb.toggle_sprite(Pos(2, 0), Sprite(&[0b1000_0000])));
+        assert!(!fb.pixel(2, 0));
+
+        fb.clear();
+        assert_eq!(fb, Framebuffer::new());
+    }
+
+    #[test]
+    fn framebuffer_dirty() {
+        let mut fb = Framebuffer::new();
+        assert_eq!(fb.take_dirty(), None);
+
+        fb.toggle_sprite(Pos(10, 4), Sprite(&[0b1000_0001, 0, 0b0100_0000]));
+        let dirty = Rect {
+            x: 10,
+            y: 4,
+            width: 8,
+            height: 3,
+        };
+        assert_eq!(fb.take_dirty(), Some(dirty));
+        assert_eq!(fb.take_dirty(), None);
+
+        // Empty sprites don't change anything
+        fb.toggle_sprite(Pos(0, 0), Sprite(&[0, 0]));
+        assert_eq!(fb.take_dirty(), None);
+
+        fb.clear();
+        assert_eq!(fb.take_dirty(), Some(fb.bounds()));
+    }
+
+    #[test]
+    fn framebuffer_hires() {
+        let mut fb = Framebuffer::new();
+        assert_eq!((fb.width(), fb.height()), (64, 32));
+
+        fb.toggle_sprite(Pos(0, 0), Sprite(&[0x80]));
+        fb.set_mode(DisplayMode::HiRes);
+        assert_eq!((fb.width(), fb.height()), (128, 64));
+        assert!(!fb.pixel(0, 0));
+
+        assert!(!fb.toggle_sprite(Pos(126, 63), Sprite(&[0b1110_0000])));
+        assert!(fb.pixel(126, 63));
+        assert!(fb.pixel(127, 63));
+        assert!(fb.pixel(0, 63));
+    }
+
+    #[test]
+    fn framebuffer_sprite16() {
+        let mut fb = Framebuffer::new();
+        fb.set_mode(DisplayMode::HiRes);
+
+        let mut sprite = [0; 32];
+        sprite[0] = 0x80;
+        sprite[31] = 0x01;
+        assert!(!fb.toggle_sprite16(Pos(120, 60), Sprite(&sprite)));
+        assert!(fb.pixel(120, 60));
+        // The last pixel wraps around to the top left
+        assert!(fb.pixel(7, 11));
+
+        assert!(fb.toggle_sprite16_clipped(Pos(120, 60), Sprite(&sprite)));
+        assert!(!fb.pixel(120, 60));
+        assert!(fb.pixel(7, 11));
+    }
+
+    #[test]
+    fn framebuffer_scroll() {
+        let mut fb = Framebuffer::new();
+        fb.toggle_sprite(Pos(0, 0), Sprite(&[0xF0]));
+        fb.toggle_sprite(Pos(60, 31), Sprite(&[0xF0]));
+
+        fb.scroll_down(2);
+        assert!(fb.pixel(0, 2));
+        assert!(!fb.pixel(0, 0));
+        // Rows scrolled past the bottom are gone
+        assert!((0..64).all(|x| !fb.pixel(x, 31)));
+
+        fb.scroll_right();
+        assert!(!fb.pixel(0, 2));
+        assert!(fb.pixel(4, 2) && fb.pixel(7, 2));
+
+        fb.scroll_left();
+        fb.scroll_left();
+        assert!(!fb.pixel(0, 2));
+        fb.scroll_right();
+        assert!((0..64).all(|x| !fb.pixel(x, 2)));
+    }
+
+    #[test]
+    fn framebuffer_ascii() {
+        let mut fb = Framebuffer::new();
+        fb.toggle_sprite(Pos(1, 1), Sprite(&[0b1001_0000, 0b0110_0000]));
+
+        let region = Rect {
+            x: 0,
+            y: 0,
+            width: 6,
+            height: 4,
+        };
+        assert_eq!(
+            fb.ascii_region(region).to_string(),
+            "......\n.#..#.\n..##..\n......\n"
+        );
+        assert_eq!(fb.ascii().to_string().lines().count(), 32);
+
+        crate::assert_display_eq!(
+            fb,
+            region,
+            "
+            ......
+            .#..#.
+            ..##..
+            ......
+            "
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "framebuffer differs")]
+    fn framebuffer_ascii_mismatch() {
+        let fb = Framebuffer::new();
+        let region = Rect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+
+        crate::assert_display_eq!(fb, region, "#.");
+    }
+
+    #[test]
+    fn framebuffer_wrapping() {
+        let mut fb = Framebuffer::new();
+
+        assert!(!fb.toggle_sprite(Pos(62, 31), Sprite(&[0b1110_0000, 0b1000_0000])));
+        assert!(fb.pixel(62, 31));
+        assert!(fb.pixel(63, 31));
+        assert!(fb.pixel(0, 31));
+        assert!(fb.pixel(62, 0));
+    }
+
+    #[test]
+    fn framebuffer_clipping() {
+        let mut fb = Framebuffer::new();
+
+        assert!(!fb.toggle_sprite_clipped(Pos(62, 31), Sprite(&[0b1110_0000, 0b1000_0000])));
+        assert!(fb.pixel(62, 31));
+        assert!(fb.pixel(63, 31));
+        assert!(!fb.pixel(0, 31));
+        assert!(!fb.pixel(62, 0));
+
+        // The position wraps around
+        fb.clear();
+        fb.toggle_sprite_clipped(Pos(65, 33), Sprite(&[0b1000_0000]));
+        assert!(fb.pixel(1, 1));
+    }
 }
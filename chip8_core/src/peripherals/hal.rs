@@ -0,0 +1,321 @@
+//! Peripherals backed by [`embedded-hal`](https://docs.rs/embedded-hal/0.2) drivers
+//!
+//! ```ignore
+//! // A timer of the HAL of the microcontroller, periodic at 60 Hz
+//! let delay_timer = CountDownTimer::new(Timer::tim2(dp.TIM2, &clocks), 60.hz());
+//! // Rows with pull-ups, columns as outputs, type erased to fit into arrays
+//! let keypad = MatrixKeypad::new(
+//!     [r0.downgrade(), r1.downgrade(), r2.downgrade(), r3.downgrade()],
+//!     [c0.downgrade(), c1.downgrade(), c2.downgrade(), c3.downgrade()],
+//! );
+//! ```
+
+use super::{FallingEdges, Keypad, Keys, RisingEdges, Timer};
+use core::cell::{Cell, RefCell};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal::timer::{CountDown, Periodic};
+
+/// A [`Timer`] decremented by a periodic hardware countdown, e.g. at 60 Hz
+///
+/// The delay and sound timers of a [`Chip8`](crate::Chip8) are decremented once per 60
+/// emulated ticks, so they only run at 60 Hz in real time if the core does. This timer
+/// ignores the core and is decremented once per elapsed period of the countdown instead.
+///
+/// The countdown is polled whenever the timer is ticked or read, which a running Chip8 does
+/// often enough. A period elapsing twice between two polls is only counted once.
+#[derive(Debug)]
+pub struct CountDownTimer<C> {
+    countdown: RefCell<C>,
+    val: Cell<u8>,
+}
+
+impl<C: CountDown + Periodic> CountDownTimer<C> {
+    /// Start `countdown` with the given period, usually 1/60 s
+    pub fn new<T: Into<C::Time>>(mut countdown: C, period: T) -> Self {
+        countdown.start(period);
+
+        Self {
+            countdown: RefCell::new(countdown),
+            val: Cell::new(0),
+        }
+    }
+
+    /// Decrement the value if a period elapsed, returns whether it just reached zero
+    fn poll(&self) -> bool {
+        let elapsed = self.countdown.borrow_mut().wait().is_ok();
+        let val = self.val.get();
+        if !elapsed || val == 0 {
+            return false;
+        }
+
+        self.val.set(val - 1);
+        val == 1
+    }
+}
+
+impl<C: CountDown + Periodic> Timer for CountDownTimer<C> {
+    fn tick(&mut self) -> bool {
+        self.poll()
+    }
+
+    fn get(&self) -> u8 {
+        self.poll();
+        self.val.get()
+    }
+
+    fn set(&mut self, val: u8) {
+        self.val.set(val);
+    }
+}
+
+/// A 4x4 matrix keypad, scanned through `embedded-hal` pins
+///
+/// The columns are driven low one at a time, a pressed key pulls its row low, so the rows
+/// need pull-up resistors. Call [`MatrixKeypad::scan`] regularly, e.g. every millisecond:
+/// a key only changes once it read the same for [`MatrixKeypad::debounce`] scans in a row.
+/// The keys pressed and released since the core last asked are reported as edges, so short
+/// taps aren't lost between two ticks.
+#[derive(Debug)]
+pub struct MatrixKeypad<R, C> {
+    rows: [R; 4],
+    columns: [C; 4],
+    layout: [[u8; 4]; 4],
+    debounce: u8,
+    /// The number of scans each key read differently from its state
+    bounces: [u8; 16],
+    keys: Keys,
+    released: FallingEdges,
+    pressed: RisingEdges,
+}
+
+impl<R, C, E> MatrixKeypad<R, C>
+where
+    R: InputPin<Error = E>,
+    C: OutputPin<Error = E>,
+{
+    /// The keys of the COSMAC VIP keypad, by row and column
+    pub const VIP_LAYOUT: [[u8; 4]; 4] = [
+        [0x1, 0x2, 0x3, 0xC],
+        [0x4, 0x5, 0x6, 0xD],
+        [0x7, 0x8, 0x9, 0xE],
+        [0xA, 0x0, 0xB, 0xF],
+    ];
+
+    /// A keypad laid out like the one of the COSMAC VIP, debounced over 5 scans
+    pub fn new(rows: [R; 4], columns: [C; 4]) -> Self {
+        Self {
+            rows,
+            columns,
+            layout: Self::VIP_LAYOUT,
+            debounce: 5,
+            bounces: [0; 16],
+            keys: Keys(0),
+            released: FallingEdges(0),
+            pressed: RisingEdges(0),
+        }
+    }
+
+    /// Use another layout, the CHIP-8 keys by row and column
+    pub fn with_layout(mut self, layout: [[u8; 4]; 4]) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Change the number of scans a key has to read the same before it changes, at least 1
+    pub fn with_debounce(mut self, scans: u8) -> Self {
+        self.debounce = scans.max(1);
+        self
+    }
+
+    /// The number of scans a key has to read the same before it changes
+    pub fn debounce(&self) -> u8 {
+        self.debounce
+    }
+
+    /// Read all keys once
+    pub fn scan(&mut self) -> Result<(), E> {
+        let mut raw = Keys(0);
+
+        for (col, column) in self.columns.iter_mut().enumerate() {
+            column.set_low()?;
+            for (row, pin) in self.rows.iter().enumerate() {
+                if pin.is_low()? {
+                    raw.0 |= 1 << (self.layout[row][col] & 0xF);
+                }
+            }
+            column.set_high()?;
+        }
+
+        let mut changed = Keys(0);
+        for (key, bounces) in self.bounces.iter_mut().enumerate() {
+            if raw.pressed(key as u8) == self.keys.pressed(key as u8) {
+                *bounces = 0;
+                continue;
+            }
+
+            *bounces += 1;
+            if *bounces >= self.debounce {
+                *bounces = 0;
+                changed.0 |= 1 << key;
+            }
+        }
+
+        let after = Keys(self.keys.0 ^ changed.0);
+        self.released.push_edges(&self.keys.falling_edges(&after));
+        self.pressed.push_edges(&self.keys.rising_edges(&after));
+        self.keys = after;
+
+        Ok(())
+    }
+}
+
+impl<R, C, E> Keypad for MatrixKeypad<R, C>
+where
+    R: InputPin<Error = E>,
+    C: OutputPin<Error = E>,
+{
+    fn pressed_keys(&self) -> Keys {
+        self.keys.clone()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        core::mem::replace(&mut self.released, FallingEdges(0))
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        core::mem::replace(&mut self.pressed, RisingEdges(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A countdown whose periods elapse when the test says so
+    #[derive(Debug, Default)]
+    struct ManualCountDown {
+        elapsed: bool,
+    }
+
+    impl CountDown for ManualCountDown {
+        type Time = u32;
+
+        fn start<T: Into<u32>>(&mut self, _count: T) {}
+
+        fn wait(&mut self) -> nb::Result<(), void::Void> {
+            match core::mem::take(&mut self.elapsed) {
+                true => Ok(()),
+                false => Err(nb::Error::WouldBlock),
+            }
+        }
+    }
+
+    impl Periodic for ManualCountDown {}
+
+    #[test]
+    fn count_down() {
+        let mut timer = CountDownTimer::new(ManualCountDown::default(), 16_667u32);
+        timer.set(2);
+
+        // Ticks of the core don't decrement the timer, elapsed periods do
+        assert!(!timer.tick());
+        assert_eq!(timer.get(), 2);
+
+        timer.countdown.get_mut().elapsed = true;
+        assert_eq!(timer.get(), 1);
+        assert_eq!(timer.get(), 1);
+
+        timer.countdown.get_mut().elapsed = true;
+        assert!(timer.tick());
+        timer.countdown.get_mut().elapsed = true;
+        assert!(!timer.tick());
+        assert_eq!(timer.get(), 0);
+    }
+
+    /// The pins of a keypad, wired to the same key matrix
+    #[derive(Debug)]
+    struct Pin<'a> {
+        idx: usize,
+        /// The column driven low, if any
+        column: &'a Cell<Option<usize>>,
+        /// The pressed keys by row and column
+        pressed: &'a Cell<[[bool; 4]; 4]>,
+    }
+
+    impl InputPin for Pin<'_> {
+        type Error = ();
+
+        fn is_high(&self) -> Result<bool, ()> {
+            self.is_low().map(|low| !low)
+        }
+
+        fn is_low(&self) -> Result<bool, ()> {
+            let column = self.column.get();
+            Ok(column.is_some_and(|col| self.pressed.get()[self.idx][col]))
+        }
+    }
+
+    impl OutputPin for Pin<'_> {
+        type Error = ();
+
+        fn set_low(&mut self) -> Result<(), ()> {
+            self.column.set(Some(self.idx));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), ()> {
+            self.column.set(None);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn matrix_keypad() {
+        let column = Cell::new(None);
+        let pressed = Cell::new([[false; 4]; 4]);
+        let pins = || {
+            [0, 1, 2, 3].map(|idx| Pin {
+                idx,
+                column: &column,
+                pressed: &pressed,
+            })
+        };
+        let mut keypad = MatrixKeypad::new(pins(), pins()).with_debounce(2);
+
+        // A bounce of a single scan is ignored
+        pressed.set([
+            [false, false, false, false],
+            [false, true, false, false],
+            [false; 4],
+            [false; 4],
+        ]);
+        keypad.scan().unwrap();
+        pressed.set([[false; 4]; 4]);
+        keypad.scan().unwrap();
+        keypad.scan().unwrap();
+        assert_eq!(keypad.pressed_keys(), Keys(0));
+
+        // Key 5 is in the second row and column, A in the last row
+        pressed.set([
+            [false, false, false, false],
+            [false, true, false, false],
+            [false; 4],
+            [true, false, false, false],
+        ]);
+        keypad.scan().unwrap();
+        keypad.scan().unwrap();
+        assert_eq!(keypad.pressed_keys(), Keys(1 << 0x5 | 1 << 0xA));
+
+        // A tap between two ticks is reported as edges
+        pressed.set([[false; 4]; 4]);
+        keypad.scan().unwrap();
+        keypad.scan().unwrap();
+        assert_eq!(keypad.pressed_keys(), Keys(0));
+        assert_eq!(keypad.last_pressed_key(), RisingEdges(1 << 0x5 | 1 << 0xA));
+        assert_eq!(
+            keypad.last_released_key(),
+            FallingEdges(1 << 0x5 | 1 << 0xA)
+        );
+        assert_eq!(keypad.last_pressed_key(), RisingEdges(0));
+    }
+}
@@ -0,0 +1,231 @@
+//! Scripted input and recorded output make the behaviour of a program reproducible, e.g.
+//!
+//! ```
+//! # use chip8_core::prelude::*;
+//! # use chip8_core::peripherals::testing::*;
+//! let mut mem = [0; 4096];
+//! let mut reg = [0; 16];
+//! let mut stack = [0; 16];
+//!
+//! // LD V0, K; RND V1, 0xFF; JP 0x204
+//! mem[0x200..0x206].copy_from_slice(&[0xF0, 0x0A, 0xC1, 0xFF, 0x12, 0x04]);
+//!
+//! let mut chip8 = Chip8::new(
+//!     Core::new(&mut mem, &mut reg, &mut stack),
+//!     700,
+//!     PeripheralSet {
+//!         keypad: ScriptedKeypad::new().tap(10, 0x5, 3),
+//!         graphics: RecordingGraphics::default(),
+//!         random: SequenceRandom::new([0x42]),
+//!         delay_timer: ManualTimer::default(),
+//!         sound_timer: ManualTimer::default(),
+//!         speaker: NullSpeaker,
+//!         persistence: RamPersistence::default(),
+//!     },
+//! )
+//! .unwrap();
+//!
+//! for _ in 0..15 {
+//!     chip8.tick().unwrap();
+//! }
+//! assert_eq!(chip8.core().registers()[..2], [0x5, 0x42]);
+//! ```
+
+use super::{
+    DisplayMode, FallingEdges, Framebuffer, Graphics, KeyEvent, Keypad, Keys, Random, Rect,
+    RisingEdges, Timer,
+};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// A keypad pressing and releasing keys at given ticks
+///
+/// The keypad queues [`KeyEvent`]s, so it is meant to be driven by a
+/// [`Chip8`](crate::Chip8), which passes the current tick to [`Keypad::poll_event`].
+#[derive(Clone, Debug)]
+pub struct ScriptedKeypad {
+    /// The events still to come, ordered by their tick
+    script: VecDeque<(u64, KeyEvent)>,
+    keys: Keys,
+}
+
+impl ScriptedKeypad {
+    /// A keypad without any input
+    pub fn new() -> Self {
+        Self {
+            script: VecDeque::new(),
+            keys: Keys(0),
+        }
+    }
+
+    /// Add an event at `tick`, after the events already scripted for it
+    pub fn event(mut self, tick: u64, event: KeyEvent) -> Self {
+        let index = self.script.partition_point(|&(at, _)| at <= tick);
+        self.script.insert(index, (tick, event));
+        self
+    }
+
+    /// Press `key` at `tick`
+    pub fn press(self, tick: u64, key: u8) -> Self {
+        self.event(tick, KeyEvent::Down(key))
+    }
+
+    /// Release `key` at `tick`
+    pub fn release(self, tick: u64, key: u8) -> Self {
+        self.event(tick, KeyEvent::Up(key))
+    }
+
+    /// Press `key` at `tick` and release it `ticks` ticks later
+    pub fn tap(self, tick: u64, key: u8, ticks: u64) -> Self {
+        self.press(tick, key).release(tick + ticks, key)
+    }
+
+    /// Whether all scripted events happened
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty()
+    }
+}
+
+impl Default for ScriptedKeypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Keypad for ScriptedKeypad {
+    /// The keys held after the events which happened so far
+    fn pressed_keys(&self) -> Keys {
+        self.keys.clone()
+    }
+
+    // The edges are derived from the events by the Chip8
+    fn last_released_key(&mut self) -> FallingEdges {
+        FallingEdges(0)
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        RisingEdges(0)
+    }
+
+    fn queues_events(&self) -> bool {
+        true
+    }
+
+    fn poll_event(&mut self, tick: u64) -> Option<KeyEvent> {
+        match self.script.front() {
+            Some(&(at, event)) if at <= tick => {
+                self.script.pop_front();
+                let bit = 1 << (event.key() & 0xF);
+                match event {
+                    KeyEvent::Down(_) => self.keys.0 |= bit,
+                    KeyEvent::Up(_) => self.keys.0 &= !bit,
+                }
+                Some(event)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A call of a [`Graphics`] method
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphicsCall {
+    /// [`Graphics::set_mode`]
+    SetMode(DisplayMode),
+    /// [`Graphics::present`] with the dirty region
+    Present(Rect),
+}
+
+/// A display recording every call, along with the latest framebuffer
+#[derive(Clone, Debug, Default)]
+pub struct RecordingGraphics {
+    calls: Vec<GraphicsCall>,
+    framebuffer: Framebuffer,
+}
+
+impl RecordingGraphics {
+    /// The calls so far, the oldest first
+    pub fn calls(&self) -> &[GraphicsCall] {
+        &self.calls
+    }
+
+    /// Forget the calls so far
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+    }
+
+    /// The latest framebuffer presented
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+}
+
+impl Graphics for RecordingGraphics {
+    fn set_mode(&mut self, mode: DisplayMode) {
+        self.calls.push(GraphicsCall::SetMode(mode));
+    }
+
+    fn present(&mut self, framebuffer: &Framebuffer, dirty: Rect) {
+        self.calls.push(GraphicsCall::Present(dirty));
+        self.framebuffer.clone_from(framebuffer);
+    }
+}
+
+/// A random number generator returning the bytes of a sequence, starting over at its end
+#[derive(Clone, Debug)]
+pub struct SequenceRandom {
+    sequence: Vec<u8>,
+    next: usize,
+}
+
+impl SequenceRandom {
+    /// Create a generator returning `sequence`
+    ///
+    /// # Panic
+    /// This function panics if `sequence` is empty.
+    pub fn new<I: IntoIterator<Item = u8>>(sequence: I) -> Self {
+        let sequence: Vec<u8> = sequence.into_iter().collect();
+        assert!(!sequence.is_empty(), "the sequence must not be empty");
+
+        Self { sequence, next: 0 }
+    }
+}
+
+impl Random for SequenceRandom {
+    fn random(&mut self) -> u8 {
+        let byte = self.sequence[self.next];
+        self.next = (self.next + 1) % self.sequence.len();
+        byte
+    }
+}
+
+/// A timer which only changes when it is set
+///
+/// Ticks are counted but don't decrement the timer, so a test decides when time passes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManualTimer {
+    val: u8,
+    ticks: u64,
+}
+
+impl ManualTimer {
+    /// The number of ticks so far
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+impl Timer for ManualTimer {
+    fn tick(&mut self) -> bool {
+        self.ticks += 1;
+        false
+    }
+
+    fn get(&self) -> u8 {
+        self.val
+    }
+
+    fn set(&mut self, val: u8) {
+        self.val = val;
+    }
+}
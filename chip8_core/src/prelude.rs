@@ -0,0 +1,17 @@
+#[cfg(feature = "std")]
+pub use crate::handle::Chip8Handle;
+#[cfg(feature = "std")]
+pub use crate::pacing::FramePacer;
+#[cfg(feature = "std")]
+pub use crate::peripherals::OsRandom;
+pub use crate::peripherals::{
+    DefaultPeripherals, DisplayMode, DownTimer, FallingEdges, Framebuffer, Graphics, KeyEvent,
+    Keypad, Keys, NullGraphics, NullKeypad, NullSpeaker, PeripheralSet, Peripherals, Persistence,
+    Pos, RamPersistence, Random, Rect, RisingEdges, Speaker, Sprite, Timer, XorShiftRandom,
+};
+#[cfg(feature = "alloc")]
+pub use crate::{peripherals::DynPeripherals, DynChip8, Snapshot};
+pub use crate::{
+    Chip8, Command, Core, Error, HaltReason, QuirksConfig, RunExit, RunSummary, Stats, TickState,
+    WriteHook,
+};
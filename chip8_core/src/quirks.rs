@@ -0,0 +1,109 @@
+/// Configuration of the behaviours which differ between CHIP-8 interpreters
+///
+/// The default configuration is the most permissive one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct QuirksConfig {
+    /// Raise [`Error::InvalidAlignment`](crate::Error::InvalidAlignment) when the PC is odd,
+    /// or when a jump or call targets an odd address.
+    ///
+    /// Some interpreters allow executing code at odd addresses, so this is off by default.
+    pub strict_alignment: bool,
+    /// When `LD Vx, K` (FX0A) accepts a key
+    pub key_wait: KeyWait,
+    /// Leave I incremented by X+1 after `LD [I], Vx` (FX55) and `LD Vx, [I]` (FX65), like
+    /// the COSMAC VIP.
+    ///
+    /// Later interpreters leave I unchanged, so this is off by default.
+    pub increment_i: bool,
+    /// Shift VY into VX with `SHR Vx, Vy` (8XY6) and `SHL Vx, Vy` (8XYE), like the COSMAC
+    /// VIP.
+    ///
+    /// CHIP-48 and SUPER-CHIP shift VX in place and ignore VY, so this is off by default.
+    pub shift_vy: bool,
+    /// Reset VF to 0 after `OR`, `AND` and `XOR` (8XY1, 8XY2, 8XY3), like the COSMAC VIP.
+    ///
+    /// Later interpreters leave VF unchanged, so this is off by default.
+    pub reset_vf: bool,
+    /// Jump to XNN + VX with `JP V0, addr` (BNNN), X being the highest nibble of the
+    /// address, like CHIP-48 and SUPER-CHIP.
+    ///
+    /// The COSMAC VIP adds V0, so this is off by default.
+    pub jump_vx: bool,
+    /// Clip sprites drawn by `DRW` (DXYN) at the edges of the display instead of wrapping
+    /// them around, like the COSMAC VIP and SUPER-CHIP. The position still wraps around.
+    ///
+    /// Wrapping keeps every pixel of a sprite on the display, so this is off by default.
+    pub clip_sprites: bool,
+    /// Set VF to 1 when `ADD I, Vx` (FX1E) takes I past 0xFFF and to 0 otherwise, like the
+    /// Amiga interpreter, which Spacefight 2091! relies on.
+    ///
+    /// Other interpreters leave VF unchanged, so this is off by default.
+    pub add_i_overflow: bool,
+    /// How many cycles instructions take, and so what the core frequency counts
+    pub timing: Timing,
+    /// The address programs are loaded to and start at
+    ///
+    /// [`QuirksConfig::START`] on most interpreters, [`QuirksConfig::ETI_660_START`] for
+    /// programs of the ETI-660.
+    pub start: u16,
+}
+
+impl QuirksConfig {
+    /// The address programs start at on the COSMAC VIP and most later interpreters
+    pub const START: u16 = 0x200;
+    /// The address programs start at on the ETI-660
+    pub const ETI_660_START: u16 = 0x600;
+}
+
+impl Default for QuirksConfig {
+    fn default() -> Self {
+        Self {
+            strict_alignment: false,
+            key_wait: KeyWait::default(),
+            increment_i: false,
+            shift_vy: false,
+            reset_vf: false,
+            jump_vx: false,
+            clip_sprites: false,
+            add_i_overflow: false,
+            timing: Timing::default(),
+            start: Self::START,
+        }
+    }
+}
+
+/// When `LD Vx, K` (FX0A) accepts a key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum KeyWait {
+    /// When a key is released, like the COSMAC VIP, which waits for a key to be pressed
+    /// and released again
+    #[default]
+    Release,
+    /// As soon as a key is pressed, like many later interpreters
+    Press,
+}
+
+/// How many cycles instructions take
+///
+/// [`Chip8`](crate::Chip8) executes the cycles of its core frequency, a frame's worth of them
+/// per frame. With anything but [`Timing::Uniform`] cheap instructions run faster than
+/// expensive ones, which some ROMs rely on for their speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Timing {
+    /// Every instruction takes one cycle, the core frequency counts instructions per second
+    #[default]
+    Uniform,
+    /// Instructions take the machine cycles of the COSMAC VIP interpreter, see
+    /// [`Instruction::vip_cycles`](crate::instructions::Instruction::vip_cycles). The core
+    /// frequency counts machine cycles per second, [`Timing::VIP_FREQ`] on the VIP.
+    CosmacVip,
+}
+
+impl Timing {
+    /// The machine cycles per second of the COSMAC VIP, its 1.76 MHz clock divided by the 8
+    /// clocks of a machine cycle, 3668 per frame
+    pub const VIP_FREQ: u32 = 3668 * 60;
+}
@@ -0,0 +1,310 @@
+use crate::instructions::Instruction;
+use crate::Error;
+
+/// The largest ROM fitting into 4 KiB of memory
+#[cfg(feature = "arbitrary")]
+const MAX_LEN: usize = 0x1000 - crate::QuirksConfig::START as usize;
+
+/// The bytes of a ROM fitting into 4 KiB of memory, taken from fuzzer input, requires the
+/// `arbitrary` feature
+#[cfg(feature = "arbitrary")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rom<'a>(pub &'a [u8]);
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Rom<'a> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let len = u.arbitrary_len::<u8>()?.min(MAX_LEN);
+        Ok(Self(u.bytes(len)?))
+    }
+
+    fn arbitrary_take_rest(u: arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let rest = u.take_rest();
+        Ok(Self(&rest[..rest.len().min(MAX_LEN)]))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (0, Some(MAX_LEN))
+    }
+}
+
+/// Check that `rom` fits into `mem_len` bytes of memory when loaded to `start`
+///
+/// Fails with [`Error::RomTooLarge`].
+pub const fn check_size(rom: &[u8], start: u16, mem_len: usize) -> Result<(), Error> {
+    if rom.len() > mem_len.saturating_sub(start as usize) {
+        return Err(Error::RomTooLarge(rom.len()));
+    }
+
+    Ok(())
+}
+
+/// Check that every instruction reachable from `start` in `MEM_LEN` bytes of memory decodes,
+/// following jumps, calls and both outcomes of skips
+///
+/// Execution stops at `RET`, `EXIT` and at `JP V0, nnn`, whose target is only known at
+/// runtime. Memory outside of `rom` is taken to be zero, so flowing out of the ROM fails
+/// like it does when running it. `SYS nnn` decodes, but as the core doesn't execute machine
+/// code it fails as well. Fails with [`Error::InvalidInstruction`] at the first instruction
+/// found which doesn't decode.
+///
+/// Meant for constants, as it uses 3 bytes of stack per byte of memory, 12 KiB for 4 KiB.
+pub const fn check_reachable<const MEM_LEN: usize>(rom: &[u8], start: u16) -> Result<(), Error> {
+    // Addresses are marked when queued, so each one is queued at most once
+    let mut queued = [false; MEM_LEN];
+    let mut pending = [0u16; MEM_LEN];
+    let mut len = 0;
+
+    let mut targets = [start, 0];
+    let mut count = 1;
+
+    loop {
+        // Queue the targets of the last instruction, failing at the ones outside of memory
+        let mut i = 0;
+        while i < count {
+            let addr = targets[i];
+            i += 1;
+            if addr as usize >= MEM_LEN {
+                return Err(Error::InvalidInstruction {
+                    opcode: word(rom, start, addr as usize),
+                    pc: addr,
+                });
+            }
+            if !queued[addr as usize] {
+                queued[addr as usize] = true;
+                pending[len] = addr;
+                len += 1;
+            }
+        }
+
+        if len == 0 {
+            return Ok(());
+        }
+        len -= 1;
+        let pc = pending[len];
+
+        let opcode = word(rom, start, pc as usize);
+        let is_sys = opcode >> 12 == 0x0 && opcode >= 0x0200;
+        if !Instruction::is_valid(opcode) || is_sys {
+            return Err(Error::InvalidInstruction { opcode, pc });
+        }
+
+        let nnn = opcode & 0xFFF;
+        let next = pc.wrapping_add(size(opcode));
+        match opcode >> 12 {
+            // RET, EXIT
+            0x0 if opcode == 0x00EE || opcode == 0x00FD => (),
+            // JP nnn
+            0x1 => {
+                targets[0] = nnn;
+                count = 1;
+            }
+            // CALL nnn
+            0x2 => {
+                targets = [nnn, next];
+                count = 2;
+            }
+            // JP V0, nnn
+            0xB => (),
+            // SE, SNE, SKP, SKNP
+            0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+                let skipped = next.wrapping_add(size(word(rom, start, next as usize)));
+                targets = [next, skipped];
+                count = 2;
+            }
+            _ => {
+                targets[0] = next;
+                count = 1;
+            }
+        }
+    }
+}
+
+/// The size of the instruction starting with `opcode`, 4 bytes for `LD I, LONG nnnn`
+const fn size(opcode: u16) -> u16 {
+    match opcode {
+        0xF000 => 4,
+        _ => 2,
+    }
+}
+
+/// The opcode at `addr` of the memory with `rom` loaded to `start`
+const fn word(rom: &[u8], start: u16, addr: usize) -> u16 {
+    u16::from_be_bytes([byte(rom, start, addr), byte(rom, start, addr + 1)])
+}
+
+const fn byte(rom: &[u8], start: u16, addr: usize) -> u8 {
+    match addr.checked_sub(start as usize) {
+        Some(offset) if offset < rom.len() => rom[offset],
+        _ => 0,
+    }
+}
+
+/// Fail constant evaluation with the message of a failed check, see
+/// [`include_rom!`](crate::include_rom)
+#[doc(hidden)]
+pub const fn assert_ok(result: Result<(), Error>) {
+    if let Err(e) = result {
+        let message = Message::new(&e);
+        panic!("{}", message.as_str());
+    }
+}
+
+/// The message of an error, formatted in constants
+#[derive(Debug)]
+struct Message {
+    buf: [u8; 96],
+    len: usize,
+}
+
+impl Message {
+    const fn new(error: &Error) -> Self {
+        let mut message = Self {
+            buf: [0; 96],
+            len: 0,
+        };
+
+        match *error {
+            Error::RomTooLarge(len) => {
+                message.push("the ROM is 0x");
+                message.push_hex(len, 1);
+                message.push(" bytes, larger than the memory after its start");
+            }
+            Error::InvalidInstruction { opcode, pc } => {
+                message.push("the ROM reaches the invalid instruction 0x");
+                message.push_hex(opcode as usize, 4);
+                message.push(" at 0x");
+                message.push_hex(pc as usize, 3);
+            }
+            _ => message.push("the ROM is invalid"),
+        }
+
+        message
+    }
+
+    const fn push(&mut self, text: &str) {
+        let bytes = text.as_bytes();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            self.buf[self.len] = bytes[idx];
+            self.len += 1;
+            idx += 1;
+        }
+    }
+
+    /// Push `value` in upper case hex digits, at least `digits` of them
+    const fn push_hex(&mut self, value: usize, digits: usize) {
+        let mut shift = usize::BITS as usize - 4;
+        while shift > 0 && value >> shift == 0 && shift >= 4 * digits {
+            shift -= 4;
+        }
+
+        loop {
+            self.buf[self.len] = b"0123456789ABCDEF"[(value >> shift) & 0xF];
+            self.len += 1;
+            if shift == 0 {
+                break;
+            }
+            shift -= 4;
+        }
+    }
+
+    const fn as_str(&self) -> &str {
+        match core::str::from_utf8(self.buf.split_at(self.len).0) {
+            Ok(text) => text,
+            Err(_) => "the ROM is invalid",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size() {
+        assert_eq!(check_size(&[0; 0xE00], 0x200, 4096), Ok(()));
+        assert_eq!(
+            check_size(&[0; 0xE01], 0x200, 4096),
+            Err(Error::RomTooLarge(0xE01))
+        );
+        assert_eq!(check_size(&[], 0x200, 0x100), Ok(()));
+        assert_eq!(check_size(&[0], 0x200, 0x100), Err(Error::RomTooLarge(1)));
+
+        // Other start addresses and memory sizes
+        assert_eq!(
+            check_size(&[0; 0xA01], 0x600, 4096),
+            Err(Error::RomTooLarge(0xA01))
+        );
+        assert_eq!(check_size(&[0; 0xE01], 0x200, 0x10000), Ok(()));
+    }
+
+    #[test]
+    fn reachable() {
+        // CALL 0x204; JP 0x202; RET
+        const ROM: &[u8] = &[0x22, 0x04, 0x12, 0x02, 0x00, 0xEE];
+        const _: () = assert!(check_reachable::<4096>(ROM, 0x200).is_ok());
+        let check = |rom: &[u8]| check_reachable::<4096>(rom, 0x200);
+
+        // Data after an endless loop, and after JP V0, nnn
+        assert_eq!(check(&[0x12, 0x00, 0xFF, 0xFF]), Ok(()));
+        assert_eq!(check(&[0xB2, 0x00, 0xFF, 0xFF]), Ok(()));
+
+        // Either outcome of a skip, the called subroutine
+        let error = |opcode, pc| Err(Error::InvalidInstruction { opcode, pc });
+        assert_eq!(
+            check(&[0x30, 0x01, 0x12, 0x06, 0x51, 0x21]),
+            error(0x5121, 0x204)
+        );
+        assert_eq!(
+            check(&[0x22, 0x04, 0x00, 0xFD, 0xFF, 0xFF]),
+            error(0xFFFF, 0x204)
+        );
+
+        // LD I, LONG nnnn, skipped as a whole
+        assert_eq!(check(&[0xF0, 0x00, 0xFF, 0xFF, 0x12, 0x04]), Ok(()));
+        assert_eq!(
+            check(&[0x30, 0x01, 0xF0, 0x00, 0xFF, 0xFF, 0x12, 0x06]),
+            Ok(())
+        );
+
+        // Running past the end of the ROM
+        assert_eq!(check(&[0x60, 0x01]), error(0x0000, 0x202));
+        assert_eq!(check(&[0x1F, 0xFE, 0x00]), error(0x0000, 0xFFE));
+
+        // SYS nnn decodes, but isn't executed
+        assert_eq!(check(&[0x03, 0x00]), error(0x0300, 0x200));
+
+        // Other start addresses and memory sizes
+        assert_eq!(check_reachable::<4096>(&[0x16, 0x00], 0x600), Ok(()));
+        assert_eq!(
+            check_reachable::<4096>(&[0x16, 0x00], 0x200),
+            error(0x0000, 0x600)
+        );
+        assert_eq!(
+            check_reachable::<2048>(&[0x18, 0x00], 0x200),
+            error(0x0000, 0x800)
+        );
+    }
+
+    #[test]
+    fn message() {
+        let message = |error| Message::new(&error).as_str().to_string();
+
+        assert_eq!(
+            message(Error::RomTooLarge(0xE01)),
+            "the ROM is 0xE01 bytes, larger than the memory after its start"
+        );
+        assert_eq!(
+            message(Error::InvalidInstruction {
+                opcode: 0x5121,
+                pc: 0x204
+            }),
+            "the ROM reaches the invalid instruction 0x5121 at 0x204"
+        );
+        assert_eq!(
+            message(Error::InvalidInstruction { opcode: 0, pc: 0 }),
+            "the ROM reaches the invalid instruction 0x0000 at 0x000"
+        );
+    }
+}
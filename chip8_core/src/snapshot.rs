@@ -0,0 +1,280 @@
+use crate::peripherals::{DisplayMode, Framebuffer};
+use crate::Error;
+use alloc::vec::Vec;
+
+/// The first bytes of every encoded snapshot, see [`Snapshot::to_bytes`]
+pub const MAGIC: &[u8; 6] = b"C8SNAP";
+/// The version of the encoding written by [`Snapshot::to_bytes`]
+pub const VERSION: u8 = 1;
+
+/// The state of a [`Core`](crate::Core) at one point in time, see
+/// [`Core::snapshot`](crate::Core::snapshot)
+///
+/// The quirks, coverage map and decode cache are not part of the state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoreSnapshot {
+    pub(crate) mem: Vec<u8>,
+    pub(crate) reg: Vec<u8>,
+    pub(crate) stack: Vec<u16>,
+    pub(crate) i: u16,
+    pub(crate) pc: u16,
+    pub(crate) sp: u8,
+    pub(crate) framebuffer: Framebuffer,
+    pub(crate) audio_pattern: [u8; 16],
+    pub(crate) pitch: u8,
+    pub(crate) flags: [u8; 16],
+    pub(crate) exited: bool,
+}
+
+/// The state of a [`Chip8`](crate::Chip8) at one point in time, see
+/// [`Chip8::snapshot`](crate::Chip8::snapshot)
+///
+/// Besides the core this holds the timers and the counters of
+/// [`Chip8::stats`](crate::Chip8::stats), the peripherals and the key state are not part
+/// of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub(crate) core: CoreSnapshot,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) timer_acc: u32,
+    pub(crate) frame_acc: u32,
+    pub(crate) ticks: u64,
+    pub(crate) instructions: u64,
+    pub(crate) frames: u64,
+}
+
+impl Snapshot {
+    /// Encode the snapshot, e.g. to store it in a file
+    ///
+    /// The encoding starts with [`MAGIC`] and [`VERSION`], followed by the fields in
+    /// declaration order as big endian numbers. Memory, registers and stack are prefixed
+    /// with their length as u32, the framebuffer is stored as its mode (0 for low, 1 for
+    /// high resolution) followed by all rows as u128.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let core = &self.core;
+        let mut bytes = Vec::with_capacity(core.mem.len() + 0x500);
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(core.mem.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&core.mem);
+        bytes.extend_from_slice(&(core.reg.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&core.reg);
+        bytes.extend_from_slice(&(core.stack.len() as u32).to_be_bytes());
+        for addr in &core.stack {
+            bytes.extend_from_slice(&addr.to_be_bytes());
+        }
+        bytes.extend_from_slice(&core.i.to_be_bytes());
+        bytes.extend_from_slice(&core.pc.to_be_bytes());
+        bytes.push(core.sp);
+
+        bytes.push(match core.framebuffer.mode() {
+            DisplayMode::LoRes => 0,
+            DisplayMode::HiRes => 1,
+        });
+        for row in core.framebuffer.rows() {
+            bytes.extend_from_slice(&row.to_be_bytes());
+        }
+        bytes.extend_from_slice(&core.audio_pattern);
+        bytes.push(core.pitch);
+        bytes.extend_from_slice(&core.flags);
+        bytes.push(core.exited as u8);
+
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.extend_from_slice(&self.timer_acc.to_be_bytes());
+        bytes.extend_from_slice(&self.frame_acc.to_be_bytes());
+        bytes.extend_from_slice(&self.ticks.to_be_bytes());
+        bytes.extend_from_slice(&self.instructions.to_be_bytes());
+        bytes.extend_from_slice(&self.frames.to_be_bytes());
+
+        bytes
+    }
+
+    /// Decode a snapshot encoded by [`Snapshot::to_bytes`]
+    ///
+    /// Malformed, truncated or overlong data is rejected with [`Error::InvalidSnapshot`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC || reader.u8()? != VERSION {
+            return Err(Error::InvalidSnapshot);
+        }
+
+        let len = reader.u32()? as usize;
+        let mem = reader.take(len)?.to_vec();
+        let len = reader.u32()? as usize;
+        let reg = reader.take(len)?.to_vec();
+        let len = reader.u32()? as usize;
+        let stack = (0..len).map(|_| reader.u16()).collect::<Result<_, _>>()?;
+        let (i, pc, sp) = (reader.u16()?, reader.u16()?, reader.u8()?);
+
+        let mode = match reader.u8()? {
+            0 => DisplayMode::LoRes,
+            1 => DisplayMode::HiRes,
+            _ => return Err(Error::InvalidSnapshot),
+        };
+        let mut rows = [0; Framebuffer::MAX_HEIGHT];
+        for row in &mut rows {
+            *row = u128::from_be_bytes(reader.array()?);
+        }
+
+        let core = CoreSnapshot {
+            mem,
+            reg,
+            stack,
+            i,
+            pc,
+            sp,
+            framebuffer: Framebuffer::from_rows(mode, rows),
+            audio_pattern: reader.array()?,
+            pitch: reader.u8()?,
+            flags: reader.array()?,
+            exited: match reader.u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(Error::InvalidSnapshot),
+            },
+        };
+        let snapshot = Snapshot {
+            core,
+            delay_timer: reader.u8()?,
+            sound_timer: reader.u8()?,
+            timer_acc: u32::from_be_bytes(reader.array()?),
+            frame_acc: u32::from_be_bytes(reader.array()?),
+            ticks: u64::from_be_bytes(reader.array()?),
+            instructions: u64::from_be_bytes(reader.array()?),
+            frames: u64::from_be_bytes(reader.array()?),
+        };
+
+        if !reader.0.is_empty() {
+            return Err(Error::InvalidSnapshot);
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Reads the fields of an encoded snapshot, failing with [`Error::InvalidSnapshot`] at
+/// the end of the data
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.0.len() {
+            return Err(Error::InvalidSnapshot);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peripherals::DefaultPeripherals;
+    use crate::{Chip8, Core};
+    use proptest::prelude::*;
+
+    #[test]
+    fn encoding() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // HIGH; LD I, 0x000; DRW V0, V0, 5; LD V0, 0x3C; LD ST, V0; CALL 0x20E; (pad);
+        // JP 0x20E
+        mem[0x200..0x210].copy_from_slice(&[
+            0x00, 0xFF, 0xA0, 0x00, 0xD0, 0x05, 0x60, 0x3C, 0xF0, 0x18, 0x22, 0x0E, 0x00, 0x00,
+            0x12, 0x0E,
+        ]);
+
+        let mut chip8 = Chip8::new(
+            Core::new(&mut mem, &mut reg, &mut stack),
+            700,
+            DefaultPeripherals::default(),
+        )
+        .unwrap();
+        chip8.run_until(|chip8| chip8.stats().instructions == 10);
+        assert_eq!(chip8.core().framebuffer().mode(), DisplayMode::HiRes);
+
+        let snapshot = chip8.snapshot();
+        let bytes = snapshot.to_bytes();
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidSnapshot)
+        );
+        let mut overlong = bytes.clone();
+        overlong.push(0);
+        assert_eq!(Snapshot::from_bytes(&overlong), Err(Error::InvalidSnapshot));
+        assert_eq!(
+            Snapshot::from_bytes(b"C8SNAP\x01"),
+            Err(Error::InvalidSnapshot)
+        );
+    }
+
+    #[test]
+    fn restore_other_machine() {
+        let mut mem = [0; 4096];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let core = Core::new(&mut mem, &mut reg, &mut stack);
+        let snapshot = core.snapshot();
+
+        let mut mem = [0; 2048];
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+        let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+        assert_eq!(core.restore(&snapshot), Err(Error::InvalidSnapshot));
+    }
+
+    proptest! {
+        #[test]
+        fn corrupted_snapshots_decode_or_fail(
+            changes in proptest::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 0..8),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            let mut mem = [0; 2048];
+            let mut reg = [0; 16];
+            let mut stack = [0; 16];
+            let chip8 = Chip8::new(
+                Core::new(&mut mem, &mut reg, &mut stack),
+                700,
+                DefaultPeripherals::default(),
+            )
+            .unwrap();
+
+            let mut bytes = chip8.snapshot().to_bytes();
+            for (index, value) in changes {
+                let index = index.index(bytes.len());
+                bytes[index] = value;
+            }
+            bytes.truncate(bytes.len() - cut.index(8));
+
+            // Whatever decodes encodes to the same bytes again
+            if let Ok(snapshot) = Snapshot::from_bytes(&bytes) {
+                prop_assert_eq!(snapshot.to_bytes(), bytes);
+            }
+        }
+    }
+}
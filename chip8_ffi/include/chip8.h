@@ -0,0 +1,73 @@
+/*
+ * C bindings for chip8_core, link against the chip8 library built by
+ * `cargo build --release -p chip8_ffi`.
+ *
+ *     Chip8Emulator *chip8 = chip8_new(720, seed);
+ *     chip8_load_rom(chip8, rom, rom_len);
+ *     while (running) {
+ *         chip8_set_keys(chip8, held_keys);
+ *         if (chip8_tick(chip8, 12) != CHIP8_OK) break;
+ *         size_t width, height;
+ *         chip8_framebuffer(chip8, pixels, sizeof pixels, &width, &height);
+ *         // draw the pixels, wait for the next frame
+ *     }
+ *     chip8_free(chip8);
+ */
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* chip8_tick executed all ticks */
+#define CHIP8_OK 0
+/* chip8_tick stopped, as the program exited */
+#define CHIP8_EXITED 1
+/* The program failed, or an argument was invalid */
+#define CHIP8_ERROR (-1)
+
+/* A CHIP-8 emulator with 4 KiB of memory */
+typedef struct Chip8Emulator Chip8Emulator;
+
+/*
+ * Create an emulator executing core_freq instructions per second, with the random numbers
+ * seeded by seed. Returns NULL if core_freq is 0 or above 1 MHz.
+ */
+Chip8Emulator *chip8_new(uint32_t core_freq, uint64_t seed);
+
+/*
+ * Reset the emulator and load len bytes at rom to 0x200. Returns CHIP8_ERROR if the ROM is
+ * larger than 3584 bytes.
+ */
+int32_t chip8_load_rom(Chip8Emulator *chip8, const uint8_t *rom, size_t len);
+
+/*
+ * Execute up to cycles cycles, e.g. a frame's worth of them. Every instruction takes one
+ * cycle, and the timers are decremented at 60 Hz of the emulated time. Returns CHIP8_OK,
+ * CHIP8_EXITED once the program exited or CHIP8_ERROR if it failed.
+ */
+int32_t chip8_tick(Chip8Emulator *chip8, uint32_t cycles);
+
+/*
+ * Copy the display to pixels, one byte per pixel, 1 if it is lit and 0 otherwise, row by
+ * row. Stores the width and height of the display to width and height unless they are NULL.
+ * Returns the number of pixels of the display, nothing is copied if len is less.
+ */
+size_t chip8_framebuffer(const Chip8Emulator *chip8, uint8_t *pixels, size_t len,
+                         size_t *width, size_t *height);
+
+/* Set the keys held down, bit N for key N */
+void chip8_set_keys(Chip8Emulator *chip8, uint16_t keys);
+
+/* Free an emulator created by chip8_new, NULL is ignored */
+void chip8_free(Chip8Emulator *chip8);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,255 @@
+//! C bindings for `chip8_core`, built as a shared library
+//!
+//! The functions are declared in `chip8_ffi/include/chip8.h`. Frontends in C, C# or Python
+//! (via ctypes) create an emulator with [`chip8_new`], load a ROM, then call [`chip8_tick`]
+//! once per frame with the ticks of the frame, e.g. 12 at 720 Hz, pass the held keys with
+//! [`chip8_set_keys`] and read the display with [`chip8_framebuffer`].
+
+use chip8_core::prelude::*;
+use std::collections::VecDeque;
+use std::mem::ManuallyDrop;
+
+/// The memory size
+const MEM_LEN: usize = 4096;
+
+/// [`chip8_tick`] executed all ticks
+pub const CHIP8_OK: i32 = 0;
+/// [`chip8_tick`] stopped, as the program exited
+pub const CHIP8_EXITED: i32 = 1;
+/// The program failed, or an argument was invalid
+pub const CHIP8_ERROR: i32 = -1;
+
+/// A keypad fed by [`chip8_set_keys`]
+///
+/// The changes of the keys are queued as events, so keys pressed and released between two
+/// calls of [`chip8_tick`] still reach the program.
+#[derive(Debug, Default)]
+struct FfiKeypad {
+    held: u16,
+    events: VecDeque<KeyEvent>,
+}
+
+impl FfiKeypad {
+    fn set(&mut self, keys: u16) {
+        for key in (0..16).filter(|key| (self.held ^ keys) & 1 << key != 0) {
+            self.events.push_back(match keys & 1 << key {
+                0 => KeyEvent::Up(key),
+                _ => KeyEvent::Down(key),
+            });
+        }
+        self.held = keys;
+    }
+}
+
+impl Keypad for FfiKeypad {
+    fn pressed_keys(&self) -> Keys {
+        Keys(self.held)
+    }
+
+    // The edges are only read from the events
+    fn last_released_key(&mut self) -> FallingEdges {
+        Keys(0).falling_edges(&Keys(0))
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        Keys(0).rising_edges(&Keys(0))
+    }
+
+    fn queues_events(&self) -> bool {
+        true
+    }
+
+    fn poll_event(&mut self, _tick: u64) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+}
+
+type FfiChip8 = Chip8<
+    'static,
+    PeripheralSet<
+        FfiKeypad,
+        NullGraphics,
+        XorShiftRandom,
+        DownTimer<'static>,
+        DownTimer<'static>,
+        NullSpeaker,
+        RamPersistence,
+    >,
+>;
+
+/// A CHIP-8 emulator owning the memory of its core, opaque to C
+#[derive(Debug)]
+pub struct Chip8Emulator {
+    /// Borrows the buffers, so it is dropped before them
+    chip8: ManuallyDrop<FfiChip8>,
+    mem: *mut [u8],
+    reg: *mut [u8; 16],
+    stack: *mut [u16; 16],
+}
+
+impl Chip8Emulator {
+    fn new(core_freq: u32, seed: u64) -> Result<Self, Error> {
+        let mem = Box::into_raw(vec![0; MEM_LEN].into_boxed_slice());
+        let reg = Box::into_raw(Box::new([0; 16]));
+        let stack = Box::into_raw(Box::new([0; 16]));
+
+        // SAFETY: The buffers were just allocated and are only freed after the core
+        // borrowing them, either below or once the emulator is dropped
+        let core = unsafe { Core::new(&mut *mem, &mut *reg, &mut *stack) };
+        let peripherals = PeripheralSet {
+            keypad: FfiKeypad::default(),
+            graphics: NullGraphics,
+            random: XorShiftRandom::new(seed),
+            delay_timer: DownTimer::new("delay"),
+            sound_timer: DownTimer::new("sound"),
+            speaker: NullSpeaker,
+            persistence: RamPersistence::default(),
+        };
+
+        match Chip8::new(core, core_freq, peripherals) {
+            Ok(chip8) => Ok(Self {
+                chip8: ManuallyDrop::new(chip8),
+                mem,
+                reg,
+                stack,
+            }),
+            Err(e) => {
+                // SAFETY: The core was dropped by Chip8::new
+                unsafe { free(mem, reg, stack) };
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for Chip8Emulator {
+    fn drop(&mut self) {
+        // SAFETY: The Chip8 is dropped exactly once, before the buffers it borrows
+        unsafe {
+            ManuallyDrop::drop(&mut self.chip8);
+            free(self.mem, self.reg, self.stack);
+        }
+    }
+}
+
+/// Free the buffers allocated by [`Chip8Emulator::new`]
+///
+/// # Safety
+/// The buffers must not be borrowed anymore.
+unsafe fn free(mem: *mut [u8], reg: *mut [u8; 16], stack: *mut [u16; 16]) {
+    drop(Box::from_raw(mem));
+    drop(Box::from_raw(reg));
+    drop(Box::from_raw(stack));
+}
+
+/// Create an emulator executing `core_freq` instructions per second, with the random
+/// numbers seeded by `seed`
+///
+/// Returns `NULL` if `core_freq` is 0 or above 1 MHz. The emulator starts with empty
+/// program memory, load a ROM with [`chip8_load_rom`]. Free it with [`chip8_free`].
+#[no_mangle]
+pub extern "C" fn chip8_new(core_freq: u32, seed: u64) -> Option<Box<Chip8Emulator>> {
+    Chip8Emulator::new(core_freq, seed).ok().map(Box::new)
+}
+
+/// Reset the emulator and load `len` bytes at `rom` to 0x200
+///
+/// Returns [`CHIP8_ERROR`] if the ROM is larger than 3584 bytes.
+///
+/// # Safety
+/// `rom` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    chip8: Option<&mut Chip8Emulator>,
+    rom: *const u8,
+    len: usize,
+) -> i32 {
+    let Some(emulator) = chip8 else {
+        return CHIP8_ERROR;
+    };
+    if rom.is_null() {
+        return CHIP8_ERROR;
+    }
+    let rom = std::slice::from_raw_parts(rom, len);
+
+    let chip8 = &mut *emulator.chip8;
+    chip8.reset();
+    match chip8.core_mut().load_program(rom) {
+        Ok(()) => CHIP8_OK,
+        Err(_) => CHIP8_ERROR,
+    }
+}
+
+/// Execute up to `cycles` cycles, e.g. a frame's worth of them
+///
+/// Every instruction takes one cycle, and the timers are decremented at 60 Hz of the
+/// emulated time. Returns [`CHIP8_OK`], [`CHIP8_EXITED`] once the program exited or
+/// [`CHIP8_ERROR`] if it failed, e.g. on an invalid instruction.
+#[no_mangle]
+pub extern "C" fn chip8_tick(chip8: Option<&mut Chip8Emulator>, cycles: u32) -> i32 {
+    let Some(emulator) = chip8 else {
+        return CHIP8_ERROR;
+    };
+
+    match emulator.chip8.run_cycles(cycles as u64).exit {
+        RunExit::Exited => CHIP8_EXITED,
+        RunExit::Error(_) => CHIP8_ERROR,
+        _ => CHIP8_OK,
+    }
+}
+
+/// Copy the display to `pixels`, one byte per pixel, 1 if it is lit and 0 otherwise, row by
+/// row
+///
+/// The width and height of the display, which change with the display mode, are stored
+/// to `width` and `height` unless they are `NULL`. Returns the number of pixels of the
+/// display, nothing is copied if `len` is less, e.g. to query the size with `NULL` and 0.
+///
+/// # Safety
+/// `pixels` must point to `len` writable bytes, `width` and `height` to a `size_t` each or
+/// be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(
+    chip8: Option<&Chip8Emulator>,
+    pixels: *mut u8,
+    len: usize,
+    width: *mut usize,
+    height: *mut usize,
+) -> usize {
+    let Some(emulator) = chip8 else {
+        return 0;
+    };
+    let framebuffer = emulator.chip8.core().framebuffer();
+    let (w, h) = (framebuffer.width(), framebuffer.height());
+
+    if !width.is_null() {
+        *width = w;
+    }
+    if !height.is_null() {
+        *height = h;
+    }
+    if pixels.is_null() || len < w * h {
+        return w * h;
+    }
+
+    let pixels = std::slice::from_raw_parts_mut(pixels, w * h);
+    for (idx, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = framebuffer.pixel(idx % w, idx / w) as u8;
+    }
+
+    w * h
+}
+
+/// Set the keys held down, bit N for key N
+#[no_mangle]
+pub extern "C" fn chip8_set_keys(chip8: Option<&mut Chip8Emulator>, keys: u16) {
+    if let Some(emulator) = chip8 {
+        emulator.chip8.peripherals_mut().keypad.set(keys);
+    }
+}
+
+/// Free an emulator created by [`chip8_new`], `NULL` is ignored
+#[no_mangle]
+pub extern "C" fn chip8_free(chip8: Option<Box<Chip8Emulator>>) {
+    drop(chip8);
+}
@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use chip8_tools::util::octo;
+use std::path::PathBuf;
+
+const HELP: &str = "\
+chip8-asm - An assembler for CHIP-8 programs written in Octo
+
+USAGE:
+    chip8-asm [OPTIONS] SOURCE_FILE
+
+ARGS:
+    SOURCE_FILE     Path to an Octo source file (*.8o)
+
+OPTIONS:
+    -o FILE         Where to write the ROM (default: SOURCE_FILE with the extension .ch8)
+
+Supports a core subset of Octo: labels, :alias, :const, :call, :byte, all CHIP-8 and SCHIP
+statements, if/then, if/begin/else/end, loop/while/again and i := long.
+";
+
+fn main() -> Result<()> {
+    let mut output = None;
+    let mut source = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().context("-o requires a value")?)),
+            _ => source = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let source = match source {
+        Some(source) => source,
+        None => {
+            eprintln!("{}", HELP);
+            return Ok(());
+        }
+    };
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+
+    let text = std::fs::read_to_string(&source)
+        .with_context(|| format!("Reading {}", source.display()))?;
+    let rom = octo::assemble(&text).with_context(|| format!("Assembling {}", source.display()))?;
+
+    std::fs::write(&output, &rom).with_context(|| format!("Writing {}", output.display()))?;
+    println!("{} bytes written to {}", rom.len(), output.display());
+
+    Ok(())
+}
@@ -0,0 +1,656 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::instructions::{self, Instruction};
+use chip8_core::prelude::*;
+use chip8_tools::util::init_logging;
+use chip8_tools::util::octo::{self, SourceMap};
+use chip8_tools::util::terminal::half_blocks;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+const HELP: &str = "\
+chip8-dap - A Debug Adapter Protocol server for CHIP-8 programs
+
+USAGE:
+    chip8-dap
+
+Speaks DAP on stdin and stdout, to be started by an editor. The launch request takes
+the arguments
+
+    program      Path to a CHIP-8 ROM (*.ch8) or Octo source file (*.8o)
+    stopOnEntry  Whether to pause before the first instruction (default: false)
+
+Octo sources are assembled on launch and support breakpoints in the source, ROMs
+support instruction breakpoints in the disassembly view. The program runs headless,
+the display is shown as a variable scope and there is no keyboard input.
+";
+
+const CORE_FREQ: u32 = 700;
+/// The number of instructions executed per frame while running
+const TICKS_PER_FRAME: u32 = CORE_FREQ / 60;
+const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+const PROGRAM_START: usize = 0x200;
+
+/// The one and only thread of a CHIP-8
+const THREAD_ID: u64 = 1;
+/// `variablesReference`s of the scopes
+const REGISTERS: u64 = 1;
+const DISPLAY: u64 = 2;
+
+type Machine = Chip8<
+    'static,
+    PeripheralSet<
+        NullKeypad,
+        NullGraphics,
+        OsRandom,
+        DownTimer<'static>,
+        DownTimer<'static>,
+        NullSpeaker,
+        RamPersistence,
+    >,
+>;
+
+/// Read a single message, `None` once the client closed the connection
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let mut body = vec![0; length.context("Message without Content-Length")?];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Read messages from stdin until it is closed
+fn spawn_reader() -> Receiver<Value> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin());
+        loop {
+            match read_message(&mut reader) {
+                Ok(Some(message)) => {
+                    if tx.send(message).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => return,
+                Err(e) => log::warn!("Dropping malformed message: {:#}", e),
+            }
+        }
+    });
+
+    rx
+}
+
+/// A loaded program
+struct Session {
+    chip8: Machine,
+    /// The Octo source the program was assembled from, with its source map
+    source: Option<(PathBuf, SourceMap)>,
+    breakpoints: BTreeSet<u16>,
+    instruction_breakpoints: BTreeSet<u16>,
+    stop_on_entry: bool,
+    running: bool,
+    /// Run until the PC reaches this address, to step over or out of calls
+    run_until: Option<u16>,
+    /// Execute a single instruction while paused
+    step: bool,
+}
+
+impl Session {
+    fn launch(program: &Path, stop_on_entry: bool) -> Result<Self> {
+        let (rom, source) = if program.extension().is_some_and(|ext| ext == "8o") {
+            let text = std::fs::read_to_string(program)
+                .with_context(|| format!("Reading {}", program.display()))?;
+            let (rom, source_map) = octo::assemble_with_source_map(&text)
+                .with_context(|| format!("Assembling {}", program.display()))?;
+            (rom, Some((program.to_path_buf(), source_map)))
+        } else {
+            let rom =
+                std::fs::read(program).with_context(|| format!("Reading {}", program.display()))?;
+            (rom, None)
+        };
+
+        let mem = Box::leak(vec![0; 4096].into_boxed_slice());
+        let reg = Box::leak(vec![0; 16].into_boxed_slice());
+        let stack = Box::leak(vec![0; 16].into_boxed_slice());
+
+        if rom.len() > mem.len() - PROGRAM_START {
+            bail!("{} doesn't fit into memory", program.display());
+        }
+        mem[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(&rom);
+
+        let chip8 = Chip8::new(
+            Core::new(mem, reg, stack),
+            CORE_FREQ,
+            PeripheralSet {
+                keypad: NullKeypad,
+                graphics: NullGraphics,
+                random: OsRandom::new().context("Seeding random number generator")?,
+                delay_timer: DownTimer::new("delay"),
+                sound_timer: DownTimer::new("sound"),
+                speaker: NullSpeaker,
+                persistence: RamPersistence::default(),
+            },
+        )?;
+
+        Ok(Self {
+            chip8,
+            source,
+            breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_on_entry,
+            running: false,
+            run_until: None,
+            step: false,
+        })
+    }
+
+    /// The source line of the statement at `addr`
+    fn line(&self, addr: u16) -> Option<usize> {
+        let (_, source_map) = self.source.as_ref()?;
+        source_map.range(..=addr).next_back().map(|(_, &line)| line)
+    }
+
+    fn source(&self) -> Value {
+        match &self.source {
+            Some((path, _)) => json!({ "path": path }),
+            None => Value::Null,
+        }
+    }
+
+    fn decode(&self, addr: u16) -> Option<Instruction> {
+        let mem = self.chip8.core().memory();
+        Instruction::decode(mem.get(addr as usize..)?, addr).ok()
+    }
+}
+
+/// Why the program stopped running
+enum Stop {
+    Breakpoint,
+    Step,
+    Exception(Error),
+    Exited,
+}
+
+struct Server {
+    seq: u64,
+    session: Option<Session>,
+}
+
+impl Server {
+    fn send(&mut self, mut message: Value) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+
+        let body = serde_json::to_string(&message)?;
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value>) -> Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(e) => response["message"] = format!("{:#}", e).into(),
+        }
+
+        self.send(response)
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) -> Result<()> {
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "description": description,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn session(&mut self) -> Result<&mut Session> {
+        self.session.as_mut().context("No program launched")
+    }
+
+    /// Handle a request, returns whether the server should quit
+    fn handle(&mut self, request: Value) -> Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let args = &request["arguments"];
+
+        let result = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsDisassembleRequest": true,
+                "supportsSteppingGranularity": true,
+            })),
+            "launch" => {
+                let program = args["program"]
+                    .as_str()
+                    .context("launch requires a program");
+                program
+                    .and_then(|program| {
+                        let stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                        Session::launch(Path::new(program), stop_on_entry)
+                    })
+                    .map(|session| {
+                        self.session = Some(session);
+                        Value::Null
+                    })
+            }
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => self.session().map(|session| {
+                session.running = !session.stop_on_entry;
+                Value::Null
+            }),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Display", "variablesReference": DISPLAY, "expensive": false },
+            ]})),
+            "variables" => self.variables(args),
+            "disassemble" => self.disassemble(args),
+            "continue" => self.session().map(|session| {
+                session.running = true;
+                json!({ "allThreadsContinued": true })
+            }),
+            "next" | "stepIn" | "stepOut" => self.step(&command, args),
+            "pause" => self.session().map(|session| {
+                session.running = false;
+                session.run_until = None;
+                Value::Null
+            }),
+            "disconnect" | "terminate" => {
+                self.respond(&request, Ok(Value::Null))?;
+                return Ok(true);
+            }
+            _ => Err(anyhow::anyhow!("Unsupported request \"{}\"", command)),
+        };
+
+        let launched = command == "launch" && result.is_ok();
+        let paused = command == "pause" && result.is_ok();
+        self.respond(&request, result)?;
+
+        if launched {
+            self.event("initialized", Value::Null)?;
+        }
+        if paused {
+            self.stopped("pause", None)?;
+        }
+        if command == "configurationDone" && self.session.as_ref().is_some_and(|s| !s.running) {
+            self.stopped("entry", None)?;
+        }
+
+        Ok(false)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value> {
+        let session = self.session()?;
+        let lines: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+
+        session.breakpoints.clear();
+        let source_map = session.source.as_ref().map(|(_, map)| map.clone());
+
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|&line| {
+                // Move breakpoints on lines without code to the next statement
+                let target = source_map.as_ref().and_then(|map| {
+                    map.iter()
+                        .filter(|(_, &l)| l >= line)
+                        .min_by_key(|(&addr, &l)| (l, addr))
+                        .map(|(&addr, &l)| (addr, l))
+                });
+
+                match target {
+                    Some((addr, line)) => {
+                        session.breakpoints.insert(addr);
+                        json!({
+                            "verified": true,
+                            "line": line,
+                            "instructionReference": format!("0x{:03X}", addr),
+                        })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "No code at this line",
+                    }),
+                }
+            })
+            .collect();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value> {
+        let session = self.session()?;
+        session.instruction_breakpoints.clear();
+
+        let breakpoints: Vec<Value> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|breakpoint| {
+                let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                let addr = breakpoint["instructionReference"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .map(|addr| addr + offset)
+                    .filter(|addr| (0..0x1000).contains(addr));
+
+                match addr {
+                    Some(addr) => {
+                        session.instruction_breakpoints.insert(addr as u16);
+                        json!({ "verified": true })
+                    }
+                    None => json!({ "verified": false, "message": "Invalid address" }),
+                }
+            })
+            .collect();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&mut self) -> Result<Value> {
+        let session = self.session()?;
+        let core = session.chip8.core();
+
+        // The current PC, followed by the CALLs on the stack
+        let addrs: Vec<u16> = std::iter::once(core.pc())
+            .chain(core.stack().iter().rev().copied())
+            .collect();
+
+        let frames: Vec<Value> = addrs
+            .iter()
+            .enumerate()
+            .map(|(id, &addr)| {
+                let name = match session.decode(addr) {
+                    Some(instruction) => format!("0x{:03X}  {}", addr, instruction),
+                    None => format!("0x{:03X}", addr),
+                };
+
+                let line = session.line(addr);
+                json!({
+                    "id": id,
+                    "name": name,
+                    "source": line.map(|_| session.source()),
+                    "line": line.unwrap_or(0),
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:03X}", addr),
+                })
+            })
+            .collect();
+
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&mut self, args: &Value) -> Result<Value> {
+        let session = self.session()?;
+        let chip8 = &session.chip8;
+        let core = chip8.core();
+
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let variables: Vec<Value> = match args["variablesReference"].as_u64() {
+            Some(REGISTERS) => core
+                .registers()
+                .iter()
+                .enumerate()
+                .map(|(x, v)| variable(format!("V{:X}", x), format!("0x{:02X}", v)))
+                .chain([
+                    variable("I".into(), format!("0x{:03X}", core.i())),
+                    variable("PC".into(), format!("0x{:03X}", core.pc())),
+                    variable("SP".into(), core.sp().to_string()),
+                    variable("DT".into(), chip8.delay_timer().to_string()),
+                    variable("ST".into(), chip8.sound_timer().to_string()),
+                ])
+                .collect(),
+            Some(DISPLAY) => {
+                let framebuffer = core.framebuffer();
+                (0..framebuffer.height())
+                    .step_by(2)
+                    .map(|line| variable(format!("{:02}", line), half_blocks(framebuffer, line)))
+                    .collect()
+            }
+            _ => bail!("Unknown variables reference"),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn disassemble(&mut self, args: &Value) -> Result<Value> {
+        let session = self.session()?;
+        let mem = session.chip8.core().memory();
+
+        let base = args["memoryReference"]
+            .as_str()
+            .and_then(parse_reference)
+            .context("Invalid memory reference")?;
+        let start = base
+            + args["offset"].as_i64().unwrap_or(0)
+            + 2 * args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_i64().unwrap_or(0);
+
+        let mut next = start;
+        let instructions: Vec<Value> = (0..count)
+            .map(|_| {
+                let addr = next;
+                let bytes = usize::try_from(addr)
+                    .ok()
+                    .and_then(|addr| mem.get(addr..addr + 2).and(mem.get(addr..)));
+
+                // Long instructions take 4 bytes, anything else is shown as 2
+                let (text, bytes) = match bytes {
+                    Some(bytes) => {
+                        let (text, len) = match instructions::decode_iter(bytes, addr as u16).next()
+                        {
+                            Some((_, Ok(instruction))) => {
+                                (instruction.to_string(), instruction.size() as usize)
+                            }
+                            _ => ("??".to_string(), 2),
+                        };
+                        let bytes: Vec<_> =
+                            bytes[..len].iter().map(|b| format!("{:02X}", b)).collect();
+                        next += len as i64;
+                        (text, bytes.join(" "))
+                    }
+                    None => {
+                        next += 2;
+                        ("??".to_string(), String::new())
+                    }
+                };
+
+                let mut instruction = json!({
+                    "address": format!("0x{:03X}", addr),
+                    "instructionBytes": bytes,
+                    "instruction": text,
+                });
+                if let Some(line) = u16::try_from(addr).ok().and_then(|a| session.line(a)) {
+                    instruction["location"] = session.source();
+                    instruction["line"] = line.into();
+                }
+                instruction
+            })
+            .collect();
+
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    fn step(&mut self, command: &str, args: &Value) -> Result<Value> {
+        let session = self.session()?;
+        let core = session.chip8.core();
+        let pc = core.pc();
+
+        session.run_until = match command {
+            // Step over calls, unless stepping single instructions
+            "next" if args["granularity"] != "instruction" => match session.decode(pc) {
+                Some(Instruction::I2NNN(_)) => Some(pc + 2),
+                _ => None,
+            },
+            "stepOut" => core.stack().last().map(|&call| call + 2),
+            _ => None,
+        };
+
+        // Stepping a single instruction is reported once the response is sent
+        session.running = session.run_until.is_some();
+        session.step = !session.running;
+
+        Ok(Value::Null)
+    }
+
+    /// Execute the instructions of one frame, or a pending single step
+    fn run(&mut self) -> Result<()> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        let single_step = std::mem::take(&mut session.step);
+        if !session.running && !single_step {
+            return Ok(());
+        }
+
+        let ticks = if single_step { 1 } else { TICKS_PER_FRAME };
+        let mut stop = None;
+
+        for _ in 0..ticks {
+            if let Err(e) = session.chip8.tick() {
+                stop = Some(Stop::Exception(e));
+                break;
+            }
+
+            let core = session.chip8.core();
+            let pc = core.pc();
+            if core.exited() {
+                stop = Some(Stop::Exited);
+                break;
+            }
+            if single_step || session.run_until == Some(pc) {
+                stop = Some(Stop::Step);
+                break;
+            }
+            if session.breakpoints.contains(&pc) || session.instruction_breakpoints.contains(&pc) {
+                stop = Some(Stop::Breakpoint);
+                break;
+            }
+        }
+
+        let stop = match stop {
+            Some(stop) => stop,
+            None => return Ok(()),
+        };
+        session.running = false;
+        session.run_until = None;
+
+        match stop {
+            Stop::Breakpoint => self.stopped("breakpoint", None),
+            Stop::Step => self.stopped("step", None),
+            Stop::Exception(e) => {
+                self.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", e) }),
+                )?;
+                self.stopped("exception", Some(e.to_string()))
+            }
+            Stop::Exited => {
+                self.session = None;
+                self.event("exited", json!({ "exitCode": 0 }))?;
+                self.event("terminated", Value::Null)
+            }
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.session.as_ref().is_some_and(|session| session.running)
+    }
+}
+
+/// Parse an address as used for memory and instruction references, e.g. `0x200`
+fn parse_reference(reference: &str) -> Option<i64> {
+    match reference.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => reference.parse().ok(),
+    }
+}
+
+fn main() -> Result<()> {
+    init_logging();
+
+    if std::env::args().nth(1).is_some() {
+        eprintln!("{}", HELP);
+        return Ok(());
+    }
+
+    let requests = spawn_reader();
+    let mut server = Server {
+        seq: 0,
+        session: None,
+    };
+
+    loop {
+        let frame = Instant::now();
+        server.run()?;
+
+        // Handle requests while waiting for the next frame, block while paused
+        let request = if server.running() {
+            match requests.recv_timeout(FRAME.saturating_sub(frame.elapsed())) {
+                Ok(request) => Some(request),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        if let Some(request) = request {
+            if request["type"] == "request" && server.handle(request)? {
+                return Ok(());
+            }
+        }
+    }
+}
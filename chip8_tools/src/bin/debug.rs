@@ -1,59 +1,1485 @@
-use chip8_core::peripherals::{DownTimer, NullKeypad};
-use chip8_core::Chip8;
-use chip8_tools::util::load_program;
-use chip8_tools::util::minifb::MinifbDisplay;
-use rand::prelude::*;
-use std::io::Write;
-use std::sync::mpsc::channel;
+use anyhow::{anyhow, bail, Context, Result};
+use chip8_core::instructions::{self, Instruction};
+use chip8_core::prelude::*;
+use chip8_tools::util::terminal::half_blocks;
+use chip8_tools::util::{init_logging, load_program};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Rect as Area};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::Frame;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
 
-fn main() {
-    let path = std::env::args().nth(1).expect("Give ROM path");
+const HELP: &str = "\
+chip8-dbg - A terminal debugger for the CHIP-8 CPU
 
-    let mut mem = vec![0; 2048];
-    let mut reg = vec![0; 16];
-    let mut stack = vec![0; 16];
+USAGE:
+    chip8-dbg [--mi] ROM_FILE
 
-    load_program(path, &mut mem[..]).expect("Failed loading ROM");
+ARGS:
+    ROM_FILE    Path to a CHIP-8 ROM (*.ch8)
 
-    let mut minifb = MinifbDisplay::new(60).expect("Could not crate minifb display");
-    let graphics_adapter = minifb.graphics_adapter();
+OPTIONS:
+    --mi        Read commands as JSON from stdin instead of showing the terminal UI, see
+                MI MODE below
 
-    let (tx_exit_gui, rx_exit_gui) = channel();
+KEYS:
+    s, Space    Execute a single instruction
+    n           Execute a single instruction, running subroutine calls to their end
+    f           Run until the current subroutine returns
+    r           Run until a breakpoint is hit, or pause
+    b           Toggle a breakpoint at the current PC
+    PgUp, PgDn  Scroll the memory view
+    i           Let the memory view follow the I register again
+    :           Enter a command, see below. Up and Down browse the entered commands, Tab
+                completes command names and labels
+    q, Esc      Quit
 
-    std::thread::spawn(move || {
-        let mut chip8 = Chip8::new(
-            chip8_core::Core::new(&mut mem[..], &mut reg[..], &mut stack[..]),
-            700,
-            NullKeypad,
-            graphics_adapter,
-            || thread_rng().gen(),
-            DownTimer::new("delay"),
-            DownTimer::new("sound"),
-        );
+COMMANDS:
+    s, step     Execute a single instruction
+    pause       Stop running
+    b ADDR [if COND]
+                Set a breakpoint at ADDR, only stopping there if COND holds
+    d ADDR      Delete the breakpoint at ADDR
+    c           Continue until a breakpoint is hit
+    until ADDR  Continue until the PC reaches ADDR or a breakpoint is hit
+    next        Like the n key
+    finish      Like the f key
+    rs [N]      Step back N instructions, 1 by default
+    bt          Print the call stack, innermost call first
+    watch VAL   Pause whenever VAL changes
+    unwatch VAL Stop watching VAL
+    mem ADDR [LEN]
+                Print LEN bytes of memory from ADDR, 0x40 by default
+    list [ADDR] [N]
+                Disassemble N instructions around ADDR, 0x10 around the PC by default
+    set mem ADDR BYTE...
+                Write the BYTEs to memory from ADDR
+    set vX VAL, set i VAL, set pc VAL
+                Set a register
+    asm ADDR INSTRUCTION
+                Assemble an instruction like \"LD V0, 5\" and write it to ADDR
+    save FILE   Save the breakpoints, watches and machine state to FILE
+    load FILE   Continue a session saved to FILE
 
-        println!("CHIP-8 Debugger");
+Addresses and values are hexadecimal, with or without 0x, or labels of jump and call
+targets like L_0x2A4. Where a VAL is expected,
+registers (v0-vF, i, pc, sp, dt, st) and bytes of memory ([ADDR] or [i]) can be used as
+well. A COND compares two of them with ==, !=, <, <=, > or >=, e.g. `v3 == 1F`.
 
-        loop {
-            let mut cmd = String::new();
+MI MODE:
+    Every line on stdin is a request like {\"id\": 1, \"command\": \"b 2A4\"}, with one of the
+    commands above, or \"quit\". Every request is answered on stdout by a line like
+
+        {\"id\": 1, \"ok\": true, \"status\": \"...\", \"output\": [...], \"state\": {...}}
+
+    or {\"id\": 1, \"ok\": false, \"error\": \"...\"}. The id is optional and returned as is,
+    output holds the lines printed by the command and state the registers, timers and
+    stack. Once running stops, e.g. at a breakpoint, the event
+    {\"event\": \"stopped\", \"status\": \"...\", \"output\": [...], \"state\": {...}} is written.
+";
+
+const CORE_FREQ: u32 = 700;
+/// The number of instructions executed per drawn frame while running
+const TICKS_PER_FRAME: u32 = CORE_FREQ / 60;
+const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+type Machine<'memory> = Chip8<
+    'memory,
+    PeripheralSet<
+        NullKeypad,
+        NullGraphics,
+        OsRandom,
+        DownTimer<'static>,
+        DownTimer<'static>,
+        NullSpeaker,
+        RamPersistence,
+    >,
+>;
+
+/// A command entered after `:`
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Command {
+    Step,
+    Pause,
+    Break(u16, Option<Condition>),
+    Delete(u16),
+    Continue,
+    Until(u16),
+    Next,
+    Finish,
+    ReverseStep(u16),
+    Backtrace,
+    Watch(Operand),
+    Unwatch(Operand),
+    Memory {
+        addr: u16,
+        len: u16,
+    },
+    /// Disassemble `count` instructions around `addr`, the PC by default
+    List {
+        addr: Option<u16>,
+        count: u16,
+    },
+    SetMemory(u16, Vec<u8>),
+    Assemble(u16, Instruction),
+    SetRegister(u8, u8),
+    SetI(u16),
+    SetPc(u16),
+    Save(PathBuf),
+    Load(PathBuf),
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut words = s.split_whitespace();
+        let name = words.next().context("Empty command")?;
+        let mut addr = || -> Result<u16> {
+            let addr = words
+                .next()
+                .with_context(|| format!("{} requires an address", name))?;
+            parse_addr(addr)
+        };
+
+        let command = match name {
+            "s" | "step" => Command::Step,
+            "pause" => Command::Pause,
+            "b" | "break" => {
+                let addr = addr()?;
+                let condition = match words.next() {
+                    Some("if") => Some(words.by_ref().collect::<Vec<_>>().join(" ").parse()?),
+                    Some(word) => bail!("Unexpected \"{}\", expected if", word),
+                    None => None,
+                };
+                Command::Break(addr, condition)
+            }
+            "d" | "delete" => Command::Delete(addr()?),
+            "c" | "continue" => Command::Continue,
+            "u" | "until" => Command::Until(addr()?),
+            "n" | "next" => Command::Next,
+            "f" | "finish" => Command::Finish,
+            "rs" | "reverse-step" => Command::ReverseStep(words.next().map_or(Ok(1), parse_addr)?),
+            "bt" | "backtrace" => Command::Backtrace,
+            "w" | "watch" => Command::Watch(words.by_ref().collect::<Vec<_>>().join(" ").parse()?),
+            "unwatch" => Command::Unwatch(words.by_ref().collect::<Vec<_>>().join(" ").parse()?),
+            "m" | "mem" => {
+                let addr = addr()?;
+                let len = words.next().map_or(Ok(0x40), parse_addr)?;
+                Command::Memory { addr, len }
+            }
+            "l" | "list" | "disasm" => {
+                let addr = words.next().map(parse_addr).transpose()?;
+                let count = words.next().map_or(Ok(LIST_COUNT), parse_addr)?;
+                Command::List { addr, count }
+            }
+            "set" => return parse_set(words),
+            "a" | "asm" => {
+                let addr = addr()?;
+                let text = words.by_ref().collect::<Vec<_>>().join(" ");
+                let text = text.trim_matches('"');
+                if text.is_empty() {
+                    bail!("asm requires an instruction");
+                }
+                let instruction = text
+                    .parse()
+                    .with_context(|| format!("Can't assemble \"{}\"", text))?;
+                Command::Assemble(addr, instruction)
+            }
+            "save" | "load" => {
+                let path = words.by_ref().collect::<Vec<_>>().join(" ");
+                if path.is_empty() {
+                    bail!("{} requires a file", name);
+                }
+                match name {
+                    "save" => Command::Save(path.into()),
+                    _ => Command::Load(path.into()),
+                }
+            }
+            _ => bail!("Unknown command \"{}\"", name),
+        };
+        if let Some(word) = words.next() {
+            bail!("Unexpected \"{}\"", word);
+        }
+
+        Ok(command)
+    }
+}
+
+/// The arguments of `set`, the target followed by a value, or bytes for memory
+fn parse_set<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let target = words.next().context("set requires mem, vX, i or pc")?;
+    let byte = |val: u16| u8::try_from(val).map_err(|_| anyhow!("{:#x} is not a byte", val));
+
+    if target == "mem" {
+        let addr = parse_addr(words.next().context("set mem requires an address")?)?;
+        let bytes = words
+            .map(|word| parse_addr(word).and_then(byte))
+            .collect::<Result<Vec<_>>>()?;
+        if bytes.is_empty() {
+            bail!("set mem requires the bytes to write");
+        }
+        return Ok(Command::SetMemory(addr, bytes));
+    }
+
+    let val = parse_addr(words.next().context("set requires a value")?)?;
+    if let Some(word) = words.next() {
+        bail!("Unexpected \"{}\"", word);
+    }
+
+    match target.to_ascii_lowercase().as_str() {
+        "i" => Ok(Command::SetI(val)),
+        "pc" => Ok(Command::SetPc(val)),
+        reg => match reg.strip_prefix('v').map(|x| u8::from_str_radix(x, 16)) {
+            Some(Ok(x)) if x < 16 => Ok(Command::SetRegister(x, byte(val)?)),
+            _ => bail!("Can't set \"{}\", expected mem, vX, i or pc", target),
+        },
+    }
+}
+
+/// A hexadecimal address or value, with or without `0x`, or a label like `L_0x2A4`
+fn parse_addr(s: &str) -> Result<u16> {
+    let digits = s.strip_prefix("L_").unwrap_or(s);
+    let digits = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+        .unwrap_or(digits);
+
+    u16::from_str_radix(digits, 16).map_err(|_| anyhow!("\"{}\" is not a hexadecimal number", s))
+}
+
+/// The names of the commands, completed by Tab
+const COMMANDS: [&str; 22] = [
+    "step",
+    "pause",
+    "break",
+    "delete",
+    "continue",
+    "until",
+    "next",
+    "finish",
+    "reverse-step",
+    "backtrace",
+    "watch",
+    "unwatch",
+    "mem",
+    "list",
+    "disasm",
+    "set",
+    "set mem",
+    "set i",
+    "set pc",
+    "asm",
+    "save",
+    "load",
+];
+
+/// The command line, with the command being entered and the ones entered before
+#[derive(Debug, Default)]
+struct Prompt {
+    /// The command being entered, if any
+    line: Option<String>,
+    /// The position of the cursor in the line, in bytes
+    cursor: usize,
+    /// The entered commands, the latest last
+    history: Vec<String>,
+    /// The entry of the history shown while browsing it
+    history_pos: Option<usize>,
+}
+
+impl Prompt {
+    fn open(&mut self) {
+        self.line = Some(String::new());
+        self.cursor = 0;
+        self.history_pos = None;
+    }
+
+    /// Close the prompt, returning the entered line and adding it to the history
+    fn submit(&mut self) -> String {
+        let line = self.line.take().unwrap_or_default();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        line
+    }
+
+    /// Handle an editing key
+    fn edit(&mut self, code: KeyCode) {
+        let Some(line) = &mut self.line else {
+            return;
+        };
+        let prev = line[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(idx, _)| idx);
+        let next = line[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8());
+
+        match code {
+            KeyCode::Char(c) => {
+                line.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            KeyCode::Backspace => {
+                line.replace_range(prev..self.cursor, "");
+                self.cursor = prev;
+            }
+            KeyCode::Delete => line.replace_range(self.cursor..next, ""),
+            KeyCode::Left => self.cursor = prev,
+            KeyCode::Right => self.cursor = next,
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = line.len(),
+            KeyCode::Up => self.browse(
+                self.history_pos
+                    .unwrap_or(self.history.len())
+                    .checked_sub(1),
+            ),
+            KeyCode::Down => match self.history_pos {
+                Some(pos) if pos + 1 < self.history.len() => self.browse(Some(pos + 1)),
+                Some(_) => {
+                    self.open();
+                }
+                None => (),
+            },
+            KeyCode::Esc => self.line = None,
+            _ => (),
+        }
+    }
+
+    /// Show the entry `pos` of the history, keeping the line if there is none
+    fn browse(&mut self, pos: Option<usize>) {
+        if let Some(entry) = pos.and_then(|pos| self.history.get(pos)) {
+            self.cursor = entry.len();
+            self.line = Some(entry.clone());
+            self.history_pos = pos;
+        }
+    }
+
+    /// The word before the cursor, the one completed by Tab, and whether it's the first
+    fn word(&self) -> Option<(&str, bool)> {
+        let line = &self.line.as_ref()?[..self.cursor];
+        let start = line.rfind(' ').map_or(0, |idx| idx + 1);
+        let command = &line[..start];
+
+        // `set` takes its target as part of the name
+        let first = command.trim().is_empty() || command.trim() == "set";
+        let word = if first {
+            line.trim_start()
+        } else {
+            &line[start..]
+        };
+        Some((word, first))
+    }
+
+    /// Replace the word before the cursor with `completion`
+    fn complete(&mut self, word: &str, completion: &str) {
+        if let Some(line) = &mut self.line {
+            line.replace_range(self.cursor - word.len()..self.cursor, completion);
+            self.cursor += completion.len() - word.len();
+        }
+    }
+}
+
+/// The longest prefix all `words` start with
+fn common_prefix<'a>(words: &[&'a str]) -> &'a str {
+    let Some(first) = words.first() else {
+        return "";
+    };
+    let len = words[1..].iter().fold(first.len(), |len, word| {
+        first
+            .bytes()
+            .zip(word.bytes())
+            .take(len)
+            .take_while(|(a, b)| a == b)
+            .count()
+    });
+
+    &first[..len]
+}
+
+/// A value of the machine state, or a constant, in breakpoint conditions and watches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    Constant(u16),
+    Register(u8),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    /// The byte of memory at an address
+    Memory(u16),
+    /// The byte of memory at I
+    MemoryAtI,
+}
+
+impl Operand {
+    fn value(self, chip8: &Machine<'_>) -> u16 {
+        let core = chip8.core();
+        let byte = |addr: u16| core.memory().get(addr as usize).copied().unwrap_or(0) as u16;
+
+        match self {
+            Operand::Constant(val) => val,
+            Operand::Register(x) => core.registers()[x as usize] as u16,
+            Operand::I => core.i(),
+            Operand::Pc => core.pc(),
+            Operand::Sp => core.sp() as u16,
+            Operand::DelayTimer => chip8.delay_timer() as u16,
+            Operand::SoundTimer => chip8.sound_timer() as u16,
+            Operand::Memory(addr) => byte(addr),
+            Operand::MemoryAtI => byte(core.i()),
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            bail!("Expected a value");
+        }
+        if let Some(addr) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            return match addr.trim() {
+                "i" | "I" => Ok(Operand::MemoryAtI),
+                addr => parse_addr(addr).map(Operand::Memory),
+            };
+        }
 
-            print!("cmd: ");
-            std::io::stdout().flush().expect("couldn't flush stdout");
+        let operand = match s.to_ascii_lowercase().as_str() {
+            "i" => Operand::I,
+            "pc" => Operand::Pc,
+            "sp" => Operand::Sp,
+            "dt" => Operand::DelayTimer,
+            "st" => Operand::SoundTimer,
+            name => match name.strip_prefix('v').map(|x| u8::from_str_radix(x, 16)) {
+                Some(Ok(x)) if x < 16 => Operand::Register(x),
+                _ => Operand::Constant(parse_addr(s)?),
+            },
+        };
 
-            if std::io::stdin().read_line(&mut cmd).is_ok() {
-                match &cmd[..] {
-                    "\n" | "s\n" | "step\n" => {
-                        chip8.tick().expect("Error ticking chip8");
-                        println!("{}", chip8);
-                        println!();
+        Ok(operand)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Constant(val) => write!(f, "{:#X}", val),
+            Operand::Register(x) => write!(f, "v{:X}", x),
+            Operand::I => write!(f, "i"),
+            Operand::Pc => write!(f, "pc"),
+            Operand::Sp => write!(f, "sp"),
+            Operand::DelayTimer => write!(f, "dt"),
+            Operand::SoundTimer => write!(f, "st"),
+            Operand::Memory(addr) => write!(f, "[{:04X}]", addr),
+            Operand::MemoryAtI => write!(f, "[i]"),
+        }
+    }
+}
+
+type Comparison = fn(&u16, &u16) -> bool;
+
+/// The comparison operators of conditions, as written
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", u16::eq),
+    ("!=", u16::ne),
+    ("<=", u16::le),
+    (">=", u16::ge),
+    ("<", u16::lt),
+    (">", u16::gt),
+];
+
+/// The condition of a breakpoint, a comparison of two operands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Condition {
+    lhs: Operand,
+    /// The index of the operator in [`COMPARISONS`]
+    op: usize,
+    rhs: Operand,
+}
+
+impl Condition {
+    fn holds(&self, chip8: &Machine<'_>) -> bool {
+        (COMPARISONS[self.op].1)(&self.lhs.value(chip8), &self.rhs.value(chip8))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (op, (lhs, rhs)) = COMPARISONS
+            .iter()
+            .enumerate()
+            .find_map(|(op, (text, _))| s.split_once(text).map(|sides| (op, sides)))
+            .with_context(|| format!("\"{}\" doesn't compare anything", s.trim()))?;
+
+        Ok(Condition {
+            lhs: lhs.parse()?,
+            op,
+            rhs: rhs.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, COMPARISONS[self.op].0, self.rhs)
+    }
+}
+
+/// A watched value and what it was after the last instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Watch {
+    operand: Operand,
+    value: u16,
+}
+
+/// A debugging session saved as JSON by `save`, e.g.
+///
+/// ```json
+/// {"breakpoints": [{"addr": 516, "condition": "v1 == 0x1"}], "watches": ["v0"], "snapshot": "..."}
+/// ```
+///
+/// Conditions and watches are stored as entered, the snapshot as the hex string of its bytes
+/// (see [`Snapshot::to_bytes`]).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Session {
+    breakpoints: Vec<SessionBreakpoint>,
+    watches: Vec<String>,
+    snapshot: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SessionBreakpoint {
+    addr: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    condition: Option<String>,
+}
+
+/// A request in MI mode
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    /// Returned with the response, to match them up
+    #[serde(default)]
+    id: serde_json::Value,
+    command: String,
+}
+
+/// Where running stops without a breakpoint, for `until`, `next` and `finish`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Until {
+    /// The address to stop at, any if `None`
+    pc: Option<u16>,
+    /// The deepest stack pointer to stop at, to skip the address in deeper calls
+    sp: u8,
+}
+
+impl Until {
+    fn reached(self, core: &Core<'_>) -> bool {
+        self.pc.is_none_or(|pc| pc == core.pc()) && core.sp() <= self.sp
+    }
+}
+
+struct Debugger<'memory> {
+    chip8: Machine<'memory>,
+    /// The breakpoints by address, with the condition they stop on
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    watches: Vec<Watch>,
+    /// The states before the latest instructions, the latest last
+    history: VecDeque<Snapshot>,
+    running: bool,
+    until: Option<Until>,
+    prompt: Prompt,
+    /// The first address of the memory view, following I if not scrolled manually
+    memory_start: Option<usize>,
+    status: String,
+    /// The output of the commands, the latest line last
+    console: VecDeque<String>,
+}
+
+/// The number of instructions `list` prints by default
+const LIST_COUNT: u16 = 0x10;
+
+/// The number of lines of command output kept
+const CONSOLE_LINES: usize = 500;
+
+/// The number of instructions `rs` can step back
+const HISTORY_LEN: usize = 2048;
+
+impl Debugger<'_> {
+    /// Execute one instruction, pausing on errors and changes of watched values
+    fn step(&mut self) -> bool {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.chip8.snapshot());
+
+        if let Err(e) = self.chip8.tick() {
+            self.pause(format!("Stopped: {}", e));
+            return false;
+        }
+
+        let mut changed = Vec::new();
+        for watch in &mut self.watches {
+            let value = watch.operand.value(&self.chip8);
+            if value != watch.value {
+                changed.push(format!(
+                    "{}: {:X} -> {:X}",
+                    watch.operand, watch.value, value
+                ));
+                watch.value = value;
+            }
+        }
+        if changed.is_empty() {
+            return true;
+        }
+
+        let pc = self.chip8.core().pc();
+        self.pause(format!("{} at {:04X}", changed.join(", "), pc));
+        for line in changed {
+            self.print(format!("{:04X}  {}", pc, line));
+        }
+        false
+    }
+
+    /// Stop running, showing `status`
+    fn pause(&mut self, status: String) {
+        self.running = false;
+        self.until = None;
+        self.status = status;
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.status = "Running".into();
+    }
+
+    /// Execute the instructions of one frame, pausing on breakpoints
+    fn run_frame(&mut self) {
+        for _ in 0..TICKS_PER_FRAME {
+            if !self.step() {
+                return;
+            }
+
+            let pc = self.chip8.core().pc();
+            if let Some(condition) = self.breakpoints.get(&pc) {
+                if condition.is_none_or(|condition| condition.holds(&self.chip8)) {
+                    self.pause(format!("Breakpoint at {:04X}", pc));
+                    return;
+                }
+            }
+            if self
+                .until
+                .is_some_and(|until| until.reached(self.chip8.core()))
+            {
+                self.pause(format!("Reached {:04X}", pc));
+                return;
+            }
+        }
+    }
+
+    /// Handle a key press, returns whether the debugger should quit
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if self.prompt.line.is_some() {
+            match code {
+                KeyCode::Enter => {
+                    let line = self.prompt.submit();
+                    self.print(format!(":{}", line));
+                    match line.parse() {
+                        Ok(command) => self.run_command(command),
+                        Err(e) => self.status = format!("{:#}", e),
                     }
-                    "e\n" | "q\n" | "exit\n" | "quit\n" => break,
-                    _ => (),
+                }
+                KeyCode::Tab => self.complete(),
+                code => self.prompt.edit(code),
+            }
+            return false;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char(':') => self.prompt.open(),
+            KeyCode::Char('s') | KeyCode::Char(' ') => self.run_command(Command::Step),
+            KeyCode::Char('n') => self.run_command(Command::Next),
+            KeyCode::Char('f') => self.run_command(Command::Finish),
+            KeyCode::Char('r') if self.running => self.pause("Paused".into()),
+            KeyCode::Char('r') => self.resume(),
+            KeyCode::Char('b') => {
+                let pc = self.chip8.core().pc();
+                if self.breakpoints.remove(&pc).is_none() {
+                    self.breakpoints.insert(pc, None);
+                }
+            }
+            KeyCode::PageUp => self.memory_start = Some(self.memory_start().saturating_sub(0x40)),
+            KeyCode::PageDown => {
+                let last = self.chip8.core().memory().len() - 0x10;
+                self.memory_start = Some((self.memory_start() + 0x40).min(last));
+            }
+            KeyCode::Char('i') => self.memory_start = None,
+            _ => (),
+        }
+
+        false
+    }
+
+    /// Execute `command`, showing errors in the status line
+    fn run_command(&mut self, command: Command) {
+        if let Err(e) = self.execute(command) {
+            self.status = format!("{:#}", e);
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Step => {
+                self.pause(String::new());
+                self.step();
+            }
+            Command::Pause => self.pause("Paused".into()),
+            Command::Break(addr, condition) => {
+                self.breakpoints.insert(addr, condition);
+                self.status = match condition {
+                    Some(condition) => format!("Breakpoint at {:04X} if {}", addr, condition),
+                    None => format!("Breakpoint at {:04X}", addr),
+                };
+            }
+            Command::Delete(addr) if self.breakpoints.remove(&addr).is_some() => {
+                self.status = format!("Deleted breakpoint at {:04X}", addr);
+            }
+            Command::Delete(addr) => self.status = format!("No breakpoint at {:04X}", addr),
+            Command::Continue => self.resume(),
+            Command::Until(addr) => self.run_until(Some(addr), u8::MAX),
+            Command::Next => {
+                let core = self.chip8.core();
+                let (pc, sp) = (core.pc(), core.sp());
+                if core.opcode() & 0xF000 == 0x2000 {
+                    self.run_until(Some(pc.wrapping_add(2)), sp);
+                } else {
+                    self.pause(String::new());
+                    self.step();
                 }
             }
+            Command::Finish => match self.chip8.core().sp() {
+                0 => bail!("Not in a subroutine"),
+                sp => self.run_until(None, sp - 1),
+            },
+            Command::ReverseStep(count) => {
+                if self.history.is_empty() {
+                    bail!("No earlier state");
+                }
+                let keep = self.history.len().saturating_sub(count.max(1) as usize);
+                let snapshot = self.history.drain(keep..).next().unwrap();
+                self.chip8.restore(&snapshot)?;
+
+                for watch in &mut self.watches {
+                    watch.value = watch.operand.value(&self.chip8);
+                }
+                self.pause(format!("Stepped back to {:04X}", self.chip8.core().pc()));
+            }
+            Command::Backtrace => {
+                for line in self.backtrace() {
+                    self.print(line);
+                }
+            }
+            Command::Watch(operand) => {
+                if let Operand::Constant(_) = operand {
+                    bail!("{} is a constant", operand);
+                }
+                if !self.watches.iter().any(|watch| watch.operand == operand) {
+                    let value = operand.value(&self.chip8);
+                    self.watches.push(Watch { operand, value });
+                }
+                self.status = format!("Watching {}", operand);
+            }
+            Command::Unwatch(operand) => {
+                let count = self.watches.len();
+                self.watches.retain(|watch| watch.operand != operand);
+                if self.watches.len() == count {
+                    bail!("Not watching {}", operand);
+                }
+                self.status = format!("Stopped watching {}", operand);
+            }
+            Command::Memory { addr, len } => {
+                let mem = self.chip8.core().memory();
+                let start = addr as usize;
+                let bytes = mem
+                    .get(start..start + len as usize)
+                    .with_context(|| format!("Memory ends at {:04X}", mem.len()))?;
+
+                let lines: Vec<String> = bytes
+                    .chunks(16)
+                    .enumerate()
+                    .map(|(row, bytes)| {
+                        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                        format!("{:04X}  {}", start + row * 16, hex.join(" "))
+                    })
+                    .collect();
+                for line in lines {
+                    self.print(line);
+                }
+            }
+            Command::List { addr, count } => {
+                let center = addr.unwrap_or(self.chip8.core().pc());
+                for (_, line) in self.listing(center as usize, count as usize) {
+                    self.print(line);
+                }
+            }
+            Command::SetMemory(addr, bytes) => {
+                let core = self.chip8.core_mut();
+                let len = core.memory().len();
+                let start = addr as usize;
+                core.memory_range_mut(start..start + bytes.len())
+                    .with_context(|| format!("Memory ends at {:04X}", len))?
+                    .copy_from_slice(&bytes);
+            }
+            Command::Assemble(addr, instruction) => {
+                let bytes = instruction.encode();
+                let core = self.chip8.core_mut();
+                let len = core.memory().len();
+                let start = addr as usize;
+                core.memory_range_mut(start..start + bytes.len())
+                    .with_context(|| format!("Memory ends at {:04X}", len))?
+                    .copy_from_slice(&bytes);
+
+                let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                self.print(format!("{:04X}  {}  {}", addr, hex, instruction));
+            }
+            Command::SetRegister(x, val) => self.chip8.core_mut().registers_mut()[x as usize] = val,
+            Command::SetI(val) => self.chip8.core_mut().set_i(val),
+            Command::SetPc(val) => self.chip8.core_mut().set_pc(val),
+            Command::Save(path) => {
+                self.save(&path)
+                    .with_context(|| format!("Saving session \"{}\"", path.display()))?;
+                self.status = format!("Saved session to {}", path.display());
+            }
+            Command::Load(path) => {
+                self.load(&path)
+                    .with_context(|| format!("Loading session \"{}\"", path.display()))?;
+                self.pause(format!("Loaded session {}", path.display()));
+            }
         }
 
-        tx_exit_gui.send(()).expect("Sending exit to gui");
+        Ok(())
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let session = Session {
+            breakpoints: self
+                .breakpoints
+                .iter()
+                .map(|(&addr, condition)| SessionBreakpoint {
+                    addr,
+                    condition: condition.map(|condition| condition.to_string()),
+                })
+                .collect(),
+            watches: self
+                .watches
+                .iter()
+                .map(|watch| watch.operand.to_string())
+                .collect(),
+            snapshot: self
+                .chip8
+                .snapshot()
+                .to_bytes()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        };
+
+        std::fs::write(path, serde_json::to_string_pretty(&session)?)?;
+        Ok(())
+    }
+
+    /// Replace the breakpoints, watches and machine state with the ones of a saved session
+    ///
+    /// Nothing changes if the session is invalid. The history of `rs` is cleared.
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let session: Session = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+        let breakpoints = session
+            .breakpoints
+            .iter()
+            .map(|breakpoint| {
+                let condition = breakpoint
+                    .condition
+                    .as_deref()
+                    .map(str::parse)
+                    .transpose()?;
+                Ok((breakpoint.addr, condition))
+            })
+            .collect::<Result<_>>()?;
+        let operands = session
+            .watches
+            .iter()
+            .map(|operand| operand.parse())
+            .collect::<Result<Vec<Operand>>>()?;
+
+        let hex = session.snapshot.as_bytes();
+        if !hex.len().is_multiple_of(2) {
+            bail!("The snapshot has an odd number of hex digits");
+        }
+        let bytes = hex
+            .chunks(2)
+            .map(|digits| {
+                std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .context("The snapshot is not a hex string")
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        self.chip8.restore(&Snapshot::from_bytes(&bytes)?)?;
+
+        self.breakpoints = breakpoints;
+        self.watches = operands
+            .into_iter()
+            .map(|operand| Watch {
+                operand,
+                value: operand.value(&self.chip8),
+            })
+            .collect();
+        self.history.clear();
+
+        Ok(())
+    }
+
+    /// The frames of the call stack, the current one first
+    ///
+    /// Every frame shows where it is executing and the subroutine it is in, as labeled by
+    /// the disassembler.
+    fn backtrace(&self) -> Vec<String> {
+        let core = self.chip8.core();
+        // The stack holds the CALL instructions which entered the frames
+        let calls = core.stack().iter().copied();
+
+        let locations = std::iter::once(core.pc()).chain(calls.clone().rev());
+        let subroutines = calls
+            .rev()
+            .map(|call| {
+                let target = core
+                    .memory()
+                    .get(call as usize..call as usize + 2)
+                    .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]) & 0xFFF);
+                format!("L_0x{:03X}", target)
+            })
+            .chain(std::iter::once("the program".to_string()));
+
+        locations
+            .zip(subroutines)
+            .enumerate()
+            .map(|(idx, (addr, subroutine))| format!("#{:<2} {:04X}  in {}", idx, addr, subroutine))
+            .collect()
+    }
+
+    /// Run until the PC is `pc` with at most `sp` return addresses on the stack
+    fn run_until(&mut self, pc: Option<u16>, sp: u8) {
+        self.resume();
+        self.until = Some(Until { pc, sp });
+    }
+
+    /// Complete the word before the cursor to a command name or label
+    ///
+    /// If several completions are possible, the word is extended to the part they have in
+    /// common and the completions are printed.
+    fn complete(&mut self) {
+        let Some((word, first)) = self.prompt.word() else {
+            return;
+        };
+        let labels: Vec<String> = if first { Vec::new() } else { self.labels() };
+        let candidates: Vec<&str> = if first {
+            COMMANDS.to_vec()
+        } else {
+            labels.iter().map(String::as_str).collect()
+        };
+
+        let matches: Vec<&str> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.len() > word.len())
+            .filter(|candidate| candidate[..word.len()].eq_ignore_ascii_case(word))
+            .collect();
+        let (word, completion) = match matches[..] {
+            [] => return,
+            [completion] => (word.to_string(), format!("{} ", completion)),
+            _ => (word.to_string(), common_prefix(&matches).to_string()),
+        };
+        if matches.len() > 1 {
+            self.print(matches.join("  "));
+        }
+
+        self.prompt.complete(&word, &completion);
+    }
+
+    /// The labels of all jump and call targets in the program, as named by the disassembler
+    fn labels(&self) -> Vec<String> {
+        let program = self.chip8.core().memory().get(0x200..).unwrap_or_default();
+        let targets: BTreeSet<u16> = instructions::decode_iter(program, 0x200)
+            .filter_map(|(_, instruction)| match instruction {
+                Ok(Instruction::I1NNN(nnn) | Instruction::I2NNN(nnn) | Instruction::IBNNN(nnn)) => {
+                    Some(nnn.value())
+                }
+                _ => None,
+            })
+            .collect();
+
+        targets
+            .into_iter()
+            .map(|addr| format!("L_0x{:03X}", addr))
+            .collect()
+    }
+
+    /// Execute a request of MI mode, returning the response
+    fn respond(&mut self, request: &str) -> serde_json::Value {
+        let request: Request = match serde_json::from_str(request) {
+            Ok(request) => request,
+            Err(e) => return json!({"ok": false, "error": format!("Invalid request: {}", e)}),
+        };
+
+        let result = request
+            .command
+            .parse()
+            .and_then(|command| self.execute(command));
+        let output: Vec<String> = self.console.drain(..).collect();
+
+        match result {
+            Ok(()) => json!({
+                "id": request.id,
+                "ok": true,
+                "status": self.status,
+                "output": output,
+                "state": self.state(),
+            }),
+            Err(e) => json!({
+                "id": request.id,
+                "ok": false,
+                "error": format!("{:#}", e),
+                "output": output,
+            }),
+        }
+    }
+
+    /// The registers, timers and stack as JSON, for MI mode
+    fn state(&self) -> serde_json::Value {
+        let core = self.chip8.core();
+
+        json!({
+            "running": self.running,
+            "pc": core.pc(),
+            "i": core.i(),
+            "sp": core.sp(),
+            "registers": core.registers(),
+            "delay_timer": self.chip8.delay_timer(),
+            "sound_timer": self.chip8.sound_timer(),
+            "stack": core.stack(),
+            "stack_capacity": core.stack_capacity(),
+            "instructions": self.chip8.stats().instructions,
+        })
+    }
+
+    /// Add a line to the command output
+    fn print(&mut self, line: String) {
+        if self.console.len() == CONSOLE_LINES {
+            self.console.pop_front();
+        }
+        self.console.push_back(line);
+    }
+
+    fn memory_start(&self) -> usize {
+        self.memory_start
+            .unwrap_or(self.chip8.core().i() as usize & !0xF)
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let framebuffer = self.chip8.core().framebuffer();
+
+        let [main, status] = split(
+            frame.area(),
+            Direction::Vertical,
+            [Constraint::Min(0), Constraint::Length(1)],
+        );
+        let [left, right] = split(
+            main,
+            Direction::Horizontal,
+            [
+                Constraint::Length(framebuffer.width() as u16 + 2),
+                Constraint::Min(0),
+            ],
+        );
+        let [display, memory] = split(
+            left,
+            Direction::Vertical,
+            [
+                Constraint::Length(framebuffer.height() as u16 / 2 + 2),
+                Constraint::Min(0),
+            ],
+        );
+        let [registers, lower_right, console] = split(
+            right,
+            Direction::Vertical,
+            [
+                Constraint::Length(8),
+                Constraint::Min(0),
+                Constraint::Length(10),
+            ],
+        );
+        let [disassembly, stack] = split(
+            lower_right,
+            Direction::Horizontal,
+            [Constraint::Min(0), Constraint::Length(14)],
+        );
+
+        let lines: Vec<Line> = (0..framebuffer.height() / 2)
+            .map(|line| Line::raw(half_blocks(framebuffer, line)))
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(pane("Display")), display);
+
+        frame.render_widget(self.registers().block(pane("Registers")), registers);
+        frame.render_widget(
+            self.disassembly(disassembly.height.saturating_sub(2))
+                .block(pane("Disassembly")),
+            disassembly,
+        );
+        frame.render_widget(self.stack().block(pane("Stack")), stack);
+        frame.render_widget(
+            self.console(console.height.saturating_sub(2))
+                .block(pane("Console")),
+            console,
+        );
+        frame.render_widget(
+            self.memory(memory.height.saturating_sub(2))
+                .block(pane("Memory")),
+            memory,
+        );
+
+        let help = "[s]tep [n]ext [f]inish [r]un/pause [b]reakpoint [PgUp/PgDn] memory \
+            [i] follow I [:]command [q]uit";
+        let line = match &self.prompt.line {
+            Some(line) => {
+                let cursor = line[..self.prompt.cursor].chars().count() as u16;
+                frame.set_cursor_position((status.x + 1 + cursor, status.y));
+                format!(":{}", line)
+            }
+            None => format!("{}  {}", help, self.status),
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+
+    fn registers(&self) -> Paragraph<'_> {
+        let core = self.chip8.core();
+
+        let mut lines = vec![
+            Line::raw(format!(
+                "PC {:04X}  I {:04X}  SP {:02X}/{:02X}",
+                core.pc(),
+                core.i(),
+                core.sp(),
+                core.stack_capacity()
+            )),
+            Line::raw(format!(
+                "DT {:02X}    ST {:02X}",
+                self.chip8.delay_timer(),
+                self.chip8.sound_timer()
+            )),
+        ];
+
+        for (row, regs) in core.registers().chunks(4).enumerate() {
+            let text: Vec<String> = regs
+                .iter()
+                .enumerate()
+                .map(|(col, val)| format!("V{:X} {:02X}", row * 4 + col, val))
+                .collect();
+            lines.push(Line::raw(text.join("  ")));
+        }
+
+        Paragraph::new(lines)
+    }
+
+    /// The instructions around the PC, which is kept in the middle of the view
+    fn disassembly(&self, height: u16) -> Paragraph<'_> {
+        let pc = self.chip8.core().pc() as usize;
+
+        let lines: Vec<Line> = self
+            .listing(pc, height as usize)
+            .into_iter()
+            .map(|(addr, text)| {
+                let line = Line::raw(text);
+                if addr == pc {
+                    line.style(Style::new().add_modifier(Modifier::REVERSED))
+                } else {
+                    line
+                }
+            })
+            .collect();
+
+        Paragraph::new(lines)
+    }
+
+    /// `count` instructions with `center` in the middle, marking the PC with an arrow and
+    /// breakpoints with a dot
+    fn listing(&self, center: usize, count: usize) -> Vec<(usize, String)> {
+        let core = self.chip8.core();
+        let mem = core.memory();
+        let pc = core.pc() as usize;
+        let start = center.saturating_sub(count / 2 * 2);
+
+        instructions::decode_iter(mem.get(start..).unwrap_or_default(), start as u16)
+            .take(count)
+            .map(|(addr, instruction)| {
+                let addr = addr as usize;
+                let text = match instruction {
+                    Ok(instruction) => instruction.to_string(),
+                    Err(_) => {
+                        let bytes: String = mem[addr..]
+                            .iter()
+                            .take(2)
+                            .map(|b| format!("{:02X}", b))
+                            .collect();
+                        format!("{} ; invalid", bytes)
+                    }
+                };
+                let marker = match (addr == pc, self.breakpoints.contains_key(&(addr as u16))) {
+                    (true, true) => "●>",
+                    (true, false) => " >",
+                    (false, true) => "● ",
+                    (false, false) => "  ",
+                };
+
+                (addr, format!("{}{:04X}  {}", marker, addr, text))
+            })
+            .collect()
+    }
+
+    /// The latest lines of command output
+    fn console(&self, height: u16) -> Paragraph<'_> {
+        let skip = self.console.len().saturating_sub(height as usize);
+        let lines: Vec<Line> = self
+            .console
+            .iter()
+            .skip(skip)
+            .map(|line| Line::raw(line.as_str()))
+            .collect();
+
+        Paragraph::new(lines)
+    }
+
+    fn stack(&self) -> Paragraph<'_> {
+        let lines: Vec<Line> = self
+            .chip8
+            .core()
+            .stack()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(idx, addr)| Line::raw(format!("{:X}: {:04X}", idx, addr)))
+            .collect();
+
+        Paragraph::new(lines)
+    }
+
+    /// A hexdump of the memory, highlighting the bytes at I and the PC
+    fn memory(&self, height: u16) -> Paragraph<'_> {
+        let core = self.chip8.core();
+        let mem = core.memory();
+        let (i, pc) = (core.i() as usize, core.pc() as usize);
+
+        let lines: Vec<Line> = (self.memory_start()..mem.len())
+            .step_by(16)
+            .take(height as usize)
+            .map(|row| {
+                let mut spans = vec![Span::raw(format!("{:04X} ", row))];
+
+                for (addr, val) in mem[row..row + 16].iter().enumerate() {
+                    let addr = row + addr;
+                    let style = if addr == pc || addr == pc + 1 {
+                        Style::new().add_modifier(Modifier::REVERSED)
+                    } else if addr == i {
+                        Style::new().add_modifier(Modifier::UNDERLINED)
+                    } else {
+                        Style::new()
+                    };
+
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(format!("{:02X}", val), style));
+                }
+
+                Line::from(spans)
+            })
+            .collect();
+
+        Paragraph::new(lines)
+    }
+}
+
+fn pane(title: &str) -> Block<'_> {
+    Block::new().borders(Borders::ALL).title(title)
+}
+
+fn split<const N: usize>(
+    area: Area,
+    direction: Direction,
+    constraints: [Constraint; N],
+) -> [Area; N] {
+    Layout::new(direction, constraints).areas(area)
+}
+
+/// Drive the debugger with the JSON requests read from stdin, until it is closed
+fn run_mi(debugger: &mut Debugger<'_>) -> Result<()> {
+    let (requests, received) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if requests.send(line).is_err() {
+                break;
+            }
+        }
     });
+    let mut stdout = io::stdout().lock();
+
+    loop {
+        // Block until the next request while paused
+        let line = if debugger.running {
+            match received.recv_timeout(FRAME) {
+                Ok(line) => Some(line?),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match received.recv() {
+                Ok(line) => Some(line?),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        if let Some(line) = line.filter(|line| !line.trim().is_empty()) {
+            if serde_json::from_str::<Request>(&line).is_ok_and(|request| request.command == "quit")
+            {
+                return Ok(());
+            }
+            writeln!(stdout, "{}", debugger.respond(&line))?;
+            stdout.flush()?;
+        }
+
+        if debugger.running {
+            debugger.run_frame();
+            if !debugger.running {
+                let output: Vec<String> = debugger.console.drain(..).collect();
+                let event = json!({
+                    "event": "stopped",
+                    "status": debugger.status,
+                    "output": output,
+                    "state": debugger.state(),
+                });
+                writeln!(stdout, "{}", event)?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    init_logging();
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mi = args.iter().any(|arg| arg == "--mi");
+    args.retain(|arg| arg != "--mi");
+    let path = match args.first() {
+        Some(path) => path.clone(),
+        None => {
+            eprintln!("{}", HELP);
+            return Ok(());
+        }
+    };
+
+    let mut mem = vec![0; 4096];
+    let mut reg = [0; 16];
+    let mut stack = [0; 16];
+
+    load_program(&path, &mut mem[..], QuirksConfig::START)
+        .with_context(|| format!("Loading program \"{}\"", path))?;
+
+    let chip8 = Chip8::new(
+        Core::new(&mut mem[..], &mut reg[..], &mut stack[..]),
+        CORE_FREQ,
+        PeripheralSet {
+            keypad: NullKeypad,
+            graphics: NullGraphics,
+            random: OsRandom::new().with_context(|| "Seeding random number generator")?,
+            delay_timer: DownTimer::new("delay"),
+            sound_timer: DownTimer::new("sound"),
+            speaker: NullSpeaker,
+            persistence: RamPersistence::default(),
+        },
+    )
+    .with_context(|| "Creating CHIP-8")?;
+
+    let mut debugger = Debugger {
+        chip8,
+        breakpoints: BTreeMap::new(),
+        watches: Vec::new(),
+        history: VecDeque::new(),
+        running: false,
+        until: None,
+        prompt: Prompt::default(),
+        memory_start: None,
+        status: String::new(),
+        console: VecDeque::new(),
+    };
+    if mi {
+        return run_mi(&mut debugger);
+    }
+
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<()> {
+        loop {
+            terminal.draw(|frame| debugger.draw(frame))?;
+
+            // Block until the next key press while paused
+            if !debugger.running || event::poll(FRAME)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && debugger.handle_key(key.code) {
+                        return Ok(());
+                    }
+                }
+            }
+
+            if debugger.running {
+                debugger.run_frame();
+            }
+        }
+    })();
+    ratatui::restore();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    minifb.run(rx_exit_gui).expect("Running minifb failed");
+    #[test]
+    fn parse_addr_forms() {
+        assert_eq!(parse_addr("2A4").unwrap(), 0x2A4);
+        assert_eq!(parse_addr("0x2A4").unwrap(), 0x2A4);
+        assert_eq!(parse_addr("0X2A4").unwrap(), 0x2A4);
+        assert_eq!(parse_addr("L_2A4").unwrap(), 0x2A4);
+        assert_eq!(parse_addr("L_0x2A4").unwrap(), 0x2A4);
+        assert_eq!(parse_addr("L_0X2A4").unwrap(), 0x2A4);
+        assert!(parse_addr("L_").is_err());
+        assert!(parse_addr("0xG").is_err());
+    }
 }
@@ -1,22 +1,408 @@
-use chip8_core::instructions::Instruction;
+use anyhow::{bail, Context, Result};
+use chip8_core::core::Coverage;
+use chip8_core::instructions::{Address, Instruction, Operand, Register, Value8};
 use chip8_core::Error;
-use chip8_tools::util::load_program;
+use chip8_tools::util::coverage::CoverageReport;
+use chip8_tools::util::read_rom;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::str::FromStr;
 
-fn main() {
-    let mut rom = vec![0; 2048];
-    let path = std::env::args().nth(1).expect("Give path to ROM");
+const HELP: &str = "\
+chip8-dis - A disassembler for CHIP-8 ROMs
 
-    load_program(path, &mut rom[..]).expect("Failed loading ROM");
+USAGE:
+    chip8-dis [OPTIONS] ROM_FILE
 
-    for (idx, chunk) in rom.chunks(2).skip(0x100).enumerate() {
-        let addr = 0x200 + idx * 2;
+ARGS:
+    ROM_FILE    Path to a CHIP-8 ROM (*.ch8), - reads it from stdin
 
-        match Instruction::try_from(chunk) {
-            Ok(opcode) => println!("0x{:04X}  {}", addr, opcode),
-            Err(Error::InvalidInstruction(opcode)) => {
-                println!("0x{:04X}               ; 0x{:04X} (invalid)", addr, opcode)
+OPTIONS:
+    --format FORMAT
+                The output format (default listing):
+                listing  addresses and mnemonics
+                octo     source code for the Octo assembler
+                json     an array of {address, bytes, label, mnemonic, operands, modified}
+    --coverage FILE
+                A coverage map written by chip8-emu --coverage. Executed addresses are
+                disassembled as code and sprite data as data, regardless of the control
+                flow. Instructions overwritten by self-modifying code are marked.
+";
+
+const PROGRAM_START: usize = 0x200;
+
+/// The name of the label at `addr`
+fn label(addr: u16) -> String {
+    format!("L_0x{:03X}", addr)
+}
+
+/// Decode the instruction at `addr` of memory, if there are two bytes left
+fn decode(mem: &[u8], addr: usize) -> Option<Result<Instruction, Error>> {
+    mem.get(addr..addr + 2)?;
+    Some(Instruction::decode(&mem[addr..], addr as u16))
+}
+
+/// The size of the instruction at `addr`, 4 bytes for `LD I, LONG nnnn`
+fn size(mem: &[u8], addr: usize) -> usize {
+    match decode(mem, addr) {
+        Some(Ok(instruction)) => instruction.size() as usize,
+        _ => 2,
+    }
+}
+
+/// Follow the control flow from the program start, marking all reachable instructions
+///
+/// Returns the addresses of reachable instructions and the targets of all reachable jumps
+/// and calls. `JP V0, addr` can't be followed statically, only `addr` itself is assumed
+/// to be reachable.
+fn traverse(mem: &[u8]) -> (BTreeSet<usize>, BTreeSet<u16>) {
+    use Instruction::*;
+
+    let mut code = BTreeSet::new();
+    let mut labels = BTreeSet::new();
+    let mut pending = vec![PROGRAM_START];
+
+    while let Some(addr) = pending.pop() {
+        if addr < PROGRAM_START || !code.insert(addr) {
+            continue;
+        }
+
+        let instruction = match decode(mem, addr) {
+            Some(Ok(instruction)) => instruction,
+            _ => {
+                code.remove(&addr);
+                continue;
+            }
+        };
+
+        let next = addr + instruction.size() as usize;
+        match instruction {
+            I00EE | I00FD => (),
+            I1NNN(nnn) | IBNNN(nnn) => {
+                labels.insert(nnn.value());
+                pending.push(nnn.value() as usize);
+            }
+            I2NNN(nnn) => {
+                labels.insert(nnn.value());
+                pending.push(nnn.value() as usize);
+                pending.push(next);
+            }
+            I3XNN(..) | I4XNN(..) | I5XY0(..) | I9XY0(..) | IEX9E(..) | IEXA1(..) => {
+                pending.push(next);
+                pending.push(next + size(mem, next));
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // Only targets which are disassembled as code get a label
+    labels.retain(|&addr| code.contains(&(addr as usize)));
+
+    (code, labels)
+}
+
+/// Format an instruction, replacing jump and call targets with their labels
+fn format_instruction(instruction: &Instruction, labels: &BTreeSet<u16>) -> String {
+    let target = |nnn: &Address| {
+        if labels.contains(&nnn.value()) {
+            label(nnn.value())
+        } else {
+            nnn.to_string()
+        }
+    };
+
+    match instruction {
+        Instruction::I1NNN(nnn) => format!("JP {}", target(nnn)),
+        Instruction::I2NNN(nnn) => format!("CALL {}", target(nnn)),
+        Instruction::IBNNN(nnn) => format!("JP V0, {}", target(nnn)),
+        _ => instruction.to_string(),
+    }
+}
+
+/// The byte as a row of a sprite, e.g. `..####..`
+fn sprite_row(byte: u8) -> String {
+    (0..8)
+        .map(|bit| {
+            if byte >> (7 - bit) & 0x01 == 1 {
+                '#'
+            } else {
+                '.'
             }
-            Err(e) => println!("0x{:04X}  {:<10}", addr, e),
+        })
+        .collect()
+}
+
+/// Convert an instruction to Octo syntax
+fn format_octo(instruction: &Instruction, labels: &BTreeSet<u16>) -> String {
+    use Instruction::*;
+
+    let target = |nnn: &Address| {
+        if labels.contains(&nnn.value()) {
+            label(nnn.value())
+        } else {
+            format!("0x{:03X}", nnn.value())
         }
+    };
+    let v = |x: &Register| format!("v{:x}", x.index());
+    let nn = |vv: &Value8| format!("0x{:02X}", vv.value());
+
+    match instruction {
+        // Octo has no mnemonic for machine code calls, emit the raw bytes
+        I0NNN(nnn) => {
+            let [high, low] = nnn.value().to_be_bytes();
+            format!("0x{:02X} 0x{:02X}", high, low)
+        }
+        I00E0 => "clear".into(),
+        I00EE => "return".into(),
+        I1NNN(nnn) => format!("jump {}", target(nnn)),
+        I2NNN(nnn) if labels.contains(&nnn.value()) => target(nnn),
+        I2NNN(nnn) => format!(":call {}", target(nnn)),
+        // Octo conditions state when the next instruction is executed, not when it is skipped
+        I3XNN(x, vv) => format!("if {} != {} then", v(x), nn(vv)),
+        I4XNN(x, vv) => format!("if {} == {} then", v(x), nn(vv)),
+        I5XY0(x, y) => format!("if {} != {} then", v(x), v(y)),
+        I6XNN(x, vv) => format!("{} := {}", v(x), nn(vv)),
+        I7XNN(x, vv) => format!("{} += {}", v(x), nn(vv)),
+        I8XY0(x, y) => format!("{} := {}", v(x), v(y)),
+        I8XY1(x, y) => format!("{} |= {}", v(x), v(y)),
+        I8XY2(x, y) => format!("{} &= {}", v(x), v(y)),
+        I8XY3(x, y) => format!("{} ^= {}", v(x), v(y)),
+        I8XY4(x, y) => format!("{} += {}", v(x), v(y)),
+        I8XY5(x, y) => format!("{} -= {}", v(x), v(y)),
+        I8XY6(x, y) => format!("{} >>= {}", v(x), v(y)),
+        I8XY7(x, y) => format!("{} =- {}", v(x), v(y)),
+        I8XYE(x, y) => format!("{} <<= {}", v(x), v(y)),
+        I9XY0(x, y) => format!("if {} == {} then", v(x), v(y)),
+        IANNN(nnn) => format!("i := 0x{:03X}", nnn.value()),
+        IF000(nnnn) => format!("i := long 0x{:04X}", nnnn.value()),
+        IBNNN(nnn) => format!("jump0 {}", target(nnn)),
+        ICXNN(x, vv) => format!("{} := random {}", v(x), nn(vv)),
+        IDXYN(x, y, n) => format!("sprite {} {} 0x{:X}", v(x), v(y), n.value()),
+        IEX9E(x) => format!("if {} -key then", v(x)),
+        IEXA1(x) => format!("if {} key then", v(x)),
+        IFX07(x) => format!("{} := delay", v(x)),
+        IFX0A(x) => format!("{} := key", v(x)),
+        IFX15(x) => format!("delay := {}", v(x)),
+        IFX18(x) => format!("buzzer := {}", v(x)),
+        IFX1E(x) => format!("i += {}", v(x)),
+        IFX29(x) => format!("i := hex {}", v(x)),
+        IFX33(x) => format!("bcd {}", v(x)),
+        IFX55(x) => format!("save {}", v(x)),
+        IFX65(x) => format!("load {}", v(x)),
+        I00CN(n) => format!("scroll-down 0x{:X}", n.value()),
+        I00FB => "scroll-right".into(),
+        I00FC => "scroll-left".into(),
+        I00FD => "exit".into(),
+        I00FE => "lores".into(),
+        I00FF => "hires".into(),
+        IFX75(x) => format!("saveflags {}", v(x)),
+        IFX85(x) => format!("loadflags {}", v(x)),
+        IF002 => "audio".into(),
+        IFX3A(x) => format!("pitch := {}", v(x)),
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Listing,
+    Octo,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "listing" => Ok(Format::Listing),
+            "octo" => Ok(Format::Octo),
+            "json" => Ok(Format::Json),
+            _ => bail!("Unknown format \"{}\", expected listing, octo or json", s),
+        }
+    }
+}
+
+/// A disassembled instruction, or a byte of data
+#[derive(Debug)]
+struct Line<'a> {
+    addr: usize,
+    bytes: &'a [u8],
+    label: Option<u16>,
+    instruction: Option<Instruction>,
+    /// Whether self-modifying code overwrote the bytes after executing them
+    modified: bool,
+}
+
+/// Split the program into reachable instructions and data bytes
+///
+/// The accesses recorded in a `coverage` map take precedence over the control flow.
+fn disassemble<'a>(mem: &'a [u8], coverage: Option<&[Coverage]>) -> (Vec<Line<'a>>, BTreeSet<u16>) {
+    let (code, labels) = traverse(mem);
+    let accessed = |addr: usize, access: Coverage| {
+        coverage
+            .and_then(|coverage| coverage.get(addr))
+            .is_some_and(|entry| entry.contains(access))
+    };
+    let is_code = |addr: usize| {
+        accessed(addr, Coverage::EXECUTED)
+            || (code.contains(&addr) && !accessed(addr, Coverage::SPRITE))
+    };
+
+    let mut lines = Vec::new();
+    let mut addr = PROGRAM_START;
+    while addr < mem.len() {
+        let instruction = match decode(mem, addr) {
+            Some(Ok(instruction)) if is_code(addr) => Some(instruction),
+            _ => None,
+        };
+        let len = match &instruction {
+            Some(instruction) => instruction.size() as usize,
+            None => 1,
+        };
+
+        lines.push(Line {
+            addr,
+            bytes: &mem[addr..addr + len],
+            label: Some(addr as u16).filter(|addr| labels.contains(addr)),
+            instruction,
+            modified: (addr..addr + len).any(|addr| accessed(addr, Coverage::MODIFIED)),
+        });
+        addr += len;
+    }
+
+    (lines, labels)
+}
+
+fn print_listing(lines: &[Line<'_>], labels: &BTreeSet<u16>) {
+    for line in lines {
+        if let Some(addr) = line.label {
+            println!("{}:", label(addr));
+        }
+
+        match &line.instruction {
+            Some(instruction) if line.modified => println!(
+                "0x{:04X}  {:<24}; modified",
+                line.addr,
+                format_instruction(instruction, labels)
+            ),
+            Some(instruction) => println!(
+                "0x{:04X}  {}",
+                line.addr,
+                format_instruction(instruction, labels)
+            ),
+            None => println!(
+                "0x{:04X}  DB 0x{:02X}  ; {}",
+                line.addr,
+                line.bytes[0],
+                sprite_row(line.bytes[0])
+            ),
+        }
+    }
+}
+
+fn print_octo(lines: &[Line<'_>], labels: &BTreeSet<u16>) {
+    // Octo assembles from 0x200 on, every line must produce exactly the original bytes. The
+    // program is entered through `main`, which has to be the first label to avoid a jump.
+    println!(": main");
+    for line in lines {
+        if let Some(addr) = line.label {
+            println!(": {}", label(addr));
+        }
+
+        match &line.instruction {
+            Some(instruction) => println!(
+                "\t{:<24}# 0x{:04X}{}",
+                format_octo(instruction, labels),
+                line.addr,
+                if line.modified { " modified" } else { "" }
+            ),
+            None => println!(
+                "\t0x{:02X}{:<20}# {}",
+                line.bytes[0],
+                "",
+                sprite_row(line.bytes[0])
+            ),
+        }
+    }
+}
+
+fn print_json(lines: &[Line<'_>], labels: &BTreeSet<u16>) -> Result<()> {
+    let records: Vec<_> = lines
+        .iter()
+        .map(|line| {
+            let (mnemonic, operands) = match &line.instruction {
+                Some(instruction) => (
+                    instruction.mnemonic(),
+                    instruction
+                        .operands()
+                        .map(|operand| match operand {
+                            Operand::Address(nnn)
+                                if instruction.is_branch() && labels.contains(&nnn.value()) =>
+                            {
+                                label(nnn.value())
+                            }
+                            operand => operand.to_string(),
+                        })
+                        .collect(),
+                ),
+                None => ("DB", vec![format!("0x{:02X}", line.bytes[0])]),
+            };
+
+            json!({
+                "address": line.addr,
+                "bytes": line.bytes,
+                "label": line.label.map(label),
+                "mnemonic": mnemonic,
+                "operands": operands,
+                "modified": line.modified,
+            })
+        })
+        .collect();
+
+    println!("{}", serde_json::to_string_pretty(&records)?);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut format = Format::Listing;
+    let mut coverage = None;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = args.next().context("--format requires a value")?.parse()?,
+            "--coverage" => {
+                coverage = Some(args.next().context("--coverage requires a value")?);
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", HELP);
+            return Ok(());
+        }
+    };
+
+    let rom = read_rom(&path).with_context(|| format!("Loading program \"{}\"", path))?;
+
+    let mut mem = vec![0; PROGRAM_START];
+    mem.extend_from_slice(&rom);
+
+    let coverage = match coverage {
+        Some(path) => Some(CoverageReport::load(path)?.map(mem.len())),
+        None => None,
+    };
+    let (lines, labels) = disassemble(&mem, coverage.as_deref());
+
+    match format {
+        Format::Listing => print_listing(&lines, &labels),
+        Format::Octo => print_octo(&lines, &labels),
+        Format::Json => print_json(&lines, &labels)?,
+    }
+
+    Ok(())
+}
@@ -1,71 +1,1017 @@
-use std::sync::mpsc::channel;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
-use chip8_core::peripherals::DownTimer;
-use chip8_core::Chip8;
-use chip8_tools::util::load_program;
-use chip8_tools::util::minifb::MinifbDisplay;
-use log::{debug, error, info};
-use rand::prelude::*;
+use anyhow::{bail, Context, Result};
+use chip8_core::core::{Coverage, WriteProtection};
+use chip8_core::prelude::*;
+use chip8_core::quirks::Timing;
+use chip8_tools::util::audio::AudioOutput;
+use chip8_tools::util::c8b::Bundle;
+use chip8_tools::util::cheats::Cheats;
+use chip8_tools::util::config::Config;
+use chip8_tools::util::coverage::CoverageReport;
+use chip8_tools::util::keymap::{KeyMap, Layout};
+use chip8_tools::util::minifb::{MinifbDisplay, StatusAdapter};
+use chip8_tools::util::netplay::{NetplayKeypad, Session};
+use chip8_tools::util::palette::{parse_color, Palette, Theme};
+use chip8_tools::util::persistence::FilePersistence;
+use chip8_tools::util::playlist;
+use chip8_tools::util::quirks::{quirks_config, Quirk};
+use chip8_tools::util::record::RecordingKeypad;
+use chip8_tools::util::romdb::{self, RomDb, RomInfo};
+#[cfg(feature = "scripting")]
+use chip8_tools::util::script::Script;
+use chip8_tools::util::server::{Call, ControlServer};
+use chip8_tools::util::terminal::TerminalDisplay;
+use chip8_tools::util::trace::Tracer;
+use chip8_tools::util::websocket::WebSocketDisplay;
+use chip8_tools::util::{init_logging, load_program_bytes, read_file};
+use clap::{Parser, ValueEnum};
+use log::{debug, error, info, warn};
 
-const HELP: &str = "\
-chip8-emu - An emulator for the CHIP-8 CPU
+const KEYS: &str = "\
+Keys:
+  Esc        Quit, or return to the menu when running several ROMs
+  P          Pause and resume (default frontend only)
+  Backspace  Reset (default frontend only)
+  + / -      Run faster or slower (default frontend only)
+  Tab        Run eight times as fast while held (default frontend only)
+  F1         Show the ROM name and speed (default frontend only)
+  F9         Shrink the window (default frontend only)
+  F10        Grow the window (default frontend only)
+  F11        Toggle fullscreen, a borderless window with the default frontend
+  F12        Save a PNG screenshot to the current directory (default frontend only)
+";
 
-USAGE:
-    chip8-emu ROM_FILE
+/// An emulator for the CHIP-8 CPU
+#[derive(Debug, Parser)]
+#[command(name = "chip8-emu", version, after_help = KEYS)]
+struct Args {
+    /// Paths to CHIP-8 ROMs (*.ch8, *.c8b, *.zip, *.gz) or directories of them, several ROMs
+    /// are picked from a menu in the terminal. `-` reads a ROM from stdin
+    #[arg(required = true)]
+    roms: Vec<PathBuf>,
 
-ARGS:
-    ROM_FILE    Path to a CHIP-8 ROM (*.ch8)
-";
+    /// The number of instructions executed per second, defaults to the one recommended for a
+    /// known ROM or 700. Counts machine cycles with the vip-timing quirk, defaulting to the
+    /// COSMAC VIP's
+    #[arg(long, value_parser = parse_hz)]
+    hz: Option<u32>,
 
-fn main() -> Result<()> {
-    env_logger::init();
+    /// The initial size of a low resolution pixel in the window (default frontend only)
+    #[arg(long, default_value_t = MinifbDisplay::DEFAULT_SCALE, value_parser = parse_scale)]
+    scale: usize,
+
+    /// Comma separated quirks to enable, instead of the ones recommended for a known ROM
+    #[arg(long, value_enum, value_delimiter = ',')]
+    quirks: Vec<Quirk>,
+
+    /// Don't look up the speed and quirks of known ROMs in the ROM database
+    #[arg(long)]
+    no_autodetect: bool,
+
+    /// The colors of the display, overrides the config file (default frontend only)
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
+
+    /// Foreground and background color as RRGGBB,RRGGBB, overrides the theme (default
+    /// frontend only)
+    #[arg(long, value_parser = parse_palette)]
+    palette: Option<(u32, u32)>,
+
+    /// Disable audio output
+    #[arg(long)]
+    mute: bool,
+
+    /// The keyboard layout deciding the default keypad, overrides the config file
+    #[arg(long, value_enum)]
+    layout: Option<Layout>,
+
+    /// Read the settings from FILE instead of the user's config file
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Start paused, resume with P, or with any keypad key if the frontend has no hotkeys
+    #[arg(long)]
+    start_paused: bool,
+
+    /// Save the SCHIP RPL user flags to FILE instead of the user's data directory
+    #[arg(long, value_name = "FILE")]
+    flags: Option<PathBuf>,
+
+    /// Patch the ROM and freeze memory with the cheats in FILE, one `ADDR = BYTES` or
+    /// `freeze ADDR = BYTES` per line
+    #[arg(long, value_name = "FILE")]
+    cheats: Option<PathBuf>,
+
+    /// Stop with an error when the program writes to the interpreter area below the start
+    /// address, e.g. when it overwrites the font
+    #[arg(long)]
+    protect_interpreter: bool,
+
+    /// Record the keypad input and random seed to FILE
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Write every executed instruction and the resulting state to FILE
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Only write the last N instructions of the trace, once the CHIP-8 fails
+    #[arg(long, value_name = "N", requires = "trace", value_parser = clap::value_parser!(u32).range(1..))]
+    trace_last: Option<u32>,
+
+    /// Record which addresses are executed and read as sprites to FILE, adding to the
+    /// coverage already in FILE
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<PathBuf>,
+
+    /// Run the Rhai script FILE, whose hooks are called after every instruction, frame and
+    /// memory write, see chip8_tools::util::script. Requires the "scripting" feature
+    #[arg(long, value_name = "FILE", conflicts_with = "headless")]
+    script: Option<PathBuf>,
+
+    /// Print a breakdown of the input and display latency on exit
+    #[arg(long)]
+    latency: bool,
+
+    /// Wait for another chip8-emu to join on ADDR, e.g. 0.0.0.0:7788, and share the keypad
+    /// with it. Both run the ROM in lockstep with the speed and random seed of the host
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["join", "headless", "start_paused"])]
+    host: Option<String>,
+
+    /// Join the chip8-emu hosting on ADDR, see --host
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["headless", "start_paused"])]
+    join: Option<String>,
 
-    let path = match std::env::args().nth(1) {
-        Some(path) => path,
-        None => {
-            eprintln!("{}", HELP);
-            return Ok(());
+    /// Serve a JSON-RPC control API and screenshots over HTTP on ADDR, e.g. 127.0.0.1:8080
+    #[arg(long, value_name = "ADDR", conflicts_with = "headless")]
+    serve: Option<String>,
+
+    /// The frontend drawing the display and reading the keypad
+    #[arg(long, value_enum, default_value_t)]
+    backend: Backend,
+
+    /// The address the websocket frontend listens on for clients
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8765")]
+    websocket: String,
+
+    /// Run without display, audio and input as fast as possible, then print the final
+    /// state and a hash of the framebuffer
+    #[arg(long, conflicts_with = "backend")]
+    headless: bool,
+
+    /// The number of instructions to execute in headless mode
+    #[arg(long, default_value_t = HEADLESS_CYCLES)]
+    max_cycles: u64,
+}
+
+/// A frontend of the emulator
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum Backend {
+    /// A window, the only frontend with hotkeys
+    #[default]
+    Minifb,
+    /// The terminal
+    Terminal,
+    /// A window drawn with SDL2, requires the "sdl" feature
+    Sdl,
+    /// A GPU accelerated window, requires the "pixels" feature
+    Pixels,
+    /// Web browsers connected over a WebSocket, e.g. chip8_web/www/remote.html, without audio
+    #[value(name = "websocket")]
+    WebSocket,
+}
+
+/// The configuration of the CHIP-8 itself, shared by all frontends
+#[derive(Debug)]
+struct Options {
+    hz: u32,
+    quirks: QuirksConfig,
+    start_paused: bool,
+    record: Option<PathBuf>,
+    /// Where the RPL user flags are saved, `None` keeps them in memory
+    flags: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    trace_last: Option<usize>,
+    coverage: Option<PathBuf>,
+    script: Option<PathBuf>,
+    /// Applied to the memory already, the frozen bytes are kept while running
+    cheats: Option<Cheats>,
+    write_protection: WriteProtection,
+    /// The random seed, chosen at random if `None`
+    seed: Option<u64>,
+    /// The peer sharing the keypad
+    netplay: Option<Session>,
+}
+
+impl Options {
+    /// The options of running `rom`, with the recommended settings of a `known` ROM
+    fn new(args: &Args, rom: &Path, known: Option<&RomInfo>) -> Self {
+        let quirks = match known {
+            Some(known) if args.quirks.is_empty() => &known.quirks,
+            _ => &args.quirks,
+        };
+
+        let quirks = quirks_config(quirks);
+        // The recommended speeds count instructions, not machine cycles
+        let hz = match quirks.timing {
+            Timing::CosmacVip => args.hz.unwrap_or(Timing::VIP_FREQ),
+            Timing::Uniform => args
+                .hz
+                .or(known.and_then(|known| known.hz))
+                .unwrap_or(DEFAULT_HZ),
+        };
+
+        Self {
+            hz,
+            quirks,
+            start_paused: args.start_paused,
+            record: args.record.clone(),
+            flags: args
+                .flags
+                .clone()
+                .or_else(|| FilePersistence::default_path(rom)),
+            trace: args.trace.clone(),
+            trace_last: args.trace_last.map(|last| last as usize),
+            coverage: args.coverage.clone(),
+            script: args.script.clone(),
+            cheats: None,
+            write_protection: if args.protect_interpreter {
+                WriteProtection::Fail
+            } else {
+                WriteProtection::Off
+            },
+            seed: None,
+            netplay: None,
+        }
+    }
+
+    /// The tracer writing the file given with `--trace`
+    fn tracer(&self) -> Result<Option<Tracer>> {
+        let Some(path) = &self.trace else {
+            return Ok(None);
+        };
+
+        Tracer::new(path, self.trace_last)
+            .map(Some)
+            .with_context(|| format!("Creating trace \"{}\"", path.display()))
+    }
+
+    /// The coverage map to record into, continuing the one given with `--coverage`
+    fn coverage_map(&self, len: usize) -> Result<Option<Vec<Coverage>>> {
+        match &self.coverage {
+            Some(path) if path.exists() => Ok(Some(CoverageReport::load(path)?.map(len))),
+            Some(_) => Ok(Some(vec![Coverage::default(); len])),
+            None => Ok(None),
         }
+    }
+
+    /// Write the coverage map recorded by `core` to the file given with `--coverage`
+    fn save_coverage(&self, core: &Core<'_>) -> Result<()> {
+        match (&self.coverage, core.coverage()) {
+            (Some(path), Some(coverage)) => {
+                let report = CoverageReport::new(coverage);
+                for &(start, end) in &report.modified {
+                    info!("Self-modifying code at 0x{:03X}..0x{:03X}", start, end);
+                }
+                report.save(path)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn parse_scale(s: &str) -> Result<usize> {
+    let scale: usize = s.parse()?;
+    if scale == 0 || !scale.is_multiple_of(2) {
+        bail!("the scale has to be an even number, high resolution pixels are half as large");
+    }
+    Ok(scale)
+}
+
+fn parse_hz(s: &str) -> Result<u32> {
+    let hz = s.parse()?;
+    check_hz(hz)?;
+    Ok(hz)
+}
+
+/// Fail for frequencies the core doesn't support, before a CHIP-8 thread fails creating it
+fn check_hz(hz: u32) -> Result<()> {
+    let max = Chip8::<DefaultPeripherals>::MAX_CORE_FREQ;
+    if !(1..=max).contains(&hz) {
+        bail!(
+            "the frequency has to be between 1 and {} Hz, not {} Hz",
+            max,
+            hz
+        );
+    }
+    Ok(())
+}
+
+fn parse_palette(s: &str) -> Result<(u32, u32)> {
+    match s.split_once(',') {
+        Some((foreground, background)) => Ok((parse_color(foreground)?, parse_color(background)?)),
+        None => bail!("expected two colors, RRGGBB,RRGGBB"),
+    }
+}
+
+/// The default number of instructions executed per second
+const DEFAULT_HZ: u32 = 700;
+
+/// The default number of instructions executed in headless mode
+const HEADLESS_CYCLES: u64 = 1_000_000;
+
+fn main() -> Result<()> {
+    init_logging();
+
+    let args = Args::parse();
+
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
     };
+    if let Some(layout) = args.layout {
+        config.keymap.layout = layout;
+    }
+    let keymap = KeyMap::try_from(&config.keymap).context("Invalid keymap")?;
+
+    if let Some(theme) = args.theme {
+        config.palette.theme = theme;
+    }
+    if let Some((foreground, background)) = args.palette {
+        config.palette.foreground = Some(foreground);
+        config.palette.background = Some(background);
+    }
+    let palette = Palette::from(&config.palette);
 
+    let roms = playlist::expand(&args.roms)?;
+    match &roms[..] {
+        [] => bail!("No ROMs (*.ch8) found"),
+        [rom] => run_rom(&args, rom, keymap, palette)?,
+        _ => {
+            while let Some(rom) = playlist::pick(&roms)? {
+                if let Err(e) = run_rom(&args, rom, keymap.clone(), palette) {
+                    error!("{:#}", e);
+                }
+            }
+        }
+    }
+
+    info!("Exiting");
+    Ok(())
+}
+
+/// Run `path` until the frontend is closed
+fn run_rom(args: &Args, path: &Path, mut keymap: KeyMap, mut palette: Palette) -> Result<()> {
     let mut mem = vec![0; 4096];
-    let mut reg = vec![0; 16];
-    let mut stack = vec![0; 16];
 
-    info!("Loading program from {}", path);
-    load_program(&path, &mut mem[..]).with_context({
-        let path = path.clone();
-        move || format!("Loading program \"{}\"", path)
-    })?;
+    info!("Loading program from {}", path.display());
+    let data = read_file(path).with_context(|| format!("Reading \"{}\"", path.display()))?;
+    let bundle = if Bundle::is_bundle(&data) {
+        Some(Bundle::parse(&data).with_context(|| format!("Parsing \"{}\"", path.display()))?)
+    } else {
+        None
+    };
+    let rom = match &bundle {
+        Some(bundle) => {
+            let (platform, rom) = bundle
+                .rom()
+                .context("The bundle holds no bytecode for CHIP-8, SCHIP or XO-CHIP")?;
+            debug!("Running the bytecode for {:?}", platform);
+            rom
+        }
+        None => &data[..],
+    };
+    let mut known = if args.no_autodetect {
+        None
+    } else {
+        let known = RomDb::load_default()?.get(rom).cloned();
+        match &known {
+            Some(known) => info!("Detected {}", known.title),
+            None => debug!("Unknown ROM, SHA-1 {}", romdb::hash(rom)),
+        }
+        known
+    };
+    // The settings of a bundle are meant for exactly this ROM
+    if let Some(bundle) = &bundle {
+        let known = known.get_or_insert_with(RomInfo::default);
+        if let Some(name) = &bundle.name {
+            known.title.clone_from(name);
+        }
+        if let Some(cycles) = bundle.cycles_per_frame {
+            known.hz = Some(cycles as u32 * Chip8::<DefaultPeripherals>::TIMER_FREQ);
+        }
+        if args.theme.is_none() && args.palette.is_none() {
+            let colors = [
+                &mut palette.background,
+                &mut palette.foreground,
+                &mut palette.plane2,
+                &mut palette.blend,
+            ];
+            for (color, &rgb) in colors.into_iter().zip(&bundle.colors) {
+                *color = rgb;
+            }
+        }
+        for &(key, c) in &bundle.keys {
+            keymap.set(key, c);
+        }
+    }
+    let mut options = Options::new(args, path, known.as_ref());
+    check_hz(options.hz).context("Invalid frequency of the ROM")?;
+
+    // XO-CHIP programs larger than 4 KiB get the whole 64 KiB
+    if rom.len() > mem.len().saturating_sub(options.quirks.start as usize) {
+        mem.resize(Core::MAX_MEM_LEN, 0);
+    }
+    let size = load_program_bytes(rom, &mut mem[..], options.quirks.start)
+        .with_context(|| format!("Loading program \"{}\"", path.display()))?;
+    debug!("Loaded {} bytes", size);
+
+    let cheats = args.cheats.as_ref().map(Cheats::load).transpose()?;
+    if let Some(cheats) = &cheats {
+        cheats.apply(&mut mem).context("Applying cheats")?;
+    }
+    options.cheats = cheats;
+
+    let session = match (&args.host, &args.join) {
+        (Some(addr), _) => Some(Session::host(addr, rom, rand::random(), options.hz)?),
+        (None, Some(addr)) => Some(Session::join(addr, rom)?),
+        (None, None) => None,
+    };
+    if let Some(session) = session {
+        options.hz = session.core_freq();
+        check_hz(options.hz).context("Invalid frequency of the host")?;
+        options.seed = Some(session.seed());
+        options.netplay = Some(session);
+    }
 
-    let mut minifb = MinifbDisplay::new(60).with_context(|| "Creating minifb display")?;
-    let graphics_adapter = minifb.graphics_adapter();
-    let keypad_adapter = minifb.keypad_adater();
+    if args.headless {
+        return run_headless(mem, &options, args.max_cycles);
+    }
 
     let (tx_stop_gui, rx_stop_gui) = channel();
 
+    // Stopped once the ROM stops, so the next one of a playlist can listen on the address
+    let (_server, calls) = match &args.serve {
+        Some(addr) => {
+            let (server, calls) = ControlServer::new(addr)
+                .with_context(|| format!("Starting control server on {}", addr))?;
+            info!("Serving the control API on http://{}/rpc", addr);
+            (Some(server), Some(calls))
+        }
+        None => (None, None),
+    };
+
+    match args.backend {
+        Backend::Sdl => run_sdl(
+            mem,
+            options,
+            keymap,
+            args.mute,
+            calls,
+            tx_stop_gui,
+            rx_stop_gui,
+        )?,
+        Backend::Pixels => run_pixels(
+            mem,
+            options,
+            keymap,
+            args.mute,
+            calls,
+            tx_stop_gui,
+            rx_stop_gui,
+        )?,
+        Backend::Terminal => {
+            let audio = open_audio(args.mute);
+            let mut display = TerminalDisplay::new()
+                .with_context(|| "Setting up terminal")?
+                .with_keymap(keymap);
+            let chip8 = spawn_chip8(
+                mem,
+                options,
+                Box::new(display.keypad_adapter()),
+                Box::new(display.graphics_adapter()),
+                Box::new(audio.as_ref().map(AudioOutput::speaker_adapter)),
+                None,
+                calls,
+                tx_stop_gui,
+            )?;
+
+            debug!("Starting terminal display");
+            display
+                .run(rx_stop_gui)
+                .with_context(|| "Running terminal display")?;
+            chip8.stop();
+        }
+        Backend::WebSocket => {
+            let mut display = WebSocketDisplay::bind(&args.websocket)
+                .with_context(|| format!("Listening on {}", args.websocket))?;
+            info!(
+                "Waiting for WebSocket clients on ws://{}",
+                display.local_addr()?
+            );
+            let chip8 = spawn_chip8(
+                mem,
+                options,
+                Box::new(display.keypad_adapter()),
+                Box::new(display.graphics_adapter()),
+                Box::new(NullSpeaker),
+                None,
+                calls,
+                tx_stop_gui,
+            )?;
+
+            display
+                .run(rx_stop_gui)
+                .with_context(|| "Running WebSocket display")?;
+            chip8.stop();
+        }
+        Backend::Minifb => {
+            let audio = open_audio(args.mute);
+            let mut minifb = MinifbDisplay::new(60, args.scale, palette)
+                .with_context(|| "Creating minifb display")?
+                .with_keymap(keymap)
+                .with_rom_name(&match known {
+                    Some(known) if !known.title.is_empty() => known.title,
+                    _ => path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into(),
+                });
+            let commands = minifb.control_channel(options.hz);
+            let chip8 = spawn_chip8(
+                mem,
+                options,
+                Box::new(minifb.keypad_adater()),
+                Box::new(minifb.graphics_adapter()),
+                Box::new(minifb.speaker_adapter(audio.as_ref().map(AudioOutput::speaker_adapter))),
+                Some(Control {
+                    commands,
+                    status: minifb.status_adapter(),
+                }),
+                calls,
+                tx_stop_gui,
+            )?;
+
+            debug!("Starting GUI");
+            minifb.run(rx_stop_gui).with_context(|| "Running minifb")?;
+            chip8.stop();
+
+            if args.latency {
+                println!("{}", minifb.latency_report());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run as fast as possible without any peripherals, for scripted testing of ROMs
+///
+/// The random number generator uses a fixed seed, so runs are reproducible.
+fn run_headless(mut mem: Vec<u8>, options: &Options, max_cycles: u64) -> Result<()> {
+    let mut reg = [0; 16];
+    let mut stack = [0; 16];
+    let mut coverage = options.coverage_map(mem.len())?;
+    let mut decode_cache = vec![None; mem.len()];
+    let mut cheats = options.cheats.clone();
+
+    let mut core = Core::new(&mut mem[..], &mut reg[..], &mut stack[..]);
+    core.set_quirks(options.quirks);
+    core.set_write_protection(options.write_protection);
+    core.set_decode_cache(&mut decode_cache);
+    if let Some(coverage) = &mut coverage {
+        core.set_coverage(coverage);
+    }
+
+    let mut chip8 =
+        Chip8::new(core, options.hz, DefaultPeripherals::default()).context("Creating CHIP-8")?;
+    if let Some(cheats) = &mut cheats {
+        chip8.set_write_hook(cheats);
+    }
+    chip8.set_stop_on_halt(true);
+    let mut tracer = options.tracer()?;
+
+    let start = Instant::now();
+    let exit = loop {
+        if chip8.stats().instructions >= max_cycles {
+            break RunExit::Stopped;
+        }
+        match step(&mut chip8, tracer.as_mut()) {
+            RunExit::Stopped => (),
+            exit => break exit,
+        }
+    };
+
+    let elapsed = start.elapsed();
+    let instructions = chip8.stats().instructions;
+    info!(
+        "Executed {} instructions in {:?} ({:.0} instructions/s)",
+        instructions,
+        elapsed,
+        instructions as f64 / elapsed.as_secs_f64()
+    );
+
+    let reason = match exit {
+        RunExit::Exited => "exited",
+        RunExit::Halted(_) => "halted",
+        RunExit::Error(_) => "failed",
+        RunExit::Breakpoint(_) | RunExit::Stopped => "out of instructions",
+    };
+    println!("instructions {}", instructions);
+    println!("exit         {}", reason);
+    println!("state        {}", chip8);
+    println!(
+        "framebuffer  {:016x}",
+        framebuffer_hash(chip8.core().framebuffer())
+    );
+    options.save_coverage(chip8.core())?;
+
+    match exit {
+        RunExit::Error(e) => {
+            Err(e).with_context(|| format!("CHIP-8 stopped after {} instructions", instructions))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A 64 bit FNV-1a hash of the display mode and pixels, stable across builds and platforms
+fn framebuffer_hash(framebuffer: &Framebuffer) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let pixels = (0..framebuffer.height())
+        .flat_map(|y| (0..framebuffer.width()).map(move |x| framebuffer.pixel(x, y) as u8));
+
+    [framebuffer.width() as u8, framebuffer.height() as u8]
+        .into_iter()
+        .chain(pixels)
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+fn open_audio(mute: bool) -> Option<AudioOutput> {
+    if mute {
+        return None;
+    }
+
+    match AudioOutput::new(AudioOutput::DEFAULT_FREQUENCY, AudioOutput::DEFAULT_VOLUME) {
+        Ok(audio) => Some(audio),
+        Err(e) => {
+            warn!("Audio disabled: {:#}", e);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn run_sdl(
+    mem: Vec<u8>,
+    options: Options,
+    keymap: KeyMap,
+    mute: bool,
+    calls: Option<Receiver<Call>>,
+    tx_stop_gui: Sender<()>,
+    rx_stop_gui: Receiver<()>,
+) -> Result<()> {
+    use chip8_tools::util::sdl::SdlDisplay;
+
+    let mut display = SdlDisplay::new(AudioOutput::DEFAULT_FREQUENCY, AudioOutput::DEFAULT_VOLUME)
+        .with_context(|| "Creating SDL display")?
+        .with_keymap(keymap);
+    let chip8 = spawn_chip8(
+        mem,
+        options,
+        Box::new(display.keypad_adapter()),
+        Box::new(display.graphics_adapter()),
+        Box::new((!mute).then(|| display.speaker_adapter())),
+        None,
+        calls,
+        tx_stop_gui,
+    )?;
+
+    debug!("Starting SDL display");
+    display
+        .run(rx_stop_gui)
+        .with_context(|| "Running SDL display")?;
+    chip8.stop();
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_sdl(
+    _mem: Vec<u8>,
+    _options: Options,
+    _keymap: KeyMap,
+    _mute: bool,
+    _calls: Option<Receiver<Call>>,
+    _tx_stop_gui: Sender<()>,
+    _rx_stop_gui: Receiver<()>,
+) -> Result<()> {
+    anyhow::bail!("chip8-emu was built without the \"sdl\" feature")
+}
+
+#[cfg(feature = "pixels")]
+fn run_pixels(
+    mem: Vec<u8>,
+    options: Options,
+    keymap: KeyMap,
+    mute: bool,
+    calls: Option<Receiver<Call>>,
+    tx_stop_gui: Sender<()>,
+    rx_stop_gui: Receiver<()>,
+) -> Result<()> {
+    use chip8_tools::util::pixels::PixelsDisplay;
+
+    let audio = open_audio(mute);
+    let mut display = PixelsDisplay::new()
+        .with_context(|| "Creating pixels display")?
+        .with_keymap(keymap);
+    let chip8 = spawn_chip8(
+        mem,
+        options,
+        Box::new(display.keypad_adapter()),
+        Box::new(display.graphics_adapter()),
+        Box::new(audio.as_ref().map(AudioOutput::speaker_adapter)),
+        None,
+        calls,
+        tx_stop_gui,
+    )?;
+
+    debug!("Starting pixels display");
+    display
+        .run(rx_stop_gui)
+        .with_context(|| "Running pixels display")?;
+    chip8.stop();
+
+    Ok(())
+}
+
+#[cfg(not(feature = "pixels"))]
+fn run_pixels(
+    _mem: Vec<u8>,
+    _options: Options,
+    _keymap: KeyMap,
+    _mute: bool,
+    _calls: Option<Receiver<Call>>,
+    _tx_stop_gui: Sender<()>,
+    _rx_stop_gui: Receiver<()>,
+) -> Result<()> {
+    anyhow::bail!("chip8-emu was built without the \"pixels\" feature")
+}
+
+/// The connection to a frontend with hotkeys and a status line, see [`spawn_chip8`]
+struct Control {
+    commands: Receiver<Command>,
+    status: StatusAdapter,
+}
+
+/// The thread running the CHIP-8, see [`spawn_chip8`]
+struct Chip8Thread {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Chip8Thread {
+    /// Stop the CHIP-8 after the current tick and wait until it saved its state
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if self.handle.join().is_err() {
+            error!("The CHIP-8 thread panicked");
+        }
+    }
+}
+
+/// Run the CHIP-8 on its own thread, telling the frontend to stop once it fails
+///
+/// If the frontend has hotkeys, their commands are executed between frames, as are the calls
+/// of the control server.
+#[allow(clippy::too_many_arguments)]
+fn spawn_chip8(
+    mut mem: Vec<u8>,
+    mut options: Options,
+    keypad: Box<dyn Keypad + Send>,
+    graphics: Box<dyn Graphics + Send>,
+    speaker: Box<dyn Speaker + Send>,
+    control: Option<Control>,
+    calls: Option<Receiver<Call>>,
+    tx_stop_gui: Sender<()>,
+) -> Result<Chip8Thread> {
+    // A known seed makes recordings reproducible
+    let seed = options.seed.unwrap_or_else(rand::random);
+    debug!("Random seed {:#018x}", seed);
+
+    let mut script = options.script.as_ref().map(Script::load).transpose()?;
+    let keypad: Box<dyn Keypad + Send> = match &script {
+        Some(script) => Box::new(script.keypad(keypad)),
+        None => keypad,
+    };
+    // The peer receives the keys pressed by the script, the recording holds the input of both
+    let keypad: Box<dyn Keypad + Send> = match options.netplay.take() {
+        Some(session) => Box::new(NetplayKeypad::new(keypad, session)),
+        None => keypad,
+    };
+    let keypad: Box<dyn Keypad + Send> = match &options.record {
+        Some(path) => Box::new(
+            RecordingKeypad::new(keypad, path, seed, options.hz)
+                .with_context(|| format!("Creating recording \"{}\"", path.display()))?,
+        ),
+        None => keypad,
+    };
+    let mut tracer = options.tracer()?;
+    let mut coverage = options.coverage_map(mem.len())?;
+    let mut cheats = options.cheats.take();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&stop);
+
     debug!("Spawning CHIP-8 thread");
-    std::thread::spawn(move || {
-        let mut chip8 = Chip8::new(
-            chip8_core::Core::new(&mut mem[..], &mut reg[..], &mut stack[..]),
-            700,
-            keypad_adapter,
-            graphics_adapter,
-            || thread_rng().gen(),
-            DownTimer::new("delay"),
-            DownTimer::new("sound"),
+    let handle = std::thread::spawn(move || {
+        let mut reg = [0; 16];
+        let mut stack = [0; 16];
+
+        // Without hotkeys the CHIP-8 can't be resumed, wait for the keypad instead
+        if options.start_paused && control.is_none() {
+            info!("Paused, press a key to start");
+            while keypad.pressed_keys() == Keys(0) {
+                if stopped.load(Ordering::Relaxed) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        // The memory right after loading, restored on resets
+        let initial = mem.clone();
+        let mut decode_cache = vec![None; mem.len()];
+
+        let mut core = Core::new(&mut mem[..], &mut reg[..], &mut stack[..]);
+        core.set_quirks(options.quirks);
+        core.set_write_protection(options.write_protection);
+        core.set_decode_cache(&mut decode_cache);
+        if let Some(coverage) = &mut coverage {
+            core.set_coverage(coverage);
+        }
+
+        let peripherals = DynPeripherals {
+            keypad,
+            graphics,
+            random: Box::new(XorShiftRandom::new(seed)),
+            delay_timer: Box::new(DownTimer::new("delay")),
+            sound_timer: Box::new(DownTimer::new("sound")),
+            speaker,
+            persistence: Box::new(options.flags.clone().map(FilePersistence::new)),
+        };
+        let mut chip8: DynChip8 = Chip8::new(core, options.hz, peripherals)
+            .expect("The frequency is checked before spawning");
+        if let Some(cheats) = &mut cheats {
+            chip8.set_write_hook(cheats);
+        }
+
+        if options.start_paused && control.is_some() {
+            info!("Paused, press P to start");
+            chip8.pause();
+        }
+        let exit = run_controlled(
+            &mut chip8,
+            control.as_ref(),
+            calls.as_ref(),
+            &initial,
+            tracer.as_mut(),
+            script.as_mut(),
+            &stopped,
         );
 
-        if let Err(e) = chip8.run() {
-            error!("CHIP-8 stopped: {}", e);
-            tx_stop_gui.send(()).expect("Sending stop to gui");
+        if let Err(e) = options.save_coverage(chip8.core()) {
+            error!("{:#}", e);
+        }
+
+        match exit {
+            RunExit::Exited => info!("The program exited"),
+            RunExit::Halted(reason) => info!("The program halted: {:?}", reason),
+            RunExit::Error(e) => {
+                error!("CHIP-8 stopped: {}", e);
+                tx_stop_gui.send(()).expect("Sending stop to gui");
+            }
+            RunExit::Breakpoint(_) | RunExit::Stopped => (),
         }
     });
 
-    debug!("Starting GUI");
-    minifb.run(rx_stop_gui).with_context(|| "Running minifb")?;
+    Ok(Chip8Thread { stop, handle })
+}
 
-    info!("Exiting");
-    Ok(())
+/// Run like [`Chip8::run`], executing the commands of the frontend and the calls of the
+/// control server, if any, between frames and publishing the state for its status line
+///
+/// The hooks of the script are called after every tick and frame.
+///
+/// Resets restore the memory to `initial`, so writes of the program are undone. Returns
+/// [`RunExit::Stopped`] once `stop` is set, or how the program ended if it exits, halts or
+/// fails first.
+fn run_controlled<P: Peripherals>(
+    chip8: &mut Chip8<'_, P>,
+    control: Option<&Control>,
+    calls: Option<&Receiver<Call>>,
+    initial: &[u8],
+    mut tracer: Option<&mut Tracer>,
+    mut script: Option<&mut Script>,
+    stop: &AtomicBool,
+) -> RunExit {
+    let mut pacer = FramePacer::new(Chip8::<P>::TIMER_FREQ);
+    chip8.set_stop_on_halt(true);
+
+    while !stop.load(Ordering::Relaxed) {
+        for command in control
+            .into_iter()
+            .flat_map(|control| control.commands.try_iter())
+        {
+            execute(chip8, command, initial);
+        }
+        for call in calls.into_iter().flat_map(Receiver::try_iter) {
+            call.answer(chip8, |chip8, command| execute(chip8, command, initial));
+        }
+
+        let mut cycles = chip8.frame_cycles();
+        while cycles > 0 {
+            let exit = match script.as_deref_mut() {
+                Some(script) => script.tick(chip8, |chip8| step(chip8, tracer.as_deref_mut())),
+                None => step(chip8, tracer.as_deref_mut()),
+            };
+            if exit != RunExit::Stopped {
+                return exit;
+            }
+            cycles = cycles.saturating_sub(chip8.last_cycles().max(1));
+        }
+        if let Some(script) = script.as_deref_mut() {
+            script.frame(chip8);
+        }
+        if let Some(control) = control {
+            control.status.update(chip8);
+        }
+        pacer.wait();
+    }
+
+    RunExit::Stopped
+}
+
+/// Execute a command of the frontend or the control server
+///
+/// Resets restore the memory to `initial`.
+fn execute<P: Peripherals>(chip8: &mut Chip8<'_, P>, command: Command, initial: &[u8]) {
+    if let Err(e) = chip8.handle(command) {
+        warn!("Ignoring {:?}: {}", command, e);
+        return;
+    }
+
+    match command {
+        Command::Reset => chip8.core_mut().memory_mut().copy_from_slice(initial),
+        Command::TogglePause if chip8.is_paused() => info!("Paused"),
+        Command::TogglePause => info!("Resumed"),
+        _ => (),
+    }
+}
+
+/// Execute a single tick with [`Chip8::run_cycles`], writing it to the trace if there is one
+fn step<P: Peripherals>(chip8: &mut Chip8<'_, P>, tracer: Option<&mut Tracer>) -> RunExit {
+    match tracer {
+        Some(tracer) => tracer.step(chip8),
+        None => chip8.run_cycles(1).exit,
+    }
+}
+
+/// Stands in for the script of `--script` without the "scripting" feature, which can't be
+/// loaded
+#[cfg(not(feature = "scripting"))]
+enum Script {}
+
+#[cfg(not(feature = "scripting"))]
+impl Script {
+    fn load<T: AsRef<Path>>(_path: T) -> Result<Self> {
+        bail!("chip8-emu was built without the \"scripting\" feature")
+    }
+
+    fn keypad<K: Keypad>(&self, _keypad: K) -> K {
+        match *self {}
+    }
+
+    fn tick<'m, P: Peripherals>(
+        &mut self,
+        _chip8: &mut Chip8<'m, P>,
+        _tick: impl FnOnce(&mut Chip8<'m, P>) -> RunExit,
+    ) -> RunExit {
+        match *self {}
+    }
+
+    fn frame<P: Peripherals>(&mut self, _chip8: &mut Chip8<'_, P>) {
+        match *self {}
+    }
 }
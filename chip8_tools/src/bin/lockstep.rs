@@ -0,0 +1,270 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use chip8_core::instructions::Instruction;
+use chip8_core::peripherals::testing::ScriptedKeypad;
+use chip8_core::prelude::*;
+use chip8_tools::util::quirks::{quirks_config, Quirk};
+use chip8_tools::util::record::Recording;
+use chip8_tools::util::{init_logging, load_program};
+use clap::Parser;
+use log::info;
+
+/// Run a ROM on two cores in lockstep and report the first tick at which they diverge
+///
+/// Both cores get the same ROM, random seed and keypad input, so they only differ in their
+/// quirks. Any difference of the registers, memory, timers or display is reported.
+#[derive(Debug, Parser)]
+#[command(name = "chip8-lockstep", version)]
+struct Args {
+    /// Path to a CHIP-8 ROM (*.ch8)
+    rom: PathBuf,
+
+    /// Comma separated quirks to enable on the first core
+    #[arg(long, value_enum, value_delimiter = ',')]
+    quirks_a: Vec<Quirk>,
+
+    /// Comma separated quirks to enable on the second core
+    #[arg(long, value_enum, value_delimiter = ',')]
+    quirks_b: Vec<Quirk>,
+
+    /// Replay the keypad input and random seed of a recording made with `chip8-emu --record`
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// The number of instructions executed per second, defaults to the one of the recording
+    /// or 700
+    #[arg(long)]
+    hz: Option<u32>,
+
+    /// The number of ticks to compare
+    #[arg(long, default_value_t = MAX_CYCLES)]
+    max_cycles: u64,
+}
+
+/// The default number of ticks to compare
+const MAX_CYCLES: u64 = 1_000_000;
+
+/// The number of differing memory bytes listed in a report
+const MAX_MEMORY_DIFFS: usize = 16;
+
+type Machine<'memory> = Chip8<
+    'memory,
+    PeripheralSet<
+        ScriptedKeypad,
+        NullGraphics,
+        XorShiftRandom,
+        DownTimer<'static>,
+        DownTimer<'static>,
+        NullSpeaker,
+        RamPersistence,
+    >,
+>;
+
+/// The memory of one of the cores
+struct Buffers {
+    mem: Vec<u8>,
+    reg: [u8; 16],
+    stack: [u16; 16],
+}
+
+impl Buffers {
+    fn new(mem: Vec<u8>) -> Self {
+        Self {
+            mem,
+            reg: [0; 16],
+            stack: [0; 16],
+        }
+    }
+
+    fn machine(
+        &mut self,
+        quirks: QuirksConfig,
+        core_freq: u32,
+        seed: u64,
+        keypad: ScriptedKeypad,
+    ) -> Result<Machine<'_>> {
+        let mut core = Core::new(&mut self.mem[..], &mut self.reg[..], &mut self.stack[..]);
+        core.set_quirks(quirks);
+
+        let peripherals = PeripheralSet {
+            keypad,
+            graphics: NullGraphics,
+            random: XorShiftRandom::new(seed),
+            delay_timer: DownTimer::new("delay"),
+            sound_timer: DownTimer::new("sound"),
+            speaker: NullSpeaker,
+            persistence: RamPersistence::default(),
+        };
+
+        Chip8::new(core, core_freq, peripherals).context("Creating CHIP-8")
+    }
+}
+
+/// The differences between the states of two cores, one line per difference
+fn diff(a: &Machine<'_>, b: &Machine<'_>) -> Vec<String> {
+    let mut diffs = Vec::new();
+    let (core_a, core_b) = (a.core(), b.core());
+
+    let mut compare = |name: String, a: u16, b: u16| {
+        if a != b {
+            diffs.push(format!("{:<16} {:#06x} {:#06x}", name, a, b));
+        }
+    };
+
+    compare("PC".into(), core_a.pc(), core_b.pc());
+    compare("I".into(), core_a.i(), core_b.i());
+    compare("SP".into(), core_a.sp().into(), core_b.sp().into());
+    for (idx, (&a, &b)) in core_a
+        .registers()
+        .iter()
+        .zip(core_b.registers())
+        .enumerate()
+    {
+        compare(format!("V{:X}", idx), a.into(), b.into());
+    }
+    for (idx, (&a, &b)) in core_a.stack().iter().zip(core_b.stack()).enumerate() {
+        compare(format!("stack[{}]", idx), a, b);
+    }
+    compare(
+        "delay timer".into(),
+        a.peripherals().delay_timer().get().into(),
+        b.peripherals().delay_timer().get().into(),
+    );
+    compare(
+        "sound timer".into(),
+        a.peripherals().sound_timer().get().into(),
+        b.peripherals().sound_timer().get().into(),
+    );
+
+    let memory: Vec<_> = core_a
+        .memory()
+        .iter()
+        .zip(core_b.memory())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .collect();
+    for &(addr, (&a, &b)) in memory.iter().take(MAX_MEMORY_DIFFS) {
+        compare(format!("memory[{:#05x}]", addr), a.into(), b.into());
+    }
+    if memory.len() > MAX_MEMORY_DIFFS {
+        diffs.push(format!(
+            "... and {} more bytes of memory",
+            memory.len() - MAX_MEMORY_DIFFS
+        ));
+    }
+
+    if core_a.exited() != core_b.exited() {
+        diffs.push(format!(
+            "{:<16} {:<6} {:<6}",
+            "exited",
+            core_a.exited(),
+            core_b.exited()
+        ));
+    }
+
+    if core_a.framebuffer() != core_b.framebuffer() {
+        diffs.push(format!(
+            "framebuffer\n{}\n{}",
+            core_a.framebuffer().ascii(),
+            core_b.framebuffer().ascii()
+        ));
+    }
+
+    diffs
+}
+
+/// The instruction at `pc`, for the report
+fn instruction_at(core: &Core<'_>, pc: u16) -> String {
+    match core
+        .memory()
+        .get(pc as usize..)
+        .map(|bytes| Instruction::decode(bytes, pc))
+    {
+        Some(Ok(ins)) => ins.to_string(),
+        _ => "an invalid instruction".into(),
+    }
+}
+
+fn main() -> Result<()> {
+    init_logging();
+
+    let args = Args::parse();
+
+    let recording = match &args.replay {
+        Some(path) => Some(Recording::load(path)?),
+        None => None,
+    };
+    let seed = recording
+        .as_ref()
+        .map_or(XorShiftRandom::DEFAULT_SEED, |recording| recording.seed);
+    let core_freq = args
+        .hz
+        .or(recording.as_ref().map(|recording| recording.core_freq))
+        .unwrap_or(700);
+    let keypad = recording
+        .as_ref()
+        .map_or_else(ScriptedKeypad::new, Recording::keypad);
+
+    let (quirks_a, quirks_b) = (quirks_config(&args.quirks_a), quirks_config(&args.quirks_b));
+    let mut mem = [vec![0; 4096], vec![0; 4096]];
+    for (mem, quirks) in mem.iter_mut().zip([quirks_a, quirks_b]) {
+        load_program(&args.rom, &mut mem[..], quirks.start)
+            .with_context(|| format!("Loading program \"{}\"", args.rom.display()))?;
+    }
+
+    let [mem_a, mem_b] = mem;
+    let mut buffers_a = Buffers::new(mem_a);
+    let mut buffers_b = Buffers::new(mem_b);
+    let mut a = buffers_a.machine(quirks_a, core_freq, seed, keypad.clone())?;
+    let mut b = buffers_b.machine(quirks_b, core_freq, seed, keypad)?;
+
+    for tick in 0..args.max_cycles {
+        let pc = (a.core().pc(), b.core().pc());
+        let result = (a.tick(), b.tick());
+
+        let mut diffs = diff(&a, &b);
+        match &result {
+            (Err(e_a), Err(e_b)) if e_a == e_b => {
+                println!("Both cores failed at tick {}: {}", tick, e_a);
+                return Ok(());
+            }
+            (Ok(state_a), Ok(state_b)) if state_a == state_b => (),
+            (e_a, e_b) => diffs.insert(0, format!("{:<16} {:?} {:?}", "result", e_a, e_b)),
+        }
+
+        if !diffs.is_empty() {
+            println!("Diverged at tick {}", tick);
+            println!(
+                "  a executed {} at {:#05x}",
+                instruction_at(a.core(), pc.0),
+                pc.0
+            );
+            println!(
+                "  b executed {} at {:#05x}",
+                instruction_at(b.core(), pc.1),
+                pc.1
+            );
+            println!();
+            println!("{:<16} {:<6} {:<6}", "", "a", "b");
+            for diff in &diffs {
+                println!("{}", diff);
+            }
+            println!();
+            println!("a  {}", a);
+            println!("b  {}", b);
+
+            bail!("The cores diverged at tick {}", tick);
+        }
+
+        if a.core().exited() {
+            println!("Both cores exited at tick {}", tick);
+            return Ok(());
+        }
+    }
+
+    info!("Compared {} ticks", args.max_cycles);
+    println!("No divergence in {} ticks", args.max_cycles);
+
+    Ok(())
+}
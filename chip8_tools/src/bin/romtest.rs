@@ -0,0 +1,140 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::prelude::*;
+use chip8_tools::util::playlist;
+use chip8_tools::util::screenshot::{self, Image};
+use chip8_tools::util::{init_logging, load_program};
+use std::path::{Path, PathBuf};
+
+const HELP: &str = "\
+chip8-romtest - Run test ROMs and compare their display against golden images
+
+USAGE:
+    chip8-romtest [OPTIONS] DIR
+
+ARGS:
+    DIR         A directory of test ROMs (*.ch8, *.zip, *.gz), e.g. from the Timendus or
+                corax89 test suites. The golden image of NAME.ch8 is NAME.png.
+
+OPTIONS:
+    --bless     Write the golden images from the current results instead of comparing
+    --max-cycles N
+                The cycle budget of each ROM (default 10000000)
+
+A ROM runs until it executes EXIT (00FD), jumps to itself, fails or runs out of cycles.
+";
+
+/// The default cycle budget of a ROM
+const MAX_CYCLES: u64 = 10_000_000;
+
+/// Why a ROM stopped running
+#[derive(Debug)]
+enum Stop {
+    Exited,
+    Halted,
+    Failed(Error),
+    OutOfCycles,
+}
+
+impl std::fmt::Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Exited => write!(f, "exited"),
+            Stop::Halted => write!(f, "halted"),
+            Stop::Failed(e) => write!(f, "failed: {}", e),
+            Stop::OutOfCycles => write!(f, "out of cycles"),
+        }
+    }
+}
+
+/// Run a ROM headless with a fixed random seed, returning the final framebuffer
+fn run(path: &Path, max_cycles: u64) -> Result<(Stop, Framebuffer)> {
+    let mut mem = vec![0; 4096];
+    let mut reg = [0; 16];
+    let mut stack = [0; 16];
+    let mut decode_cache = vec![None; mem.len()];
+
+    load_program(path, &mut mem[..], QuirksConfig::START)
+        .with_context(|| format!("Loading {}", path.display()))?;
+
+    let mut core = Core::new(&mut mem[..], &mut reg[..], &mut stack[..]);
+    core.set_decode_cache(&mut decode_cache);
+    let mut chip8 = Chip8::new(core, 700, DefaultPeripherals::default())?;
+    chip8.set_stop_on_halt(true);
+
+    let summary = chip8.run_until(|chip8| chip8.stats().instructions >= max_cycles);
+    let stop = match summary.exit {
+        RunExit::Error(e) => Stop::Failed(e),
+        RunExit::Exited => Stop::Exited,
+        RunExit::Halted(_) => Stop::Halted,
+        _ => Stop::OutOfCycles,
+    };
+
+    Ok((stop, chip8.core().framebuffer().clone()))
+}
+
+fn main() -> Result<()> {
+    init_logging();
+
+    let mut bless = false;
+    let mut max_cycles = MAX_CYCLES;
+    let mut dir = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            "--max-cycles" => {
+                let cycles = args.next().context("--max-cycles requires a value")?;
+                max_cycles = cycles
+                    .parse()
+                    .with_context(|| format!("Invalid cycle count \"{}\"", cycles))?;
+            }
+            _ => dir = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            eprintln!("{}", HELP);
+            return Ok(());
+        }
+    };
+
+    let roms = playlist::roms_in(&dir)?;
+    if roms.is_empty() {
+        bail!("No ROMs (*.ch8) found in {}", dir.display());
+    }
+
+    let mut failed = 0;
+    for rom in &roms {
+        let name = rom.file_stem().unwrap_or_default().to_string_lossy();
+        let golden = rom.with_extension("png");
+
+        let (stop, framebuffer) = run(rom, max_cycles)?;
+
+        let result = if bless {
+            screenshot::save_png(&framebuffer, &golden, 1)?;
+            "BLESSED"
+        } else if !golden.exists() {
+            failed += 1;
+            "FAIL (no golden image)"
+        } else if screenshot::load_png(&golden)? != Image::from_framebuffer(&framebuffer, 1) {
+            failed += 1;
+            "FAIL (display differs)"
+        } else {
+            "ok"
+        };
+
+        println!("{:<40} {:<24} {}", name, stop.to_string(), result);
+    }
+
+    println!();
+    println!("{} ROMs, {} failed", roms.len(), failed);
+
+    if failed > 0 {
+        bail!("{} of {} ROMs failed", failed, roms.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::core::Coverage;
+use chip8_core::prelude::*;
+use rand::prelude::*;
+
+const HELP: &str = "\
+chip8-score - A Monte Carlo playability scorer for CHIP-8 ROMs
+
+Runs a ROM many times with seeded random key presses and reports how
+often it crashes, how much of it gets executed and how long it survives.
+
+USAGE:
+    chip8-score ROM_FILE [RUNS] [MAX_FRAMES] [SEED]
+
+ARGS:
+    ROM_FILE      Path to a CHIP-8 ROM (*.ch8)
+    RUNS          Number of runs, each with its own seed (default: 100)
+    MAX_FRAMES    Frame budget of a single run (default: 3600)
+    SEED          Seed of the first run (default: 0)
+";
+
+const CORE_FREQ: u32 = 700;
+const TICKS_PER_FRAME: u64 = CORE_FREQ as u64 / 60;
+const PROGRAM_START: usize = 0x200;
+const MEMORY_SIZE: usize = 4096;
+
+/// A keypad pressing random keys, changing its state every few frames
+///
+/// The state changes in [`RandomKeypad::step`] before a tick, so the held keys and the edges
+/// read during the tick agree.
+struct RandomKeypad {
+    rng: StdRng,
+    current: Keys,
+    released: FallingEdges,
+    pressed: RisingEdges,
+    ticks: u64,
+}
+
+impl RandomKeypad {
+    const TICKS_PER_CHANGE: u64 = 4 * TICKS_PER_FRAME;
+
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            current: Keys(0),
+            released: Keys(0).falling_edges(&Keys(0)),
+            pressed: Keys(0).rising_edges(&Keys(0)),
+            ticks: 0,
+        }
+    }
+
+    /// Advance to the next tick, changing the held keys every few frames
+    fn step(&mut self) {
+        self.ticks += 1;
+        let next = if self.ticks < Self::TICKS_PER_CHANGE {
+            self.current.clone()
+        } else {
+            self.ticks = 0;
+            if self.rng.gen_bool(0.5) {
+                Keys(1 << self.rng.gen_range(0..16))
+            } else {
+                Keys(0)
+            }
+        };
+
+        self.released = self.current.falling_edges(&next);
+        self.pressed = self.current.rising_edges(&next);
+        self.current = next;
+    }
+}
+
+impl Keypad for RandomKeypad {
+    fn pressed_keys(&self) -> Keys {
+        self.current.clone()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        self.released.clone()
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        self.pressed.clone()
+    }
+}
+
+/// How a single run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// The ROM exited or can't make progress anymore, e.g. as it jumped to itself, the
+    /// common idiom for "program finished"
+    Halted,
+    /// The core returned an error other than an invalid instruction
+    Crashed,
+    /// The core hit an invalid instruction
+    InvalidOpcode,
+    /// The frame budget was exhausted
+    Survived,
+}
+
+#[derive(Debug)]
+struct RunResult {
+    outcome: Outcome,
+    frames: u64,
+    coverage: Vec<Coverage>,
+}
+
+fn run_once(rom: &[u8], seed: u64, max_frames: u64) -> RunResult {
+    let mut coverage = vec![Coverage::default(); MEMORY_SIZE];
+    let mut mem = vec![0; MEMORY_SIZE];
+    let mut reg = [0; 16];
+    let mut stack = [0; 16];
+
+    mem[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+
+    let mut core = Core::new(&mut mem[..], &mut reg[..], &mut stack[..]);
+    core.set_coverage(&mut coverage);
+
+    let mut chip8 = Chip8::new(
+        core,
+        CORE_FREQ,
+        PeripheralSet {
+            keypad: RandomKeypad::new(seed),
+            graphics: NullGraphics,
+            random: XorShiftRandom::new(seed.wrapping_add(u64::MAX / 2)),
+            delay_timer: DownTimer::new("delay"),
+            sound_timer: DownTimer::new("sound"),
+            speaker: NullSpeaker,
+            persistence: RamPersistence::default(),
+        },
+    )
+    .expect("Creating CHIP-8");
+    chip8.set_stop_on_halt(true);
+
+    let mut ticks = 0;
+    let outcome = loop {
+        if ticks >= max_frames * TICKS_PER_FRAME {
+            break Outcome::Survived;
+        }
+
+        chip8.peripherals_mut().keypad.step();
+        match chip8.run_cycles(1).exit {
+            RunExit::Stopped | RunExit::Breakpoint(_) => ticks += 1,
+            RunExit::Halted(_) | RunExit::Exited => break Outcome::Halted,
+            RunExit::Error(Error::InvalidInstruction { .. }) => break Outcome::InvalidOpcode,
+            RunExit::Error(_) => break Outcome::Crashed,
+        }
+    };
+
+    RunResult {
+        outcome,
+        frames: ticks / TICKS_PER_FRAME,
+        coverage,
+    }
+}
+
+/// Fraction of the ROM bytes which were executed
+fn rom_coverage(coverage: &[Coverage], rom_len: usize) -> f64 {
+    let count = coverage[PROGRAM_START..PROGRAM_START + rom_len]
+        .iter()
+        .filter(|access| access.contains(Coverage::EXECUTED))
+        .count();
+
+    count as f64 / rom_len as f64
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    100.0 * count as f64 / total as f64
+}
+
+fn parse_arg<T: std::str::FromStr>(idx: usize, name: &str, default: T) -> Result<T> {
+    match std::env::args().nth(idx) {
+        Some(arg) => arg
+            .parse()
+            .ok()
+            .with_context(|| format!("Invalid value for {}: \"{}\"", name, arg)),
+        None => Ok(default),
+    }
+}
+
+fn main() -> Result<()> {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", HELP);
+            return Ok(());
+        }
+    };
+
+    let runs: u64 = parse_arg(2, "RUNS", 100)?;
+    let max_frames: u64 = parse_arg(3, "MAX_FRAMES", 3600)?;
+    let first_seed: u64 = parse_arg(4, "SEED", 0)?;
+
+    let rom = std::fs::read(&path).with_context(|| format!("Loading program \"{}\"", path))?;
+    if rom.is_empty() || rom.len() > MEMORY_SIZE - PROGRAM_START {
+        bail!(
+            "ROM \"{}\" has an invalid size of {} bytes",
+            path,
+            rom.len()
+        );
+    }
+    if runs == 0 {
+        bail!("RUNS must be at least 1");
+    }
+    let end_seed = first_seed
+        .checked_add(runs)
+        .with_context(|| format!("SEED {} + RUNS {} overflows a u64 seed", first_seed, runs))?;
+
+    let results: Vec<_> = (first_seed..end_seed)
+        .map(|seed| run_once(&rom, seed, max_frames))
+        .collect();
+
+    let count = |outcome| results.iter().filter(|r| r.outcome == outcome).count();
+    let total = results.len();
+
+    let halted: Vec<_> = results
+        .iter()
+        .filter(|r| r.outcome == Outcome::Halted)
+        .collect();
+
+    let mut union = vec![Coverage::default(); MEMORY_SIZE];
+    for result in &results {
+        for (u, c) in union.iter_mut().zip(&result.coverage) {
+            u.0 |= c.0;
+        }
+    }
+    let avg_coverage = results
+        .iter()
+        .map(|r| rom_coverage(&r.coverage, rom.len()))
+        .sum::<f64>()
+        / total as f64;
+
+    println!("ROM              {} ({} bytes)", path, rom.len());
+    println!(
+        "Runs             {} (seeds {}..{}, {} frames each)",
+        total, first_seed, end_seed, max_frames
+    );
+    println!();
+
+    for (name, outcome) in [
+        ("Halted", Outcome::Halted),
+        ("Crashed", Outcome::Crashed),
+        ("Invalid opcode", Outcome::InvalidOpcode),
+        ("Survived", Outcome::Survived),
+    ] {
+        let n = count(outcome);
+        println!("{:<16} {:>5} ({:5.1}%)", name, n, percent(n, total));
+    }
+    println!();
+
+    println!(
+        "Memory executed  {:5.1}% avg, {:5.1}% across all runs",
+        100.0 * avg_coverage,
+        100.0 * rom_coverage(&union, rom.len())
+    );
+
+    if halted.is_empty() {
+        println!("Frames to halt   n/a (no run halted)");
+    } else {
+        let avg_frames = halted.iter().map(|r| r.frames).sum::<u64>() as f64 / halted.len() as f64;
+        println!("Frames to halt   {:.1} avg", avg_frames);
+    }
+
+    Ok(())
+}
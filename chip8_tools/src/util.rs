@@ -1,11 +1,110 @@
+pub mod archive;
+pub mod audio;
+pub mod c8b;
+pub mod cheats;
+pub mod config;
+pub mod coverage;
+pub mod keymap;
+pub mod latency;
 pub mod minifb;
+pub mod netplay;
+pub mod octo;
+pub mod palette;
+pub mod persistence;
+#[cfg(feature = "pixels")]
+pub mod pixels;
+pub mod playlist;
+pub mod quirks;
+pub mod record;
+pub mod romdb;
+pub mod screenshot;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod server;
+pub mod terminal;
+pub mod trace;
+pub mod websocket;
 
 use std::io::{self, Read};
 use std::path::Path;
 
-pub fn load_program<P: AsRef<Path>>(path: P, target: &mut [u8]) -> io::Result<()> {
-    let mut rom = std::fs::File::open(path.as_ref())?;
-    let _ = rom.read(&mut target[0x200..])?;
+/// Log to stderr, filtered by `RUST_LOG`
+///
+/// With the "tracing" feature the spans of the core are printed as well.
+pub fn init_logging() {
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
 
-    Ok(())
+    #[cfg(not(feature = "tracing"))]
+    env_logger::init();
+}
+
+/// Read a ROM file as it is, from stdin if `path` is `-`
+///
+/// ROMs in `.zip` and `.gz` files are unpacked, see [`archive::unpack`].
+pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let path = path.as_ref();
+    if path == Path::new("-") {
+        let mut rom = Vec::new();
+        io::stdin().lock().read_to_end(&mut rom)?;
+        return Ok(rom);
+    }
+
+    archive::unpack(path, std::fs::read(path)?)
+}
+
+/// Read the bytecode of a ROM, see [`read_file`]
+///
+/// Of a CHIP-8 binary bundle the bytecode for the first platform the core runs is read, see
+/// [`Bundle::rom`](c8b::Bundle::rom).
+pub fn read_rom<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let data = read_file(path)?;
+    if !c8b::Bundle::is_bundle(&data) {
+        return Ok(data);
+    }
+
+    let bundle = c8b::Bundle::parse(&data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", e)))?;
+    match bundle.rom() {
+        Some((_, rom)) => Ok(rom.to_vec()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the bundle holds no bytecode for CHIP-8, SCHIP or XO-CHIP",
+        )),
+    }
+}
+
+/// Load the ROM at `path` into the program area of `target`, see [`read_rom`]
+///
+/// Returns the size of the ROM, which fails to load if it is larger than the program area.
+pub fn load_program<P: AsRef<Path>>(path: P, target: &mut [u8], start: u16) -> io::Result<usize> {
+    load_program_bytes(&read_rom(path)?, target, start)
+}
+
+/// Copy `rom` into the program area of `target`, starting at `start`, see
+/// [`QuirksConfig::start`](chip8_core::QuirksConfig::start)
+///
+/// Returns the size of the ROM, which fails to load if it is larger than the program area,
+/// e.g. 0xE00 bytes for 4 KiB of memory starting at 0x200.
+pub fn load_program_bytes(rom: &[u8], target: &mut [u8], start: u16) -> io::Result<usize> {
+    let program = target.get_mut(start as usize..).unwrap_or_default();
+    if rom.len() > program.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the ROM is {:#x} bytes, larger than the {:#x} bytes of program memory",
+                rom.len(),
+                program.len()
+            ),
+        ));
+    }
+
+    program[..rom.len()].copy_from_slice(rom);
+
+    Ok(rom.len())
 }
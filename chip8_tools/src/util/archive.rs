@@ -0,0 +1,43 @@
+use flate2::read::GzDecoder;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// The extensions of the files ROMs are loaded from, see [`unpack`]
+pub const EXTENSIONS: [&str; 4] = ["ch8", "c8b", "zip", "gz"];
+
+/// The ROM in `data` read from `path`, unpacked if the extension is `.zip` or `.gz`
+///
+/// Of a zip archive the first `.ch8` or `.c8b` entry is loaded, a gzip file holds the ROM
+/// itself.
+pub fn unpack(path: &Path, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("zip") => unzip(data),
+        Some(ext) if ext.eq_ignore_ascii_case("gz") => {
+            let mut rom = Vec::new();
+            GzDecoder::new(&data[..]).read_to_end(&mut rom)?;
+            Ok(rom)
+        }
+        _ => Ok(data),
+    }
+}
+
+/// The first `.ch8` or `.c8b` entry of a zip archive
+fn unzip(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    for idx in 0..archive.len() {
+        let mut entry = archive.by_index(idx)?;
+        let name = entry.name().to_ascii_lowercase();
+        if entry.is_file() && (name.ends_with(".ch8") || name.ends_with(".c8b")) {
+            let mut rom = Vec::new();
+            entry.read_to_end(&mut rom)?;
+            return Ok(rom);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "the archive doesn't contain a ROM (*.ch8, *.c8b)",
+    ))
+}
@@ -0,0 +1,158 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chip8_core::peripherals::pattern_rate;
+use chip8_core::prelude::*;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use log::{debug, error};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+/// An XO-CHIP audio pattern with its playback rate in samples per second
+type Pattern = ([u8; 16], f32);
+
+#[derive(Debug)]
+struct Tone {
+    playing: AtomicBool,
+    pattern: Mutex<Option<Pattern>>,
+}
+
+/// A beeper playing on the default audio output device
+///
+/// Plays a square wave, or the XO-CHIP audio pattern once a ROM has set one.
+pub struct AudioOutput {
+    _stream: Stream,
+    tone: Arc<Tone>,
+}
+
+impl std::fmt::Debug for AudioOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioOutput")
+            .field("tone", &self.tone)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AudioOutput {
+    /// The default tone frequency in Hz
+    pub const DEFAULT_FREQUENCY: f32 = 440.0;
+    /// The default volume, 0.0 (silent) - 1.0 (full scale)
+    pub const DEFAULT_VOLUME: f32 = 0.25;
+
+    pub fn new(frequency: f32, volume: f32) -> Result<Self> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| anyhow!("No audio output device available"))?;
+        let supported = device
+            .default_output_config()
+            .with_context(|| "Querying audio output config")?;
+
+        debug!("Audio output on {:?} with {:?}", device.name(), supported);
+
+        let tone = Arc::new(Tone {
+            playing: AtomicBool::new(false),
+            pattern: Mutex::new(None),
+        });
+        let volume = volume.clamp(0.0, 1.0);
+        let config = supported.config();
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => {
+                Self::build_stream::<f32>(&device, &config, tone.clone(), frequency, volume)
+            }
+            SampleFormat::I16 => {
+                Self::build_stream::<i16>(&device, &config, tone.clone(), frequency, volume)
+            }
+            SampleFormat::U16 => {
+                Self::build_stream::<u16>(&device, &config, tone.clone(), frequency, volume)
+            }
+            format => bail!("Unsupported audio sample format {:?}", format),
+        }?;
+
+        stream.play().with_context(|| "Starting audio stream")?;
+
+        Ok(Self {
+            _stream: stream,
+            tone,
+        })
+    }
+
+    pub fn speaker_adapter(&self) -> SpeakerAdapter {
+        SpeakerAdapter(self.tone.clone())
+    }
+
+    fn build_stream<T>(
+        device: &Device,
+        config: &StreamConfig,
+        tone: Arc<Tone>,
+        frequency: f32,
+        volume: f32,
+    ) -> Result<Stream>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0 as f32;
+        let mut pattern = None;
+        let mut phase = 0.0;
+
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                // Never block the audio thread, keep the previous pattern if it is being updated
+                if let Ok(current) = tone.pattern.try_lock() {
+                    pattern = *current;
+                }
+                let playing = tone.playing.load(Ordering::Relaxed);
+
+                for frame in data.chunks_mut(channels) {
+                    let high = match (playing, &pattern) {
+                        (false, _) => None,
+                        (true, Some((bits, rate))) => {
+                            phase = (phase + rate / sample_rate) % 128.0;
+                            let idx = phase as usize;
+                            Some(bits[idx / 8] >> (7 - idx % 8) & 0x01 == 1)
+                        }
+                        (true, None) => {
+                            phase = (phase + frequency / sample_rate) % 1.0;
+                            Some(phase < 0.5)
+                        }
+                    };
+
+                    let value = match high {
+                        Some(true) => volume,
+                        Some(false) => -volume,
+                        None => 0.0,
+                    };
+
+                    for sample in frame {
+                        *sample = T::from_sample(value);
+                    }
+                }
+            },
+            |e| error!("Audio stream error: {}", e),
+            None,
+        )?;
+
+        Ok(stream)
+    }
+}
+
+#[derive(Debug)]
+pub struct SpeakerAdapter(Arc<Tone>);
+
+impl Speaker for SpeakerAdapter {
+    fn start(&mut self) {
+        self.0.playing.store(true, Ordering::Relaxed);
+    }
+
+    fn stop(&mut self) {
+        self.0.playing.store(false, Ordering::Relaxed);
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        let mut current = self.0.pattern.lock().expect("Locking audio pattern failed");
+        *current = Some((*pattern, pattern_rate(pitch)));
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{bail, Context, Result};
+
+/// The first bytes of every bundle
+pub const MAGIC: &[u8; 3] = b"CBF";
+
+/// The platforms a bundle holds bytecode for, as numbered in the bytecode table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+    /// A platform the core doesn't run
+    Other(u8),
+}
+
+impl From<u8> for Platform {
+    fn from(id: u8) -> Self {
+        match id {
+            0x01 => Platform::Chip8,
+            0x02 => Platform::Schip,
+            0x03 => Platform::XoChip,
+            id => Platform::Other(id),
+        }
+    }
+}
+
+/// A CHIP-8 binary bundle (*.c8b), a ROM with the settings it is meant to run with
+///
+/// All numbers are big endian, offsets count from the start of the file:
+///
+/// ```text
+/// "CBF" u8 version (0)
+/// bytecode table: u8 platform, u16 offset, u16 length ... u8 0
+/// property table: u8 type, u16 offset ... u8 0
+/// ```
+///
+/// The properties are
+///
+/// | type | content                                                          |
+/// | ---- | ---------------------------------------------------------------- |
+/// | 0x01 | u16 cycles per frame                                             |
+/// | 0x02 | u8 count, RGB colors: background, foreground, plane 2, blend     |
+/// | 0x03 | u8 count, pairs of u8 CHIP-8 key and ASCII keyboard key          |
+/// | 0x04 | the name, a NUL terminated UTF-8 string                          |
+/// | 0x05 | the description, a NUL terminated UTF-8 string                   |
+/// | 0x06 | the author, a NUL terminated UTF-8 string                        |
+///
+/// Unknown properties are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bundle {
+    /// The bytecode of every platform, in the order of the bytecode table
+    pub bytecode: Vec<(Platform, Vec<u8>)>,
+    pub cycles_per_frame: Option<u16>,
+    /// The colors as `0xRRGGBB`
+    pub colors: Vec<u32>,
+    /// Keyboard keys mapped to CHIP-8 keys
+    pub keys: Vec<(u8, char)>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+}
+
+impl Bundle {
+    /// Whether `data` is a bundle rather than a plain ROM
+    pub fn is_bundle(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Parse a bundle
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if !Self::is_bundle(data) {
+            bail!("Not a CHIP-8 binary bundle");
+        }
+        match data.get(3) {
+            Some(0) => (),
+            Some(version) => bail!("Unsupported bundle version {}", version),
+            None => bail!("Truncated bundle header"),
+        }
+
+        let mut bundle = Self::default();
+        let mut reader = Reader { data, pos: 4 };
+
+        loop {
+            let platform = reader.u8()?;
+            if platform == 0 {
+                break;
+            }
+            let (offset, len) = (reader.u16()? as usize, reader.u16()? as usize);
+            let bytecode = data
+                .get(offset..offset + len)
+                .with_context(|| format!("Bytecode of platform {:#04x} out of bounds", platform))?;
+            bundle.bytecode.push((platform.into(), bytecode.to_vec()));
+        }
+
+        loop {
+            let property = reader.u8()?;
+            if property == 0 {
+                break;
+            }
+            let mut value = Reader {
+                data,
+                pos: reader.u16()? as usize,
+            };
+            bundle
+                .read_property(property, &mut value)
+                .with_context(|| format!("Invalid property {:#04x}", property))?;
+        }
+
+        Ok(bundle)
+    }
+
+    fn read_property(&mut self, property: u8, value: &mut Reader<'_>) -> Result<()> {
+        match property {
+            0x01 => self.cycles_per_frame = Some(value.u16()?),
+            0x02 => {
+                for _ in 0..value.u8()? {
+                    let [r, g, b] = [value.u8()?, value.u8()?, value.u8()?];
+                    self.colors.push(u32::from_be_bytes([0, r, g, b]));
+                }
+            }
+            0x03 => {
+                for _ in 0..value.u8()? {
+                    let (key, c) = (value.u8()?, value.u8()?);
+                    if key > 0xF || !c.is_ascii_graphic() {
+                        bail!("Can't map CHIP-8 key {:#04x} to {:#04x}", key, c);
+                    }
+                    self.keys.push((key, c as char));
+                }
+            }
+            0x04 => self.name = Some(value.string()?),
+            0x05 => self.description = Some(value.string()?),
+            0x06 => self.author = Some(value.string()?),
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// The bytecode for the first platform the core runs
+    pub fn rom(&self) -> Option<(Platform, &[u8])> {
+        self.bytecode
+            .iter()
+            .find(|(platform, _)| !matches!(platform, Platform::Other(_)))
+            .map(|(platform, bytecode)| (*platform, &bytecode[..]))
+    }
+}
+
+/// Reads the fields of a bundle
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Result<u8> {
+        let byte = *self.data.get(self.pos).context("Truncated bundle")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let rest = self.data.get(self.pos..).unwrap_or_default();
+        let len = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .context("Unterminated string")?;
+        self.pos += len + 1;
+
+        Ok(std::str::from_utf8(&rest[..len])?.to_string())
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::prelude::*;
+use std::ops::Range;
+use std::path::Path;
+
+/// Cheats of a ROM, patches applied once it is loaded and bytes kept from changing
+///
+/// A cheat file holds one cheat per line, the address and the bytes in hexadecimal, e.g.
+///
+/// ```text
+/// # Skip the title screen
+/// 0x2A4 = 12 C0
+/// # Infinite lives
+/// freeze 0x3F0 = 03
+/// ```
+///
+/// Frozen bytes are written at load as well, and written again whenever the program
+/// overwrites them, see [`WriteHook`]. Empty lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cheats {
+    patches: Vec<(usize, Vec<u8>)>,
+    frozen: Vec<(usize, Vec<u8>)>,
+}
+
+impl Cheats {
+    /// Read a cheat file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading cheats \"{}\"", path.display()))?;
+
+        Self::parse(&text).with_context(|| format!("Parsing cheats \"{}\"", path.display()))
+    }
+
+    /// Parse the cheats of a cheat file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut cheats = Self::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (cheats, line) = match line.strip_prefix("freeze ") {
+                Some(line) => (&mut cheats.frozen, line),
+                None => (&mut cheats.patches, line),
+            };
+            cheats.push(parse_cheat(line).with_context(|| format!("Line {}", idx + 1))?);
+        }
+
+        Ok(cheats)
+    }
+
+    /// Write the patches and frozen bytes to `mem`, the memory of the CHIP-8 with the ROM
+    /// loaded
+    pub fn apply(&self, mem: &mut [u8]) -> Result<()> {
+        for (addr, bytes) in self.patches.iter().chain(&self.frozen) {
+            match mem.get_mut(*addr..*addr + bytes.len()) {
+                Some(target) => target.copy_from_slice(bytes),
+                None => bail!("The cheat at {:#x} is out of memory", addr),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteHook for Cheats {
+    fn written(&mut self, core: &mut Core<'_>, range: Range<usize>) {
+        for (addr, bytes) in &self.frozen {
+            let start = range.start.max(*addr);
+            let end = range.end.min(*addr + bytes.len());
+            if start >= end {
+                continue;
+            }
+
+            if let Some(target) = core.memory_range_mut(start..end) {
+                target.copy_from_slice(&bytes[start - addr..end - addr]);
+            }
+        }
+    }
+}
+
+/// Parse `ADDR = BYTES`
+fn parse_cheat(line: &str) -> Result<(usize, Vec<u8>)> {
+    let Some((addr, bytes)) = line.split_once('=') else {
+        bail!("Expected ADDR = BYTES, e.g. 0x3F0 = 03");
+    };
+
+    let addr = addr.trim();
+    let addr = usize::from_str_radix(addr.trim_start_matches("0x"), 16)
+        .with_context(|| format!("Invalid address {}", addr))?;
+
+    let digits: String = bytes.split_whitespace().collect();
+    if digits.is_empty() || !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        bail!(
+            "Expected bytes as pairs of hex digits, got \"{}\"",
+            bytes.trim()
+        );
+    }
+    let bytes = (0..digits.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&digits[idx..idx + 2], 16))
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid bytes \"{}\"", bytes.trim()))?;
+
+    Ok((addr, bytes))
+}
@@ -0,0 +1,39 @@
+use super::keymap::KeyMapConfig;
+use super::palette::PaletteConfig;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The settings of the frontends, read from a TOML file
+///
+/// Every section is optional, missing settings keep their defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub keymap: KeyMapConfig,
+    pub palette: PaletteConfig,
+}
+
+impl Config {
+    /// The config file of the user, `chip8/config.toml` in the platform's config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+    }
+
+    /// Read a config file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading config \"{}\"", path.display()))?;
+
+        toml::from_str(&text).with_context(|| format!("Parsing config \"{}\"", path.display()))
+    }
+
+    /// Read the config file of the user, if there is one
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+}
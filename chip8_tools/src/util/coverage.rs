@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use chip8_core::core::Coverage;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A coverage map saved as JSON, the ranges of addresses which were executed, read as
+/// sprite data, written, and written after being executed by self-modifying code
+///
+/// Ranges include their start and exclude their end, e.g.
+///
+/// ```json
+/// {"executed": [[512, 608], [640, 652]], "sprite": [[700, 715]], "written": [[612, 614]],
+///  "modified": [[612, 614]]}
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoverageReport {
+    pub executed: Vec<(usize, usize)>,
+    pub sprite: Vec<(usize, usize)>,
+    pub written: Vec<(usize, usize)>,
+    pub modified: Vec<(usize, usize)>,
+}
+
+impl CoverageReport {
+    /// The report of a coverage map recorded by the core
+    pub fn new(coverage: &[Coverage]) -> Self {
+        Self {
+            executed: ranges(coverage, Coverage::EXECUTED),
+            sprite: ranges(coverage, Coverage::SPRITE),
+            written: ranges(coverage, Coverage::WRITTEN),
+            modified: ranges(coverage, Coverage::MODIFIED),
+        }
+    }
+
+    /// Read a report
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading coverage \"{}\"", path.display()))?;
+
+        serde_json::from_str(&text)
+            .with_context(|| format!("Parsing coverage \"{}\"", path.display()))
+    }
+
+    /// Write the report
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        std::fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Writing coverage \"{}\"", path.display()))
+    }
+
+    /// The coverage map of the first `len` addresses, e.g. to continue recording
+    pub fn map(&self, len: usize) -> Vec<Coverage> {
+        let mut map = vec![Coverage::default(); len];
+
+        for (ranges, access) in [
+            (&self.executed, Coverage::EXECUTED),
+            (&self.sprite, Coverage::SPRITE),
+            (&self.written, Coverage::WRITTEN),
+            (&self.modified, Coverage::MODIFIED),
+        ] {
+            for &(start, end) in ranges {
+                for entry in map.iter_mut().take(end).skip(start) {
+                    entry.0 |= access.0;
+                }
+            }
+        }
+
+        map
+    }
+}
+
+/// The ranges of addresses in `coverage` with the `access`
+fn ranges(coverage: &[Coverage], access: Coverage) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for (addr, _) in coverage
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.contains(access))
+    {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == addr => *end += 1,
+            _ => ranges.push((addr, addr + 1)),
+        }
+    }
+
+    ranges
+}
@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+use chip8_core::prelude::*;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// The CHIP-8 keys in the order they are laid out on the COSMAC VIP keypad
+///
+/// ```text
+/// 1 2 3 C
+/// 4 5 6 D
+/// 7 8 9 E
+/// A 0 B F
+/// ```
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+/// A keyboard layout, deciding which keys make up the default keypad
+///
+/// The default keypad is the 4x4 block of keys below and including `1` to `4`, so it has
+/// the same shape on every layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    #[default]
+    Qwerty,
+    Qwertz,
+    Azerty,
+    Dvorak,
+    Colemak,
+}
+
+impl Layout {
+    /// The keys of the keypad block, row by row
+    fn keys(self) -> &'static str {
+        match self {
+            Layout::Qwerty => "1234qwerasdfzxcv",
+            Layout::Qwertz => "1234qwerasdfyxcv",
+            Layout::Azerty => "1234azerqsdfwxcv",
+            Layout::Dvorak => "1234',.paoeu;qjk",
+            Layout::Colemak => "1234qwfparstzxcd",
+        }
+    }
+}
+
+/// The `[keymap]` section of the config file
+///
+/// ```toml
+/// [keymap]
+/// layout = "azerty"
+///
+/// # Overrides of single keys, CHIP-8 key = keyboard key
+/// [keymap.keys]
+/// 0 = "x"
+/// f = "m"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyMapConfig {
+    pub layout: Layout,
+    pub keys: BTreeMap<String, char>,
+}
+
+/// The mapping of keyboard keys to the 16 CHIP-8 keys
+///
+/// Keyboard keys are identified by the lower case character printed on them, frontends
+/// translate their key codes with the keyboard layout of the operating system. Keys which
+/// aren't mapped are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    /// The keyboard key of every CHIP-8 key
+    keys: [char; 16],
+}
+
+impl KeyMap {
+    /// The default keypad of a layout
+    pub fn new(layout: Layout) -> Self {
+        let mut keys = ['\0'; 16];
+        for (&key, c) in KEYPAD.iter().zip(layout.keys().chars()) {
+            keys[key as usize] = c;
+        }
+
+        Self { keys }
+    }
+
+    /// Map the CHIP-8 `key` to the keyboard key `c`
+    ///
+    /// A keyboard key maps to a single CHIP-8 key, it is removed from any other key.
+    pub fn set(&mut self, key: u8, c: char) {
+        let c = c.to_ascii_lowercase();
+
+        for mapped in self.keys.iter_mut().filter(|mapped| **mapped == c) {
+            *mapped = '\0';
+        }
+        self.keys[key as usize & 0xF] = c;
+    }
+
+    /// The CHIP-8 key mapped to the keyboard key `c`
+    pub fn get(&self, c: char) -> Option<u8> {
+        let c = c.to_ascii_lowercase();
+
+        self.keys
+            .iter()
+            .position(|&mapped| mapped == c)
+            .map(|key| key as u8)
+    }
+
+    /// The CHIP-8 keys held down, given the held keyboard keys
+    pub fn keys<I: IntoIterator<Item = char>>(&self, pressed: I) -> Keys {
+        Keys(
+            pressed
+                .into_iter()
+                .filter_map(|c| self.get(c))
+                .fold(0, |keys, key| keys | 1 << key),
+        )
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::new(Layout::default())
+    }
+}
+
+impl TryFrom<&KeyMapConfig> for KeyMap {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &KeyMapConfig) -> Result<Self> {
+        let mut keymap = Self::new(config.layout);
+
+        for (key, &c) in &config.keys {
+            let mut digits = key.chars();
+            let key = match (digits.next().and_then(|c| c.to_digit(16)), digits.next()) {
+                (Some(key), None) => key as u8,
+                _ => bail!("\"{}\" is not a CHIP-8 key, expected 0 - f", key),
+            };
+            if c.is_whitespace() || c.is_control() {
+                bail!("Can't map CHIP-8 key {:X} to whitespace", key);
+            }
+
+            keymap.set(key, c);
+        }
+
+        Ok(keymap)
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Aggregated latency samples of one stage of the pipeline
+#[derive(Debug, Default, Clone)]
+pub struct LatencySamples {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencySamples {
+    fn add(&mut self, sample: Duration) {
+        self.count += 1;
+        self.total += sample;
+        self.max = self.max.max(sample);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.total / self.count as u32)
+        }
+    }
+}
+
+impl fmt::Display for LatencySamples {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mean() {
+            Some(mean) => write!(
+                f,
+                "mean {:>8.3} ms, max {:>8.3} ms ({} samples)",
+                mean.as_secs_f64() * 1000.0,
+                self.max.as_secs_f64() * 1000.0,
+                self.count
+            ),
+            None => write!(f, "no samples"),
+        }
+    }
+}
+
+/// A breakdown of the latency between a key press and the resulting frame being shown
+///
+/// * `input_to_tick`: key state received by the frontend until a core tick reads it
+/// * `tick_to_present`: frame produced by the core until the frontend presents it
+/// * `input_to_present`: the whole pipeline
+#[derive(Debug, Default, Clone)]
+pub struct LatencyReport {
+    pub input_to_tick: LatencySamples,
+    pub tick_to_present: LatencySamples,
+    pub input_to_present: LatencySamples,
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "input -> tick     {}", self.input_to_tick)?;
+        writeln!(f, "tick -> present   {}", self.tick_to_present)?;
+        write!(f, "input -> present  {}", self.input_to_present)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Events {
+    /// The latest input which wasn't read by the core yet
+    input_received: Option<Instant>,
+    /// An input read by the core, waiting for the next frame to be presented
+    input_ticked: Option<(Instant, Instant)>,
+    /// The oldest and the newest frame produced by the core which weren't presented yet
+    frames_ticked: Option<(Instant, Instant)>,
+    report: LatencyReport,
+}
+
+/// Collects timestamps of the events flowing from the frontend through the core and back
+#[derive(Debug, Default)]
+pub struct LatencyTracker(Mutex<Events>);
+
+impl LatencyTracker {
+    /// The frontend received a new key state
+    pub fn input_received(&self, at: Instant) {
+        self.events().input_received = Some(at);
+    }
+
+    /// The core read the current key state
+    pub fn input_ticked(&self, at: Instant) {
+        let mut events = self.events();
+
+        if let Some(received) = events.input_received.take() {
+            events.report.input_to_tick.add(at - received);
+            events.input_ticked = Some((received, at));
+        }
+    }
+
+    /// The core produced a new frame
+    pub fn frame_ticked(&self, at: Instant) {
+        let mut events = self.events();
+
+        let oldest = events.frames_ticked.map_or(at, |(oldest, _)| oldest);
+        events.frames_ticked = Some((oldest, at));
+    }
+
+    /// The frontend presented the latest frame
+    pub fn presented(&self, at: Instant) {
+        let mut events = self.events();
+
+        let (oldest, newest) = match events.frames_ticked.take() {
+            Some(frames) => frames,
+            None => return,
+        };
+        events.report.tick_to_present.add(at - oldest);
+
+        // Only frames produced after the input was read can show its effect
+        if let Some((received, ticked)) = events.input_ticked {
+            if newest >= ticked {
+                events.report.input_to_present.add(at - received);
+                events.input_ticked = None;
+            }
+        }
+    }
+
+    /// A snapshot of the collected latencies
+    pub fn report(&self) -> LatencyReport {
+        self.events().report.clone()
+    }
+
+    fn events(&self) -> std::sync::MutexGuard<'_, Events> {
+        self.0.lock().expect("Locking latency events failed")
+    }
+}
@@ -1,132 +1,547 @@
-use chip8_core::peripherals::{FallingEdges, Graphics, Keypad, Keys, Pos, Sprite};
-use log::debug;
-use minifb::{Error, Key, Window, WindowOptions};
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    mpsc::Receiver,
-    Arc, Mutex,
-};
-
-#[derive(Debug)]
+use super::keymap::KeyMap;
+use super::latency::{LatencyReport, LatencyTracker};
+use super::palette::Palette;
+use super::screenshot;
+use chip8_core::prelude::*;
+use log::{debug, info, warn};
+use minifb::{Error, Key, KeyRepeat, ScaleMode, Window, WindowOptions};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The latest frame of the core, waiting to be drawn by the GUI
+#[derive(Debug, Default)]
 struct Buffer {
-    buf: Mutex<Vec<u32>>,
-    changed: AtomicBool,
+    framebuffer: Framebuffer,
+    dirty: Option<Rect>,
 }
 
+/// The most events queued for the core, older ones are dropped while it doesn't tick
+const MAX_KEY_EVENTS: usize = 64;
+
 #[derive(Debug)]
 pub struct CurrentKeys {
     prev: Keys,
     current: Keys,
+    /// The changes of the keys not yet read by the core
+    events: VecDeque<KeyEvent>,
+}
+
+impl CurrentKeys {
+    /// Update the held keys, queueing an event for every key which changed
+    fn update(&mut self, keys: Keys) {
+        let changed = self.current.0 ^ keys.0;
+
+        for key in (0..16).filter(|key| changed & 1 << key != 0) {
+            if self.events.len() == MAX_KEY_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(if keys.0 & 1 << key != 0 {
+                KeyEvent::Down(key)
+            } else {
+                KeyEvent::Up(key)
+            });
+        }
+
+        self.prev = std::mem::replace(&mut self.current, keys);
+    }
 }
 
 #[derive(Debug)]
 pub struct MinifbDisplay {
     window: Window,
-    buffer: Arc<Buffer>,
+    buffer: Arc<Mutex<Buffer>>,
+    scaled: Vec<u32>,
+    width: usize,
+    height: usize,
+    fps_target: u64,
+    /// The size of a low resolution pixel the window was opened with
+    scale: usize,
+    borderless: bool,
+    /// Where the framebuffer was drawn last, `None` if the window has to be redrawn
+    viewport: Option<Viewport>,
+    palette: Palette,
+    keymap: KeyMap,
+    control: Option<Control>,
     keys: Arc<Mutex<CurrentKeys>>,
+    latency: Arc<LatencyTracker>,
+    /// Whether a speaker without audio output is sounding
+    sounding: Arc<AtomicBool>,
+    /// Whether the title currently shows the sound indicator
+    sound_shown: bool,
+    /// The state published by the CHIP-8 for the status line
+    status: Arc<Mutex<Status>>,
+    /// The name of the running ROM, shown in the status line
+    rom_name: String,
+    /// The status line drawn over the display, if it is shown
+    status_line: Option<StatusLine>,
 }
 
-fn map_keys(keys: &[Key]) -> Keys {
-    let mut final_value = 0;
-
-    for key in keys {
-        let val = match key {
-            Key::Key1 => 0x1,
-            Key::Key2 => 0x2,
-            Key::Key3 => 0x3,
-            Key::Key4 => 0xC,
-            Key::Q => 0x4,
-            Key::W => 0x5,
-            Key::E => 0x6,
-            Key::R => 0xD,
-            Key::A => 0x7,
-            Key::S => 0x8,
-            Key::D => 0x9,
-            Key::F => 0xE,
-            Key::Z => 0xA,
-            Key::X => 0x0,
-            Key::C => 0xB,
-            Key::V => 0xF,
-            _ => 0x0,
-        };
+const TITLE: &str = "CHIP-8 Emulator";
+const TITLE_SOUNDING: &str = "CHIP-8 Emulator [BEEP]";
+
+/// The latest state of the CHIP-8, published by a [`StatusAdapter`]
+#[derive(Clone, Copy, Debug, Default)]
+struct Status {
+    stats: Stats,
+    paused: bool,
+}
+
+/// The status line shown with F1, the speed of the CHIP-8 measured over the last interval
+#[derive(Debug)]
+struct StatusLine {
+    /// The time and stats at the start of the current interval
+    sample: (Instant, Stats),
+    /// Instructions and frames per second in the last interval
+    rates: (u64, u64),
+    text: String,
+}
 
-        debug!("final_value {}", final_value);
-        final_value |= 1 << val;
+impl StatusLine {
+    /// How often the rates are measured
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    fn new(now: Instant, stats: Stats) -> Self {
+        Self {
+            sample: (now, stats),
+            rates: (0, 0),
+            text: String::new(),
+        }
     }
 
-    Keys(final_value)
+    /// Update the text to the latest `status`, returns whether it changed
+    fn update(&mut self, now: Instant, status: Status, rom_name: &str) -> bool {
+        let (start, stats) = self.sample;
+        let elapsed = now - start;
+        if elapsed >= Self::INTERVAL {
+            let delta = status.stats.since(&stats);
+            let per_second = |count: u64| (count as f64 / elapsed.as_secs_f64()).round() as u64;
+
+            self.rates = (per_second(delta.instructions), per_second(delta.frames));
+            self.sample = (now, status.stats);
+        }
+
+        let (ips, fps) = self.rates;
+        let mut text = format!("{}  {} IPS  {} FPS", rom_name, ips, fps);
+        if status.paused {
+            text.push_str("  PAUSED");
+        }
+
+        if text == self.text {
+            return false;
+        }
+        self.text = text;
+        true
+    }
 }
 
-impl MinifbDisplay {
-    const SCALE: usize = 10;
+/// The rows of a character in the status line font, three pixels wide with the most
+/// significant bit on the left
+///
+/// Lowercase letters are drawn as uppercase ones, characters without a glyph as `?`.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        _ => [0b111, 0b001, 0b011, 0b000, 0b010],
+    }
+}
 
-    pub fn new(fps_target: u64) -> Result<Self, Error> {
-        let width = GraphicsAdapter::WIDTH * Self::SCALE;
-        let height = GraphicsAdapter::HEIGHT * Self::SCALE;
+/// Draw `text` into the top left corner of a `width` pixels wide `buffer`, on a box of the
+/// `background` color
+///
+/// Every pixel of the font is `scale` pixels large, text beyond the buffer is cut off.
+fn draw_text(
+    buffer: &mut [u32],
+    width: usize,
+    text: &str,
+    scale: usize,
+    foreground: u32,
+    background: u32,
+) {
+    let height = buffer.len() / width.max(1);
+    let mut fill = |column: usize, row: usize, color: u32| {
+        let (start, end) = (
+            (column * scale).min(width),
+            ((column + 1) * scale).min(width),
+        );
+        for y in (row * scale..(row + 1) * scale).take_while(|&y| y < height) {
+            buffer[y * width + start..y * width + end].fill(color);
+        }
+    };
 
-        let mut window = Window::new("CHIP-8 Emulator", width, height, WindowOptions::default())?;
+    // A margin of one pixel around the text and between the glyphs
+    let columns = text.chars().count() * 4 + 1;
+    for row in 0..7 {
+        for column in 0..columns {
+            fill(column, row, background);
+        }
+    }
 
-        window.limit_update_rate(Some(std::time::Duration::from_micros(
-            1_000_000 / fps_target,
-        )));
+    for (idx, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for x in (0..3).filter(|x| bits & 0b100 >> x != 0) {
+                fill(1 + idx * 4 + x, 1 + row, foreground);
+            }
+        }
+    }
+}
+
+/// The character printed on a key, as used by [`KeyMap`]
+fn key_char(key: Key) -> Option<char> {
+    let c = match key {
+        Key::Key0 => '0',
+        Key::Key1 => '1',
+        Key::Key2 => '2',
+        Key::Key3 => '3',
+        Key::Key4 => '4',
+        Key::Key5 => '5',
+        Key::Key6 => '6',
+        Key::Key7 => '7',
+        Key::Key8 => '8',
+        Key::Key9 => '9',
+        Key::A => 'a',
+        Key::B => 'b',
+        Key::C => 'c',
+        Key::D => 'd',
+        Key::E => 'e',
+        Key::F => 'f',
+        Key::G => 'g',
+        Key::H => 'h',
+        Key::I => 'i',
+        Key::J => 'j',
+        Key::K => 'k',
+        Key::L => 'l',
+        Key::M => 'm',
+        Key::N => 'n',
+        Key::O => 'o',
+        Key::P => 'p',
+        Key::Q => 'q',
+        Key::R => 'r',
+        Key::S => 's',
+        Key::T => 't',
+        Key::U => 'u',
+        Key::V => 'v',
+        Key::W => 'w',
+        Key::X => 'x',
+        Key::Y => 'y',
+        Key::Z => 'z',
+        Key::Apostrophe => '\'',
+        Key::Backquote => '`',
+        Key::Backslash => '\\',
+        Key::Comma => ',',
+        Key::Equal => '=',
+        Key::LeftBracket => '[',
+        Key::Minus => '-',
+        Key::Period => '.',
+        Key::RightBracket => ']',
+        Key::Semicolon => ';',
+        Key::Slash => '/',
+        _ => return None,
+    };
+
+    Some(c)
+}
 
-        let buffer = Buffer {
-            buf: Mutex::new(vec![0; width * height]),
-            changed: AtomicBool::new(false),
+/// The hotkeys controlling the CHIP-8, see [`MinifbDisplay::control_channel`]
+#[derive(Debug)]
+struct Control {
+    commands: Sender<Command>,
+    /// The core frequency without turbo
+    core_freq: u32,
+    turbo: bool,
+}
+
+impl Control {
+    /// How much faster the CHIP-8 runs while turbo is held
+    const TURBO: u32 = 8;
+
+    fn send(&self, command: Command) {
+        // The CHIP-8 thread stops on errors, the GUI notices that on its own
+        let _ = self.commands.send(command);
+    }
+
+    fn set_core_freq(&mut self, core_freq: u32) {
+        self.core_freq = core_freq.max(1);
+        info!("Core frequency {} Hz", self.core_freq);
+
+        if !self.turbo {
+            self.send(Command::SetCoreFreq(self.core_freq));
+        }
+    }
+
+    fn set_turbo(&mut self, turbo: bool) {
+        if turbo == self.turbo {
+            return;
+        }
+
+        self.turbo = turbo;
+        let core_freq = if turbo {
+            self.core_freq * Self::TURBO
+        } else {
+            self.core_freq
         };
+        self.send(Command::SetCoreFreq(core_freq));
+    }
+}
+
+/// Where the framebuffer is drawn inside the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Viewport {
+    /// The size of a framebuffer pixel, zero if the window is too small
+    scale: usize,
+    x: usize,
+    y: usize,
+}
+
+impl Viewport {
+    /// The largest integer scaling of `framebuffer` fitting a window, centered
+    fn fit(width: usize, height: usize, framebuffer: &Framebuffer) -> Self {
+        let scale = (width / framebuffer.width()).min(height / framebuffer.height());
+
+        Self {
+            scale,
+            x: (width - framebuffer.width() * scale) / 2,
+            y: (height - framebuffer.height() * scale) / 2,
+        }
+    }
+}
+
+fn open_window(
+    width: usize,
+    height: usize,
+    borderless: bool,
+    fps_target: u64,
+) -> Result<Window, Error> {
+    let options = WindowOptions {
+        borderless,
+        title: !borderless,
+        topmost: borderless,
+        resize: true,
+        scale_mode: ScaleMode::UpperLeft,
+        ..WindowOptions::default()
+    };
+    let mut window = Window::new(TITLE, width, height, options)?;
+
+    window.limit_update_rate(Some(std::time::Duration::from_micros(
+        1_000_000 / fps_target,
+    )));
+    if borderless {
+        window.set_position(0, 0);
+    }
+
+    Ok(window)
+}
+
+impl MinifbDisplay {
+    /// The default scale of a low resolution pixel
+    pub const DEFAULT_SCALE: usize = 10;
+
+    /// Open a window, `scale` is the size of a low resolution pixel
+    ///
+    /// High resolution pixels are half as large, so `scale` has to be even.
+    pub fn new(fps_target: u64, scale: usize, palette: Palette) -> Result<Self, Error> {
+        let width = DisplayMode::LoRes.width() * scale;
+        let height = DisplayMode::LoRes.height() * scale;
+
+        let window = open_window(width, height, false, fps_target)?;
 
         let current_keys = Mutex::new(CurrentKeys {
             prev: Keys(0),
             current: Keys(0),
+            events: VecDeque::new(),
         });
 
         Ok(Self {
             window,
-            buffer: Arc::new(buffer),
+            buffer: Arc::new(Mutex::new(Buffer::default())),
+            scaled: vec![palette.background; width * height],
+            width,
+            height,
+            fps_target,
+            scale,
+            borderless: false,
+            viewport: None,
+            palette,
+            keymap: KeyMap::default(),
+            control: None,
             keys: Arc::new(current_keys),
+            latency: Arc::new(LatencyTracker::default()),
+            sounding: Arc::new(AtomicBool::new(false)),
+            sound_shown: false,
+            status: Arc::new(Mutex::new(Status::default())),
+            rom_name: String::new(),
+            status_line: None,
         })
     }
 
+    /// Show `name` as the name of the running ROM in the status line
+    pub fn with_rom_name(mut self, name: &str) -> Self {
+        self.rom_name = name.to_string();
+        self
+    }
+
+    /// Use `keymap` instead of the QWERTY keypad
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    /// Control the CHIP-8 with hotkeys, the returned receiver gets their commands
+    ///
+    /// `core_freq` is the frequency the CHIP-8 runs at, the speed hotkeys change it in
+    /// steps of 25%. P pauses and resumes, Backspace resets, + and - change the speed and
+    /// the CHIP-8 runs eight times as fast while Tab is held. P, + and - are only hotkeys if
+    /// the keymap doesn't use them, Pause and the keys of the number block always work.
+    pub fn control_channel(&mut self, core_freq: u32) -> Receiver<Command> {
+        let (commands, receiver) = channel();
+        self.control = Some(Control {
+            commands,
+            core_freq,
+            turbo: false,
+        });
+
+        receiver
+    }
+
+    /// A speaker playing on `audio`, or showing the sound in the window title without it
+    ///
+    /// The indicator can't be seen in the borderless window, which has no title bar.
+    pub fn speaker_adapter<S: Speaker>(&self, audio: Option<S>) -> SpeakerAdapter<S> {
+        SpeakerAdapter(audio, self.sounding.clone())
+    }
+
+    /// Publishes the state of the CHIP-8 for the status line shown with F1
+    pub fn status_adapter(&self) -> StatusAdapter {
+        StatusAdapter(self.status.clone())
+    }
+
     pub fn keypad_adater(&self) -> KeypadAdapter {
-        KeypadAdapter(self.keys.clone())
+        KeypadAdapter(self.keys.clone(), self.latency.clone())
     }
 
     pub fn graphics_adapter(&self) -> GraphicsAdapter {
-        GraphicsAdapter(self.buffer.clone())
+        GraphicsAdapter(self.buffer.clone(), self.latency.clone())
     }
 
-    pub fn run(&mut self, stop: Receiver<()>) -> Result<(), Error> {
-        let (width, height) = self.window.get_size();
+    /// The latencies between key presses, core ticks and presented frames so far
+    pub fn latency_report(&self) -> LatencyReport {
+        self.latency.report()
+    }
 
+    /// A copy of the latest frame presented by the core
+    pub fn screenshot(&self) -> Framebuffer {
+        let buffer = self.buffer.lock().expect("Locking graphics buffer failed");
+        buffer.framebuffer.clone()
+    }
+
+    /// Run the window until it is closed, ESC is pressed or a stop is received
+    ///
+    /// The window can be resized freely, the display is scaled by the largest integer
+    /// factor fitting the window and centered. F9 and F10 shrink and grow the window, F11
+    /// toggles a borderless window in the top left corner of the screen, as close to
+    /// fullscreen as minifb gets. F12 saves a screenshot to the current directory. F1
+    /// toggles a status line with the ROM name and the instructions and frames the CHIP-8
+    /// executes per second, published by a [`StatusAdapter`].
+    pub fn run(&mut self, stop: Receiver<()>) -> Result<(), Error> {
         while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
             if let Ok(()) = stop.try_recv() {
                 return Ok(());
             }
 
-            let pressed_keys =
-                if let Some(pressed_keys) = self.window.get_keys_pressed(minifb::KeyRepeat::Yes) {
-                    map_keys(&pressed_keys[..])
-                } else {
-                    Keys(0)
-                };
+            if self.window.is_key_pressed(Key::F12, KeyRepeat::No) {
+                self.save_screenshot();
+            }
+            if self.window.is_key_pressed(Key::F1, KeyRepeat::No) {
+                self.toggle_status_line();
+            }
+            if self.window.is_key_pressed(Key::F9, KeyRepeat::No) && self.scale > 2 {
+                self.reopen(self.scale - 2, self.borderless)?;
+            }
+            if self.window.is_key_pressed(Key::F10, KeyRepeat::No) {
+                self.reopen(self.scale + 2, self.borderless)?;
+            }
+            if self.window.is_key_pressed(Key::F11, KeyRepeat::No) {
+                self.reopen(self.scale, !self.borderless)?;
+            }
+            self.resize();
+            self.handle_hotkeys();
+            self.show_sound();
+            self.update_status_line();
+
+            let pressed_keys = if let Some(held_keys) = self.window.get_keys() {
+                self.keymap.keys(held_keys.into_iter().filter_map(key_char))
+            } else {
+                Keys(0)
+            };
 
             {
                 let keys = &mut self.keys.lock().expect("Locking keys failed");
-                let current = keys.current.clone();
-                keys.prev.update(&current);
-                keys.current = pressed_keys;
-            }
 
-            if self.buffer.changed.swap(false, Ordering::Relaxed) {
-                let buffer = {
-                    self.buffer
-                        .buf
-                        .lock()
-                        .expect("Locking graphics buffer failed")
-                        .clone()
-                };
+                if pressed_keys != keys.current {
+                    self.latency.input_received(Instant::now());
+                }
+                keys.update(pressed_keys);
+            }
 
-                self.window.update_with_buffer(&buffer, width, height)?;
+            let pending = {
+                let mut buffer = self.buffer.lock().expect("Locking graphics buffer failed");
+                match (buffer.dirty.take(), self.viewport) {
+                    (Some(dirty), _) => Some((buffer.framebuffer.clone(), dirty)),
+                    // The window changed, everything has to be redrawn
+                    (None, None) => Some((buffer.framebuffer.clone(), full(&buffer.framebuffer))),
+                    (None, Some(_)) => None,
+                }
+            };
+
+            if let Some((framebuffer, dirty)) = pending {
+                self.draw(&framebuffer, dirty);
+                self.draw_status_line();
+                self.window
+                    .update_with_buffer(&self.scaled, self.width, self.height)?;
+                self.latency.presented(Instant::now());
             } else {
                 self.window.update();
             }
@@ -135,43 +550,180 @@ impl MinifbDisplay {
         Ok(())
     }
 
-    pub fn set_pixel(buffer: &mut [u32], x: usize, y: usize, on: bool) -> bool {
-        let x_first = Self::SCALE * x;
-        let y_first = Self::SCALE * y;
+    /// Send the commands of the pressed hotkeys, if the CHIP-8 is controlled
+    fn handle_hotkeys(&mut self) {
+        let Some(control) = &mut self.control else {
+            return;
+        };
 
-        let x_range = x_first..(Self::SCALE * x + Self::SCALE);
-        let y_range = y_first..(Self::SCALE * y + Self::SCALE);
+        let window = &self.window;
+        let keymap = &self.keymap;
+        let pressed = |keys: &[Key]| {
+            keys.iter().any(|&key| {
+                let free = key_char(key).is_none_or(|c| keymap.get(c).is_none());
+                free && window.is_key_pressed(key, KeyRepeat::No)
+            })
+        };
 
-        let val = if on { 0xFF_FF_FF } else { 0 };
+        if pressed(&[Key::P, Key::Pause]) {
+            control.send(Command::TogglePause);
+        }
+        if pressed(&[Key::Backspace]) {
+            info!("Reset");
+            control.send(Command::Reset);
+        }
+        if pressed(&[Key::Equal, Key::NumPadPlus]) {
+            control.set_core_freq(control.core_freq + (control.core_freq / 4).max(1));
+        }
+        if pressed(&[Key::Minus, Key::NumPadMinus]) {
+            control.set_core_freq(control.core_freq - control.core_freq / 4);
+        }
+        control.set_turbo(window.is_key_down(Key::Tab));
+    }
+
+    /// Replace the window by one with a low resolution pixel size of `scale`
+    fn reopen(&mut self, scale: usize, borderless: bool) -> Result<(), Error> {
+        let width = DisplayMode::LoRes.width() * scale;
+        let height = DisplayMode::LoRes.height() * scale;
+        debug!("Reopening window with {}x{} pixels", width, height);
+
+        self.window = open_window(width, height, borderless, self.fps_target)?;
+        self.scale = scale;
+        self.borderless = borderless;
+        self.sound_shown = false;
 
-        let collision = on && buffer[x_first + y_first * GraphicsAdapter::WIDTH * Self::SCALE] != 0;
+        Ok(())
+    }
 
-        for x in x_range {
-            for y in y_range.clone() {
-                buffer[x + y * GraphicsAdapter::WIDTH * Self::SCALE] ^= val;
+    /// Mark the title while a speaker without audio output is sounding
+    fn show_sound(&mut self) {
+        let sounding = self.sounding.load(Ordering::Relaxed);
+        if sounding != self.sound_shown {
+            self.window
+                .set_title(if sounding { TITLE_SOUNDING } else { TITLE });
+            self.sound_shown = sounding;
+        }
+    }
+
+    fn toggle_status_line(&mut self) {
+        self.status_line = match self.status_line {
+            Some(_) => None,
+            None => {
+                let status = *self.status.lock().expect("Locking status failed");
+                Some(StatusLine::new(Instant::now(), status.stats))
             }
+        };
+
+        // Redraw the display, covering the status line or making room for it
+        self.viewport = None;
+    }
+
+    /// Update the status line to the latest state of the CHIP-8, if it is shown
+    fn update_status_line(&mut self) {
+        let Some(status_line) = &mut self.status_line else {
+            return;
+        };
+
+        let status = *self.status.lock().expect("Locking status failed");
+        if status_line.update(Instant::now(), status, &self.rom_name) {
+            // A shorter text doesn't cover all of the previous one
+            self.viewport = None;
         }
+    }
 
-        collision
+    fn draw_status_line(&mut self) {
+        if let Some(status_line) = &self.status_line {
+            draw_text(
+                &mut self.scaled,
+                self.width,
+                &status_line.text,
+                (self.scale / 5).max(1),
+                self.palette.foreground,
+                self.palette.background,
+            );
+        }
     }
 
-    pub fn reset_pixel(buffer: &mut [u32], x: usize, y: usize) {
-        let x_range = (Self::SCALE * x)..(Self::SCALE * x + Self::SCALE);
-        let y_range = (Self::SCALE * y)..(Self::SCALE * y + Self::SCALE);
+    /// Adapt the window buffer to the size of the window
+    fn resize(&mut self) {
+        let (width, height) = self.window.get_size();
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.scaled.resize(width * height, self.palette.background);
+        self.viewport = None;
+    }
+
+    fn save_screenshot(&self) {
+        let framebuffer = self.screenshot();
+        let path = screenshot::timestamped_path(".");
 
-        for x in x_range {
-            for y in y_range.clone() {
-                buffer[x + y * GraphicsAdapter::WIDTH * Self::SCALE] = 0;
+        // Screenshots use the scale of the window, but at least one pixel
+        let scale = Viewport::fit(self.width, self.height, &framebuffer)
+            .scale
+            .max(1);
+
+        match screenshot::save_png(&framebuffer, &path, scale) {
+            Ok(()) => info!("Saved screenshot to {}", path.display()),
+            Err(e) => warn!("Saving screenshot failed: {:#}", e),
+        }
+    }
+
+    /// Rescale the dirty region of the framebuffer into the window buffer
+    ///
+    /// If the viewport changed since the last frame, the whole window is redrawn.
+    fn draw(&mut self, framebuffer: &Framebuffer, dirty: Rect) {
+        let viewport = Viewport::fit(self.width, self.height, framebuffer);
+
+        let dirty = if self.viewport == Some(viewport) {
+            dirty
+        } else {
+            self.scaled.fill(self.palette.background);
+            self.viewport = Some(viewport);
+            full(framebuffer)
+        };
+
+        for y in dirty.y..(dirty.y + dirty.height) {
+            for x in dirty.x..(dirty.x + dirty.width) {
+                self.set_pixel(x, y, viewport, framebuffer.pixel(x, y));
             }
         }
     }
+
+    fn set_pixel(&mut self, x: usize, y: usize, viewport: Viewport, on: bool) {
+        let Viewport { scale, .. } = viewport;
+        let x_range = (viewport.x + scale * x)..(viewport.x + scale * x + scale);
+        let y_range = (viewport.y + scale * y)..(viewport.y + scale * y + scale);
+
+        let val = self.palette.color(on as u8);
+
+        for y in y_range {
+            let row = y * self.width;
+            self.scaled[row + x_range.start..row + x_range.end].fill(val);
+        }
+    }
+}
+
+/// The whole area of the framebuffer
+fn full(framebuffer: &Framebuffer) -> Rect {
+    Rect {
+        x: 0,
+        y: 0,
+        width: framebuffer.width(),
+        height: framebuffer.height(),
+    }
 }
 
 #[derive(Debug)]
-pub struct KeypadAdapter(Arc<Mutex<CurrentKeys>>);
+pub struct KeypadAdapter(Arc<Mutex<CurrentKeys>>, Arc<LatencyTracker>);
 
 impl Keypad for KeypadAdapter {
     fn pressed_keys(&self) -> Keys {
+        self.1.input_ticked(Instant::now());
+
         let keys = &self.0.lock().expect("Locking keys buffer failed").current;
         keys.clone()
     }
@@ -181,42 +733,94 @@ impl Keypad for KeypadAdapter {
 
         keys.prev.falling_edges(&keys.current)
     }
-}
 
-#[derive(Debug)]
-pub struct GraphicsAdapter(Arc<Buffer>);
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
 
-impl Graphics for GraphicsAdapter {
-    fn clear(&mut self) {
-        let mut buffer = self.0.buf.lock().expect("Locking graphics buffer failed");
+        keys.prev.rising_edges(&keys.current)
+    }
 
-        for x in 0..Self::WIDTH {
-            for y in 0..Self::HEIGHT {
-                MinifbDisplay::reset_pixel(&mut buffer, x, y);
-            }
+    // The snapshot is replaced every frame, so a slow core misses taps shorter than a tick,
+    // the queued events keep them
+    fn queues_events(&self) -> bool {
+        true
+    }
+
+    fn poll_event(&mut self, _tick: u64) -> Option<KeyEvent> {
+        let event = self
+            .0
+            .lock()
+            .expect("Locking keys buffer failed")
+            .events
+            .pop_front();
+        if event.is_some() {
+            self.1.input_ticked(Instant::now());
         }
+
+        event
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusAdapter(Arc<Mutex<Status>>);
+
+impl StatusAdapter {
+    /// Publish the state of `chip8`, e.g. once per frame
+    pub fn update<P: Peripherals>(&self, chip8: &Chip8<'_, P>) {
+        *self.0.lock().expect("Locking status failed") = Status {
+            stats: chip8.stats(),
+            paused: chip8.is_paused(),
+        };
     }
+}
 
-    fn toggle_sprite(&mut self, pos: Pos, sprite: Sprite<'_>) -> bool {
-        let mut collision = false;
-        let mut buffer = self.0.buf.lock().expect("Locking graphics buffer failed");
+#[derive(Debug)]
+pub struct SpeakerAdapter<S>(Option<S>, Arc<AtomicBool>);
 
-        for y in 0..sprite.0.len() {
-            for x in 0..8 {
-                let x_pos = (pos.0 as usize + x) % Self::WIDTH;
-                let y_pos = (pos.1 as usize + y) % Self::HEIGHT;
-                let sprite_bit = sprite.0[y] >> (7 - x) as u32 & 0x01 == 1;
+impl<S: Speaker> Speaker for SpeakerAdapter<S> {
+    fn start(&mut self) {
+        match &mut self.0 {
+            Some(audio) => audio.start(),
+            None => self.1.store(true, Ordering::Relaxed),
+        }
+    }
 
-                if MinifbDisplay::set_pixel(&mut buffer, x_pos, y_pos, sprite_bit) {
-                    collision = true;
-                }
-            }
+    fn stop(&mut self) {
+        match &mut self.0 {
+            Some(audio) => audio.stop(),
+            None => self.1.store(false, Ordering::Relaxed),
         }
+    }
 
-        collision
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        self.0.set_pattern(pattern, pitch);
     }
+}
 
-    fn refresh(&mut self) {
-        self.0.changed.store(true, Ordering::Relaxed);
+#[derive(Debug)]
+pub struct GraphicsAdapter(Arc<Mutex<Buffer>>, Arc<LatencyTracker>);
+
+impl GraphicsAdapter {
+    /// A copy of the latest frame presented by the core
+    pub fn screenshot(&self) -> Framebuffer {
+        let buffer = self.0.lock().expect("Locking graphics buffer failed");
+        buffer.framebuffer.clone()
+    }
+}
+
+impl Graphics for GraphicsAdapter {
+    fn present(&mut self, framebuffer: &Framebuffer, dirty: Rect) {
+        self.1.frame_ticked(Instant::now());
+
+        let mut buffer = self.0.lock().expect("Locking graphics buffer failed");
+
+        // Pending regions of a different display mode don't fit the new framebuffer
+        buffer.dirty = match buffer.dirty {
+            Some(pending) if buffer.framebuffer.mode() == framebuffer.mode() => {
+                Some(pending.union(dirty))
+            }
+            _ => Some(dirty),
+        };
+        buffer.framebuffer.clone_from(framebuffer);
     }
 }
@@ -0,0 +1,255 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::prelude::*;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// The first bytes of the handshake
+const MAGIC: &[u8; 5] = b"C8NET";
+/// The version of the protocol, both peers have to speak the same
+const VERSION: u8 = 0;
+
+/// The number of exchanges the own input is applied late, so the input of the peer is
+/// usually there by the time it is needed
+const DELAY: usize = 2;
+
+/// A connection to another emulator running the same ROM, see [`NetplayKeypad`]
+///
+/// The peers greet each other with [`MAGIC`], [`VERSION`] and the SHA-1 of their ROM, the
+/// host follows with the random seed and core frequency as big endian u64 and u32. Then
+/// both send their keys as big endian u16, once per exchange.
+#[derive(Debug)]
+pub struct Session {
+    stream: TcpStream,
+    seed: u64,
+    core_freq: u32,
+}
+
+impl Session {
+    /// Wait for a peer to connect to `addr`, which then runs `rom` with the given seed and
+    /// core frequency
+    pub fn host(addr: &str, rom: &[u8], seed: u64, core_freq: u32) -> Result<Self> {
+        let listener = TcpListener::bind(addr).with_context(|| format!("Listening on {}", addr))?;
+        info!("Waiting for a netplay peer on {}", addr);
+        let (mut stream, peer) = listener.accept()?;
+        info!("Netplay peer {} connected", peer);
+
+        greet(&mut stream, rom)?;
+        let mut settings = seed.to_be_bytes().to_vec();
+        settings.extend_from_slice(&core_freq.to_be_bytes());
+        stream.write_all(&settings)?;
+
+        Self::new(stream, seed, core_freq)
+    }
+
+    /// Connect to the host at `addr` and take its seed and core frequency
+    pub fn join(addr: &str, rom: &[u8]) -> Result<Self> {
+        let mut stream =
+            TcpStream::connect(addr).with_context(|| format!("Connecting to {}", addr))?;
+        info!("Connected to netplay host {}", addr);
+
+        greet(&mut stream, rom)?;
+        let seed = u64::from_be_bytes(read_array(&mut stream)?);
+        let core_freq = u32::from_be_bytes(read_array(&mut stream)?);
+
+        Self::new(stream, seed, core_freq)
+    }
+
+    fn new(stream: TcpStream, seed: u64, core_freq: u32) -> Result<Self> {
+        // The exchanges are tiny and latency bound
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            seed,
+            core_freq,
+        })
+    }
+
+    /// The random seed both peers use
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The core frequency both peers use
+    pub fn core_freq(&self) -> u32 {
+        self.core_freq
+    }
+
+    fn send(&mut self, keys: &Keys) -> std::io::Result<()> {
+        self.stream.write_all(&keys.0.to_be_bytes())
+    }
+
+    fn receive(&mut self) -> std::io::Result<Keys> {
+        read_array(&mut self.stream).map(|keys| Keys(u16::from_be_bytes(keys)))
+    }
+}
+
+/// Exchange the greeting and make sure the peer runs the same ROM
+fn greet(stream: &mut TcpStream, rom: &[u8]) -> Result<()> {
+    let digest = sha1_smol::Sha1::from(rom).digest().bytes();
+
+    let mut greeting = MAGIC.to_vec();
+    greeting.push(VERSION);
+    greeting.extend_from_slice(&digest);
+    stream.write_all(&greeting)?;
+
+    let peer: [u8; 26] = read_array(stream).context("Reading the greeting of the peer")?;
+    if &peer[..5] != MAGIC {
+        bail!("The peer doesn't speak the netplay protocol");
+    }
+    if peer[5] != VERSION {
+        bail!(
+            "The peer speaks netplay version {}, not {}",
+            peer[5],
+            VERSION
+        );
+    }
+    if peer[6..] != digest {
+        bail!("The peer runs another ROM");
+    }
+
+    Ok(())
+}
+
+fn read_array<const N: usize>(stream: &mut TcpStream) -> std::io::Result<[u8; N]> {
+    let mut array = [0; N];
+    stream.read_exact(&mut array)?;
+    Ok(array)
+}
+
+/// A keypad shared by two emulators, which run in lockstep
+///
+/// Every frame's worth of ticks, at the same tick on both sides, the keys of the local
+/// keypad are sent to the peer and the keys of the peer are received. The CHIP-8 sees the
+/// keys of both, so the player on either side can use their half of the keypad. The own
+/// input is applied [`DELAY`] exchanges late, like the one of the peer, so both sides apply
+/// the same input at the same tick.
+///
+/// Waiting for the peer keeps both sides at the same pace. As long as both run the same ROM
+/// with the same quirks, they stay in sync, pausing or resetting only one of them doesn't.
+/// Once the peer disconnects, only the local input is used.
+#[derive(Debug)]
+pub struct NetplayKeypad<K> {
+    keypad: K,
+    session: Option<Session>,
+    /// The ticks between two exchanges
+    interval: u64,
+    /// The tick of the next exchange
+    next: u64,
+    /// The local keys, if the keypad queues events
+    local: Keys,
+    /// The local keys sent but not applied yet
+    pending: VecDeque<Keys>,
+    keys: Keys,
+    events: VecDeque<KeyEvent>,
+}
+
+impl<K: Keypad> NetplayKeypad<K> {
+    /// Share the input of `keypad` over `session`
+    pub fn new(keypad: K, session: Session) -> Self {
+        let interval = (session.core_freq / Chip8::<DefaultPeripherals>::TIMER_FREQ).max(1);
+
+        Self {
+            keypad,
+            session: Some(session),
+            interval: interval as u64,
+            next: 0,
+            local: Keys(0),
+            pending: VecDeque::new(),
+            keys: Keys(0),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// The keys of the local keypad
+    fn local_keys(&mut self, tick: u64) -> Keys {
+        if !self.keypad.queues_events() {
+            return self.keypad.pressed_keys();
+        }
+
+        while let Some(event) = self.keypad.poll_event(tick) {
+            let bit = 1 << (event.key() & 0xF);
+            match event {
+                KeyEvent::Down(_) => self.local.0 |= bit,
+                KeyEvent::Up(_) => self.local.0 &= !bit,
+            }
+        }
+        self.local.clone()
+    }
+
+    /// Send the local keys and apply the ones due now
+    fn exchange(&mut self, tick: u64) {
+        let local = self.local_keys(tick);
+
+        let keys = match self.session.as_mut() {
+            Some(session) => {
+                // Both sides start with DELAY exchanges without any keys in flight
+                if self.pending.is_empty() {
+                    for _ in 0..DELAY {
+                        self.pending.push_back(Keys(0));
+                    }
+                    for keys in &self.pending {
+                        if let Err(e) = session.send(keys) {
+                            warn!("Netplay peer lost: {}", e);
+                        }
+                    }
+                }
+
+                let result = session.send(&local).and_then(|()| session.receive());
+                self.pending.push_back(local.clone());
+                let own = self.pending.pop_front().unwrap_or(Keys(0));
+
+                match result {
+                    Ok(remote) => Keys(own.0 | remote.0),
+                    Err(e) => {
+                        warn!("Netplay peer lost: {}", e);
+                        self.session = None;
+                        local
+                    }
+                }
+            }
+            None => local,
+        };
+
+        let mut released = self.keys.falling_edges(&keys);
+        while let Some(key) = released.pop_next_idx() {
+            self.events.push_back(KeyEvent::Up(key));
+        }
+        let mut pressed = self.keys.rising_edges(&keys);
+        while let Some(key) = pressed.pop_next_idx() {
+            self.events.push_back(KeyEvent::Down(key));
+        }
+        self.keys = keys;
+    }
+}
+
+impl<K: Keypad> Keypad for NetplayKeypad<K> {
+    fn pressed_keys(&self) -> Keys {
+        self.keys.clone()
+    }
+
+    // The edges are only read from the events
+    fn last_released_key(&mut self) -> FallingEdges {
+        Keys(0).falling_edges(&Keys(0))
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        Keys(0).rising_edges(&Keys(0))
+    }
+
+    // The events come with the tick, which is the same on both sides
+    fn queues_events(&self) -> bool {
+        true
+    }
+
+    fn poll_event(&mut self, tick: u64) -> Option<KeyEvent> {
+        while tick >= self.next {
+            self.exchange(tick);
+            self.next += self.interval;
+        }
+
+        self.events.pop_front()
+    }
+}
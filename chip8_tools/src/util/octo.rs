@@ -0,0 +1,655 @@
+//! An assembler for a subset of [Octo](https://johnearnest.github.io/Octo/docs/Manual.html)
+//!
+//! Supported are labels, `:alias`, `:const`, `:call`, `:byte`, all CHIP-8 and SCHIP
+//! statements, `if ... then`, `if ... begin ... else ... end`,
+//! `loop ... while ... again` and `i := long`. Macros, `:org`, `:calc` and the comparison
+//! pseudo-ops `<`, `>`, `<=` and `>=` are not supported.
+
+use anyhow::{anyhow, bail, Result};
+use chip8_core::instructions::Instruction::{self, *};
+use chip8_core::Core;
+use std::collections::{BTreeMap, HashMap};
+
+/// The address the program is loaded to
+pub const PROGRAM_START: u16 = 0x200;
+
+/// The size of the memory programs are assembled for, the 64 KiB of XO-CHIP which
+/// `i := long` addresses
+const MEMORY_SIZE: usize = Core::MAX_MEM_LEN;
+
+/// The largest program which fits into memory
+const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_START as usize;
+
+/// Assemble Octo source code into a ROM
+///
+/// Execution begins at the label `main`. Unless `main` is the first label, the ROM starts
+/// with a jump to it.
+pub fn assemble(source: &str) -> Result<Vec<u8>> {
+    assemble_with_source_map(source).map(|(rom, _)| rom)
+}
+
+/// The line of each statement, by the address of its first byte
+pub type SourceMap = BTreeMap<u16, usize>;
+
+/// Assemble Octo source code into a ROM, also returning where each statement ended up
+pub fn assemble_with_source_map(source: &str) -> Result<(Vec<u8>, SourceMap)> {
+    Assembler::new(source).assemble()
+}
+
+/// A whitespace separated word of the source
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// How a reference to a label is patched into the ROM
+#[derive(Debug, Clone, Copy)]
+enum Width {
+    /// The lower 12 bits of an instruction
+    Nnn,
+    /// A 16 bit address following `i := long`
+    Long,
+}
+
+/// A reference to a label which is resolved after all labels are known
+#[derive(Debug)]
+struct Fixup<'a> {
+    offset: usize,
+    width: Width,
+    label: Token<'a>,
+}
+
+/// An open control flow block
+#[derive(Debug)]
+enum Block {
+    /// `if ... begin`, with the offset of the jump past the block
+    If(usize),
+    /// `else`, with the offset of the jump past the block
+    Else(usize),
+    /// `loop`, with the address of its start and the offsets of the jumps of its `while`s
+    Loop(u16, Vec<usize>),
+}
+
+/// A condition of `if` or `while`
+#[derive(Debug)]
+enum Condition {
+    Eq(u8, u8),
+    Ne(u8, u8),
+    EqReg(u8, u8),
+    NeReg(u8, u8),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    /// The instruction skipping the next one if the condition holds
+    fn skip_if(&self) -> Instruction {
+        match *self {
+            Condition::Eq(x, nn) => I3XNN(x.into(), nn.into()),
+            Condition::Ne(x, nn) => I4XNN(x.into(), nn.into()),
+            Condition::EqReg(x, y) => I5XY0(x.into(), y.into()),
+            Condition::NeReg(x, y) => I9XY0(x.into(), y.into()),
+            Condition::Key(x) => IEX9E(x.into()),
+            Condition::NotKey(x) => IEXA1(x.into()),
+        }
+    }
+
+    /// The instruction skipping the next one unless the condition holds
+    fn skip_unless(&self) -> Instruction {
+        match *self {
+            Condition::Eq(x, nn) => I4XNN(x.into(), nn.into()),
+            Condition::Ne(x, nn) => I3XNN(x.into(), nn.into()),
+            Condition::EqReg(x, y) => I9XY0(x.into(), y.into()),
+            Condition::NeReg(x, y) => I5XY0(x.into(), y.into()),
+            Condition::Key(x) => IEXA1(x.into()),
+            Condition::NotKey(x) => IEX9E(x.into()),
+        }
+    }
+}
+
+struct Assembler<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    rom: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    aliases: HashMap<&'a str, u8>,
+    consts: HashMap<&'a str, i32>,
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<(Token<'a>, Block)>,
+    source_map: SourceMap,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str) -> Self {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(line, text)| {
+                let code = text.split('#').next().unwrap_or_default();
+                code.split_whitespace().map(move |text| Token {
+                    text,
+                    line: line + 1,
+                })
+            })
+            .collect();
+
+        Self {
+            tokens,
+            pos: 0,
+            rom: Vec::new(),
+            labels: HashMap::new(),
+            aliases: HashMap::new(),
+            consts: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            source_map: SourceMap::new(),
+        }
+    }
+
+    fn assemble(mut self) -> Result<(Vec<u8>, SourceMap)> {
+        let main_first = matches!(
+            self.tokens.as_slice(),
+            [colon, main, ..] if colon.text == ":" && main.text == "main"
+        );
+        if !main_first {
+            let main = Token {
+                text: "main",
+                line: 1,
+            };
+            self.emit_jump(main, |nnn| I1NNN(nnn.into()));
+        }
+
+        while let Some(token) = self.next() {
+            let addr = self.here();
+            self.statement(token)?;
+
+            if self.rom.len() > MAX_PROGRAM_SIZE {
+                bail!(
+                    "line {}: The program is larger than the {} bytes which fit into memory",
+                    token.line,
+                    MAX_PROGRAM_SIZE
+                );
+            }
+            if self.here() != addr {
+                self.source_map.insert(addr, token.line);
+            }
+        }
+
+        if let Some((token, _)) = self.blocks.pop() {
+            bail!("line {}: \"{}\" is never closed", token.line, token.text);
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = *self.labels.get(fixup.label.text).ok_or_else(|| {
+                anyhow!(
+                    "line {}: Undefined label \"{}\"",
+                    fixup.label.line,
+                    fixup.label.text
+                )
+            })?;
+            if let Width::Nnn = fixup.width {
+                if addr > 0xFFF {
+                    bail!(
+                        "line {}: Label \"{}\" at 0x{:X} is out of reach, only \"i := long\" \
+                         addresses more than 4 KiB",
+                        fixup.label.line,
+                        fixup.label.text,
+                        addr
+                    );
+                }
+            }
+            self.patch(fixup.offset, fixup.width, addr);
+        }
+
+        Ok((self.rom, self.source_map))
+    }
+
+    /// The address of the next emitted byte
+    fn here(&self) -> u16 {
+        // Only the end of a program filling all of memory is past the last address
+        (PROGRAM_START as usize + self.rom.len()) as u16
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    /// The next token, which has to exist after `after`
+    fn expect_any(&mut self, after: Token<'a>) -> Result<Token<'a>> {
+        self.next().ok_or_else(|| {
+            anyhow!(
+                "line {}: Unexpected end of file after \"{}\"",
+                after.line,
+                after.text
+            )
+        })
+    }
+
+    /// The next token, which has to be `text`
+    fn expect(&mut self, after: Token<'a>, text: &str) -> Result<()> {
+        let token = self.expect_any(after)?;
+        if token.text != text {
+            bail!(
+                "line {}: Expected \"{}\", found \"{}\"",
+                token.line,
+                text,
+                token.text
+            );
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.rom.extend_from_slice(&instruction.encode());
+    }
+
+    /// Emit an instruction with the address of `label`, which is patched in later
+    fn emit_jump(&mut self, label: Token<'a>, instruction: fn(u16) -> Instruction) {
+        let offset = self.rom.len();
+        self.emit(instruction(0));
+        self.fixups.push(Fixup {
+            offset,
+            width: Width::Nnn,
+            label,
+        });
+    }
+
+    /// Emit an instruction with the address `target`, which is a label or a number
+    fn emit_target(
+        &mut self,
+        target: Token<'a>,
+        instruction: fn(u16) -> Instruction,
+    ) -> Result<()> {
+        if is_identifier(target.text) && !self.consts.contains_key(target.text) {
+            self.emit_jump(target, instruction);
+        } else {
+            let addr = self.address(target)?;
+            self.emit(instruction(addr));
+        }
+        Ok(())
+    }
+
+    /// Point the address at `offset` to `addr`
+    fn patch(&mut self, offset: usize, width: Width, addr: u16) {
+        match width {
+            Width::Nnn => {
+                let [high, low] = addr.to_be_bytes();
+                self.rom[offset] = self.rom[offset] & 0xF0 | high & 0x0F;
+                self.rom[offset + 1] = low;
+            }
+            Width::Long => self.rom[offset..offset + 2].copy_from_slice(&addr.to_be_bytes()),
+        }
+    }
+
+    /// A number literal or constant
+    fn number(&self, token: Token<'a>) -> Result<i32> {
+        if let Some(&value) = self.consts.get(token.text) {
+            return Ok(value);
+        }
+
+        let (negative, digits) = match token.text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token.text),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i32::from_str_radix(hex, 16)
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            i32::from_str_radix(bin, 2)
+        } else {
+            digits.parse()
+        }
+        .map_err(|_| {
+            anyhow!(
+                "line {}: Expected a number, found \"{}\"",
+                token.line,
+                token.text
+            )
+        })?;
+
+        Ok(if negative { -value } else { value })
+    }
+
+    /// An 8 bit number, negative numbers are stored as two's complement
+    fn byte(&self, token: Token<'a>) -> Result<u8> {
+        match self.number(token)? {
+            value @ -128..=255 => Ok(value as u8),
+            value => bail!("line {}: {} does not fit into a byte", token.line, value),
+        }
+    }
+
+    /// A 16 bit address of `i := long`, given as a number
+    fn long_address(&self, token: Token<'a>) -> Result<u16> {
+        match self.number(token)? {
+            value @ 0..=0xFFFF => Ok(value as u16),
+            value => bail!("line {}: {} is not a valid long address", token.line, value),
+        }
+    }
+
+    /// A 4 bit number
+    fn nibble(&self, token: Token<'a>) -> Result<u8> {
+        match self.number(token)? {
+            value @ 0..=15 => Ok(value as u8),
+            value => bail!("line {}: {} does not fit into a nibble", token.line, value),
+        }
+    }
+
+    /// A 12 bit address, given as a number or a label which is already defined
+    fn address(&self, token: Token<'a>) -> Result<u16> {
+        if let Some(&addr) = self.labels.get(token.text) {
+            return Ok(addr);
+        }
+
+        match self.number(token)? {
+            value @ 0..=0xFFF => Ok(value as u16),
+            value => bail!("line {}: {} is not a valid address", token.line, value),
+        }
+    }
+
+    /// The index of a register `v0` - `vf` or an alias of one
+    fn register(&self, token: Token<'a>) -> Result<u8> {
+        self.try_register(token.text).ok_or_else(|| {
+            anyhow!(
+                "line {}: Expected a register, found \"{}\"",
+                token.line,
+                token.text
+            )
+        })
+    }
+
+    fn try_register(&self, text: &str) -> Option<u8> {
+        if let Some(&index) = self.aliases.get(text) {
+            return Some(index);
+        }
+
+        match text.strip_prefix(['v', 'V']) {
+            Some(index) if index.len() == 1 => u8::from_str_radix(index, 16).ok(),
+            _ => None,
+        }
+    }
+
+    /// Define a name, which may be used only once
+    fn define(&self, name: Token<'a>) -> Result<&'a str> {
+        if !is_identifier(name.text) || self.try_register(name.text).is_some() {
+            bail!("line {}: \"{}\" is not a valid name", name.line, name.text);
+        }
+        if self.labels.contains_key(name.text) || self.consts.contains_key(name.text) {
+            bail!("line {}: \"{}\" is already defined", name.line, name.text);
+        }
+        Ok(name.text)
+    }
+
+    fn condition(&mut self, after: Token<'a>) -> Result<Condition> {
+        let x = self.expect_any(after)?;
+        let x = self.register(x)?;
+        let op = self.expect_any(after)?;
+
+        match op.text {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            _ => (),
+        }
+
+        let rhs = self.expect_any(op)?;
+        let y = self.try_register(rhs.text);
+        match (op.text, y) {
+            ("==", Some(y)) => Ok(Condition::EqReg(x, y)),
+            ("!=", Some(y)) => Ok(Condition::NeReg(x, y)),
+            ("==", None) => Ok(Condition::Eq(x, self.byte(rhs)?)),
+            ("!=", None) => Ok(Condition::Ne(x, self.byte(rhs)?)),
+            _ => bail!("line {}: Unsupported comparison \"{}\"", op.line, op.text),
+        }
+    }
+
+    fn statement(&mut self, token: Token<'a>) -> Result<()> {
+        match token.text {
+            ":" => {
+                let name = self.expect_any(token)?;
+                let name = self.define(name)?;
+                self.labels.insert(name, self.here());
+            }
+            ":alias" => {
+                let name = self.expect_any(token)?;
+                let register = self.expect_any(name)?;
+                let register = self.register(register)?;
+                let name = self.define(name)?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.expect_any(token)?;
+                let value = self.expect_any(name)?;
+                let value = match self.number(value)? {
+                    value @ -128..=0xFFFF => value,
+                    number => bail!(
+                        "line {}: {} does not fit into a byte or an address",
+                        value.line,
+                        number
+                    ),
+                };
+                let name = self.define(name)?;
+                self.consts.insert(name, value);
+            }
+            ":call" => {
+                let target = self.expect_any(token)?;
+                self.emit_target(target, |nnn| I2NNN(nnn.into()))?;
+            }
+            ":byte" => {
+                let value = self.expect_any(token)?;
+                let value = self.byte(value)?;
+                self.rom.push(value);
+            }
+            "clear" => self.emit(I00E0),
+            "return" | ";" => self.emit(I00EE),
+            "exit" => self.emit(I00FD),
+            "lores" => self.emit(I00FE),
+            "hires" => self.emit(I00FF),
+            "scroll-right" => self.emit(I00FB),
+            "scroll-left" => self.emit(I00FC),
+            "scroll-down" => {
+                let n = self.expect_any(token)?;
+                let n = self.nibble(n)?.into();
+                self.emit(I00CN(n));
+            }
+            "audio" => self.emit(IF002),
+            "jump" => {
+                let target = self.expect_any(token)?;
+                self.emit_target(target, |nnn| I1NNN(nnn.into()))?;
+            }
+            "jump0" => {
+                let target = self.expect_any(token)?;
+                self.emit_target(target, |nnn| IBNNN(nnn.into()))?;
+            }
+            "if" => {
+                let condition = self.condition(token)?;
+                let then = self.expect_any(token)?;
+                match then.text {
+                    "then" => self.emit(condition.skip_unless()),
+                    "begin" => {
+                        self.emit(condition.skip_if());
+                        let offset = self.rom.len();
+                        self.emit(I1NNN(0.into()));
+                        self.blocks.push((token, Block::If(offset)));
+                    }
+                    _ => bail!(
+                        "line {}: Expected \"then\" or \"begin\", found \"{}\"",
+                        then.line,
+                        then.text
+                    ),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((_, Block::If(offset))) => {
+                    let jump = self.rom.len();
+                    self.emit(I1NNN(0.into()));
+                    self.patch(offset, Width::Nnn, self.here());
+                    self.blocks.push((token, Block::Else(jump)));
+                }
+                _ => bail!("line {}: \"else\" without \"if ... begin\"", token.line),
+            },
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If(offset) | Block::Else(offset))) => {
+                    self.patch(offset, Width::Nnn, self.here());
+                }
+                _ => bail!("line {}: \"end\" without \"if ... begin\"", token.line),
+            },
+            "loop" => self
+                .blocks
+                .push((token, Block::Loop(self.here(), Vec::new()))),
+            "while" => {
+                let condition = self.condition(token)?;
+                let offset = self.rom.len() + 2;
+                match self.blocks.last_mut() {
+                    Some((_, Block::Loop(_, breaks))) => breaks.push(offset),
+                    _ => bail!("line {}: \"while\" outside of a loop", token.line),
+                }
+                self.emit(condition.skip_if());
+                self.emit(I1NNN(0.into()));
+            }
+            "again" => match self.blocks.pop() {
+                Some((_, Block::Loop(start, breaks))) => {
+                    self.emit(I1NNN(start.into()));
+                    for offset in breaks {
+                        self.patch(offset, Width::Nnn, self.here());
+                    }
+                }
+                _ => bail!("line {}: \"again\" without \"loop\"", token.line),
+            },
+            "i" => self.index(token)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(token, ":=")?;
+                let x = self.expect_any(token)?;
+                let x = self.register(x)?.into();
+                self.emit(match token.text {
+                    "delay" => IFX15(x),
+                    "buzzer" => IFX18(x),
+                    _ => IFX3A(x),
+                });
+            }
+            "sprite" => {
+                let x = self.expect_any(token)?;
+                let y = self.expect_any(x)?;
+                let n = self.expect_any(y)?;
+                let instruction = IDXYN(
+                    self.register(x)?.into(),
+                    self.register(y)?.into(),
+                    self.nibble(n)?.into(),
+                );
+                self.emit(instruction);
+            }
+            "bcd" | "save" | "load" | "saveflags" | "loadflags" => {
+                let x = self.expect_any(token)?;
+                let x = self.register(x)?.into();
+                self.emit(match token.text {
+                    "bcd" => IFX33(x),
+                    "save" => IFX55(x),
+                    "load" => IFX65(x),
+                    "saveflags" => IFX75(x),
+                    _ => IFX85(x),
+                });
+            }
+            _ if self.try_register(token.text).is_some() => self.assignment(token)?,
+            _ if is_identifier(token.text) && !self.consts.contains_key(token.text) => {
+                self.emit_jump(token, |nnn| I2NNN(nnn.into()));
+            }
+            _ => {
+                let value = self.byte(token)?;
+                self.rom.push(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Statements assigning to `i`
+    fn index(&mut self, token: Token<'a>) -> Result<()> {
+        let op = self.expect_any(token)?;
+        let rhs = self.expect_any(op)?;
+
+        match (op.text, rhs.text) {
+            ("+=", _) => {
+                let x = self.register(rhs)?;
+                self.emit(IFX1E(x.into()));
+            }
+            (":=", "hex") => {
+                let x = self.expect_any(rhs)?;
+                let x = self.register(x)?;
+                self.emit(IFX29(x.into()));
+            }
+            // XO-CHIP `F000 NNNN`, the address follows the opcode
+            (":=", "long") => {
+                let target = self.expect_any(rhs)?;
+                self.emit(IF000(0.into()));
+                let offset = self.rom.len() - 2;
+
+                if let Some(&addr) = self.labels.get(target.text) {
+                    self.patch(offset, Width::Long, addr);
+                } else if is_identifier(target.text) && !self.consts.contains_key(target.text) {
+                    self.fixups.push(Fixup {
+                        offset,
+                        width: Width::Long,
+                        label: target,
+                    });
+                } else {
+                    let addr = self.long_address(target)?;
+                    self.patch(offset, Width::Long, addr);
+                }
+            }
+            (":=", _) => self.emit_target(rhs, |nnn| IANNN(nnn.into()))?,
+            _ => bail!(
+                "line {}: Unsupported operator \"{}\" for i",
+                op.line,
+                op.text
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Statements assigning to a register
+    fn assignment(&mut self, token: Token<'a>) -> Result<()> {
+        let x = self.register(token)?;
+        let op = self.expect_any(token)?;
+        let rhs = self.expect_any(op)?;
+
+        let instruction = match (op.text, self.try_register(rhs.text)) {
+            (":=", Some(y)) => I8XY0(x.into(), y.into()),
+            ("|=", Some(y)) => I8XY1(x.into(), y.into()),
+            ("&=", Some(y)) => I8XY2(x.into(), y.into()),
+            ("^=", Some(y)) => I8XY3(x.into(), y.into()),
+            ("+=", Some(y)) => I8XY4(x.into(), y.into()),
+            ("-=", Some(y)) => I8XY5(x.into(), y.into()),
+            (">>=", Some(y)) => I8XY6(x.into(), y.into()),
+            ("=-", Some(y)) => I8XY7(x.into(), y.into()),
+            ("<<=", Some(y)) => I8XYE(x.into(), y.into()),
+            (":=", None) => match rhs.text {
+                "delay" => IFX07(x.into()),
+                "key" => IFX0A(x.into()),
+                "random" => {
+                    let mask = self.expect_any(rhs)?;
+                    ICXNN(x.into(), self.byte(mask)?.into())
+                }
+                _ => I6XNN(x.into(), self.byte(rhs)?.into()),
+            },
+            ("+=", None) => I7XNN(x.into(), self.byte(rhs)?.into()),
+            ("-=", None) => I7XNN(x.into(), self.byte(rhs)?.wrapping_neg().into()),
+            _ => bail!(
+                "line {}: Unsupported operation \"{} {}\"",
+                op.line,
+                op.text,
+                rhs.text
+            ),
+        };
+        self.emit(instruction);
+
+        Ok(())
+    }
+}
+
+/// Whether `text` may be the name of a label, alias or constant
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer};
+
+/// The colors of the display, as `0xRRGGBB`
+///
+/// XO-CHIP draws on two bit planes and the color of a pixel depends on the planes it is set
+/// on. Plain CHIP-8 and SCHIP only draw on the first plane, so only `background` and
+/// `foreground` are visible.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// Pixels set on neither plane
+    pub background: u32,
+    /// Pixels set only on the first plane
+    pub foreground: u32,
+    /// Pixels set only on the second plane
+    pub plane2: u32,
+    /// Pixels set on both planes
+    pub blend: u32,
+}
+
+impl Palette {
+    /// The color of a pixel set on `planes`, bit 0 being the first plane
+    pub fn color(&self, planes: u8) -> u32 {
+        match planes & 0b11 {
+            0b00 => self.background,
+            0b01 => self.foreground,
+            0b10 => self.plane2,
+            _ => self.blend,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::default().palette()
+    }
+}
+
+/// A predefined palette
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// White on black
+    #[default]
+    Classic,
+    /// Black on white
+    Inverted,
+    /// A green phosphor CRT
+    Green,
+    /// An amber phosphor CRT
+    Amber,
+    /// The default colors of Octo
+    Octo,
+    /// A green reflective LCD
+    Lcd,
+}
+
+impl Theme {
+    pub fn palette(self) -> Palette {
+        let [background, foreground, plane2, blend] = match self {
+            Theme::Classic => [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            Theme::Inverted => [0xFFFFFF, 0x000000, 0x555555, 0xAAAAAA],
+            Theme::Green => [0x0A140A, 0x33FF66, 0x1F9E3F, 0x115522],
+            Theme::Amber => [0x140C00, 0xFFB000, 0xB37B00, 0x664600],
+            Theme::Octo => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            Theme::Lcd => [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F],
+        };
+
+        Palette {
+            background,
+            foreground,
+            plane2,
+            blend,
+        }
+    }
+}
+
+/// Parse a color of the form `RRGGBB`, optionally prefixed by `#`
+pub fn parse_color(s: &str) -> Result<u32> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(color),
+        _ => Err(anyhow!("\"{}\" is not a color of the form RRGGBB", s)),
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let color = String::deserialize(deserializer)?;
+    parse_color(&color)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// The `[palette]` section of the config file
+///
+/// ```toml
+/// [palette]
+/// theme = "amber"
+///
+/// # Overrides of single colors
+/// background = "202020"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteConfig {
+    pub theme: Theme,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub background: Option<u32>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub foreground: Option<u32>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub plane2: Option<u32>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub blend: Option<u32>,
+}
+
+impl From<&PaletteConfig> for Palette {
+    fn from(config: &PaletteConfig) -> Self {
+        let theme = config.theme.palette();
+
+        Self {
+            background: config.background.unwrap_or(theme.background),
+            foreground: config.foreground.unwrap_or(theme.foreground),
+            plane2: config.plane2.unwrap_or(theme.plane2),
+            blend: config.blend.unwrap_or(theme.blend),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use chip8_core::prelude::*;
+use log::{debug, warn};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// RPL user flags stored in a file, so the saves of a game outlive the emulator
+///
+/// The file holds the 16 flags as raw bytes, it is written whenever the program changes
+/// them. A missing file holds no flags yet.
+#[derive(Debug)]
+pub struct FilePersistence {
+    path: PathBuf,
+}
+
+impl FilePersistence {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The flags file of a ROM, `chip8/flags/<ROM name>.rpl` in the platform's data directory
+    pub fn default_path<P: AsRef<Path>>(rom: P) -> Option<PathBuf> {
+        let name = rom.as_ref().file_stem()?;
+        dirs::data_dir().map(|dir| {
+            dir.join("chip8")
+                .join("flags")
+                .join(name)
+                .with_extension("rpl")
+        })
+    }
+
+    fn write(&self, flags: &[u8; 16]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, flags)
+    }
+}
+
+impl Persistence for FilePersistence {
+    fn load(&mut self) -> [u8; 16] {
+        let mut flags = [0; 16];
+
+        match std::fs::read(&self.path) {
+            Ok(bytes) => {
+                let len = bytes.len().min(16);
+                flags[..len].copy_from_slice(&bytes[..len]);
+                debug!("Loaded RPL flags from {}", self.path.display());
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => warn!("Reading RPL flags \"{}\": {}", self.path.display(), e),
+        }
+
+        flags
+    }
+
+    fn store(&mut self, flags: &[u8; 16]) {
+        if let Err(e) = self.write(flags) {
+            warn!("Saving RPL flags \"{}\": {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use super::keymap::KeyMap;
+use anyhow::Result;
+use chip8_core::prelude::*;
+use log::debug;
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use std::sync::{mpsc::Receiver, Arc, Mutex};
+use std::time::{Duration, Instant};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::{Fullscreen, Window, WindowBuilder};
+
+#[derive(Debug)]
+struct CurrentKeys {
+    prev: Keys,
+    current: Keys,
+}
+
+/// A GPU accelerated window using pixels and winit
+///
+/// The framebuffer is scaled by the largest integer factor fitting the window, which can
+/// be resized freely, and presented with vsync. F11 toggles fullscreen.
+///
+/// Only available with the "pixels" feature.
+pub struct PixelsDisplay {
+    event_loop: EventLoop<()>,
+    window: Window,
+    pixels: Pixels,
+    frame: Arc<Mutex<Option<Framebuffer>>>,
+    keys: Arc<Mutex<CurrentKeys>>,
+    keymap: KeyMap,
+}
+
+impl std::fmt::Debug for PixelsDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PixelsDisplay")
+            .field("window", &self.window)
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The character printed on a key, as used by [`KeyMap`]
+fn key_char(key: VirtualKeyCode) -> Option<char> {
+    let c = match key {
+        VirtualKeyCode::Key0 => '0',
+        VirtualKeyCode::Key1 => '1',
+        VirtualKeyCode::Key2 => '2',
+        VirtualKeyCode::Key3 => '3',
+        VirtualKeyCode::Key4 => '4',
+        VirtualKeyCode::Key5 => '5',
+        VirtualKeyCode::Key6 => '6',
+        VirtualKeyCode::Key7 => '7',
+        VirtualKeyCode::Key8 => '8',
+        VirtualKeyCode::Key9 => '9',
+        VirtualKeyCode::A => 'a',
+        VirtualKeyCode::B => 'b',
+        VirtualKeyCode::C => 'c',
+        VirtualKeyCode::D => 'd',
+        VirtualKeyCode::E => 'e',
+        VirtualKeyCode::F => 'f',
+        VirtualKeyCode::G => 'g',
+        VirtualKeyCode::H => 'h',
+        VirtualKeyCode::I => 'i',
+        VirtualKeyCode::J => 'j',
+        VirtualKeyCode::K => 'k',
+        VirtualKeyCode::L => 'l',
+        VirtualKeyCode::M => 'm',
+        VirtualKeyCode::N => 'n',
+        VirtualKeyCode::O => 'o',
+        VirtualKeyCode::P => 'p',
+        VirtualKeyCode::Q => 'q',
+        VirtualKeyCode::R => 'r',
+        VirtualKeyCode::S => 's',
+        VirtualKeyCode::T => 't',
+        VirtualKeyCode::U => 'u',
+        VirtualKeyCode::V => 'v',
+        VirtualKeyCode::W => 'w',
+        VirtualKeyCode::X => 'x',
+        VirtualKeyCode::Y => 'y',
+        VirtualKeyCode::Z => 'z',
+        VirtualKeyCode::Apostrophe => '\'',
+        VirtualKeyCode::Grave => '`',
+        VirtualKeyCode::Backslash => '\\',
+        VirtualKeyCode::Comma => ',',
+        VirtualKeyCode::Equals => '=',
+        VirtualKeyCode::LBracket => '[',
+        VirtualKeyCode::Minus => '-',
+        VirtualKeyCode::Period => '.',
+        VirtualKeyCode::RBracket => ']',
+        VirtualKeyCode::Semicolon => ';',
+        VirtualKeyCode::Slash => '/',
+        _ => return None,
+    };
+
+    Some(c)
+}
+
+impl PixelsDisplay {
+    /// The initial scale of a low resolution pixel
+    const SCALE: usize = 10;
+    const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+    pub fn new() -> Result<Self> {
+        let event_loop = EventLoop::new();
+
+        let (width, height) = (DisplayMode::LoRes.width(), DisplayMode::LoRes.height());
+        let window = WindowBuilder::new()
+            .with_title("CHIP-8 Emulator")
+            .with_inner_size(LogicalSize::new(
+                (width * Self::SCALE) as f64,
+                (height * Self::SCALE) as f64,
+            ))
+            .with_min_inner_size(LogicalSize::new(width as f64, height as f64))
+            .build(&event_loop)?;
+
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, &window);
+        let pixels = PixelsBuilder::new(width as u32, height as u32, surface)
+            .enable_vsync(true)
+            .build()?;
+
+        Ok(Self {
+            event_loop,
+            window,
+            pixels,
+            frame: Arc::new(Mutex::new(None)),
+            keys: Arc::new(Mutex::new(CurrentKeys {
+                prev: Keys(0),
+                current: Keys(0),
+            })),
+            keymap: KeyMap::default(),
+        })
+    }
+
+    /// Use `keymap` instead of the QWERTY keypad
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    pub fn keypad_adapter(&self) -> KeypadAdapter {
+        KeypadAdapter(self.keys.clone())
+    }
+
+    pub fn graphics_adapter(&self) -> GraphicsAdapter {
+        GraphicsAdapter(self.frame.clone())
+    }
+
+    /// Run the window until it is closed, ESC is pressed or a stop is received
+    pub fn run(&mut self, stop: Receiver<()>) -> Result<()> {
+        let Self {
+            event_loop,
+            window,
+            pixels,
+            frame,
+            keys,
+            keymap,
+        } = self;
+
+        let mut pressed: u16 = 0;
+        let mut result = Ok(());
+
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(Instant::now() + Self::FRAME);
+
+            match event {
+                Event::WindowEvent { event, .. } => match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(key),
+                                state,
+                                ..
+                            },
+                        ..
+                    } => {
+                        if key == VirtualKeyCode::Escape {
+                            *control_flow = ControlFlow::Exit;
+                        } else if key == VirtualKeyCode::F11 {
+                            if state == ElementState::Pressed {
+                                let fullscreen = match window.fullscreen() {
+                                    Some(_) => None,
+                                    None => Some(Fullscreen::Borderless(None)),
+                                };
+                                window.set_fullscreen(fullscreen);
+                            }
+                        } else if let Some(key) = key_char(key).and_then(|c| keymap.get(c)) {
+                            match state {
+                                ElementState::Pressed => pressed |= 1 << key,
+                                ElementState::Released => pressed &= !(1 << key),
+                            }
+                        }
+                    }
+                    WindowEvent::Resized(size) => {
+                        if let Err(e) = pixels.resize_surface(size.width, size.height) {
+                            result = Err(e.into());
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                    _ => (),
+                },
+                Event::MainEventsCleared => {
+                    if let Ok(()) = stop.try_recv() {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+
+                    {
+                        let keys = &mut keys.lock().expect("Locking keys failed");
+                        let current = keys.current.clone();
+                        keys.prev.update(&current);
+                        keys.current = Keys(pressed);
+                    }
+
+                    let pending = frame.lock().expect("Locking frame failed").take();
+                    if let Some(framebuffer) = pending {
+                        if let Err(e) = Self::blit(pixels, &framebuffer) {
+                            result = Err(e);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                        window.request_redraw();
+                    }
+                }
+                Event::RedrawRequested(_) => {
+                    if let Err(e) = pixels.render() {
+                        result = Err(e.into());
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                _ => (),
+            }
+        });
+
+        result
+    }
+
+    /// Copy the framebuffer into the pixel buffer, resizing it on display mode changes
+    fn blit(pixels: &mut Pixels, framebuffer: &Framebuffer) -> Result<()> {
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+
+        if pixels.texture().width() != width as u32 {
+            debug!("Resizing pixel buffer to {}x{}", width, height);
+            pixels.resize_buffer(width as u32, height as u32)?;
+        }
+
+        for (idx, rgba) in pixels.frame_mut().chunks_exact_mut(4).enumerate() {
+            let on = framebuffer.pixel(idx % width, idx / width);
+            rgba.copy_from_slice(if on {
+                &[0xFF, 0xFF, 0xFF, 0xFF]
+            } else {
+                &[0x00, 0x00, 0x00, 0xFF]
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct KeypadAdapter(Arc<Mutex<CurrentKeys>>);
+
+impl Keypad for KeypadAdapter {
+    fn pressed_keys(&self) -> Keys {
+        let keys = &self.0.lock().expect("Locking keys buffer failed").current;
+        keys.clone()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
+
+        keys.prev.falling_edges(&keys.current)
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
+
+        keys.prev.rising_edges(&keys.current)
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphicsAdapter(Arc<Mutex<Option<Framebuffer>>>);
+
+impl Graphics for GraphicsAdapter {
+    fn present(&mut self, framebuffer: &Framebuffer, _dirty: Rect) {
+        let mut frame = self.0.lock().expect("Locking frame failed");
+        *frame = Some(framebuffer.clone());
+    }
+}
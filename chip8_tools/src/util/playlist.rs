@@ -0,0 +1,67 @@
+use super::archive;
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The ROMs in `paths`, with every directory replaced by the ROMs in it
+pub fn expand(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            roms.extend(roms_in(path)?);
+        } else {
+            roms.push(path.clone());
+        }
+    }
+
+    Ok(roms)
+}
+
+/// The ROMs (*.ch8) and archives of ROMs in `dir`, sorted by name
+pub fn roms_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| archive::EXTENSIONS.iter().any(|rom| ext == *rom))
+        {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+
+    Ok(roms)
+}
+
+/// List `roms` on the terminal and ask which one to run, `None` if the user quits
+pub fn pick(roms: &[PathBuf]) -> Result<Option<&Path>> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    println!();
+    for (idx, rom) in roms.iter().enumerate() {
+        let name = rom.file_name().unwrap_or(rom.as_os_str());
+        println!("{:>4}  {}", idx + 1, name.to_string_lossy());
+    }
+
+    loop {
+        print!("Run ROM (1-{}, q to quit): ", roms.len());
+        stdout.flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        match line.trim() {
+            "q" | "quit" => return Ok(None),
+            choice => match choice.parse::<usize>() {
+                Ok(idx @ 1..) if idx <= roms.len() => return Ok(Some(&roms[idx - 1])),
+                _ => println!("No ROM \"{}\"", choice),
+            },
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use chip8_core::prelude::*;
+use chip8_core::quirks::{KeyWait, Timing};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// A behaviour which differs between CHIP-8 interpreters, see [`QuirksConfig`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quirk {
+    /// Fail on odd PCs and jumps to odd addresses
+    StrictAlignment,
+    /// Accept a key for FX0A when it is pressed instead of released
+    KeyPress,
+    /// Leave I incremented by X+1 after FX55 and FX65
+    IncrementI,
+    /// Shift VY instead of VX with 8XY6 and 8XYE
+    ShiftVy,
+    /// Reset VF after 8XY1, 8XY2 and 8XY3
+    ResetVf,
+    /// Jump to XNN + VX with BNNN instead of NNN + V0
+    JumpVx,
+    /// Clip sprites at the edges of the display instead of wrapping them around
+    ClipSprites,
+    /// Set VF when FX1E takes I past 0xFFF, like the Amiga interpreter
+    AddIOverflow,
+    /// Load and start programs at 0x600, like the ETI-660
+    Eti660,
+    /// Let instructions take the machine cycles of the COSMAC VIP, the speed is then given
+    /// in machine cycles per second
+    VipTiming,
+}
+
+/// The default configuration with `quirks` enabled
+pub fn quirks_config(quirks: &[Quirk]) -> QuirksConfig {
+    let mut config = QuirksConfig::default();
+
+    for quirk in quirks {
+        match quirk {
+            Quirk::StrictAlignment => config.strict_alignment = true,
+            Quirk::KeyPress => config.key_wait = KeyWait::Press,
+            Quirk::IncrementI => config.increment_i = true,
+            Quirk::ShiftVy => config.shift_vy = true,
+            Quirk::ResetVf => config.reset_vf = true,
+            Quirk::JumpVx => config.jump_vx = true,
+            Quirk::ClipSprites => config.clip_sprites = true,
+            Quirk::AddIOverflow => config.add_i_overflow = true,
+            Quirk::Eti660 => config.start = QuirksConfig::ETI_660_START,
+            Quirk::VipTiming => config.timing = Timing::CosmacVip,
+        }
+    }
+
+    config
+}
@@ -0,0 +1,184 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::peripherals::testing::ScriptedKeypad;
+use chip8_core::prelude::*;
+use log::warn;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+
+/// A keypad writing the input of another keypad to a file
+///
+/// The file starts with the random seed and core frequency of the session, followed by
+/// a line `TICK KEYS` whenever the pressed keys change, e.g.
+///
+/// ```text
+/// # chip8-emu keypad recording
+/// seed 0x2545f4914f6cdd1d
+/// hz 700
+/// 0 0000
+/// 153 0010
+/// 170 0000
+/// ```
+#[derive(Debug)]
+pub struct RecordingKeypad<K> {
+    keypad: K,
+    output: Option<LineWriter<File>>,
+    tick: u64,
+    /// The keys most recently returned to the core
+    current: Cell<u16>,
+    /// The keys most recently written to the file
+    recorded: Option<u16>,
+}
+
+impl<K> RecordingKeypad<K> {
+    pub fn new<P: AsRef<Path>>(keypad: K, path: P, seed: u64, core_freq: u32) -> io::Result<Self> {
+        let mut output = LineWriter::new(File::create(path)?);
+        writeln!(output, "# chip8-emu keypad recording")?;
+        writeln!(output, "seed {:#018x}", seed)?;
+        writeln!(output, "hz {}", core_freq)?;
+
+        Ok(Self {
+            keypad,
+            output: Some(output),
+            tick: 0,
+            current: Cell::new(0),
+            recorded: None,
+        })
+    }
+
+    fn record(&mut self, keys: u16) {
+        if self.recorded == Some(keys) {
+            return;
+        }
+
+        if let Some(output) = &mut self.output {
+            if let Err(e) = writeln!(output, "{} {:04x}", self.tick, keys) {
+                warn!("Recording stopped: {}", e);
+                self.output = None;
+            }
+        }
+        self.recorded = Some(keys);
+    }
+}
+
+impl<K: Keypad> Keypad for RecordingKeypad<K> {
+    fn pressed_keys(&self) -> Keys {
+        let keys = self.keypad.pressed_keys();
+        self.current.set(keys.0);
+        keys
+    }
+
+    // The core asks for released keys once per tick, right after the pressed ones
+    fn last_released_key(&mut self) -> FallingEdges {
+        self.record(self.current.get());
+        self.tick += 1;
+
+        self.keypad.last_released_key()
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        self.keypad.last_pressed_key()
+    }
+
+    fn queues_events(&self) -> bool {
+        self.keypad.queues_events()
+    }
+
+    // Queued events aren't polled every tick, but come with the tick they are read at
+    fn poll_event(&mut self, tick: u64) -> Option<KeyEvent> {
+        let event = self.keypad.poll_event(tick)?;
+
+        let bit = 1 << (event.key() & 0xF);
+        let keys = match event {
+            KeyEvent::Down(_) => self.current.get() | bit,
+            KeyEvent::Up(_) => self.current.get() & !bit,
+        };
+        self.current.set(keys);
+        self.tick = tick;
+        self.record(keys);
+
+        Some(event)
+    }
+}
+
+/// A recording written by [`RecordingKeypad`], read back to replay the session
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    pub seed: u64,
+    pub core_freq: u32,
+    /// The keys pressed from a tick on, ordered by tick
+    pub keys: Vec<(u64, Keys)>,
+}
+
+impl Recording {
+    /// Read a recording file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading recording \"{}\"", path.display()))?;
+
+        text.parse()
+            .with_context(|| format!("Parsing recording \"{}\"", path.display()))
+    }
+
+    /// A keypad pressing and releasing the keys at the ticks they were recorded at
+    pub fn keypad(&self) -> ScriptedKeypad {
+        let mut keypad = ScriptedKeypad::new();
+        let mut held = Keys(0);
+
+        for (tick, keys) in &self.keys {
+            let mut released = held.falling_edges(keys);
+            while let Some(key) = released.pop_next_idx() {
+                keypad = keypad.release(*tick, key);
+            }
+            let mut pressed = held.rising_edges(keys);
+            while let Some(key) = pressed.pop_next_idx() {
+                keypad = keypad.press(*tick, key);
+            }
+            held = keys.clone();
+        }
+
+        keypad
+    }
+}
+
+impl std::str::FromStr for Recording {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut seed = None;
+        let mut core_freq = None;
+        let mut keys: Vec<(u64, Keys)> = Vec::new();
+
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let context = || format!("Line {}: \"{}\"", number + 1, line);
+            match line.split_once(' ').with_context(context)? {
+                ("seed", value) => {
+                    let value = value.trim_start_matches("0x");
+                    seed = Some(u64::from_str_radix(value, 16).with_context(context)?);
+                }
+                ("hz", value) => core_freq = Some(value.parse().with_context(context)?),
+                (tick, value) => {
+                    let tick: u64 = tick.parse().with_context(context)?;
+                    let value = u16::from_str_radix(value, 16).with_context(context)?;
+                    if keys.last().is_some_and(|&(last, _)| last > tick) {
+                        bail!("{}: ticks have to be in order", context());
+                    }
+                    keys.push((tick, Keys(value)));
+                }
+            }
+        }
+
+        Ok(Self {
+            seed: seed.context("The recording has no seed")?,
+            core_freq: core_freq.context("The recording has no core frequency")?,
+            keys,
+        })
+    }
+}
@@ -0,0 +1,73 @@
+use super::quirks::Quirk;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The database shipped with the tools
+const BUNDLED: &str = include_str!("../../data/romdb.json");
+
+/// The recommended settings of a known ROM
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+    pub title: String,
+    /// The number of instructions executed per second the ROM was written for
+    pub hz: Option<u32>,
+    #[serde(default)]
+    pub quirks: Vec<Quirk>,
+}
+
+/// Known ROMs by the SHA-1 hash of their bytes, read from JSON
+///
+/// Hashes are lowercase hex strings, e.g.
+///
+/// ```json
+/// {"0123456789abcdef0123456789abcdef01234567": {"title": "Pong", "hz": 500, "quirks": ["key-press"]}}
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct RomDb(HashMap<String, RomInfo>);
+
+impl RomDb {
+    /// The database of the user, `chip8/romdb.json` in the platform's config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8").join("romdb.json"))
+    }
+
+    /// The database shipped with the tools
+    pub fn bundled() -> Self {
+        serde_json::from_str(BUNDLED).expect("The bundled ROM database is invalid")
+    }
+
+    /// Read a database
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading ROM database \"{}\"", path.display()))?;
+
+        serde_json::from_str(&text)
+            .with_context(|| format!("Parsing ROM database \"{}\"", path.display()))
+    }
+
+    /// The bundled database, extended and overridden by the one of the user, if there is one
+    pub fn load_default() -> Result<Self> {
+        let mut db = Self::bundled();
+
+        if let Some(path) = Self::default_path().filter(|path| path.exists()) {
+            db.0.extend(Self::load(path)?.0);
+        }
+
+        Ok(db)
+    }
+
+    /// The settings of `rom`, if it is known
+    pub fn get(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.0.get(&hash(rom))
+    }
+}
+
+/// The SHA-1 hash of `rom` as used by [`RomDb`]
+pub fn hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
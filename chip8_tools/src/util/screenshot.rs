@@ -0,0 +1,97 @@
+use anyhow::{bail, Context, Result};
+use chip8_core::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A path in `dir` named after the current time, e.g. `chip8-1650000000123.png`
+pub fn timestamped_path<P: AsRef<Path>>(dir: P) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis());
+
+    dir.as_ref().join(format!("chip8-{}.png", millis))
+}
+
+/// An 8 bit grayscale image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Render the framebuffer, each pixel scaled to `scale` x `scale`
+    pub fn from_framebuffer(framebuffer: &Framebuffer, scale: usize) -> Self {
+        let (width, height) = (framebuffer.width() * scale, framebuffer.height() * scale);
+
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                if framebuffer.pixel(x / scale, y / scale) {
+                    0xFF
+                } else {
+                    0x00
+                }
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Save the framebuffer as a grayscale PNG, each pixel scaled to `scale` x `scale`
+pub fn save_png<P: AsRef<Path>>(framebuffer: &Framebuffer, path: P, scale: usize) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("Creating {}", path.display()))?;
+
+    write_png(framebuffer, BufWriter::new(file), scale)
+}
+
+/// Encode the framebuffer as a grayscale PNG to `writer`, see [`save_png`]
+pub fn write_png<W: Write>(framebuffer: &Framebuffer, writer: W, scale: usize) -> Result<()> {
+    let Image {
+        width,
+        height,
+        data,
+    } = Image::from_framebuffer(framebuffer, scale);
+
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+
+    Ok(())
+}
+
+/// Load a PNG saved by [`save_png`]
+pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Image> {
+    let path = path.as_ref();
+
+    let file = File::open(path).with_context(|| format!("Opening {}", path.display()))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+
+    if info.color_type != png::ColorType::Grayscale {
+        bail!("{} is not a grayscale image", path.display());
+    }
+    data.truncate(info.buffer_size());
+
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        data,
+    })
+}
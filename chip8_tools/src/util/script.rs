@@ -0,0 +1,386 @@
+use anyhow::{anyhow, Result};
+use chip8_core::prelude::*;
+use log::{debug, info, warn};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The hooks a script may define
+#[derive(Clone, Copy, Debug, Default)]
+struct Hooks {
+    tick: bool,
+    frame: bool,
+    write: bool,
+}
+
+/// A [Rhai](https://rhai.rs) script hooked into the CHIP-8, for cheats, bots and ROM specific
+/// instrumentation
+///
+/// The script is run once when it is loaded, then the functions it defines are called:
+///
+/// | function               | called                                                      |
+/// | ---------------------- | ----------------------------------------------------------- |
+/// | `on_tick()`            | after every executed instruction                            |
+/// | `on_frame()`           | after every frame, 60 times per second                      |
+/// | `on_write(addr, value)`| for every byte the program wrote to memory                  |
+///
+/// They access the CHIP-8 as `this`:
+///
+/// ```text
+/// fn on_frame() {
+///     // Infinite lives
+///     this.poke(0x3F0, 3);
+///     // Hold 5 every other second
+///     if this.tick % 1400 < 700 { this.press(5) } else { this.release(5) }
+///     this.vars.frames = (this.vars.frames ?? 0) + 1;
+/// }
+/// ```
+///
+/// | member                     | access                                                  |
+/// | -------------------------- | ------------------------------------------------------- |
+/// | `pc`, `i`                  | the program counter and index register                  |
+/// | `tick`                     | the number of ticks executed so far, read only          |
+/// | `v(x)`, `set_v(x, value)`  | the registers V0 to VF                                  |
+/// | `peek(addr)`, `poke(addr, value)` | the memory                                       |
+/// | `press(key)`, `release(key)` | hold keys of the keypad, in addition to the player's  |
+/// | `vars`                     | a map kept between calls, for the state of the script   |
+///
+/// Once a hook fails, the error is logged and the script is stopped.
+#[derive(Debug)]
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// The [`Machine`] bound to `this`
+    machine: Dynamic,
+    hooks: Hooks,
+    /// The keys held by the script, shared with its [`ScriptKeypad`]
+    keys: Arc<Mutex<Keys>>,
+}
+
+impl Script {
+    /// Compile the script at `path` and run it
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        let mut engine = Engine::new();
+        engine.on_print(|text| info!("{}", text));
+        engine.on_debug(|text, _, pos| debug!("{} {}", pos, text));
+        register(&mut engine);
+
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|e| anyhow!("Compiling script \"{}\": {}", path.display(), e))?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow!("Running script \"{}\": {}", path.display(), e))?;
+
+        let mut hooks = Hooks::default();
+        for function in ast.iter_functions() {
+            match (function.name, function.params.len()) {
+                ("on_tick", 0) => hooks.tick = true,
+                ("on_frame", 0) => hooks.frame = true,
+                ("on_write", 2) => hooks.write = true,
+                _ => (),
+            }
+        }
+        debug!("Script hooks {:?}", hooks);
+
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            machine: Dynamic::from(Machine::new()),
+            hooks,
+            keys: Arc::new(Mutex::new(Keys(0))),
+        })
+    }
+
+    /// A keypad holding the keys of `keypad` and the ones pressed by the script
+    pub fn keypad<K: Keypad>(&self, keypad: K) -> ScriptKeypad<K> {
+        ScriptKeypad {
+            keypad,
+            injected: Arc::clone(&self.keys),
+            local: Keys(0),
+            keys: Keys(0),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Execute a tick of `chip8` with `tick`, then call `on_write` and `on_tick` unless the run
+    /// ended
+    pub fn tick<'m, P: Peripherals>(
+        &mut self,
+        chip8: &mut Chip8<'m, P>,
+        tick: impl FnOnce(&mut Chip8<'m, P>) -> RunExit,
+    ) -> RunExit {
+        let writes = match self.hooks.write {
+            true => writes(chip8),
+            false => None,
+        };
+
+        let exit = tick(chip8);
+        if exit != RunExit::Stopped {
+            return exit;
+        }
+
+        for addr in writes.into_iter().flatten() {
+            let value = chip8.core().memory().get(addr).copied().unwrap_or_default();
+            self.call(chip8, "on_write", (addr as i64, value as i64));
+        }
+        if self.hooks.tick {
+            self.call(chip8, "on_tick", ());
+        }
+
+        exit
+    }
+
+    /// Call `on_frame`, after the ticks of a frame
+    pub fn frame<P: Peripherals>(&mut self, chip8: &mut Chip8<'_, P>) {
+        if self.hooks.frame {
+            self.call(chip8, "on_frame", ());
+        }
+    }
+
+    /// Call the hook `name` with `chip8` as `this`, then apply its changes
+    fn call<P: Peripherals>(
+        &mut self,
+        chip8: &mut Chip8<'_, P>,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) {
+        self.machine
+            .write_lock::<Machine>()
+            .expect("The script holds a machine")
+            .load(chip8);
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(false)
+            .bind_this_ptr(&mut self.machine);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            args,
+        );
+
+        let mut machine = self
+            .machine
+            .write_lock::<Machine>()
+            .expect("The script holds a machine");
+        machine.store(chip8);
+        *self.keys.lock().expect("Locking script keys failed") = machine.keys.clone();
+
+        if let Err(e) = result {
+            warn!("Script stopped, {} failed: {}", name, e);
+            self.hooks = Hooks::default();
+        }
+    }
+}
+
+/// The addresses the next instruction of `chip8` writes, if it writes to memory
+fn writes<P: Peripherals>(chip8: &Chip8<'_, P>) -> Option<Range<usize>> {
+    let core = chip8.core();
+    if chip8.is_paused() || core.exited() {
+        return None;
+    }
+
+    let (opcode, i) = (core.opcode(), core.i() as usize);
+    match opcode & 0xF0FF {
+        // LD B, Vx
+        0xF033 => Some(i..i + 3),
+        // LD [I], Vx
+        0xF055 => Some(i..i + ((opcode >> 8) & 0xF) as usize + 1),
+        _ => None,
+    }
+}
+
+/// The CHIP-8 as seen by the hooks of a [`Script`]
+#[derive(Clone, Debug)]
+struct Machine {
+    reg: [u8; 16],
+    pc: u16,
+    i: u16,
+    tick: u64,
+    mem: Vec<u8>,
+    /// The writes of the hook, applied once it returns
+    pokes: Vec<(usize, u8)>,
+    keys: Keys,
+    vars: Map,
+}
+
+impl Machine {
+    fn new() -> Self {
+        Self {
+            reg: [0; 16],
+            pc: 0,
+            i: 0,
+            tick: 0,
+            mem: Vec::new(),
+            pokes: Vec::new(),
+            keys: Keys(0),
+            vars: Map::new(),
+        }
+    }
+
+    /// Copy the state of `chip8`
+    fn load<P: Peripherals>(&mut self, chip8: &Chip8<'_, P>) {
+        let core = chip8.core();
+
+        self.reg.copy_from_slice(&core.registers()[..16]);
+        self.pc = core.pc();
+        self.i = core.i();
+        self.tick = chip8.ticks();
+        self.mem.clear();
+        self.mem.extend_from_slice(core.memory());
+    }
+
+    /// Apply the changes of a hook to `chip8`
+    fn store<P: Peripherals>(&mut self, chip8: &mut Chip8<'_, P>) {
+        let core = chip8.core_mut();
+
+        core.registers_mut()[..16].copy_from_slice(&self.reg);
+        core.set_pc(self.pc);
+        core.set_i(self.i);
+        for (addr, value) in self.pokes.drain(..) {
+            if let Some(byte) = core.memory_range_mut(addr..addr + 1) {
+                byte[0] = value;
+            }
+        }
+    }
+}
+
+/// Register [`Machine`] as `Chip8` with its members
+fn register(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Machine>("Chip8")
+        .register_get("pc", |machine: &mut Machine| machine.pc as i64)
+        .register_set("pc", |machine: &mut Machine, pc: i64| {
+            machine.pc = word(pc)?;
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_get("i", |machine: &mut Machine| machine.i as i64)
+        .register_set("i", |machine: &mut Machine, i: i64| {
+            machine.i = word(i)?;
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_get("tick", |machine: &mut Machine| machine.tick as i64)
+        .register_get("vars", |machine: &mut Machine| machine.vars.clone())
+        .register_set("vars", |machine: &mut Machine, vars: Map| {
+            machine.vars = vars
+        })
+        .register_fn("v", |machine: &mut Machine, x: i64| {
+            Ok::<_, Box<EvalAltResult>>(machine.reg[index(x, 16, "register")?] as i64)
+        })
+        .register_fn("set_v", |machine: &mut Machine, x: i64, value: i64| {
+            machine.reg[index(x, 16, "register")?] = byte(value)?;
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_fn("peek", |machine: &mut Machine, addr: i64| {
+            Ok::<_, Box<EvalAltResult>>(
+                machine.mem[index(addr, machine.mem.len(), "address")?] as i64,
+            )
+        })
+        .register_fn("poke", |machine: &mut Machine, addr: i64, value: i64| {
+            let addr = index(addr, machine.mem.len(), "address")?;
+            let value = byte(value)?;
+            machine.mem[addr] = value;
+            machine.pokes.push((addr, value));
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_fn("press", |machine: &mut Machine, key: i64| {
+            machine.keys.0 |= 1 << index(key, 16, "key")?;
+            Ok::<_, Box<EvalAltResult>>(())
+        })
+        .register_fn("release", |machine: &mut Machine, key: i64| {
+            machine.keys.0 &= !(1 << index(key, 16, "key")?);
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+}
+
+/// `value` as an index below `len`
+fn index(value: i64, len: usize, what: &str) -> Result<usize, Box<EvalAltResult>> {
+    match usize::try_from(value) {
+        Ok(idx) if idx < len => Ok(idx),
+        _ => Err(format!("Invalid {} {:#x}", what, value).into()),
+    }
+}
+
+fn byte(value: i64) -> Result<u8, Box<EvalAltResult>> {
+    u8::try_from(value).map_err(|_| format!("Invalid byte {:#x}", value).into())
+}
+
+fn word(value: i64) -> Result<u16, Box<EvalAltResult>> {
+    u16::try_from(value).map_err(|_| format!("Invalid address {:#x}", value).into())
+}
+
+/// A keypad holding the keys of the player and the ones pressed by a [`Script`]
+#[derive(Debug)]
+pub struct ScriptKeypad<K> {
+    keypad: K,
+    injected: Arc<Mutex<Keys>>,
+    /// The keys of the player, if the keypad queues events
+    local: Keys,
+    keys: Keys,
+    events: VecDeque<KeyEvent>,
+}
+
+impl<K: Keypad> ScriptKeypad<K> {
+    /// The keys of the player
+    fn local_keys(&mut self, tick: u64) -> Keys {
+        if !self.keypad.queues_events() {
+            return self.keypad.pressed_keys();
+        }
+
+        while let Some(event) = self.keypad.poll_event(tick) {
+            let bit = 1 << (event.key() & 0xF);
+            match event {
+                KeyEvent::Down(_) => self.local.0 |= bit,
+                KeyEvent::Up(_) => self.local.0 &= !bit,
+            }
+        }
+        self.local.clone()
+    }
+}
+
+impl<K: Keypad> Keypad for ScriptKeypad<K> {
+    fn pressed_keys(&self) -> Keys {
+        self.keys.clone()
+    }
+
+    // The edges are only read from the events
+    fn last_released_key(&mut self) -> FallingEdges {
+        Keys(0).falling_edges(&Keys(0))
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        Keys(0).rising_edges(&Keys(0))
+    }
+
+    // Keeps the short taps of both the player and the script
+    fn queues_events(&self) -> bool {
+        true
+    }
+
+    fn poll_event(&mut self, tick: u64) -> Option<KeyEvent> {
+        let injected = self.injected.lock().expect("Locking script keys failed").0;
+        let keys = Keys(self.local_keys(tick).0 | injected);
+
+        let mut released = self.keys.falling_edges(&keys);
+        while let Some(key) = released.pop_next_idx() {
+            self.events.push_back(KeyEvent::Up(key));
+        }
+        let mut pressed = self.keys.rising_edges(&keys);
+        while let Some(key) = pressed.pop_next_idx() {
+            self.events.push_back(KeyEvent::Down(key));
+        }
+        self.keys = keys;
+
+        self.events.pop_front()
+    }
+}
@@ -0,0 +1,343 @@
+use super::keymap::KeyMap;
+use anyhow::{anyhow, Result};
+use chip8_core::prelude::*;
+use log::{debug, warn};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::{EventPump, GameControllerSubsystem};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Receiver,
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+#[derive(Debug)]
+struct CurrentKeys {
+    prev: Keys,
+    current: Keys,
+}
+
+/// A square wave played by SDL while the sound timer is active
+struct SquareWave {
+    phase: f32,
+    phase_inc: f32,
+    volume: f32,
+    playing: Arc<AtomicBool>,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let playing = self.playing.load(Ordering::Relaxed);
+
+        for sample in out.iter_mut() {
+            *sample = if !playing {
+                0.0
+            } else if self.phase < 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// A window, keypad and beeper using SDL2
+///
+/// Unlike minifb, SDL reports key releases, so keys are held exactly as long as they are
+/// pressed. Game controllers are supported as well, the D-pad is mapped to 2/4/6/8 and the
+/// A and B buttons to 5 and 0.
+///
+/// Only available with the "sdl" feature.
+pub struct SdlDisplay {
+    canvas: Canvas<Window>,
+    event_pump: EventPump,
+    controller_subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    frame: Arc<Mutex<Option<Framebuffer>>>,
+    keys: Arc<Mutex<CurrentKeys>>,
+    pressed: u16,
+    keymap: KeyMap,
+    playing: Arc<AtomicBool>,
+    _audio: Option<AudioDevice<SquareWave>>,
+}
+
+impl std::fmt::Debug for SdlDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SdlDisplay")
+            .field("keys", &self.keys)
+            .field("pressed", &self.pressed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The character printed on a key, as used by [`KeyMap`]
+fn key_char(keycode: Keycode) -> Option<char> {
+    let name = keycode.name();
+    let mut chars = name.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+fn map_button(button: Button) -> Option<u8> {
+    let val = match button {
+        Button::DPadUp => 0x2,
+        Button::DPadLeft => 0x4,
+        Button::DPadRight => 0x6,
+        Button::DPadDown => 0x8,
+        Button::A => 0x5,
+        Button::B => 0x0,
+        _ => return None,
+    };
+
+    Some(val)
+}
+
+impl SdlDisplay {
+    /// The scale of a low resolution pixel, high resolution pixels are half as large
+    const SCALE: usize = 10;
+    const WIDTH: usize = DisplayMode::LoRes.width() * Self::SCALE;
+    const HEIGHT: usize = DisplayMode::LoRes.height() * Self::SCALE;
+    const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+    /// Open a window and the default audio device
+    ///
+    /// The beeper plays a square wave with the given frequency in Hz and volume
+    /// (0.0 - 1.0). If no audio device can be opened, the display runs silently.
+    pub fn new(frequency: f32, volume: f32) -> Result<Self> {
+        let sdl = sdl2::init().map_err(|e| anyhow!("Initializing SDL: {}", e))?;
+        let video = sdl
+            .video()
+            .map_err(|e| anyhow!("Initializing SDL video: {}", e))?;
+
+        let window = video
+            .window("CHIP-8 Emulator", Self::WIDTH as u32, Self::HEIGHT as u32)
+            .position_centered()
+            .build()?;
+        let canvas = window.into_canvas().present_vsync().build()?;
+
+        let event_pump = sdl
+            .event_pump()
+            .map_err(|e| anyhow!("Creating SDL event pump: {}", e))?;
+        let controller_subsystem = sdl
+            .game_controller()
+            .map_err(|e| anyhow!("Initializing SDL game controllers: {}", e))?;
+
+        let playing = Arc::new(AtomicBool::new(false));
+        let audio = sdl.audio().and_then(|audio| {
+            let desired = AudioSpecDesired {
+                freq: Some(44_100),
+                channels: Some(1),
+                samples: None,
+            };
+
+            audio.open_playback(None, &desired, |spec| {
+                debug!("SDL audio output with {:?}", spec);
+
+                SquareWave {
+                    phase: 0.0,
+                    phase_inc: frequency / spec.freq as f32,
+                    volume: volume.clamp(0.0, 1.0),
+                    playing: playing.clone(),
+                }
+            })
+        });
+
+        let audio = match audio {
+            Ok(audio) => {
+                audio.resume();
+                Some(audio)
+            }
+            Err(e) => {
+                warn!("SDL audio disabled: {}", e);
+                None
+            }
+        };
+
+        Ok(Self {
+            canvas,
+            event_pump,
+            controller_subsystem,
+            controllers: Vec::new(),
+            frame: Arc::new(Mutex::new(None)),
+            keys: Arc::new(Mutex::new(CurrentKeys {
+                prev: Keys(0),
+                current: Keys(0),
+            })),
+            pressed: 0,
+            keymap: KeyMap::default(),
+            playing,
+            _audio: audio,
+        })
+    }
+
+    /// Use `keymap` instead of the QWERTY keypad
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    pub fn keypad_adapter(&self) -> KeypadAdapter {
+        KeypadAdapter(self.keys.clone())
+    }
+
+    pub fn graphics_adapter(&self) -> GraphicsAdapter {
+        GraphicsAdapter(self.frame.clone())
+    }
+
+    pub fn speaker_adapter(&self) -> SpeakerAdapter {
+        SpeakerAdapter(self.playing.clone())
+    }
+
+    /// Run the window until it is closed, ESC is pressed or a stop is received
+    pub fn run(&mut self, stop: Receiver<()>) -> Result<()> {
+        loop {
+            if let Ok(()) = stop.try_recv() {
+                return Ok(());
+            }
+
+            for event in self.event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        scancode: Some(Scancode::Escape),
+                        ..
+                    } => return Ok(()),
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(key) = key_char(keycode).and_then(|c| self.keymap.get(c)) {
+                            self.pressed |= 1 << key;
+                        }
+                    }
+                    Event::KeyUp {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(key) = key_char(keycode).and_then(|c| self.keymap.get(c)) {
+                            self.pressed &= !(1 << key);
+                        }
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        match self.controller_subsystem.open(which) {
+                            Ok(controller) => {
+                                debug!("Opened game controller {}", controller.name());
+                                self.controllers.push(controller);
+                            }
+                            Err(e) => warn!("Opening game controller {}: {}", which, e),
+                        }
+                    }
+                    Event::ControllerButtonDown { button, .. } => {
+                        if let Some(key) = map_button(button) {
+                            self.pressed |= 1 << key;
+                        }
+                    }
+                    Event::ControllerButtonUp { button, .. } => {
+                        if let Some(key) = map_button(button) {
+                            self.pressed &= !(1 << key);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            {
+                let keys = &mut self.keys.lock().expect("Locking keys failed");
+                let current = keys.current.clone();
+                keys.prev.update(&current);
+                keys.current = Keys(self.pressed);
+            }
+
+            let pending = self.frame.lock().expect("Locking frame failed").take();
+
+            match pending {
+                Some(framebuffer) => self.draw(&framebuffer)?,
+                None => std::thread::sleep(Self::FRAME),
+            }
+        }
+    }
+
+    /// Draw the whole framebuffer, the contents of the back buffer are undefined after presenting
+    fn draw(&mut self, framebuffer: &Framebuffer) -> Result<()> {
+        let scale = Self::WIDTH / framebuffer.width();
+
+        let lit: Vec<SdlRect> = (0..framebuffer.height())
+            .flat_map(|y| (0..framebuffer.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| framebuffer.pixel(x, y))
+            .map(|(x, y)| {
+                SdlRect::new(
+                    (x * scale) as i32,
+                    (y * scale) as i32,
+                    scale as u32,
+                    scale as u32,
+                )
+            })
+            .collect();
+
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+        self.canvas.fill_rects(&lit).map_err(|e| anyhow!(e))?;
+        self.canvas.present();
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct KeypadAdapter(Arc<Mutex<CurrentKeys>>);
+
+impl Keypad for KeypadAdapter {
+    fn pressed_keys(&self) -> Keys {
+        let keys = &self.0.lock().expect("Locking keys buffer failed").current;
+        keys.clone()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
+
+        keys.prev.falling_edges(&keys.current)
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
+
+        keys.prev.rising_edges(&keys.current)
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphicsAdapter(Arc<Mutex<Option<Framebuffer>>>);
+
+impl Graphics for GraphicsAdapter {
+    fn present(&mut self, framebuffer: &Framebuffer, _dirty: Rect) {
+        let mut frame = self.0.lock().expect("Locking frame failed");
+        *frame = Some(framebuffer.clone());
+    }
+}
+
+#[derive(Debug)]
+pub struct SpeakerAdapter(Arc<AtomicBool>);
+
+impl Speaker for SpeakerAdapter {
+    fn start(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn stop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
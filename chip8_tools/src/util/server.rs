@@ -0,0 +1,286 @@
+use crate::util::screenshot::write_png;
+use anyhow::{anyhow, Result};
+use chip8_core::prelude::*;
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tiny_http::{Header, Method as HttpMethod, Request, Response, Server};
+
+/// How long the server waits for the CHIP-8 to answer a call
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The scale of the screenshots served without a `scale` parameter
+const SCREENSHOT_SCALE: usize = 8;
+
+/// The methods of the control server
+const METHODS: &str = "pause, resume, reset, step, state, memory";
+
+/// A server controlling the CHIP-8 over HTTP, for automation and integration tests
+///
+/// `POST /rpc` takes [JSON-RPC 2.0](https://www.jsonrpc.org/specification) requests:
+///
+/// | method   | params                  | result                                      |
+/// | -------- | ----------------------- | ------------------------------------------- |
+/// | `pause`  |                         | the state                                   |
+/// | `resume` |                         | the state                                   |
+/// | `reset`  |                         | the state                                   |
+/// | `step`   | `count`, defaults to 1  | the state after pausing and executing them  |
+/// | `state`  |                         | registers, timers, stack, paused            |
+/// | `memory` | `addr`, `len` (16)      | `addr` and the `bytes`                      |
+///
+/// `GET /screenshot.png?scale=N` returns the display as a PNG, each pixel scaled to N x N.
+///
+/// The calls are executed by the thread running the CHIP-8, see [`Call::answer`]. The
+/// server stops when it is dropped.
+pub struct ControlServer {
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    /// Listen on `addr`, e.g. `127.0.0.1:8080`, returns the server and the calls to answer
+    pub fn new(addr: &str) -> Result<(Self, Receiver<Call>)> {
+        let server = Arc::new(Server::http(addr).map_err(|e| anyhow!(e))?);
+        let (calls, received) = channel();
+
+        let listener = Arc::clone(&server);
+        let thread = std::thread::spawn(move || {
+            for request in listener.incoming_requests() {
+                if let Err(e) = serve(request, &calls) {
+                    warn!("Control server: {}", e);
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                server,
+                thread: Some(thread),
+            },
+            received,
+        ))
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A call of the [`ControlServer`], to be answered by the thread running the CHIP-8
+#[derive(Debug)]
+pub struct Call {
+    method: Method,
+    reply: Sender<Result<Reply, String>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Method {
+    Command(Command),
+    Step(u32),
+    State,
+    Memory { addr: usize, len: usize },
+    Screenshot(usize),
+}
+
+#[derive(Debug)]
+enum Reply {
+    Json(Value),
+    Png(Vec<u8>),
+}
+
+impl Call {
+    /// Execute the call between two frames and send the result to the client
+    ///
+    /// Pause, resume and reset are passed to `execute`, which runs them like the commands of
+    /// the frontend.
+    pub fn answer<'m, P: Peripherals>(
+        self,
+        chip8: &mut Chip8<'m, P>,
+        execute: impl FnOnce(&mut Chip8<'m, P>, Command),
+    ) {
+        let result = match self.method {
+            Method::Command(command) => {
+                execute(chip8, command);
+                Ok(Reply::Json(state(chip8)))
+            }
+            Method::Step(count) => step(chip8, count).map(|()| Reply::Json(state(chip8))),
+            Method::State => Ok(Reply::Json(state(chip8))),
+            Method::Memory { addr, len } => match chip8.core().memory().get(addr..addr + len) {
+                Some(bytes) => Ok(Reply::Json(json!({"addr": addr, "bytes": bytes}))),
+                None => Err(format!("{:#x} bytes at {:#x} are out of bounds", len, addr)),
+            },
+            Method::Screenshot(scale) => {
+                let mut png = Vec::new();
+                write_png(chip8.core().framebuffer(), &mut png, scale)
+                    .map(|()| Reply::Png(png))
+                    .map_err(|e| format!("{:#}", e))
+            }
+        };
+
+        // The client may have given up waiting
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Pause and execute `count` instructions
+fn step<P: Peripherals>(chip8: &mut Chip8<'_, P>, count: u32) -> Result<(), String> {
+    chip8.resume();
+    let result = (0..count).try_for_each(|_| chip8.tick().map(|_| ()));
+    chip8.pause();
+
+    result.map_err(|e| e.to_string())
+}
+
+/// The registers, timers and stack as JSON
+fn state<P: Peripherals>(chip8: &Chip8<'_, P>) -> Value {
+    let core = chip8.core();
+
+    json!({
+        "paused": chip8.is_paused(),
+        "pc": core.pc(),
+        "i": core.i(),
+        "sp": core.sp(),
+        "registers": core.registers(),
+        "delay_timer": chip8.delay_timer(),
+        "sound_timer": chip8.sound_timer(),
+        "stack": core.stack(),
+        "stack_capacity": core.stack_capacity(),
+        "instructions": chip8.stats().instructions,
+    })
+}
+
+/// A JSON-RPC 2.0 request
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A JSON-RPC 2.0 error, with the code and message
+type RpcError = (i32, String);
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const SERVER_ERROR: i32 = -32000;
+
+impl RpcRequest {
+    fn method(&self) -> Result<Method, RpcError> {
+        Ok(match &self.method[..] {
+            "pause" => Method::Command(Command::Pause),
+            "resume" => Method::Command(Command::Resume),
+            "reset" => Method::Command(Command::Reset),
+            "step" => Method::Step(self.param("count")?.unwrap_or(1)),
+            "state" => Method::State,
+            "memory" => Method::Memory {
+                addr: self
+                    .param("addr")?
+                    .ok_or((INVALID_PARAMS, "Missing addr".to_string()))?,
+                len: self.param("len")?.unwrap_or(16),
+            },
+            method => {
+                return Err((
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method {}, expected one of {}", method, METHODS),
+                ))
+            }
+        })
+    }
+
+    /// The named parameter, if given
+    fn param<T: for<'de> Deserialize<'de>>(&self, name: &str) -> Result<Option<T>, RpcError> {
+        match self.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => T::deserialize(value)
+                .map(Some)
+                .map_err(|e| (INVALID_PARAMS, format!("Invalid {}: {}", name, e))),
+        }
+    }
+}
+
+/// Answer a single HTTP request
+fn serve(mut request: Request, calls: &Sender<Call>) -> std::io::Result<()> {
+    debug!("Control server: {} {}", request.method(), request.url());
+
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    match (request.method(), path) {
+        (HttpMethod::Post, "/rpc") => {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body)?;
+            let response = rpc(&body, calls);
+            request.respond(
+                Response::from_string(response.to_string())
+                    .with_header(content_type("application/json")),
+            )
+        }
+        (HttpMethod::Get, "/screenshot.png") => {
+            let scale = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("scale="))
+                .map_or(Ok(SCREENSHOT_SCALE), str::parse);
+            let result = match scale {
+                Ok(scale @ 1..=64) => call(calls, Method::Screenshot(scale)),
+                _ => Err((INVALID_PARAMS, "The scale has to be 1 to 64".to_string())),
+            };
+
+            match result {
+                Ok(Reply::Png(png)) => {
+                    request.respond(Response::from_data(png).with_header(content_type("image/png")))
+                }
+                Ok(Reply::Json(_)) => unreachable!("screenshots are PNGs"),
+                Err((code, message)) => {
+                    let status = if code == INVALID_PARAMS { 400 } else { 503 };
+                    request.respond(Response::from_string(message).with_status_code(status))
+                }
+            }
+        }
+        _ => request.respond(Response::from_string("Not found").with_status_code(404)),
+    }
+}
+
+/// The JSON-RPC response to `body`
+fn rpc(body: &str, calls: &Sender<Call>) -> Value {
+    let request: RpcRequest = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(e) => return rpc_error(Value::Null, (PARSE_ERROR, e.to_string())),
+    };
+
+    match request.method().and_then(|method| call(calls, method)) {
+        Ok(Reply::Json(result)) => json!({"jsonrpc": "2.0", "id": request.id, "result": result}),
+        Ok(Reply::Png(_)) => unreachable!("screenshots are only served over GET"),
+        Err(e) => rpc_error(request.id, e),
+    }
+}
+
+fn rpc_error(id: Value, (code, message): RpcError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// Pass `method` to the CHIP-8 and wait for the answer
+fn call(calls: &Sender<Call>, method: Method) -> Result<Reply, RpcError> {
+    let (reply, answer) = channel();
+    let stopped = || (SERVER_ERROR, "The CHIP-8 isn't running".to_string());
+
+    calls.send(Call { method, reply }).map_err(|_| stopped())?;
+    match answer.recv_timeout(TIMEOUT) {
+        Ok(result) => result.map_err(|message| (SERVER_ERROR, message)),
+        Err(_) => Err(stopped()),
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("Valid header")
+}
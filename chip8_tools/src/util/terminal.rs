@@ -0,0 +1,259 @@
+use super::keymap::KeyMap;
+use chip8_core::prelude::*;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use log::debug;
+use std::io::{self, Stdout, Write};
+use std::sync::{mpsc::Receiver, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The latest frame of the core, waiting to be drawn
+#[derive(Debug, Default)]
+struct Buffer {
+    framebuffer: Framebuffer,
+    dirty: Option<Rect>,
+}
+
+#[derive(Debug)]
+struct CurrentKeys {
+    prev: Keys,
+    current: Keys,
+}
+
+/// A display and keypad running in a terminal
+///
+/// Two rows of pixels are drawn per line of text using unicode half blocks, so a 64x32
+/// display needs a terminal of at least 64x16 characters (128x32 in hires mode).
+///
+/// Most terminals only report key presses, not releases. Unless the terminal supports
+/// reporting key releases, a key is considered to be held for [`TerminalDisplay::HOLD`]
+/// after it was last pressed or repeated.
+#[derive(Debug)]
+pub struct TerminalDisplay {
+    out: Stdout,
+    buffer: Arc<Mutex<Buffer>>,
+    keys: Arc<Mutex<CurrentKeys>>,
+    pressed_at: [Option<Instant>; 16],
+    release_events: bool,
+    mode: Option<DisplayMode>,
+    keymap: KeyMap,
+}
+
+/// Render two rows of pixels, starting at row `2 * line`, as unicode half blocks
+pub fn half_blocks(framebuffer: &Framebuffer, line: usize) -> String {
+    (0..framebuffer.width())
+        .map(|x| {
+            match (
+                framebuffer.pixel(x, 2 * line),
+                framebuffer.pixel(x, 2 * line + 1),
+            ) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            }
+        })
+        .collect()
+}
+
+impl TerminalDisplay {
+    /// How long a key is considered held if the terminal doesn't report releases
+    pub const HOLD: Duration = Duration::from_millis(200);
+    const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+    /// Switch the terminal into raw mode and an alternate screen
+    ///
+    /// The terminal is restored when the display is dropped.
+    pub fn new() -> io::Result<Self> {
+        let mut out = io::stdout();
+
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        debug!("Terminal reports key releases: {}", release_events);
+
+        Ok(Self {
+            out,
+            buffer: Arc::new(Mutex::new(Buffer::default())),
+            keys: Arc::new(Mutex::new(CurrentKeys {
+                prev: Keys(0),
+                current: Keys(0),
+            })),
+            pressed_at: [None; 16],
+            release_events,
+            mode: None,
+            keymap: KeyMap::default(),
+        })
+    }
+
+    /// Use `keymap` instead of the QWERTY keypad
+    pub fn with_keymap(mut self, keymap: KeyMap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    pub fn keypad_adapter(&self) -> KeypadAdapter {
+        KeypadAdapter(self.keys.clone())
+    }
+
+    pub fn graphics_adapter(&self) -> GraphicsAdapter {
+        GraphicsAdapter(self.buffer.clone())
+    }
+
+    /// Run the terminal loop until ESC or Ctrl-C is pressed, or a stop is received
+    pub fn run(&mut self, stop: Receiver<()>) -> io::Result<()> {
+        loop {
+            if let Ok(()) = stop.try_recv() {
+                return Ok(());
+            }
+
+            while event::poll(Duration::ZERO)? {
+                match event::read()? {
+                    Event::Key(key) if self.handle_key(key) => return Ok(()),
+                    Event::Resize(..) => {
+                        self.mode = None;
+                    }
+                    _ => (),
+                }
+            }
+
+            self.update_keys();
+
+            let pending = {
+                let mut buffer = self.buffer.lock().expect("Locking graphics buffer failed");
+                let dirty = if self.mode == Some(buffer.framebuffer.mode()) {
+                    buffer.dirty.take()
+                } else {
+                    // First frame, resize or mode switch: redraw everything
+                    buffer.dirty = None;
+                    Some(buffer.framebuffer.bounds())
+                };
+
+                dirty.map(|dirty| (buffer.framebuffer.clone(), dirty))
+            };
+
+            if let Some((framebuffer, dirty)) = pending {
+                self.draw(&framebuffer, dirty)?;
+            }
+
+            // Sleeps for a frame, unless an event arrives earlier
+            event::poll(Self::FRAME)?;
+        }
+    }
+
+    /// Handle a key event, returns whether the display should be closed
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Esc => true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => true,
+            KeyCode::Char(c) => {
+                if let Some(idx) = self.keymap.get(c) {
+                    self.pressed_at[idx as usize] = match key.kind {
+                        KeyEventKind::Press | KeyEventKind::Repeat => Some(Instant::now()),
+                        KeyEventKind::Release => None,
+                    };
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn update_keys(&mut self) {
+        let now = Instant::now();
+        let mut pressed = 0;
+
+        for (idx, pressed_at) in self.pressed_at.iter().enumerate() {
+            if let Some(at) = pressed_at {
+                if self.release_events || now - *at < Self::HOLD {
+                    pressed |= 1 << idx;
+                }
+            }
+        }
+
+        let keys = &mut self.keys.lock().expect("Locking keys failed");
+        let current = keys.current.clone();
+        keys.prev.update(&current);
+        keys.current = Keys(pressed);
+    }
+
+    /// Draw the lines of the terminal covering the dirty region
+    fn draw(&mut self, framebuffer: &Framebuffer, dirty: Rect) -> io::Result<()> {
+        if self.mode != Some(framebuffer.mode()) {
+            self.mode = Some(framebuffer.mode());
+            queue!(self.out, Clear(ClearType::All))?;
+        }
+
+        let first = dirty.y / 2;
+        let last = (dirty.y + dirty.height).div_ceil(2);
+
+        for line in first..last {
+            let text = half_blocks(framebuffer, line);
+            queue!(self.out, MoveTo(0, line as u16), Print(text))?;
+        }
+
+        self.out.flush()
+    }
+}
+
+impl Drop for TerminalDisplay {
+    fn drop(&mut self) {
+        if self.release_events {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[derive(Debug)]
+pub struct KeypadAdapter(Arc<Mutex<CurrentKeys>>);
+
+impl Keypad for KeypadAdapter {
+    fn pressed_keys(&self) -> Keys {
+        let keys = &self.0.lock().expect("Locking keys buffer failed").current;
+        keys.clone()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
+
+        keys.prev.falling_edges(&keys.current)
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        let keys = &self.0.lock().expect("Locking keys buffer failed");
+
+        keys.prev.rising_edges(&keys.current)
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphicsAdapter(Arc<Mutex<Buffer>>);
+
+impl Graphics for GraphicsAdapter {
+    fn present(&mut self, framebuffer: &Framebuffer, dirty: Rect) {
+        let mut buffer = self.0.lock().expect("Locking graphics buffer failed");
+
+        buffer.dirty = match buffer.dirty {
+            Some(pending) if buffer.framebuffer.mode() == framebuffer.mode() => {
+                Some(pending.union(dirty))
+            }
+            _ => Some(dirty),
+        };
+        buffer.framebuffer.clone_from(framebuffer);
+    }
+}
@@ -0,0 +1,84 @@
+use chip8_core::prelude::*;
+use log::warn;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+
+/// A log of the executed instructions, one line per tick
+///
+/// Every line holds the tick, the address and opcode of the instruction, followed by the
+/// state of the core after executing it, e.g.
+///
+/// ```text
+/// 1041 022A 7001 PC 022C SP 00 I 0256 regs [05, 00, ...] [ADD V0, 01]
+/// ```
+///
+/// In ring buffer mode only the last lines are kept, and written once the CHIP-8 fails.
+#[derive(Debug)]
+pub struct Tracer {
+    output: Option<LineWriter<File>>,
+    /// The most recent lines and the number of lines to keep, in ring buffer mode
+    ring: Option<(VecDeque<String>, usize)>,
+}
+
+impl Tracer {
+    /// Trace every tick to `path`, or only the `last` ticks before an error
+    pub fn new<P: AsRef<Path>>(path: P, last: Option<usize>) -> io::Result<Self> {
+        Ok(Self {
+            output: Some(LineWriter::new(File::create(path)?)),
+            ring: last.map(|len| (VecDeque::with_capacity(len), len)),
+        })
+    }
+
+    /// Execute a tick of `chip8` with [`Chip8::run_cycles`] and trace the executed instruction
+    ///
+    /// Nothing is executed while the CHIP-8 is paused or once the program exited or halted,
+    /// so these ticks aren't traced.
+    pub fn step<P: Peripherals>(&mut self, chip8: &mut Chip8<'_, P>) -> RunExit {
+        let core = chip8.core();
+        let (pc, opcode) = (core.pc(), core.opcode());
+
+        let tick = chip8.ticks();
+        let exit = chip8.run_cycles(1).exit;
+
+        if let RunExit::Error(e) = &exit {
+            self.write(format!("{} {:04X} {:04X} error: {}", tick, pc, opcode, e));
+            self.dump();
+        } else if chip8.ticks() != tick {
+            self.write(format!("{} {:04X} {:04X} {}", tick, pc, opcode, chip8));
+        }
+
+        exit
+    }
+
+    fn write(&mut self, line: String) {
+        match &mut self.ring {
+            Some((lines, len)) => {
+                if lines.len() == *len {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+            None => self.write_line(&line),
+        }
+    }
+
+    /// Write the lines kept in ring buffer mode
+    fn dump(&mut self) {
+        if let Some((lines, _)) = self.ring.take() {
+            for line in lines {
+                self.write_line(&line);
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if let Some(output) = &mut self.output {
+            if let Err(e) = writeln!(output, "{}", line) {
+                warn!("Tracing stopped: {}", e);
+                self.output = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use chip8_core::prelude::*;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc::Receiver, Arc, Mutex};
+use std::time::Duration;
+use tungstenite::{Error, HandshakeError, Message, WebSocket};
+
+/// The state shared by the display, its clients and the adapters
+#[derive(Debug)]
+struct Shared {
+    framebuffer: Framebuffer,
+    /// Counts the presented frames, so clients know whether they are up to date
+    version: u64,
+    keys: Keys,
+    events: VecDeque<KeyEvent>,
+    stopped: bool,
+}
+
+impl Shared {
+    /// Press or release `key`, unless it already is
+    fn set_key(&mut self, key: u8, pressed: bool) {
+        if self.keys.pressed(key) == pressed {
+            return;
+        }
+
+        self.keys.0 ^= 1 << key;
+        self.events.push_back(match pressed {
+            true => KeyEvent::Down(key),
+            false => KeyEvent::Up(key),
+        });
+    }
+}
+
+/// A key event sent by a client
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ClientMessage {
+    Down(u8),
+    Up(u8),
+}
+
+/// A display and keypad in web browsers, connected over WebSockets
+///
+/// Every client receives the framebuffer as a binary message once it connects and whenever it
+/// changed, at most once per frame: the width and height as u8, followed by the pixels row by
+/// row, 8 per byte with the leftmost one in the most significant bit.
+///
+/// Clients send key events as text messages, `{"down": 5}` and `{"up": 5}`. All clients share
+/// the keypad, the keys held by a client are released once it disconnects.
+/// `chip8_web/www/remote.html` is such a client.
+#[derive(Debug)]
+pub struct WebSocketDisplay {
+    listener: TcpListener,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl WebSocketDisplay {
+    const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+    /// Listen for clients on `addr`, e.g. `127.0.0.1:8765`
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            shared: Arc::new(Mutex::new(Shared {
+                framebuffer: Framebuffer::new(),
+                version: 0,
+                keys: Keys(0),
+                events: VecDeque::new(),
+                stopped: false,
+            })),
+        })
+    }
+
+    /// The address the display listens on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn keypad_adapter(&self) -> KeypadAdapter {
+        KeypadAdapter(self.shared.clone())
+    }
+
+    pub fn graphics_adapter(&self) -> GraphicsAdapter {
+        GraphicsAdapter(self.shared.clone())
+    }
+
+    /// Accept clients until a stop is received, each client is served by its own thread
+    pub fn run(&mut self, stop: Receiver<()>) -> io::Result<()> {
+        loop {
+            if let Ok(()) = stop.try_recv() {
+                self.lock().stopped = true;
+                return Ok(());
+            }
+
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    info!("WebSocket client {} connected", addr);
+                    let shared = self.shared.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = serve(stream, &shared) {
+                            warn!("WebSocket client {}: {}", addr, e);
+                        }
+                        info!("WebSocket client {} disconnected", addr);
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(Self::FRAME),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared
+            .lock()
+            .expect("Locking WebSocket display failed")
+    }
+}
+
+/// Exchange frames and key events with a client until it disconnects or the display stops
+fn serve(stream: TcpStream, shared: &Mutex<Shared>) -> io::Result<()> {
+    // Accepted streams inherit the non-blocking mode of the listener on some platforms
+    stream.set_nonblocking(false)?;
+    let mut socket = tungstenite::accept(stream).map_err(|e| match e {
+        HandshakeError::Failure(e) => into_io(e),
+        HandshakeError::Interrupted(_) => ErrorKind::WouldBlock.into(),
+    })?;
+    // Wait for key events no longer than a frame, then send the next one
+    socket
+        .get_ref()
+        .set_read_timeout(Some(WebSocketDisplay::FRAME))?;
+
+    let mut held = Keys(0);
+    let result = exchange(&mut socket, shared, &mut held);
+
+    let mut shared = shared.lock().expect("Locking WebSocket display failed");
+    for key in (0..16).filter(|&key| held.pressed(key)) {
+        shared.set_key(key, false);
+    }
+
+    result
+}
+
+fn exchange(
+    socket: &mut WebSocket<TcpStream>,
+    shared: &Mutex<Shared>,
+    held: &mut Keys,
+) -> io::Result<()> {
+    let mut sent = None;
+
+    loop {
+        let message = match socket.read() {
+            Ok(Message::Text(text)) => Some(text),
+            Ok(_) => None,
+            Err(Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                None
+            }
+            Err(Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(into_io(e)),
+        };
+
+        let frame = {
+            let mut shared = shared.lock().expect("Locking WebSocket display failed");
+            if shared.stopped {
+                return socket.close(None).map_err(into_io);
+            }
+
+            match message.as_deref().map(serde_json::from_str) {
+                Some(Ok(ClientMessage::Down(key))) if key < 16 => {
+                    held.0 |= 1 << key;
+                    shared.set_key(key, true);
+                }
+                Some(Ok(ClientMessage::Up(key))) if key < 16 => {
+                    held.0 &= !(1 << key);
+                    shared.set_key(key, false);
+                }
+                Some(_) => debug!("Ignoring WebSocket message {:?}", message),
+                None => (),
+            }
+
+            (sent != Some(shared.version)).then(|| (shared.version, encode(&shared.framebuffer)))
+        };
+
+        if let Some((version, data)) = frame {
+            socket.send(Message::Binary(data)).map_err(into_io)?;
+            sent = Some(version);
+        }
+    }
+}
+
+fn into_io(e: Error) -> io::Error {
+    match e {
+        Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+/// The framebuffer as sent to the clients, see [`WebSocketDisplay`]
+fn encode(framebuffer: &Framebuffer) -> Vec<u8> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let mut data = vec![0; 2 + width * height / 8];
+    data[0] = width as u8;
+    data[1] = height as u8;
+
+    for y in 0..height {
+        for x in (0..width).filter(|&x| framebuffer.pixel(x, y)) {
+            let idx = y * width + x;
+            data[2 + idx / 8] |= 0x80 >> (idx % 8);
+        }
+    }
+
+    data
+}
+
+#[derive(Debug)]
+pub struct KeypadAdapter(Arc<Mutex<Shared>>);
+
+impl Keypad for KeypadAdapter {
+    fn pressed_keys(&self) -> Keys {
+        self.0.lock().expect("Locking keys failed").keys.clone()
+    }
+
+    // The edges are only read from the events
+    fn last_released_key(&mut self) -> FallingEdges {
+        Keys(0).falling_edges(&Keys(0))
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        Keys(0).rising_edges(&Keys(0))
+    }
+
+    // Events may arrive in bursts over the network, queueing them keeps short taps
+    fn queues_events(&self) -> bool {
+        true
+    }
+
+    fn poll_event(&mut self, _tick: u64) -> Option<KeyEvent> {
+        self.0
+            .lock()
+            .expect("Locking keys failed")
+            .events
+            .pop_front()
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphicsAdapter(Arc<Mutex<Shared>>);
+
+impl Graphics for GraphicsAdapter {
+    fn present(&mut self, framebuffer: &Framebuffer, _dirty: Rect) {
+        let mut shared = self.0.lock().expect("Locking framebuffer failed");
+
+        shared.framebuffer.clone_from(framebuffer);
+        shared.version += 1;
+    }
+}
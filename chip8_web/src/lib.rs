@@ -0,0 +1,230 @@
+//! A browser frontend for `chip8_core`, using wasm-bindgen
+//!
+//! Build with `wasm-pack build --target web chip8_web` and serve the repository root, the
+//! page at `chip8_web/www/index.html` loads the generated package from `chip8_web/pkg`.
+
+use chip8_core::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+/// The core frequency
+const CORE_FREQ: u32 = 720;
+
+#[derive(Debug)]
+struct CurrentKeys {
+    prev: Keys,
+    current: Keys,
+    /// The keys held down according to the latest keyboard events
+    pressed: u16,
+}
+
+#[derive(Debug)]
+struct KeypadAdapter(Rc<RefCell<CurrentKeys>>);
+
+impl Keypad for KeypadAdapter {
+    fn pressed_keys(&self) -> Keys {
+        self.0.borrow().current.clone()
+    }
+
+    fn last_released_key(&mut self) -> FallingEdges {
+        let keys = self.0.borrow();
+
+        keys.prev.falling_edges(&keys.current)
+    }
+
+    fn last_pressed_key(&mut self) -> RisingEdges {
+        let keys = self.0.borrow();
+
+        keys.prev.rising_edges(&keys.current)
+    }
+}
+
+fn random() -> u8 {
+    (js_sys::Math::random() * 256.0) as u8
+}
+
+/// Map a `KeyboardEvent.code` to a key of the hex keypad, using the usual layout:
+///
+/// ```text
+/// 1 2 3 4        1 2 3 C
+/// Q W E R   ->   4 5 6 D
+/// A S D F        7 8 9 E
+/// Z X C V        A 0 B F
+/// ```
+fn map_key(code: &str) -> Option<u8> {
+    let val = match code {
+        "Digit1" => 0x1,
+        "Digit2" => 0x2,
+        "Digit3" => 0x3,
+        "Digit4" => 0xC,
+        "KeyQ" => 0x4,
+        "KeyW" => 0x5,
+        "KeyE" => 0x6,
+        "KeyR" => 0xD,
+        "KeyA" => 0x7,
+        "KeyS" => 0x8,
+        "KeyD" => 0x9,
+        "KeyF" => 0xE,
+        "KeyZ" => 0xA,
+        "KeyX" => 0x0,
+        "KeyC" => 0xB,
+        "KeyV" => 0xF,
+        _ => return None,
+    };
+
+    Some(val)
+}
+
+type WebChip8 = Chip8<
+    'static,
+    PeripheralSet<
+        KeypadAdapter,
+        NullGraphics,
+        fn() -> u8,
+        DownTimer<'static>,
+        DownTimer<'static>,
+        NullSpeaker,
+        RamPersistence,
+    >,
+>;
+
+/// A CHIP-8 emulator running in the browser
+///
+/// The page calls [`Emulator::step_frame`] 60 times per second from `requestAnimationFrame`,
+/// followed by [`Emulator::render`] to draw the display onto a canvas.
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: WebChip8,
+    keys: Rc<RefCell<CurrentKeys>>,
+    image: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Create an emulator running the given ROM
+    ///
+    /// The memory of the core is leaked, as the core borrows it for its whole lifetime.
+    /// Emulators are meant to live as long as the page.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Emulator, JsError> {
+        let mem = Box::leak(vec![0; 4096].into_boxed_slice());
+        let reg = Box::leak(Box::new([0; 16]));
+        let stack = Box::leak(Box::new([0; 16]));
+
+        let mut core = Core::new(mem, reg, stack);
+        core.load_program(rom)
+            .map_err(|_| JsError::new("ROM doesn't fit into memory"))?;
+
+        let keys = Rc::new(RefCell::new(CurrentKeys {
+            prev: Keys(0),
+            current: Keys(0),
+            pressed: 0,
+        }));
+
+        let chip8 = Chip8::new(
+            core,
+            CORE_FREQ,
+            PeripheralSet {
+                keypad: KeypadAdapter(keys.clone()),
+                graphics: NullGraphics,
+                random: random as fn() -> u8,
+                delay_timer: DownTimer::new("delay"),
+                sound_timer: DownTimer::new("sound"),
+                speaker: NullSpeaker,
+                persistence: RamPersistence::default(),
+            },
+        )
+        .map_err(|e| JsError::new(&format!("{:?}", e)))?;
+
+        Ok(Self {
+            chip8,
+            keys,
+            image: Vec::new(),
+        })
+    }
+
+    /// Handle a `keydown` event, returns whether the key is mapped to the keypad
+    pub fn key_down(&mut self, code: &str) -> bool {
+        match map_key(code) {
+            Some(key) => {
+                self.keys.borrow_mut().pressed |= 1 << key;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Handle a `keyup` event, returns whether the key is mapped to the keypad
+    pub fn key_up(&mut self, code: &str) -> bool {
+        match map_key(code) {
+            Some(key) => {
+                self.keys.borrow_mut().pressed &= !(1 << key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Run the core for 1/60 of a second
+    pub fn step_frame(&mut self) -> Result<(), JsError> {
+        {
+            let mut keys = self.keys.borrow_mut();
+            let current = keys.current.clone();
+            keys.prev.update(&current);
+            keys.current = Keys(keys.pressed);
+        }
+
+        let cycles = self.chip8.frame_cycles();
+        self.chip8
+            .run_cycles(cycles as u64)
+            .exit
+            .into_result()
+            .map_err(|e| JsError::new(&format!("{:?}", e)))?;
+
+        Ok(())
+    }
+
+    /// The width of the display in pixels, changes with the display mode
+    pub fn width(&self) -> usize {
+        self.chip8.core().framebuffer().width()
+    }
+
+    /// The height of the display in pixels, changes with the display mode
+    pub fn height(&self) -> usize {
+        self.chip8.core().framebuffer().height()
+    }
+
+    /// Whether the sound timer is active
+    pub fn beeping(&self) -> bool {
+        self.chip8.sound_timer() != 0
+    }
+
+    /// Whether the program waits for a key, e.g. to show a prompt
+    pub fn waiting_for_key(&self) -> bool {
+        self.chip8.core().waiting_for_key()
+    }
+
+    /// Draw the display at 1:1 scale, the canvas is expected to be scaled with CSS
+    pub fn render(&mut self, ctx: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        let framebuffer = self.chip8.core().framebuffer();
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+
+        self.image.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let val = if framebuffer.pixel(x, y) { 0xFF } else { 0x00 };
+                self.image.extend_from_slice(&[val, val, val, 0xFF]);
+            }
+        }
+
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&self.image),
+            width as u32,
+            height as u32,
+        )?;
+        ctx.put_image_data(&image, 0.0, 0.0)
+    }
+}
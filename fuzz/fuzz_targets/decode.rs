@@ -0,0 +1,13 @@
+#![no_main]
+
+use chip8_core::instructions::Instruction;
+use libfuzzer_sys::fuzz_target;
+
+// Encoding and disassembling an instruction are the inverse of decoding and assembling it
+fuzz_target!(|instruction: Instruction| {
+    let opcode = instruction.encode();
+    assert_eq!(Instruction::decode(&opcode, 0x200), Ok(instruction.clone()));
+
+    let text = instruction.to_string();
+    assert_eq!(text.parse(), Ok(instruction), "{}", text);
+});
@@ -0,0 +1,50 @@
+#![no_main]
+
+use chip8_core::prelude::*;
+use chip8_core::rom::Rom;
+use libfuzzer_sys::fuzz_target;
+
+/// The ticks a ROM is executed for, unless it fails or exits earlier
+const TICKS: usize = 10_000;
+
+// Any ROM has to run until it fails with an Error or exits, without panicking
+fuzz_target!(|input: (QuirksConfig, u16, bool, Rom<'_>)| {
+    let (quirks, keys, large, rom) = input;
+
+    // The largest memory lets I reach 0xFFFF, the smallest ends most accesses early
+    let mut mem = vec![0; if large { Core::MAX_MEM_LEN } else { 4096 }];
+    let mut reg = [0; 16];
+    let mut stack = [0; 16];
+
+    let mut core = Core::new(&mut mem, &mut reg, &mut stack);
+    core.set_quirks(quirks);
+    if core.load_program(rom.0).is_err() {
+        return;
+    }
+
+    let mut random = XorShiftRandom::new(0);
+    let mut delay = DownTimer::new("delay");
+    let mut sound = DownTimer::new("sound");
+    // The keys are pressed and released on alternating ticks, for LD Vx, K
+    let (none, held) = (Keys(0), Keys(keys));
+
+    for tick in 0..TICKS {
+        let (before, after) = match tick % 2 {
+            0 => (&none, &held),
+            _ => (&held, &none),
+        };
+
+        let result = core.tick(
+            after.clone(),
+            before.falling_edges(after),
+            before.rising_edges(after),
+            &mut NullGraphics,
+            &mut random,
+            &mut delay,
+            &mut sound,
+        );
+        if result.is_err() || core.exited() {
+            break;
+        }
+    }
+});